@@ -0,0 +1,198 @@
+//! Keeps the audio of every spoken message, e.g. for editing highlights. Clips are
+//! named by the SHA-1 of their bytes, which the message log records.
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::session::fmt_thousands;
+
+/// The `[archive]` table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub enabled: bool,
+    pub dir: String,
+    /// Once the clips add up to more than this, the least recently spoken go.
+    pub max_bytes: Option<u64>,
+    /// Clips not spoken for this many days are deleted.
+    pub max_age_days: Option<u64>,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: "archive".into(),
+            max_bytes: None,
+            max_age_days: None,
+        }
+    }
+}
+
+impl ArchiveConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enabled && self.dir.trim().is_empty() {
+            return Err("archive.dir must not be empty".into());
+        }
+        Ok(())
+    }
+
+    /// Where the clip with `hash` is kept.
+    pub fn path(&self, hash: &str) -> PathBuf {
+        Path::new(&self.dir).join(format!("{hash}.wav"))
+    }
+}
+
+/// What `prune` deleted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Pruned {
+    pub clips: u64,
+    pub bytes: u64,
+}
+
+impl std::fmt::Display for Pruned {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} clips ({:.1} MiB)",
+            fmt_thousands(self.clips),
+            self.bytes as f64 / (1024. * 1024.)
+        )
+    }
+}
+
+/// Adds `wav` to the archive and returns its hash. A clip already there is marked
+/// as just used. `cached` is the file of the same audio in the audio cache, which
+/// is hard-linked rather than copied where the filesystem allows.
+pub fn store(config: &ArchiveConfig, wav: &[u8], cached: Option<&Path>) -> io::Result<String> {
+    let hash = hex::encode(Sha1::digest(wav));
+    let path = config.path(&hash);
+    if path.exists() {
+        // the modification time is the last use for `prune`
+        File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now())?;
+        return Ok(hash);
+    }
+    fs::create_dir_all(&config.dir)?;
+    if cached.is_none_or(|cached| fs::hard_link(cached, &path).is_err()) {
+        fs::write(&path, wav)?;
+    }
+    Ok(hash)
+}
+
+/// Deletes clips older than `max_age_days`, then the least recently used ones
+/// until they fit in `max_bytes`.
+pub fn prune(config: &ArchiveConfig) -> io::Result<Pruned> {
+    let mut clips = match clips(&config.dir) {
+        Ok(clips) => clips,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Pruned::default()),
+        Err(e) => return Err(e),
+    };
+    clips.sort_by_key(|&(_, _, used)| used);
+    let cutoff = config
+        .max_age_days
+        .map(|days| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60));
+    let mut total: u64 = clips.iter().map(|&(_, bytes, _)| bytes).sum();
+    let mut pruned = Pruned::default();
+    for (path, bytes, used) in clips {
+        let expired = cutoff.is_some_and(|cutoff| used < cutoff);
+        let too_big = config.max_bytes.is_some_and(|max| total > max);
+        if !expired && !too_big {
+            // the rest were used later
+            break;
+        }
+        fs::remove_file(&path)?;
+        total -= bytes;
+        pruned.clips += 1;
+        pruned.bytes += bytes;
+    }
+    Ok(pruned)
+}
+
+/// The clips in `dir` with their size and last use.
+fn clips(dir: &str) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut clips = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|extension| extension != "wav") {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+        clips.push((path, metadata.len(), metadata.modified()?));
+    }
+    Ok(clips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive_in(name: &str) -> ArchiveConfig {
+        let dir = std::env::temp_dir().join(format!("archive-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        ArchiveConfig {
+            enabled: true,
+            dir: dir.to_string_lossy().into_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn set_used(config: &ArchiveConfig, hash: &str, ago: Duration) {
+        File::options()
+            .write(true)
+            .open(config.path(hash))
+            .unwrap()
+            .set_modified(SystemTime::now() - ago)
+            .unwrap();
+    }
+
+    #[test]
+    fn stores_each_clip_once_and_links_cached_ones() {
+        let config = archive_in("store");
+        let first = store(&config, b"one", None).unwrap();
+        assert_eq!(store(&config, b"one", None).unwrap(), first);
+        assert_eq!(fs::read(config.path(&first)).unwrap(), b"one");
+        let cached = Path::new(&config.dir).join("cached.bin");
+        fs::write(&cached, b"two").unwrap();
+        let second = store(&config, b"two", Some(&cached)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(config.path(&second)).unwrap(), b"two");
+        // the cache evicting its copy leaves the archived one
+        fs::remove_file(cached).unwrap();
+        assert!(config.path(&second).exists());
+        _ = fs::remove_dir_all(&config.dir);
+    }
+
+    #[test]
+    fn prunes_old_clips_then_the_least_recently_used() {
+        let mut config = archive_in("prune");
+        let day = Duration::from_secs(24 * 60 * 60);
+        let old = store(&config, b"old clip", None).unwrap();
+        let stale = store(&config, b"stale", None).unwrap();
+        let fresh = store(&config, b"fresh", None).unwrap();
+        set_used(&config, &old, day * 40);
+        set_used(&config, &stale, day * 2);
+        config.max_age_days = Some(30);
+        assert_eq!(prune(&config).unwrap(), Pruned { clips: 1, bytes: 8 });
+        config.max_bytes = Some(7);
+        assert_eq!(prune(&config).unwrap().clips, 1);
+        assert!(!config.path(&stale).exists());
+        assert!(config.path(&fresh).exists());
+        _ = fs::remove_dir_all(&config.dir);
+    }
+
+    #[test]
+    fn prunes_nothing_without_an_archive() {
+        let config = archive_in("missing");
+        assert_eq!(prune(&config).unwrap(), Pruned::default());
+    }
+}
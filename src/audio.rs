@@ -0,0 +1,658 @@
+use std::{
+    collections::VecDeque,
+    io,
+    ops::RangeInclusive,
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rodio::{
+    buffer::SamplesBuffer,
+    cpal::{
+        self, traits::StreamTrait, BufferSize, FromSample, SampleFormat, SampleRate, SizedSample,
+        StreamConfig, SupportedBufferSize, SupportedStreamConfig,
+    },
+    dynamic_mixer::{self, DynamicMixer, DynamicMixerController},
+    Device, DeviceTrait, OutputStream, OutputStreamHandle, Source,
+};
+
+use crate::Configuration;
+
+/// An open output stream that sources can be played on.
+pub enum Output {
+    /// Stream opened by rodio with the device's default buffer size.
+    Rodio {
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+    },
+    /// Stream opened directly through cpal, used for a fixed buffer size.
+    Cpal {
+        _stream: cpal::Stream,
+        mixer: Arc<DynamicMixerController<f32>>,
+    },
+}
+
+impl Output {
+    pub fn play<S>(&self, source: S) -> Result<(), String>
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        match self {
+            Self::Rodio { handle, .. } => handle.play_raw(source).map_err(|e| e.to_string()),
+            Self::Cpal { mixer, .. } => {
+                mixer.add(source);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A decoded clip ready to be played.
+pub type Clip = Box<dyn Source<Item = f32> + Send>;
+
+/// Accepted `synthesizer_gain_db` and `output_gain_db`.
+pub const GAINS_DB: RangeInclusive<f32> = -60.0..=24.0;
+/// Accepted `gain_headroom_db`.
+pub const HEADROOMS_DB: RangeInclusive<f32> = -24.0..=0.0;
+/// How quickly the limiter lets the level back up after a peak.
+const LIMITER_RELEASE: Duration = Duration::from_millis(50);
+/// How many recent messages the automatic gain control averages over.
+const AGC_PHRASES: usize = 10;
+/// Length of the fades at the boundary between two clips.
+const DECLICK_CROSSFADE: Duration = Duration::from_millis(3);
+/// Length of the fade-in put before a clip that does not start at zero.
+const DECLICK_FADE_IN: Duration = Duration::from_millis(1);
+/// A clip starting louder than this starts with a click.
+const CLICK_THRESHOLD: f32 = 0.01;
+
+/// Automatic gain control state of this session.
+pub static AGC: Mutex<Agc> = Mutex::new(Agc {
+    history: VecDeque::new(),
+});
+
+/// Applies the configured processing to decoded audio: `synthesizer_gain_db`, the
+/// automatic gain control, the channel conversion and volume, then
+/// `output_gain_db` and a limiter at `gain_headroom_db`.
+pub fn apply_effects<S>(source: S, config: &Configuration) -> Clip
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let source = gain(Box::new(source), config.synthesizer_gain_db);
+    let source: Clip = if config.agc_enabled {
+        let (channels, rate) = (source.channels(), source.sample_rate());
+        let samples: Vec<f32> = source.collect();
+        let gain =
+            AGC.lock()
+                .unwrap()
+                .gain(rms(&samples), config.agc_target_rms, config.agc_max_gain);
+        Box::new(SamplesBuffer::new(channels, rate, samples).amplify(gain))
+    } else {
+        source
+    };
+    let source: Clip = match config.output_channels {
+        Some(to) => {
+            let (from, rate) = (source.channels(), source.sample_rate());
+            let samples = convert_channels(source.collect(), from, to.into());
+            Box::new(SamplesBuffer::new(to.into(), rate, samples))
+        }
+        None => source,
+    };
+    let source: Clip = if config.volume == 1.0 {
+        source
+    } else {
+        Box::new(source.amplify(config.volume))
+    };
+    let source = gain(source, config.output_gain_db);
+    Box::new(Limiter::new(
+        source,
+        db_to_amplitude(config.gain_headroom_db),
+    ))
+}
+
+/// Fades the end of mono clip `a` out and the start of `b` in over
+/// `crossfade_frames` samples each, so that going from one to the other passes
+/// through zero instead of jumping, which is heard as a click.
+pub fn declick_boundary(a: &[f32], b: &[f32], crossfade_frames: usize) -> (Vec<f32>, Vec<f32>) {
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    fade_out(&mut a, 1, crossfade_frames);
+    fade_in(&mut b, 1, crossfade_frames);
+    (a, b)
+}
+
+/// `samples` with a ramp from zero to its first frame put before it, if that
+/// frame is loud enough to click.
+pub fn ramp_in(samples: Vec<f32>, channels: u16, frames: usize) -> Vec<f32> {
+    let channels = usize::from(channels.max(1));
+    let Some(first) = samples.get(..channels) else {
+        return samples;
+    };
+    if first.iter().all(|sample| sample.abs() <= CLICK_THRESHOLD) {
+        return samples;
+    }
+    let mut ramped = Vec::with_capacity(frames * channels + samples.len());
+    for frame in 0..frames {
+        let gain = frame as f32 / frames as f32;
+        ramped.extend(first.iter().map(|sample| sample * gain));
+    }
+    ramped.extend(samples);
+    ramped
+}
+
+/// Declicks a message for `declick`: a short fade-in before it if it does not
+/// start at zero, and a fade-out at its end, where silence or the next message
+/// follows.
+pub fn declick(source: Clip) -> Clip {
+    let (channels, rate) = (source.channels(), source.sample_rate());
+    let mut samples = ramp_in(source.collect(), channels, frames_in(DECLICK_FADE_IN, rate));
+    fade_out(&mut samples, channels, frames_in(DECLICK_CROSSFADE, rate));
+    Box::new(SamplesBuffer::new(channels, rate, samples))
+}
+
+/// The frames of the fades between clips at `sample_rate`.
+pub fn declick_frames(sample_rate: u32) -> usize {
+    frames_in(DECLICK_CROSSFADE, sample_rate)
+}
+
+/// The fade-in before a clip that does not start at zero, in frames at
+/// `sample_rate`.
+pub fn ramp_in_frames(sample_rate: u32) -> usize {
+    frames_in(DECLICK_FADE_IN, sample_rate)
+}
+
+fn frames_in(duration: Duration, sample_rate: u32) -> usize {
+    ((sample_rate as f64 * duration.as_secs_f64()).round() as usize).max(1)
+}
+
+/// Scales the first `frames` frames of interleaved `samples` up from zero.
+fn fade_in(samples: &mut [f32], channels: u16, frames: usize) {
+    let channels = usize::from(channels.max(1));
+    let frames = frames.min(samples.len() / channels);
+    for (frame, samples) in samples.chunks_mut(channels).take(frames).enumerate() {
+        let gain = frame as f32 / frames as f32;
+        samples.iter_mut().for_each(|sample| *sample *= gain);
+    }
+}
+
+/// Scales the last `frames` frames of interleaved `samples` down to zero.
+fn fade_out(samples: &mut [f32], channels: u16, frames: usize) {
+    let channels = usize::from(channels.max(1));
+    let frames = frames.min(samples.len() / channels);
+    for (frame, samples) in samples.rchunks_mut(channels).take(frames).enumerate() {
+        let gain = frame as f32 / frames as f32;
+        samples.iter_mut().for_each(|sample| *sample *= gain);
+    }
+}
+
+/// Converts decibels relative to full scale to a linear factor.
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+fn gain(source: Clip, db: f32) -> Clip {
+    if db == 0.0 {
+        source
+    } else {
+        Box::new(source.amplify(db_to_amplitude(db)))
+    }
+}
+
+/// Root mean square level of `samples`.
+pub fn rms(samples: &[f32]) -> f32 {
+    let sum: f32 = samples.iter().map(|sample| sample * sample).sum();
+    (sum / samples.len().max(1) as f32).sqrt()
+}
+
+/// Brings each clip to the average level the last few were played at.
+#[derive(Debug, Default)]
+pub struct Agc {
+    /// RMS level each recent clip was played at, oldest first.
+    history: VecDeque<f32>,
+}
+
+impl Agc {
+    /// The gain for a clip at `rms`: what brings it to the average of the recent
+    /// clips, or to `target` for the first one, but at most `max_gain`. Silent
+    /// clips are left alone and don't count towards the average.
+    pub fn gain(&mut self, rms: f32, target: f32, max_gain: f32) -> f32 {
+        if rms <= f32::EPSILON {
+            return 1.0;
+        }
+        let level = if self.history.is_empty() {
+            target
+        } else {
+            self.history.iter().sum::<f32>() / self.history.len() as f32
+        };
+        let gain = (level / rms).min(max_gain);
+        if self.history.len() == AGC_PHRASES {
+            self.history.pop_front();
+        }
+        self.history.push_back(rms * gain);
+        gain
+    }
+}
+
+/// Keeps peaks at or below `ceiling`. The gain drops at once for a sample that would
+/// exceed it and recovers over [`LIMITER_RELEASE`], so loud passages are turned down
+/// instead of clipped.
+pub struct Limiter<S> {
+    source: S,
+    ceiling: f32,
+    gain: f32,
+    /// How much of the remaining reduction is released per sample.
+    release: f32,
+}
+
+impl<S: Source<Item = f32>> Limiter<S> {
+    pub fn new(source: S, ceiling: f32) -> Self {
+        let rate = source.sample_rate() as f32 * f32::from(source.channels());
+        Self {
+            source,
+            ceiling,
+            gain: 1.0,
+            release: 1.0 - (-1.0 / (LIMITER_RELEASE.as_secs_f32() * rate)).exp(),
+        }
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Limiter<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+        self.gain += (1.0 - self.gain) * self.release;
+        if (sample * self.gain).abs() > self.ceiling {
+            self.gain = self.ceiling / sample.abs();
+        }
+        Some(sample * self.gain)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Limiter<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// A 16-bit PCM WAV file of interleaved `samples`.
+pub fn encode_wav(channels: u16, sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data_len = 2 * samples.len() as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&channels.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(2 * u32::from(channels) * sample_rate).to_le_bytes());
+    wav.extend_from_slice(&(2 * channels).to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// Writes interleaved `samples`, e.g. audio that went through [`apply_effects`], to
+/// `path` as a 16-bit PCM WAV file.
+pub fn write_wav(path: &Path, samples: &[f32], sample_rate: u32, channels: u16) -> io::Result<()> {
+    let spec = hound::WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(hound_error)?;
+    for sample in samples {
+        let sample = (sample.clamp(-1., 1.) * f32::from(i16::MAX)) as i16;
+        writer.write_sample(sample).map_err(hound_error)?;
+    }
+    writer.finalize().map_err(hound_error)
+}
+
+fn hound_error(e: hound::Error) -> io::Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+/// Converts interleaved samples from `from` channels to `to` channels. Downmixing to
+/// mono averages every frame; upmixing from mono copies the sample to each channel.
+pub fn convert_channels(samples: Vec<f32>, from: u16, to: u16) -> Vec<f32> {
+    let (from, to) = (usize::from(from.max(1)), usize::from(to.max(1)));
+    if from == to {
+        return samples;
+    }
+    let mut out = Vec::with_capacity(samples.len() / from * to);
+    for frame in samples.chunks_exact(from) {
+        if to == 1 {
+            out.push(frame.iter().sum::<f32>() / from as f32);
+        } else {
+            // extra channels repeat the last input channel
+            out.extend((0..to).map(|channel| frame[channel.min(from - 1)]));
+        }
+    }
+    out
+}
+
+/// Checks `audio_buffer_size_frames`. Smaller buffers lower latency but make dropouts
+/// more likely when the system is busy.
+pub fn validate_buffer_size(frames: u32) -> Result<(), String> {
+    if frames.is_power_of_two() && (32..=8192).contains(&frames) {
+        Ok(())
+    } else {
+        Err(format!(
+            "audio_buffer_size_frames must be a power of two between 32 and 8192, got {frames}"
+        ))
+    }
+}
+
+/// Opens an output stream on `device`, at `output_sample_rate` and with
+/// `audio_buffer_size_frames` if they are configured.
+pub fn open_output(device: &Device, config: &Configuration) -> Result<Output, String> {
+    let default = device
+        .default_output_config()
+        .map_err(|e| format!("could not query output device: {e}"))?;
+    log::info!(
+        "output device default: {} Hz, {} channels",
+        default.sample_rate().0,
+        default.channels()
+    );
+    let stream_config = match config.output_sample_rate {
+        Some(rate) => with_sample_rate(device, &default, rate)?,
+        None => default,
+    };
+    log::info!("opening output at {} Hz", stream_config.sample_rate().0);
+    match config.audio_buffer_size_frames {
+        Some(frames) => open_fixed(device, &stream_config, frames),
+        None => OutputStream::try_from_device_config(device, stream_config)
+            .map(|(_stream, handle)| Output::Rodio { _stream, handle })
+            .map_err(|e| format!("could not open output device: {e}")),
+    }
+}
+
+/// Finds a supported config at `rate`, preferring the default's channel count and format.
+fn with_sample_rate(
+    device: &Device,
+    default: &SupportedStreamConfig,
+    rate: u32,
+) -> Result<SupportedStreamConfig, String> {
+    let mut ranges: Vec<_> = device
+        .supported_output_configs()
+        .map_err(|e| format!("could not query output device: {e}"))?
+        .filter(|range| (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&rate))
+        .collect();
+    ranges.sort_by_key(|range| {
+        (
+            range.channels() != default.channels(),
+            range.sample_format() != default.sample_format(),
+        )
+    });
+    ranges
+        .into_iter()
+        .next()
+        .map(|range| range.with_sample_rate(SampleRate(rate)))
+        .ok_or_else(|| format!("output device does not support {rate} Hz"))
+}
+
+fn open_fixed(
+    device: &Device,
+    supported: &SupportedStreamConfig,
+    frames: u32,
+) -> Result<Output, String> {
+    if let SupportedBufferSize::Range { min, max } = supported.buffer_size() {
+        if !(*min..=*max).contains(&frames) {
+            log::warn!("output device supports buffers of {min}-{max} frames, asked for {frames}");
+        }
+    }
+    let stream_config = StreamConfig {
+        channels: supported.channels(),
+        sample_rate: supported.sample_rate(),
+        buffer_size: BufferSize::Fixed(frames),
+    };
+    let (controller, mixer) =
+        dynamic_mixer::mixer::<f32>(stream_config.channels, stream_config.sample_rate.0);
+    let (started, first_callback) = mpsc::channel();
+    let stream = match supported.sample_format() {
+        SampleFormat::F32 => build::<f32>(device, &stream_config, mixer, started),
+        SampleFormat::I16 => build::<i16>(device, &stream_config, mixer, started),
+        SampleFormat::U16 => build::<u16>(device, &stream_config, mixer, started),
+        format => return Err(format!("unsupported sample format {format}")),
+    }
+    .map_err(|e| format!("could not open output device: {e}"))?;
+    let play_start = Instant::now();
+    stream
+        .play()
+        .map_err(|e| format!("could not start output stream: {e}"))?;
+    let buffer =
+        Duration::from_secs_f64(f64::from(frames) / f64::from(stream_config.sample_rate.0));
+    match first_callback.recv_timeout(Duration::from_secs(1)) {
+        Ok(()) => log::info!(
+            "audio buffer of {frames} frames holds {:.1} ms; first callback after {:.1} ms",
+            buffer.as_secs_f64() * 1000.,
+            play_start.elapsed().as_secs_f64() * 1000.
+        ),
+        Err(_) => log::warn!("output stream did not request audio within a second"),
+    }
+    Ok(Output::Cpal {
+        _stream: stream,
+        mixer: controller,
+    })
+}
+
+fn build<T>(
+    device: &Device,
+    stream_config: &StreamConfig,
+    mut mixer: DynamicMixer<f32>,
+    started: mpsc::Sender<()>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let mut started = Some(started);
+    device.build_output_stream::<T, _, _>(
+        stream_config,
+        move |data: &mut [T], _| {
+            if let Some(started) = started.take() {
+                _ = started.send(());
+            }
+            for sample in data.iter_mut() {
+                *sample = T::from_sample(mixer.next().unwrap_or(0.));
+            }
+        },
+        |e| log::error!("output stream error: {e}"),
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak(source: impl Source<Item = f32>) -> f32 {
+        source.map(f32::abs).fold(0.0, f32::max)
+    }
+
+    fn square(amplitude: f32) -> SamplesBuffer<f32> {
+        let samples = (0..4800)
+            .map(|i| if i % 48 < 24 { amplitude } else { -amplitude })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, 48_000, samples)
+    }
+
+    /// The largest jump between two neighbouring samples.
+    fn largest_step(samples: &[f32]) -> f32 {
+        samples
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn crossfades_a_step_between_clips() {
+        let (high, low) = (vec![0.9; 400], vec![-0.9; 400]);
+        assert!(largest_step(&[high.clone(), low.clone()].concat()) > 1.7);
+        let (a, b) = declick_boundary(&high, &low, 144);
+        assert_eq!((a.len(), b.len()), (400, 400));
+        assert_eq!((*a.last().unwrap(), b[0]), (0.0, 0.0));
+        let joined = [a.clone(), b.clone()].concat();
+        assert!(
+            largest_step(&joined) <= 0.9 / 144. + 1e-6,
+            "{}",
+            largest_step(&joined)
+        );
+        // away from the boundary the clips are untouched
+        assert_eq!(a[..256], high[..256]);
+        assert_eq!(b[144..], low[144..]);
+        // clips shorter than the fade are faded over their whole length
+        let (a, b) = declick_boundary(&[0.5; 4], &[0.5; 4], 144);
+        assert_eq!(a, [0.375, 0.25, 0.125, 0.0]);
+        assert_eq!(b, [0.0, 0.125, 0.25, 0.375]);
+    }
+
+    #[test]
+    fn ramps_in_only_a_clip_that_starts_loud() {
+        assert_eq!(
+            ramp_in(vec![0.8, 0.8], 1, 4),
+            [0.0, 0.2, 0.4, 0.6, 0.8, 0.8]
+        );
+        assert_eq!(
+            ramp_in(vec![0.4, -0.8, 0.4, -0.8], 2, 2),
+            [0.0, -0.0, 0.2, -0.4, 0.4, -0.8, 0.4, -0.8]
+        );
+        assert_eq!(ramp_in(vec![0.005, 0.9], 1, 4), [0.005, 0.9]);
+        assert!(ramp_in(Vec::new(), 1, 4).is_empty());
+        let declicked: Vec<f32> = declick(Box::new(square(1.0))).collect();
+        assert_eq!(declicked.len(), 4800 + 48);
+        assert_eq!((declicked[0], *declicked.last().unwrap()), (0.0, 0.0));
+        assert!(largest_step(&declicked[..48]) <= 1. / 48. + 1e-6);
+    }
+
+    #[test]
+    fn converts_decibels() {
+        assert_eq!(db_to_amplitude(0.0), 1.0);
+        assert!((db_to_amplitude(-6.0) - 0.501).abs() < 0.001);
+        assert!((db_to_amplitude(20.0) - 10.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn limiter_holds_peaks_at_the_ceiling() {
+        let ceiling = db_to_amplitude(-3.0);
+        assert!(peak(Limiter::new(square(2.0), ceiling)) <= ceiling + f32::EPSILON);
+        // quiet audio passes untouched
+        let quiet: Vec<f32> = Limiter::new(square(0.5), ceiling).collect();
+        assert_eq!(quiet, square(0.5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn applies_both_gains_before_the_limiter() {
+        let mut config = Configuration {
+            volume: 1.0,
+            synthesizer_gain_db: -6.0,
+            output_gain_db: -6.0,
+            gain_headroom_db: 0.0,
+            ..Default::default()
+        };
+        let level = peak(apply_effects(square(1.0), &config));
+        assert!((level - db_to_amplitude(-12.0)).abs() < 0.001, "{level}");
+        config.output_gain_db = 24.0;
+        config.gain_headroom_db = -3.0;
+        let level = peak(apply_effects(square(1.0), &config));
+        assert!(level <= db_to_amplitude(-3.0) + f32::EPSILON, "{level}");
+    }
+
+    #[test]
+    fn writes_and_reads_back_a_sine_wave() {
+        let path = std::env::temp_dir().join(format!("sine-{}.wav", std::process::id()));
+        let sine: Vec<f32> = (0..4800)
+            .map(|i| 0.8 * (std::f32::consts::TAU * 440. * i as f32 / 48_000.).sin())
+            .collect();
+        write_wav(&path, &sine, 48_000, 2).unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().sample_rate, 48_000);
+        let read: Vec<f32> = reader
+            .samples::<i16>()
+            .map(|sample| f32::from(sample.unwrap()) / f32::from(i16::MAX))
+            .collect();
+        assert_eq!(read.len(), sine.len());
+        for (read, written) in read.iter().zip(&sine) {
+            assert!((read - written).abs() < 1e-4, "{read} != {written}");
+        }
+        _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn evens_out_clips_of_different_loudness() {
+        let clip = |amplitude: f32| vec![amplitude, -amplitude, amplitude, -amplitude];
+        let mut agc = Agc::default();
+        let played: Vec<f32> = [0.05, 0.2, 0.4, 0.05, 0.2, 0.4]
+            .into_iter()
+            .map(|amplitude| {
+                let level = rms(&clip(amplitude));
+                level * agc.gain(level, 0.1, 6.0)
+            })
+            .collect();
+        for level in played {
+            assert!((level - 0.1).abs() < 1e-6, "{level}");
+        }
+    }
+
+    #[test]
+    fn caps_the_gain_and_adapts_to_the_recent_clips() {
+        let mut agc = Agc::default();
+        assert_eq!(agc.gain(0.0, 0.1, 6.0), 1.0);
+        // the first clip goes to the target, as far as the cap allows
+        assert_eq!(agc.gain(0.01, 0.1, 6.0), 6.0);
+        // the next is matched to the 0.06 that one was played at
+        assert!((agc.gain(0.12, 0.1, 6.0) - 0.5).abs() < 1e-6);
+        for _ in 0..AGC_PHRASES {
+            agc.gain(0.3, 0.1, 6.0);
+        }
+        // only the last ten count, all played at 0.06
+        assert!((agc.gain(0.03, 0.1, 6.0) - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn converts_between_mono_and_stereo() {
+        assert_eq!(
+            convert_channels(vec![0.1, -0.2], 1, 2),
+            [0.1, 0.1, -0.2, -0.2]
+        );
+        assert_eq!(
+            convert_channels(vec![0.2, 0.4, -1.0, 0.0], 2, 1),
+            [0.3, -0.5]
+        );
+        let samples = vec![0.1, 0.2, 0.3];
+        assert_eq!(convert_channels(samples.clone(), 2, 2), samples);
+        assert_eq!(convert_channels(samples.clone(), 1, 1), samples);
+        // 0 channels is taken as mono rather than dividing by zero
+        assert_eq!(convert_channels(samples.clone(), 0, 1), samples);
+    }
+
+    #[test]
+    fn drops_a_stereo_frame_cut_short() {
+        assert_eq!(convert_channels(vec![0.2, 0.4, 0.6], 2, 1), [0.3]);
+        assert_eq!(convert_channels(vec![0.2, 0.4, 0.6], 2, 3), [0.2, 0.4, 0.4]);
+    }
+}
@@ -0,0 +1,179 @@
+//! `--audition`: a sample phrase in each voice for a language in turn, so that a
+//! `gcloud_voice` can be picked by ear rather than by editing the config after
+//! each try. It runs in the terminal; the clips go through the audio cache, so
+//! auditioning again is quick.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{speech, voices, voices::VoiceInfo, Configuration};
+
+/// Default for `--text` with `--audition`.
+pub const DEFAULT_TEXT: &str = "Hi! This is how I sound. Do you like it?";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Next,
+    Select,
+    Quit,
+}
+
+impl Answer {
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim().to_lowercase().as_str() {
+            "" | "n" => Some(Self::Next),
+            "s" => Some(Self::Select),
+            "q" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Plays `text` in each voice for `language`, the active one if not given, and
+/// prints the config lines for the voice picked.
+pub fn run(config: &Configuration, language: Option<&str>, text: &str) -> Result<(), String> {
+    speech::verify_audio_device(&config.output_device).map_err(|e| e.to_string())?;
+    let language = language.unwrap_or(config.active_voice().0).to_owned();
+    let voices = voices::list(config, Some(&language))?;
+    if voices.is_empty() {
+        return Err(format!("no voices for language {language}"));
+    }
+    let chosen = audition(&voices, io::stdin().lock(), io::stdout(), |voice| {
+        let config = with_voice(config, &language, &voice.name);
+        let wav = speech::synthesize_cached(text, &config).map_err(|e| e.to_string())?;
+        speech::play_to_end(wav, &config).map_err(|e| e.to_string())?;
+        Ok(())
+    })
+    .map_err(|e| e.to_string())?;
+    if let Some(voice) = chosen {
+        println!("\nPut this in config.toml, or in the voice profile in use:\n");
+        print!("{}", toml_lines(&language, &voice.name));
+    }
+    Ok(())
+}
+
+/// `config` speaking in `voice`, whatever voice profile would be active.
+fn with_voice(config: &Configuration, language: &str, voice: &str) -> Configuration {
+    let mut config = config.clone();
+    config.voice_profiles.clear();
+    config.gcloud_language = language.to_owned();
+    config.gcloud_voice = voice.to_owned();
+    config
+}
+
+/// Announces and plays each voice, then asks what next, until one is selected, the
+/// user quits or the voices run out.
+fn audition(
+    voices: &[VoiceInfo],
+    mut input: impl BufRead,
+    mut out: impl Write,
+    mut play: impl FnMut(&VoiceInfo) -> Result<(), String>,
+) -> io::Result<Option<&VoiceInfo>> {
+    for (i, voice) in voices.iter().enumerate() {
+        writeln!(
+            out,
+            "[{}/{}] {} ({})",
+            i + 1,
+            voices.len(),
+            voice.name,
+            voice.gender
+        )?;
+        if let Err(e) = play(voice) {
+            writeln!(out, "  could not play it: {e}")?;
+        }
+        let answer = loop {
+            write!(out, "Enter: next, s: select, q: quit > ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break Answer::Quit;
+            }
+            match Answer::parse(&line) {
+                Some(answer) => break answer,
+                None => writeln!(out, "  `{}` is not one of those", line.trim())?,
+            }
+        };
+        match answer {
+            Answer::Next => {}
+            Answer::Select => return Ok(Some(voice)),
+            Answer::Quit => return Ok(None),
+        }
+    }
+    writeln!(out, "That was the last voice.")?;
+    Ok(None)
+}
+
+fn toml_lines(language: &str, voice: &str) -> String {
+    format!(
+        "gcloud_language = {}\ngcloud_voice = {}\n",
+        toml::Value::from(language),
+        toml::Value::from(voice)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voices(names: &[&str]) -> Vec<VoiceInfo> {
+        names
+            .iter()
+            .map(|name| VoiceInfo {
+                name: (*name).into(),
+                language_codes: vec!["en-US".into()],
+                gender: "female".into(),
+                sample_rate_hz: 24_000,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_each_voice_until_one_is_selected() {
+        let voices = voices(&["en-US-Neural2-A", "en-US-Neural2-C", "en-US-Neural2-E"]);
+        let mut played = Vec::new();
+        let mut out = Vec::new();
+        let chosen = audition(&voices, &b"\nwhat\nS\n"[..], &mut out, |voice| {
+            played.push(voice.name.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            chosen.map(|voice| voice.name.as_str()),
+            Some("en-US-Neural2-C")
+        );
+        assert_eq!(played, ["en-US-Neural2-A", "en-US-Neural2-C"]);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("[1/3] en-US-Neural2-A (female)\n"), "{out}");
+        assert!(out.contains("[2/3] en-US-Neural2-C (female)\n"), "{out}");
+        assert!(out.contains("`what` is not one of those"), "{out}");
+    }
+
+    #[test]
+    fn stops_at_quit_the_end_of_input_or_the_last_voice() {
+        let voices = voices(&["en-US-Standard-A", "en-US-Standard-B"]);
+        let play = |_: &VoiceInfo| Err("no device".to_owned());
+        let mut out = Vec::new();
+        assert_eq!(
+            audition(&voices, &b"q\n"[..], &mut out, play).unwrap(),
+            None
+        );
+        assert!(String::from_utf8_lossy(&out).contains("  could not play it: no device\n"));
+        assert_eq!(
+            audition(&voices, &b"\n"[..], io::sink(), play).unwrap(),
+            None
+        );
+        let mut out = Vec::new();
+        assert_eq!(
+            audition(&voices, &b"\n\n"[..], &mut out, play).unwrap(),
+            None
+        );
+        assert!(String::from_utf8_lossy(&out).ends_with("That was the last voice.\n"));
+    }
+
+    #[test]
+    fn quotes_the_config_lines() {
+        assert_eq!(
+            toml_lines("en-US", "en-US-Neural2-A"),
+            "gcloud_language = \"en-US\"\ngcloud_voice = \"en-US-Neural2-A\"\n"
+        );
+    }
+}
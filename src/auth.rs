@@ -0,0 +1,275 @@
+//! How requests to Google are authenticated: with the API key when one is set,
+//! otherwise with Application Default Credentials found the way Google's own
+//! client libraries find them.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{http_log, Configuration};
+
+const ADC_FILE: &str = "application_default_credentials.json";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const DEFAULT_METADATA_HOST: &str = "metadata.google.internal";
+/// How long the metadata server has to answer, as it is only there on GCP.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(3);
+/// Tokens are refreshed this long before they expire.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Where the credentials for Google come from, in order of precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// `gcloud_token` or `gcloud_token_file`.
+    ApiKey,
+    /// A credentials file, from `GOOGLE_APPLICATION_CREDENTIALS` or the one
+    /// `gcloud auth application-default login` writes.
+    File(PathBuf),
+    /// The metadata server of the GCP machine this runs on.
+    MetadataServer,
+}
+
+impl Credentials {
+    pub fn resolve(config: &Configuration) -> Self {
+        Self::resolve_with(config, |name| env::var_os(name))
+    }
+
+    fn resolve_with(config: &Configuration, var: impl Fn(&str) -> Option<OsString>) -> Self {
+        if !config.gcloud_token.is_empty() {
+            return Self::ApiKey;
+        }
+        if let Some(path) = var("GOOGLE_APPLICATION_CREDENTIALS").filter(|path| !path.is_empty()) {
+            return Self::File(path.into());
+        }
+        let gcloud_dir = match var("CLOUDSDK_CONFIG") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None if cfg!(windows) => var("APPDATA").map(|dir| PathBuf::from(dir).join("gcloud")),
+            None => var("HOME").map(|dir| PathBuf::from(dir).join(".config").join("gcloud")),
+        };
+        match gcloud_dir.map(|dir| dir.join(ADC_FILE)) {
+            Some(path) if path.exists() => Self::File(path),
+            _ => Self::MetadataServer,
+        }
+    }
+
+    /// Where the credentials come from, for the log.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::ApiKey => "the API key".into(),
+            Self::File(path) => format!("application default credentials from {}", path.display()),
+            Self::MetadataServer => {
+                "application default credentials from the metadata server".into()
+            }
+        }
+    }
+}
+
+/// An OAuth access token and the project to bill, if the credentials name one.
+#[derive(Debug, Clone)]
+struct Token {
+    access_token: String,
+    quota_project: Option<String>,
+    refresh_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Adds the credentials to a request to a Google API.
+pub fn authorize(
+    builder: RequestBuilder,
+    config: &Configuration,
+) -> Result<RequestBuilder, String> {
+    let credentials = Credentials::resolve(config);
+    if credentials == Credentials::ApiKey {
+        return Ok(builder.header("X-goog-api-key", &config.gcloud_token));
+    }
+    let token = token(&credentials, config)?;
+    let builder = builder.bearer_auth(&token.access_token);
+    Ok(match &token.quota_project {
+        Some(project) => builder.header("x-goog-user-project", project),
+        None => builder,
+    })
+}
+
+/// Like `authorize`, but with a placeholder for an access token instead of
+/// fetching one, so that `--dry-run` sends nothing.
+pub fn authorize_without_token(builder: RequestBuilder, config: &Configuration) -> RequestBuilder {
+    match Credentials::resolve(config) {
+        Credentials::ApiKey => builder.header("X-goog-api-key", &config.gcloud_token),
+        credentials => {
+            builder.bearer_auth(format!("<access token from {}>", credentials.describe()))
+        }
+    }
+}
+
+/// An access token from `credentials`, fetched again once it is about to expire.
+pub fn access_token(credentials: &Credentials, config: &Configuration) -> Result<String, String> {
+    token(credentials, config).map(|token| token.access_token)
+}
+
+fn token(credentials: &Credentials, config: &Configuration) -> Result<Token, String> {
+    static TOKENS: OnceLock<Mutex<HashMap<String, Token>>> = OnceLock::new();
+    let tokens = TOKENS.get_or_init(Default::default);
+    let key = credentials.describe();
+    if let Some(token) = tokens.lock().unwrap().get(&key) {
+        if token.refresh_at > Instant::now() {
+            return Ok(token.clone());
+        }
+    }
+    let token = match credentials {
+        Credentials::ApiKey => return Err("an API key is not an access token".into()),
+        Credentials::File(path) => from_file(path, config)?,
+        Credentials::MetadataServer => from_metadata_server()?,
+    };
+    log::debug!("got an access token from {key}");
+    tokens.lock().unwrap().insert(key, token.clone());
+    Ok(token)
+}
+
+/// Exchanges the refresh token in an `authorized_user` credentials file.
+fn from_file(path: &Path, config: &Configuration) -> Result<Token, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("could not read credentials {}: {e}", path.display()))?;
+    let file: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("credentials {} are not JSON: {e}", path.display()))?;
+    let field = |name: &str| file[name].as_str().map(str::to_owned);
+    match file["type"].as_str() {
+        Some("authorized_user") => {}
+        Some(kind) => {
+            return Err(format!(
+                "{kind} credentials in {} are not supported; use an API key or `gcloud auth application-default login`",
+                path.display()
+            ))
+        }
+        None => return Err(format!("credentials {} have no type", path.display())),
+    }
+    let form = ["client_id", "client_secret", "refresh_token"].map(|name| {
+        field(name)
+            .map(|value| (name, value))
+            .ok_or_else(|| format!("credentials {} have no {name}", path.display()))
+    });
+    let mut form: Vec<(&str, String)> = form.into_iter().collect::<Result<_, _>>()?;
+    form.push(("grant_type", "refresh_token".into()));
+    let token_uri = field("token_uri").unwrap_or_else(|| DEFAULT_TOKEN_URI.into());
+    let client = http_log::client(config).map_err(|e| e.to_string())?;
+    let response = http_log::send(&client, client.post(&token_uri).form(&form))
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json::<TokenResponse>())
+        .map_err(|e| {
+            format!(
+                "could not get an access token from {token_uri}: {}",
+                e.without_url()
+            )
+        })?;
+    Ok(Token {
+        quota_project: field("quota_project_id"),
+        ..token_from(response)
+    })
+}
+
+/// Asks the metadata server of a GCP machine for a token of its service account.
+/// It is only reachable directly, so no proxy is used.
+fn from_metadata_server() -> Result<Token, String> {
+    let host = env::var("GCE_METADATA_HOST").unwrap_or_else(|_| DEFAULT_METADATA_HOST.into());
+    let url = format!("http://{host}/computeMetadata/v1/instance/service-accounts/default/token");
+    let client = Client::builder()
+        .no_proxy()
+        .timeout(METADATA_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let response = http_log::send(&client, client.get(url).header("Metadata-Flavor", "Google"))
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json::<TokenResponse>())
+        .map_err(|_| {
+            "no credentials for Google: set gcloud_token, set GOOGLE_APPLICATION_CREDENTIALS or run `gcloud auth application-default login`".to_owned()
+        })?;
+    Ok(token_from(response))
+}
+
+fn token_from(response: TokenResponse) -> Token {
+    let lifetime = Duration::from_secs(response.expires_in).saturating_sub(REFRESH_MARGIN);
+    Token {
+        access_token: response.access_token,
+        quota_project: None,
+        refresh_at: Instant::now() + lifetime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.into()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn prefers_the_api_key_then_the_environment_then_gcloud() {
+        let dir = env::temp_dir().join(format!("adc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let adc = dir.join(ADC_FILE);
+        fs::write(&adc, "{}").unwrap();
+        let gcloud = dir.to_str().unwrap();
+        let mut config = Configuration {
+            gcloud_token: "key".into(),
+            ..Default::default()
+        };
+        let all = vars(&[
+            ("GOOGLE_APPLICATION_CREDENTIALS", "/keys/app.json"),
+            ("CLOUDSDK_CONFIG", gcloud),
+        ]);
+        assert_eq!(
+            Credentials::resolve_with(&config, &all),
+            Credentials::ApiKey
+        );
+        config.gcloud_token.clear();
+        assert_eq!(
+            Credentials::resolve_with(&config, &all),
+            Credentials::File("/keys/app.json".into())
+        );
+        let gcloud_only = vars(&[("CLOUDSDK_CONFIG", gcloud)]);
+        assert_eq!(
+            Credentials::resolve_with(&config, gcloud_only),
+            Credentials::File(adc.clone())
+        );
+        fs::remove_file(adc).unwrap();
+        assert_eq!(
+            Credentials::resolve_with(&config, vars(&[("CLOUDSDK_CONFIG", gcloud)])),
+            Credentials::MetadataServer
+        );
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_service_account_keys() {
+        let path = env::temp_dir().join(format!("adc-sa-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"type": "service_account", "private_key": "..."}"#,
+        )
+        .unwrap();
+        let error = from_file(&path, &Configuration::default()).unwrap_err();
+        assert!(
+            error.starts_with("service_account credentials in "),
+            "{error}"
+        );
+        _ = fs::remove_file(path);
+    }
+}
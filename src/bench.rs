@@ -0,0 +1,252 @@
+//! `--bench`: how quickly the configured provider turns a message into audio, over
+//! several runs, to compare voices, providers and regions. The request and decode
+//! times are the ones `/metrics` records, read back after each run.
+
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    cache::AudioBytes, metrics::METRICS, provider::ProviderConfig, proxy, speech,
+    stats::OutputFormat, Configuration,
+};
+
+/// Default for `--text`.
+pub const DEFAULT_TEXT: &str = "The quick brown fox jumps over the lazy dog.";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a phase took over all runs.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Phase {
+    pub name: &'static str,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+}
+
+impl Phase {
+    fn new(name: &'static str, mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        let ms = |duration: Duration| duration.as_secs_f64() * 1e3;
+        Self {
+            name,
+            min_ms: ms(samples[0]),
+            median_ms: ms(percentile(&samples, 50.)),
+            p95_ms: ms(percentile(&samples, 95.)),
+        }
+    }
+}
+
+/// The nearest-rank percentile of `sorted`.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100. * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    pub provider: &'static str,
+    pub language: String,
+    pub voice: String,
+    pub runs: usize,
+    pub characters: usize,
+    /// Size of the synthesized WAV audio.
+    pub audio_bytes: usize,
+    pub phases: Vec<Phase>,
+}
+
+/// Synthesizes and decodes `text` `runs` times, bypassing the audio cache, and
+/// plays the audio to nowhere.
+///
+/// Phases are `dns+connect`, measured by opening a connection of its own to the
+/// endpoint, or the proxy in front of it; `request`, the whole synthesis
+/// request, which connects again; `decode`; and `first_sample`, from starting
+/// the request to having the first sample to play.
+pub fn measure(config: &Configuration, runs: usize, text: &str) -> Result<Report, String> {
+    let (language, voice) = config.active_voice();
+    let target = connect_target(config);
+    let (mut connect, mut request, mut decode, mut first_sample) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut audio_bytes = 0;
+    for run in 1..=runs {
+        if let Some((host, port)) = &target {
+            connect.push(connect_time(host, *port)?);
+        }
+        let start = Instant::now();
+        let wav = speech::synthesize(text, language, voice, config)
+            .map_err(|e| format!("run {run}: {e}"))?;
+        request.push(METRICS.request_duration.last());
+        audio_bytes = wav.len();
+        let mut samples =
+            speech::decode(AudioBytes::Owned(wav)).map_err(|e| format!("run {run}: {e}"))?;
+        decode.push(METRICS.decode_duration.last());
+        samples
+            .next()
+            .ok_or_else(|| format!("run {run}: the audio has no samples"))?;
+        first_sample.push(start.elapsed());
+        // the rest goes where a null sink would put it
+        samples.for_each(drop);
+        log::debug!("benchmark run {run} of {runs} took {:?}", start.elapsed());
+    }
+    let mut phases = Vec::new();
+    if !connect.is_empty() {
+        phases.push(Phase::new("dns+connect", connect));
+    }
+    phases.push(Phase::new("request", request));
+    phases.push(Phase::new("decode", decode));
+    phases.push(Phase::new("first_sample", first_sample));
+    Ok(Report {
+        provider: config.provider.name(),
+        language: language.to_owned(),
+        voice: voice.to_owned(),
+        runs,
+        characters: text.chars().count(),
+        audio_bytes,
+        phases,
+    })
+}
+
+/// The host and port requests to the provider connect to, if it is reached over
+/// the network.
+fn connect_target(config: &Configuration) -> Option<(String, u16)> {
+    match config.provider {
+        ProviderConfig::Google { .. } => {}
+        ProviderConfig::Mock { .. } => return None,
+    }
+    let endpoint = Url::parse(&config.gcloud_endpoint).ok()?;
+    let target = proxy::for_url(config, &endpoint)
+        .and_then(|proxy| Url::parse(&proxy).ok())
+        .unwrap_or(endpoint);
+    Some((
+        target.host_str()?.to_owned(),
+        target.port_or_known_default()?,
+    ))
+}
+
+fn connect_time(host: &str, port: u16) -> Result<Duration, String> {
+    let start = Instant::now();
+    let address = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("could not resolve {host}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("{host} has no address"))?;
+    TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+        .map_err(|e| format!("could not connect to {host}:{port}: {e}"))?;
+    Ok(start.elapsed())
+}
+
+/// Runs and prints the benchmark for `--bench`.
+pub fn run(
+    config: &Configuration,
+    runs: usize,
+    text: &str,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let report = measure(config, runs, text)?;
+    match format {
+        OutputFormat::Text => print!("{}", render_text(&report)),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Csv => print!("{}", render_csv(&report)),
+    }
+    Ok(())
+}
+
+fn render_text(report: &Report) -> String {
+    let voice = match report.voice.as_str() {
+        "" => "default voice",
+        voice => voice,
+    };
+    let mut out = format!(
+        "{} {} ({}), {} runs of {} characters, {} bytes of audio\n",
+        report.provider, voice, report.language, report.runs, report.characters, report.audio_bytes
+    );
+    out += &format!(
+        "{:<14}{:>12}{:>12}{:>12}\n",
+        "PHASE", "MIN", "MEDIAN", "P95"
+    );
+    for phase in &report.phases {
+        out += &format!(
+            "{:<14}{:>12}{:>12}{:>12}\n",
+            phase.name,
+            format!("{:.1} ms", phase.min_ms),
+            format!("{:.1} ms", phase.median_ms),
+            format!("{:.1} ms", phase.p95_ms)
+        );
+    }
+    out
+}
+
+fn render_csv(report: &Report) -> String {
+    let mut out = "phase,min_ms,median_ms,p95_ms\n".to_owned();
+    for phase in &report.phases {
+        out += &format!(
+            "{},{:.3},{:.3},{:.3}\n",
+            phase.name, phase.min_ms, phase.median_ms, phase.p95_ms
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_runs_by_nearest_rank() {
+        let ms = |ms: &[u64]| ms.iter().copied().map(Duration::from_millis).collect();
+        let phase = Phase::new("request", ms(&[40, 10, 30, 20]));
+        assert_eq!(
+            (phase.min_ms, phase.median_ms, phase.p95_ms),
+            (10., 20., 40.)
+        );
+        let phase = Phase::new("request", ms(&(1..=20).rev().collect::<Vec<_>>()));
+        assert_eq!(
+            (phase.min_ms, phase.median_ms, phase.p95_ms),
+            (1., 10., 19.)
+        );
+        let phase = Phase::new("decode", ms(&[7]));
+        assert_eq!((phase.min_ms, phase.median_ms, phase.p95_ms), (7., 7., 7.));
+    }
+
+    #[test]
+    fn lines_up_the_table() {
+        let report = Report {
+            provider: "google",
+            language: "en-US".into(),
+            voice: "en-US-Neural2-A".into(),
+            runs: 3,
+            characters: 44,
+            audio_bytes: 96_044,
+            phases: vec![
+                Phase {
+                    name: "dns+connect",
+                    min_ms: 8.3,
+                    median_ms: 9.,
+                    p95_ms: 12.5,
+                },
+                Phase {
+                    name: "request",
+                    min_ms: 180.,
+                    median_ms: 201.4,
+                    p95_ms: 1250.,
+                },
+            ],
+        };
+        assert_eq!(
+            render_text(&report),
+            "\
+google en-US-Neural2-A (en-US), 3 runs of 44 characters, 96044 bytes of audio
+PHASE                  MIN      MEDIAN         P95
+dns+connect         8.3 ms      9.0 ms     12.5 ms
+request           180.0 ms    201.4 ms   1250.0 ms
+"
+        );
+    }
+}
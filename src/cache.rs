@@ -0,0 +1,605 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use indexmap::IndexMap;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    clock,
+    session::{fmt_duration, fmt_thousands},
+    Configuration,
+};
+
+const CACHE_DIR: &str = "cache";
+const MMAP_DATA: &str = "cache.bin";
+const MMAP_INDEX: &str = "cache_index.json";
+const LRU_INDEX: &str = "lru.json";
+const RESPONSES: &str = "responses.json";
+
+/// Where synthesized audio is kept between launches.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    /// One WAV file per phrase under `cache/`.
+    #[default]
+    File,
+    /// All phrases in one memory-mapped `cache/cache.bin`, located through `cache_index.json`.
+    Mmap,
+}
+
+/// Audio bytes returned by a cache, either owned or borrowed from a memory map.
+pub enum AudioBytes {
+    Owned(Vec<u8>),
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl AsRef<[u8]> for AudioBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Owned(bytes) => bytes,
+            Self::Mapped(map, range) => &map[range.clone()],
+        }
+    }
+}
+
+pub trait AudioCache {
+    fn get(&mut self, key: &str) -> Option<AudioBytes>;
+    fn put(&mut self, key: &str, audio: &[u8]) -> io::Result<()>;
+}
+
+/// Opens the configured cache, or `None` if caching is off.
+pub fn open(config: &Configuration) -> Option<Box<dyn AudioCache>> {
+    if !config.cache_enabled {
+        return None;
+    }
+    let (dir, max_entries) = (PathBuf::from(CACHE_DIR), config.cache_max_entries);
+    let cache: io::Result<Box<dyn AudioCache>> = match config.cache_backend {
+        CacheBackend::File => FileCache::open(dir, max_entries).map(|cache| Box::new(cache) as _),
+        CacheBackend::Mmap => MmapCache::open(dir, max_entries).map(|cache| Box::new(cache) as _),
+    };
+    cache
+        .inspect_err(|e| log::warn!("could not open audio cache: {e}"))
+        .ok()
+}
+
+/// The file holding the clip with `key`, if the cache is on and keeps one file per
+/// clip.
+pub fn clip_file(config: &Configuration, key: &str) -> Option<PathBuf> {
+    let path = clip_path(Path::new(CACHE_DIR), key);
+    (config.cache_enabled && config.cache_backend == CacheBackend::File && path.exists())
+        .then_some(path)
+}
+
+/// Deletes every cached clip along with the indexes.
+pub fn clear() -> io::Result<()> {
+    match fs::remove_dir_all(CACHE_DIR) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Prints hit rate, size and the least and most recently used clips.
+pub fn print_stats() -> io::Result<()> {
+    let lru = Lru::load(Path::new(CACHE_DIR))?;
+    let lookups = lru.hits + lru.misses;
+    match lookups {
+        0 => println!("hit rate:  -"),
+        _ => println!(
+            "hit rate:  {:.1}% ({} hits, {} misses)",
+            lru.hits as f64 * 100. / lookups as f64,
+            fmt_thousands(lru.hits),
+            fmt_thousands(lru.misses)
+        ),
+    }
+    println!("entries:   {}", fmt_thousands(lru.entries.len() as u64));
+    let bytes: u64 = lru.entries.values().map(|entry| entry.bytes).sum();
+    println!("size:      {:.1} MiB", bytes as f64 / (1024. * 1024.));
+    if let Some((key, entry)) = lru.entries.first() {
+        println!("oldest:    {}", describe_entry(key, entry));
+    }
+    if let Some((key, entry)) = lru.entries.last() {
+        println!("newest:    {}", describe_entry(key, entry));
+    }
+    Ok(())
+}
+
+fn describe_entry(key: &str, entry: &CacheEntry) -> String {
+    let age = entry.last_access.elapsed().unwrap_or_default();
+    let secs = SystemTime::UNIX_EPOCH
+        .elapsed()
+        .unwrap_or_default()
+        .saturating_sub(age)
+        .as_secs();
+    let used = clock::local_at(secs);
+    format!(
+        "{} used {} {:02}:{:02} ({} ago, {} plays)",
+        &key[..key.len().min(12)],
+        used.date,
+        used.hour,
+        used.minute,
+        fmt_duration(age),
+        fmt_thousands(entry.access_count)
+    )
+}
+
+/// Identifies a clip by everything that affects the synthesized audio.
+pub fn key(
+    text: &str,
+    provider: &str,
+    language: &str,
+    voice: &str,
+    rate: f64,
+    encoding: &str,
+) -> String {
+    let mut hasher = Sha1::new();
+    for part in [text, language, voice, encoding] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    // clips cached before the rate was configurable keep their keys
+    if rate != 1.0 {
+        hasher.update(rate.to_string().as_bytes());
+        hasher.update([0]);
+    }
+    // and so do clips from Google, which was the only provider
+    if provider != "google" {
+        hasher.update(provider.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// A synthesis response kept for a conditional request, by its ETag.
+#[derive(Serialize, Deserialize)]
+struct StoredResponse {
+    etag: String,
+    /// Base64, as the body is not necessarily text.
+    body: String,
+}
+
+/// The synthesis responses kept in `cache/responses.json` by `save_responses`,
+/// as ETags and bodies by request fingerprint.
+pub fn load_responses() -> io::Result<HashMap<String, (String, Vec<u8>)>> {
+    let stored: HashMap<String, StoredResponse> =
+        match fs::read(PathBuf::from(CACHE_DIR).join(RESPONSES)) {
+            Ok(json) => serde_json::from_slice(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+    Ok(stored
+        .into_iter()
+        .filter_map(|(fingerprint, response)| {
+            let body = BASE64.decode(response.body).ok()?;
+            Some((fingerprint, (response.etag, body)))
+        })
+        .collect())
+}
+
+/// Keeps synthesis responses next to the audio, so that they can be revalidated
+/// after a restart.
+pub fn save_responses(responses: &HashMap<String, (String, Vec<u8>)>) -> io::Result<()> {
+    let stored: HashMap<_, _> = responses
+        .iter()
+        .map(|(fingerprint, (etag, body))| {
+            let response = StoredResponse {
+                etag: etag.clone(),
+                body: BASE64.encode(body),
+            };
+            (fingerprint, response)
+        })
+        .collect();
+    fs::create_dir_all(CACHE_DIR)?;
+    fs::write(
+        PathBuf::from(CACHE_DIR).join(RESPONSES),
+        serde_json::to_vec(&stored)?,
+    )
+}
+
+pub type CacheKey = String;
+
+/// Bookkeeping for one cached clip.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub bytes: u64,
+    pub access_count: u64,
+    pub last_access: SystemTime,
+}
+
+/// Clips in least-recently-used order, oldest first, kept in `cache/lru.json`
+/// along with lifetime hit and miss counts.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Lru {
+    hits: u64,
+    misses: u64,
+    entries: IndexMap<CacheKey, CacheEntry>,
+}
+
+impl Lru {
+    fn load(dir: &Path) -> io::Result<Self> {
+        match fs::read_to_string(dir.join(LRU_INDEX)) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(LRU_INDEX), serde_json::to_vec(self)?)
+    }
+
+    /// Records a hit on `key`, moving it to the back.
+    fn hit(&mut self, key: &str, bytes: u64) {
+        self.hits += 1;
+        let entry = match self.entries.get_index_of(key) {
+            Some(index) => {
+                let last = self.entries.len() - 1;
+                self.entries.move_index(index, last);
+                &mut self.entries[last]
+            }
+            // clips cached before the index existed
+            None => self.entries.entry(key.to_owned()).or_insert(CacheEntry {
+                bytes,
+                access_count: 0,
+                last_access: SystemTime::now(),
+            }),
+        };
+        entry.access_count += 1;
+        entry.last_access = SystemTime::now();
+    }
+
+    fn miss(&mut self) {
+        self.misses += 1;
+    }
+
+    /// Adds `key` at the back and removes entries from the front until at most
+    /// `max_entries` remain, returning the removed keys.
+    fn insert(&mut self, key: &str, bytes: u64, max_entries: usize) -> Vec<CacheKey> {
+        self.entries.shift_remove(key);
+        self.entries.insert(
+            key.to_owned(),
+            CacheEntry {
+                bytes,
+                access_count: 0,
+                last_access: SystemTime::now(),
+            },
+        );
+        let mut evicted = Vec::new();
+        while self.entries.len() > max_entries {
+            let Some((key, entry)) = self.entries.shift_remove_index(0) else {
+                break;
+            };
+            log::debug!(
+                "evicting cached clip {key} ({} plays, last used {:?} ago)",
+                entry.access_count,
+                entry.last_access.elapsed().unwrap_or_default()
+            );
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// The file `FileCache` keeps the clip with `key` in.
+fn clip_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.wav"))
+}
+
+pub struct FileCache {
+    dir: PathBuf,
+    lru: Lru,
+    max_entries: usize,
+}
+
+impl FileCache {
+    fn open(dir: PathBuf, max_entries: usize) -> io::Result<Self> {
+        Ok(Self {
+            lru: Lru::load(&dir)?,
+            dir,
+            max_entries,
+        })
+    }
+}
+
+impl AudioCache for FileCache {
+    fn get(&mut self, key: &str) -> Option<AudioBytes> {
+        let audio = fs::read(clip_path(&self.dir, key)).ok();
+        match &audio {
+            Some(audio) => self.lru.hit(key, audio.len() as u64),
+            None => self.lru.miss(),
+        }
+        if let Err(e) = self.lru.save(&self.dir) {
+            log::warn!("could not save cache index: {e}");
+        }
+        audio.map(AudioBytes::Owned)
+    }
+
+    fn put(&mut self, key: &str, audio: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(clip_path(&self.dir, key), audio)?;
+        for key in self.lru.insert(key, audio.len() as u64, self.max_entries) {
+            if let Err(e) = fs::remove_file(clip_path(&self.dir, &key)) {
+                log::warn!("could not delete evicted clip {key}: {e}");
+            }
+        }
+        self.lru.save(&self.dir)
+    }
+}
+
+/// Appends every clip to one data file as `[u64 offset][u64 len][data]` records.
+/// Evicted clips stay in the file until the next compaction.
+pub struct MmapCache {
+    dir: PathBuf,
+    /// Key to the byte range of the clip's data in the data file.
+    index: HashMap<String, (u64, u64)>,
+    lru: Lru,
+    map: Option<Arc<Mmap>>,
+    max_entries: usize,
+}
+
+impl MmapCache {
+    fn open(dir: PathBuf, max_entries: usize) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let index = match fs::read_to_string(dir.join(MMAP_INDEX)) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let mut cache = Self {
+            index,
+            lru: Lru::load(&dir)?,
+            dir,
+            map: None,
+            max_entries,
+        };
+        let live: u64 = cache.index.values().map(|(_, len)| len + 16).sum();
+        let total = fs::metadata(cache.data_path()).map_or(0, |meta| meta.len());
+        if total > live * 2 {
+            cache.compact_cache()?;
+        }
+        Ok(cache)
+    }
+
+    fn data_path(&self) -> PathBuf {
+        self.dir.join(MMAP_DATA)
+    }
+
+    fn save_index(&self) -> io::Result<()> {
+        fs::write(self.dir.join(MMAP_INDEX), serde_json::to_vec(&self.index)?)
+    }
+
+    fn mapped(&mut self) -> io::Result<Arc<Mmap>> {
+        if let Some(map) = &self.map {
+            return Ok(map.clone());
+        }
+        let file = File::open(self.data_path())?;
+        // SAFETY: the data file is only ever appended to or replaced wholesale by
+        // compact_cache, which drops this mapping first.
+        let map = Arc::new(unsafe { Mmap::map(&file)? });
+        self.map = Some(map.clone());
+        Ok(map)
+    }
+
+    /// Rewrites the data file with only the clips still in the index.
+    pub fn compact_cache(&mut self) -> io::Result<()> {
+        let old = match self.mapped() {
+            Ok(map) => map,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let tmp = self.dir.join(format!("{MMAP_DATA}.tmp"));
+        let mut out = File::create(&tmp)?;
+        let mut index = HashMap::with_capacity(self.index.len());
+        let mut offset = 0;
+        for (key, (start, len)) in &self.index {
+            let Some(data) = old.get(*start as usize..(*start + *len) as usize) else {
+                continue;
+            };
+            write_record(&mut out, offset, data)?;
+            index.insert(key.clone(), (offset + 16, *len));
+            offset += 16 + len;
+        }
+        out.sync_all()?;
+        self.map = None;
+        drop(old);
+        fs::rename(tmp, self.data_path())?;
+        self.index = index;
+        self.save_index()
+    }
+}
+
+impl AudioCache for MmapCache {
+    fn get(&mut self, key: &str) -> Option<AudioBytes> {
+        let audio = self.index.get(key).copied().and_then(|(start, len)| {
+            let map = self.mapped().ok()?;
+            let range = start as usize..(start + len) as usize;
+            (range.end <= map.len()).then_some(AudioBytes::Mapped(map, range))
+        });
+        match &audio {
+            Some(audio) => self.lru.hit(key, audio.as_ref().len() as u64),
+            None => self.lru.miss(),
+        }
+        if let Err(e) = self.lru.save(&self.dir) {
+            log::warn!("could not save cache index: {e}");
+        }
+        audio
+    }
+
+    fn put(&mut self, key: &str, audio: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.data_path())?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        write_record(&mut file, offset, audio)?;
+        self.map = None;
+        self.index
+            .insert(key.to_owned(), (offset + 16, audio.len() as u64));
+        for key in self.lru.insert(key, audio.len() as u64, self.max_entries) {
+            self.index.remove(&key);
+        }
+        self.lru.save(&self.dir)?;
+        self.save_index()
+    }
+}
+
+fn write_record(out: &mut impl Write, offset: u64, data: &[u8]) -> io::Result<()> {
+    out.write_all(&offset.to_le_bytes())?;
+    out.write_all(&(data.len() as u64).to_le_bytes())?;
+    out.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cache-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn bytes(audio: Option<AudioBytes>) -> Vec<u8> {
+        audio.expect("cached").as_ref().to_vec()
+    }
+
+    /// The `[offset][len]` header in front of the clip starting at `start`.
+    fn header(data: &[u8], start: u64) -> (u64, u64) {
+        let start = start as usize;
+        let word = |at: usize| u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+        (word(start - 16), word(start - 8))
+    }
+
+    #[test]
+    fn mmap_cache_round_trips_through_the_mapped_range() {
+        let dir = scratch("mmap-round-trip");
+        let mut cache = MmapCache::open(dir.clone(), 10).unwrap();
+        cache.put("a", b"first").unwrap();
+        cache.put("b", b"second clip").unwrap();
+        assert_eq!(cache.index["a"], (16, 5));
+        assert_eq!(cache.index["b"], (16 + 5 + 16, 11));
+        match cache.get("b") {
+            Some(AudioBytes::Mapped(map, range)) => {
+                assert_eq!(range, 37..48);
+                assert_eq!(&map[range], b"second clip");
+            }
+            _ => panic!("not mapped"),
+        }
+        assert_eq!(bytes(cache.get("a")), b"first");
+        assert!(cache.get("c").is_none());
+        let data = fs::read(dir.join(MMAP_DATA)).unwrap();
+        assert_eq!(header(&data, 16), (0, 5));
+        assert_eq!(header(&data, 37), (21, 11));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn compaction_keeps_only_indexed_records() {
+        let dir = scratch("mmap-compaction");
+        let mut cache = MmapCache::open(dir.clone(), 2).unwrap();
+        cache.put("a", b"evicted").unwrap();
+        cache.put("b", b"kept").unwrap();
+        cache.put("c", b"also kept").unwrap();
+        assert!(!cache.index.contains_key("a"));
+        // evicted clips stay in the file until then
+        assert_eq!(
+            fs::metadata(dir.join(MMAP_DATA)).unwrap().len(),
+            3 * 16 + 7 + 4 + 9
+        );
+        cache.compact_cache().unwrap();
+        let data = fs::read(dir.join(MMAP_DATA)).unwrap();
+        assert_eq!(data.len(), 2 * 16 + 4 + 9);
+        let mut records: Vec<_> = cache.index.values().copied().collect();
+        records.sort();
+        assert_eq!(records[0].0, 16);
+        assert_eq!(records[1].0, 16 + records[0].1 + 16);
+        for (start, len) in records {
+            assert_eq!(header(&data, start), (start - 16, len));
+        }
+        assert_eq!(bytes(cache.get("b")), b"kept");
+        assert_eq!(bytes(cache.get("c")), b"also kept");
+        assert!(cache.get("a").is_none());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reopens_from_the_index() {
+        let dir = scratch("mmap-reopen");
+        let mut cache = MmapCache::open(dir.clone(), 1).unwrap();
+        cache.put("a", b"evicted").unwrap();
+        cache.put("b", b"x").unwrap();
+        cache.put("c", b"kept").unwrap();
+        drop(cache);
+        let mut cache = MmapCache::open(dir.clone(), 1).unwrap();
+        assert_eq!(cache.index.len(), 1);
+        assert_eq!(bytes(cache.get("c")), b"kept");
+        assert!(cache.get("a").is_none());
+        // with more than half the file evicted, opening compacted it
+        assert_eq!(fs::metadata(dir.join(MMAP_DATA)).unwrap().len(), 16 + 4);
+        _ = fs::remove_dir_all(dir);
+    }
+
+    fn keys(lru: &Lru) -> Vec<&str> {
+        lru.entries.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn a_hit_moves_the_clip_to_the_back() {
+        let mut lru = Lru::default();
+        for key in ["a", "b", "c"] {
+            assert!(lru.insert(key, 1, 10).is_empty());
+        }
+        lru.hit("a", 1);
+        assert_eq!(keys(&lru), ["b", "c", "a"]);
+        assert_eq!(lru.entries["a"].access_count, 1);
+        // clips cached before the index existed are added at the back
+        lru.hit("old", 7);
+        assert_eq!(keys(&lru), ["b", "c", "a", "old"]);
+        assert_eq!(lru.entries["old"].bytes, 7);
+        // and putting a clip again moves it too
+        assert!(lru.insert("b", 2, 10).is_empty());
+        assert_eq!(keys(&lru), ["c", "a", "old", "b"]);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_clip_and_deletes_its_file() {
+        let dir = scratch("file-eviction");
+        let mut cache = FileCache::open(dir.clone(), 2).unwrap();
+        cache.put("a", b"a").unwrap();
+        cache.put("b", b"b").unwrap();
+        assert_eq!(bytes(cache.get("a")), b"a");
+        cache.put("c", b"c").unwrap();
+        assert_eq!(keys(&cache.lru), ["a", "c"]);
+        assert!(!clip_path(&dir, "b").exists());
+        assert!(clip_path(&dir, "a").exists());
+        assert!(cache.get("b").is_none());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn counts_hits_and_misses_for_the_stats() {
+        let dir = scratch("file-stats");
+        let mut cache = FileCache::open(dir.clone(), 10).unwrap();
+        assert!(cache.get("a").is_none());
+        cache.put("a", b"audio").unwrap();
+        assert_eq!(bytes(cache.get("a")), b"audio");
+        assert_eq!(bytes(cache.get("a")), b"audio");
+        drop(cache);
+        // as --cache-stats reads them
+        let lru = Lru::load(&dir).unwrap();
+        assert_eq!((lru.hits, lru.misses), (2, 1));
+        assert_eq!(lru.entries["a"].access_count, 2);
+        assert_eq!(lru.entries["a"].bytes, 5);
+        _ = fs::remove_dir_all(dir);
+    }
+}
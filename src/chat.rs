@@ -0,0 +1,242 @@
+use eframe::epaint::ahash::HashSet;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// How `@mentions` in chat messages are spoken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionMode {
+    /// Mentions are read as written.
+    Keep,
+    /// Mentions are removed.
+    Strip,
+    /// Each mentioned name is spoken once, without the `@`.
+    #[default]
+    Name,
+}
+
+/// The `[chat]` table: rules for chat messages read from `--watch-file`. Typed
+/// messages are not affected.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ChatConfig {
+    pub mentions: MentionMode,
+    /// A word repeated back to back more often than this, like emote spam, is
+    /// only spoken this many times; 0 for no limit.
+    pub max_repeats: usize,
+    /// Messages matching any of these patterns are skipped, e.g. bot commands.
+    pub ignore: Vec<String>,
+    /// Text matching these patterns is removed, e.g. channel point redemption prefixes.
+    pub strip: Vec<String>,
+    /// If not empty, only these users are read.
+    pub allow_users: Vec<String>,
+    /// These users are never read.
+    pub deny_users: Vec<String>,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            mentions: MentionMode::default(),
+            max_repeats: 3,
+            ignore: vec!["^!".into()],
+            strip: Vec::new(),
+            allow_users: Vec::new(),
+            deny_users: Vec::new(),
+        }
+    }
+}
+
+pub struct ChatFilter {
+    mentions: MentionMode,
+    max_repeats: usize,
+    ignore: Vec<Regex>,
+    strip: Vec<Regex>,
+    allow_users: HashSet<String>,
+    deny_users: HashSet<String>,
+}
+
+impl ChatFilter {
+    /// Fails if one of the `ignore` or `strip` patterns is invalid.
+    pub fn new(config: &ChatConfig) -> Result<Self, String> {
+        let compile = |field: &str, patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Regex::new(pattern)
+                        .map_err(|e| format!("invalid chat.{field} pattern {pattern:?}: {e}"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let users = |users: &[String]| users.iter().map(|user| user.to_lowercase()).collect();
+        Ok(Self {
+            mentions: config.mentions,
+            max_repeats: config.max_repeats,
+            ignore: compile("ignore", &config.ignore)?,
+            strip: compile("strip", &config.strip)?,
+            allow_users: users(&config.allow_users),
+            deny_users: users(&config.deny_users),
+        })
+    }
+
+    /// The part of a message from `user` worth reading out, or `None` to skip it.
+    pub fn apply(&self, user: Option<&str>, text: &str) -> Option<String> {
+        let user = user.map(|user| user.trim_start_matches('@').to_lowercase());
+        if user
+            .as_ref()
+            .is_some_and(|user| self.deny_users.contains(user))
+        {
+            return None;
+        }
+        if !self.allow_users.is_empty()
+            && !user
+                .as_ref()
+                .is_some_and(|user| self.allow_users.contains(user))
+        {
+            return None;
+        }
+        if self.ignore.iter().any(|pattern| pattern.is_match(text)) {
+            return None;
+        }
+        let mut text = text.to_owned();
+        for pattern in &self.strip {
+            text = pattern.replace_all(&text, "").into_owned();
+        }
+        let mut words = Vec::new();
+        let mut mentioned = HashSet::default();
+        let mut run = 0;
+        for word in text.split_whitespace() {
+            let word = match word.strip_prefix('@') {
+                Some(name) if !name.is_empty() => match self.mentions {
+                    MentionMode::Keep => word,
+                    MentionMode::Strip => continue,
+                    MentionMode::Name => {
+                        let name = name.trim_end_matches([',', ':', '.', '!', '?']);
+                        if !mentioned.insert(name.to_lowercase()) {
+                            continue;
+                        }
+                        name
+                    }
+                },
+                _ => word,
+            };
+            run = match words.last() {
+                Some(&last) if last == word => run + 1,
+                _ => 1,
+            };
+            if self.max_repeats == 0 || run <= self.max_repeats {
+                words.push(word);
+            }
+        }
+        let text = words.join(" ");
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(config: ChatConfig) -> ChatFilter {
+        ChatFilter::new(&config).unwrap()
+    }
+
+    #[test]
+    fn reads_a_realistic_chat() {
+        let chat = filter(ChatConfig {
+            strip: vec![r"^\[Redeemed [^\]]*\]\s*".into()],
+            deny_users: vec!["Nightbot".into(), "StreamElements".into()],
+            ..Default::default()
+        });
+        for (user, line, spoken) in [
+            ("viewer1", "hi chat", Some("hi chat")),
+            (
+                "viewer2",
+                "@streamer how are you?",
+                Some("streamer how are you?"),
+            ),
+            (
+                "viewer3",
+                "@streamer @streamer @mod_bob look at this",
+                Some("streamer mod_bob look at this"),
+            ),
+            (
+                "viewer4",
+                "KEKW KEKW KEKW KEKW KEKW KEKW",
+                Some("KEKW KEKW KEKW"),
+            ),
+            (
+                "viewer5",
+                "PogChamp PogChamp nice PogChamp",
+                Some("PogChamp PogChamp nice PogChamp"),
+            ),
+            ("viewer6", "!uptime", None),
+            ("viewer7", "!so @friend", None),
+            ("Nightbot", "Follow the stream on twitter!", None),
+            ("streamelements", "viewer8 just subscribed!", None),
+            (
+                "viewer9",
+                "[Redeemed Hydrate] drink some water",
+                Some("drink some water"),
+            ),
+            ("viewer10", "[Redeemed Hydrate]", None),
+            ("viewer11", "   ", None),
+            (
+                "viewer12",
+                "is it 2 @ 3pm or 4?",
+                Some("is it 2 @ 3pm or 4?"),
+            ),
+        ] {
+            assert_eq!(chat.apply(Some(user), line).as_deref(), spoken, "{line}");
+        }
+    }
+
+    #[test]
+    fn strips_or_keeps_mentions() {
+        let strip = filter(ChatConfig {
+            mentions: MentionMode::Strip,
+            ..Default::default()
+        });
+        assert_eq!(strip.apply(None, "@a @b hello").as_deref(), Some("hello"));
+        assert_eq!(strip.apply(None, "@a"), None);
+        let keep = filter(ChatConfig {
+            mentions: MentionMode::Keep,
+            ..Default::default()
+        });
+        assert_eq!(keep.apply(None, "@a @a hi").as_deref(), Some("@a @a hi"));
+    }
+
+    #[test]
+    fn allow_list_skips_everyone_else() {
+        let chat = filter(ChatConfig {
+            allow_users: vec!["Mod".into()],
+            ..Default::default()
+        });
+        assert_eq!(chat.apply(Some("mod"), "hello").as_deref(), Some("hello"));
+        assert_eq!(chat.apply(Some("@MOD"), "hello").as_deref(), Some("hello"));
+        assert_eq!(chat.apply(Some("viewer"), "hello"), None);
+        assert_eq!(chat.apply(None, "hello"), None);
+    }
+
+    #[test]
+    fn repeats_are_unlimited_at_zero() {
+        let chat = filter(ChatConfig {
+            max_repeats: 0,
+            ..Default::default()
+        });
+        assert_eq!(chat.apply(None, "a a a a a").as_deref(), Some("a a a a a"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let config = ChatConfig {
+            ignore: vec!["(".into()],
+            ..Default::default()
+        };
+        let error = ChatFilter::new(&config).err().unwrap();
+        assert!(
+            error.starts_with(r#"invalid chat.ignore pattern "(": "#),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Configuration;
+
+/// Appended to truncated messages when `announce_truncation` is set.
+const TRUNCATION_NOTICE: &str = " Message truncated.";
+
+/// What happens to a message longer than `max_chars`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LengthPolicy {
+    /// The message is not sent.
+    #[default]
+    Refuse,
+    /// The message is cut after the last sentence that fits.
+    Truncate,
+    /// The message is sent in several pieces.
+    Chunk,
+}
+
+/// Rejects limits no message could be kept under: 0, or for `truncate` with
+/// `announce_truncation`, one with no room left beside the notice.
+pub fn validate(config: &Configuration) -> Result<(), String> {
+    if let Some(max) = config.max_chars {
+        let notice = TRUNCATION_NOTICE.chars().count();
+        if max == 0 {
+            return Err("max_chars must be at least 1".into());
+        }
+        if config.max_chars_policy == LengthPolicy::Truncate
+            && config.announce_truncation
+            && max <= notice
+        {
+            return Err(format!(
+                "max_chars must be over {notice} to fit{TRUNCATION_NOTICE:?} after truncated text, or announce_truncation off, got {max}"
+            ));
+        }
+    }
+    if config.clipboard_max_chars == 0 {
+        return Err("clipboard_max_chars must be at least 1".into());
+    }
+    // longer clipboard text is never spoken, so there would be nothing to split
+    if config.clipboard_chunking && config.max_clipboard_length <= config.clipboard_max_chars {
+        return Err(format!(
+            "max_clipboard_length must be over clipboard_max_chars with clipboard_chunking on, got {} and {}",
+            config.max_clipboard_length, config.clipboard_max_chars
+        ));
+    }
+    Ok(())
+}
+
+/// Applies `max_chars` to `text`, returning the pieces to speak in order, or why
+/// the message was refused.
+pub fn enforce_max_chars(text: &str, config: &Configuration) -> Result<Vec<String>, String> {
+    let count = text.chars().count();
+    let max = match config.max_chars {
+        Some(max) if count > max => max,
+        _ => return Ok(vec![text.to_owned()]),
+    };
+    match config.max_chars_policy {
+        LengthPolicy::Refuse => Err(format!("Too long: {count} characters, the limit is {max}")),
+        LengthPolicy::Truncate => {
+            let notice = if config.announce_truncation {
+                TRUNCATION_NOTICE
+            } else {
+                ""
+            };
+            let room = max.saturating_sub(notice.chars().count());
+            let kept = chunk_text_by_sentences(text, room)
+                .into_iter()
+                .next()
+                .unwrap_or_default();
+            log::info!(
+                "truncated a {count}-character message to {} characters",
+                kept.trim_end().chars().count()
+            );
+            Ok(vec![format!("{}{notice}", kept.trim_end())])
+        }
+        LengthPolicy::Chunk => {
+            let chunks = chunk_text_by_sentences(text, max);
+            log::info!(
+                "split a {count}-character message into {} pieces",
+                chunks.len()
+            );
+            Ok(chunks)
+        }
+    }
+}
+
+/// Splits `text` into pieces of at most `max_chars` characters, cutting after sentence
+/// ends where possible and at whitespace otherwise. Concatenating the pieces gives back
+/// the original text.
+pub fn chunk_text_by_sentences(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while let Some((limit, _)) = rest.char_indices().nth(max_chars) {
+        let window = &rest[..limit];
+        let cut = last_sentence_end(window)
+            .or_else(|| last_whitespace_end(window))
+            .unwrap_or(limit);
+        chunks.push(rest[..cut].to_owned());
+        rest = &rest[cut..];
+    }
+    if !rest.is_empty() {
+        chunks.push(rest.to_owned());
+    }
+    chunks
+}
+
+/// Returns at most the first `max_chars` characters of `text`.
+pub fn truncate_chars(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((i, _)) => &text[..i],
+        None => text,
+    }
+}
+
+/// Byte offset just past the last `.`, `!` or `?` that is followed by whitespace.
+fn last_sentence_end(window: &str) -> Option<usize> {
+    let mut chars = window.char_indices().peekable();
+    let mut end = None;
+    while let Some((_, c)) = chars.next() {
+        if let (true, Some(&(i, next))) = (matches!(c, '.' | '!' | '?'), chars.peek()) {
+            if next.is_whitespace() {
+                end = Some(i + next.len_utf8());
+            }
+        }
+    }
+    end
+}
+
+fn last_whitespace_end(window: &str) -> Option<usize> {
+    window
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn chunks_join_back_into_the_text() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = testing::text(&mut rng, 200);
+            let max_chars = rng.gen_range(1..40);
+            let chunks = chunk_text_by_sentences(&text, max_chars);
+            assert_eq!(chunks.concat(), text);
+            for chunk in &chunks {
+                assert!(!chunk.is_empty(), "empty chunk of {text:?}");
+                assert!(chunk.chars().count() <= max_chars, "{chunk:?} of {text:?}");
+            }
+        }
+    }
+
+    fn limited(max_chars: usize, policy: LengthPolicy, announce: bool) -> Configuration {
+        Configuration {
+            max_chars: Some(max_chars),
+            max_chars_policy: policy,
+            announce_truncation: announce,
+            clipboard_max_chars: 200,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_every_policy_within_max_chars() {
+        let text = "One sentence. Another one here. And a third.";
+        let length = text.chars().count();
+        for policy in [
+            LengthPolicy::Refuse,
+            LengthPolicy::Truncate,
+            LengthPolicy::Chunk,
+        ] {
+            // at the limit, the text is sent as is
+            let config = limited(length, policy, true);
+            assert_eq!(enforce_max_chars(text, &config), Ok(vec![text.into()]));
+            for max in 1..length {
+                for announce in [false, true] {
+                    let config = limited(max, policy, announce);
+                    if validate(&config).is_err() {
+                        continue;
+                    }
+                    let Ok(pieces) = enforce_max_chars(text, &config) else {
+                        assert_eq!(policy, LengthPolicy::Refuse);
+                        continue;
+                    };
+                    for piece in pieces {
+                        assert!(piece.chars().count() <= max, "{piece:?} for {max}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reports_refusals_and_truncations() {
+        let text = "One sentence. Another one here.";
+        assert_eq!(
+            enforce_max_chars(text, &limited(30, LengthPolicy::Refuse, true)),
+            Err("Too long: 31 characters, the limit is 30".into())
+        );
+        assert_eq!(
+            enforce_max_chars(text, &limited(30, LengthPolicy::Truncate, false)),
+            Ok(vec!["One sentence.".into()])
+        );
+        assert_eq!(
+            enforce_max_chars(
+                "One sentence. Another one here. And a third.",
+                &limited(40, LengthPolicy::Truncate, true)
+            ),
+            Ok(vec!["One sentence. Message truncated.".into()])
+        );
+        assert_eq!(
+            enforce_max_chars(text, &limited(30, LengthPolicy::Chunk, true)),
+            Ok(vec!["One sentence. ".into(), "Another one here.".into()])
+        );
+    }
+
+    #[test]
+    fn rejects_limits_nothing_fits_in() {
+        assert_eq!(validate(&limited(20, LengthPolicy::Truncate, true)), Ok(()));
+        assert!(validate(&limited(19, LengthPolicy::Truncate, true))
+            .unwrap_err()
+            .starts_with("max_chars must be over 19"));
+        assert_eq!(
+            validate(&limited(19, LengthPolicy::Truncate, false)),
+            Ok(())
+        );
+        assert_eq!(validate(&limited(19, LengthPolicy::Chunk, true)), Ok(()));
+        assert_eq!(
+            validate(&limited(0, LengthPolicy::Chunk, false)),
+            Err("max_chars must be at least 1".into())
+        );
+        assert_eq!(
+            validate(&Configuration {
+                clipboard_max_chars: 0,
+                ..limited(10, LengthPolicy::Chunk, false)
+            }),
+            Err("clipboard_max_chars must be at least 1".into())
+        );
+        let clipboard = |max_clipboard_length| Configuration {
+            clipboard_chunking: true,
+            max_clipboard_length,
+            ..limited(10, LengthPolicy::Chunk, false)
+        };
+        assert_eq!(validate(&clipboard(201)), Ok(()));
+        assert_eq!(
+            validate(&clipboard(200)),
+            Err("max_clipboard_length must be over clipboard_max_chars with clipboard_chunking on, got 200 and 200".into())
+        );
+        assert_eq!(
+            validate(&Configuration {
+                clipboard_chunking: false,
+                ..clipboard(200)
+            }),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn truncation_keeps_a_prefix() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = testing::text(&mut rng, 100);
+            let max_chars = rng.gen_range(0..120);
+            let truncated = truncate_chars(&text, max_chars);
+            assert!(text.starts_with(truncated));
+            assert_eq!(
+                truncated.chars().count(),
+                text.chars().count().min(max_chars)
+            );
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::PathBuf;
+
+use crate::{
+    clock::{Date, TimeBound},
+    convert::AudioFormat,
+    history::Source,
+    stats::OutputFormat,
+    transcript,
+};
+
+/// Default for `--silence-ms`.
+const DEFAULT_SILENCE_MS: u64 = 500;
+/// Default for `--runs`.
+const DEFAULT_RUNS: usize = 10;
+
+/// Command-line options.
+#[derive(Debug, Clone)]
+pub struct Args {
+    /// Run without the overlay window, speaking messages from the enabled sources.
+    pub daemon: bool,
+    /// Speak new clipboard text as it appears.
+    pub watch_clipboard: bool,
+    /// Speak lines appended to this file.
+    pub watch_file: Option<PathBuf>,
+    /// Print a usage summary and exit.
+    pub stats: bool,
+    /// Print the output devices and exit.
+    pub devices: bool,
+    pub format: OutputFormat,
+    /// `--format` for `--convert`.
+    pub audio_format: Option<AudioFormat>,
+    /// Only consider history from this date on.
+    pub since: Option<Date>,
+    /// Delete the audio cache and exit.
+    pub clear_cache: bool,
+    /// Print audio cache statistics and exit.
+    pub cache_stats: bool,
+    /// Print the overlay's keyboard shortcuts and exit.
+    pub list_hotkeys: bool,
+    /// Check the output device, API connectivity and synthesis, then exit.
+    pub verify_config: bool,
+    /// Synthesize with the mock provider, whatever `provider` says.
+    pub use_mock_provider: bool,
+    /// Re-encode the audio files in the first folder into the second, then exit.
+    pub convert: Option<(PathBuf, PathBuf)>,
+    /// Sample rate to convert to; the file's own rate when unset.
+    pub resample: Option<u32>,
+    /// Render the lines of the first file to the second as one WAV file, then exit.
+    pub export_queue: Option<(PathBuf, PathBuf)>,
+    /// Silence between the lines of `--export-queue`.
+    pub silence_ms: u64,
+    /// Print the message log as CSV or JSON and exit.
+    pub export: bool,
+    /// `--from`, `--to` and `--source` for `--export`.
+    pub export_filter: transcript::Filter,
+    /// Print the version and where errors are logged, then exit.
+    pub version: bool,
+    /// Apply the archive's retention limits and exit.
+    pub archive_prune: bool,
+    /// Print the metrics in the Prometheus text format and exit.
+    pub emit_metrics_once: bool,
+    /// Print the voices of the configured provider and exit.
+    pub list_voices: bool,
+    /// Only list or audition the voices for this language code, e.g. `en` or `en-GB`.
+    pub language: Option<String>,
+    /// Time synthesis and decoding with the configured provider, then exit.
+    pub bench: bool,
+    /// Synthesis runs for `--bench`.
+    pub runs: usize,
+    /// Text `--bench` and `--audition` synthesize instead of their default sentence.
+    pub text: Option<String>,
+    /// Play a sample in each voice for a language in turn to pick one, then exit.
+    pub audition: bool,
+    /// Print the requests `--say` turns into instead of sending them, then exit.
+    pub dry_run: bool,
+    /// The message for `--dry-run`.
+    pub say: Option<String>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            daemon: false,
+            watch_clipboard: false,
+            watch_file: None,
+            stats: false,
+            devices: false,
+            format: OutputFormat::default(),
+            audio_format: None,
+            since: None,
+            clear_cache: false,
+            cache_stats: false,
+            list_hotkeys: false,
+            verify_config: false,
+            use_mock_provider: false,
+            convert: None,
+            resample: None,
+            export_queue: None,
+            silence_ms: DEFAULT_SILENCE_MS,
+            export: false,
+            export_filter: transcript::Filter::default(),
+            version: false,
+            archive_prune: false,
+            emit_metrics_once: false,
+            list_voices: false,
+            language: None,
+            bench: false,
+            runs: DEFAULT_RUNS,
+            text: None,
+            audition: false,
+            dry_run: false,
+            say: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--daemon" => parsed.daemon = true,
+                "--watch-clipboard" => parsed.watch_clipboard = true,
+                "--watch-file" => parsed.watch_file = Some(value(&mut args, &arg)?.into()),
+                "--stats" => parsed.stats = true,
+                "--json" => parsed.format = OutputFormat::Json,
+                "--devices" => parsed.devices = true,
+                "--clear-cache" => parsed.clear_cache = true,
+                "--cache-stats" => parsed.cache_stats = true,
+                "--list-hotkeys" => parsed.list_hotkeys = true,
+                "--use-mock-provider" => parsed.use_mock_provider = true,
+                "--verify-config" => parsed.verify_config = true,
+                "--format" => match value(&mut args, &arg)?.as_str() {
+                    "text" => parsed.format = OutputFormat::Text,
+                    "json" => parsed.format = OutputFormat::Json,
+                    "csv" => parsed.format = OutputFormat::Csv,
+                    "wav" => parsed.audio_format = Some(AudioFormat::Wav),
+                    "mp3" => parsed.audio_format = Some(AudioFormat::Mp3),
+                    "ogg" => parsed.audio_format = Some(AudioFormat::Ogg),
+                    other => return Err(format!("unknown format `{other}`")),
+                },
+                "--convert" => {
+                    let input = value(&mut args, &arg)?;
+                    let output = value(&mut args, &arg)?;
+                    parsed.convert = Some((input.into(), output.into()));
+                }
+                "--resample" => {
+                    let rate = value(&mut args, &arg)?;
+                    parsed.resample = Some(
+                        rate.parse()
+                            .map_err(|_| format!("`{rate}` is not a sample rate"))?,
+                    );
+                }
+                "--export-queue" => {
+                    let input = value(&mut args, &arg)?;
+                    let output = value(&mut args, &arg)?;
+                    parsed.export_queue = Some((input.into(), output.into()));
+                }
+                "--silence-ms" => {
+                    let ms = value(&mut args, &arg)?;
+                    parsed.silence_ms = ms
+                        .parse()
+                        .map_err(|_| format!("`{ms}` is not a number of milliseconds"))?;
+                }
+                "--export" => parsed.export = true,
+                "--version" => parsed.version = true,
+                "--archive-prune" => parsed.archive_prune = true,
+                "--emit-metrics-once" => parsed.emit_metrics_once = true,
+                "--list-voices" => parsed.list_voices = true,
+                "--language" => parsed.language = Some(value(&mut args, &arg)?),
+                "--bench" => parsed.bench = true,
+                "--runs" => {
+                    let runs = value(&mut args, &arg)?;
+                    parsed.runs = runs
+                        .parse()
+                        .ok()
+                        .filter(|&runs| runs > 0)
+                        .ok_or_else(|| format!("`{runs}` is not a positive number of runs"))?;
+                }
+                "--text" => parsed.text = Some(value(&mut args, &arg)?),
+                "--audition" => parsed.audition = true,
+                "--dry-run" => parsed.dry_run = true,
+                "--say" => parsed.say = Some(value(&mut args, &arg)?),
+                "--from" => parsed.export_filter.from = Some(time(&mut args, &arg)?),
+                "--to" => parsed.export_filter.to = Some(time(&mut args, &arg)?),
+                "--source" => {
+                    parsed.export_filter.source = Some(match value(&mut args, &arg)?.as_str() {
+                        "overlay" => Source::Overlay,
+                        "http" => Source::Http,
+                        "clipboard" => Source::Clipboard,
+                        "file" => Source::File,
+                        other => return Err(format!("unknown source `{other}`")),
+                    });
+                }
+                "--since" => {
+                    let date = value(&mut args, &arg)?;
+                    parsed.since = Some(
+                        Date::parse(&date)
+                            .ok_or_else(|| format!("`{date}` is not a YYYY-MM-DD date"))?,
+                    );
+                }
+                _ => return Err(format!("unknown argument `{arg}`")),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Whether the process should run headless instead of showing the overlay.
+    pub fn headless(&self) -> bool {
+        self.daemon || self.watch_clipboard || self.watch_file.is_some()
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("`{flag}` needs a value"))
+}
+
+fn time(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<TimeBound, String> {
+    let time = value(args, flag)?;
+    TimeBound::parse(&time).ok_or_else(|| {
+        format!("`{time}` is not a YYYY-MM-DD date or an RFC 3339 time like 2024-06-01T18:30:00Z")
+    })
+}
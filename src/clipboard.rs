@@ -0,0 +1,231 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::Duration,
+};
+
+use regex::Regex;
+
+use crate::{chunking, history::Source, queue::SpeechQueue, Configuration};
+
+/// Polls the clipboard and queues new text for speaking.
+pub struct ClipboardWatcher {
+    clipboard: arboard::Clipboard,
+    last_seen: Option<String>,
+    /// The clipboard text queued last, which is not spoken twice in a row.
+    last_spoken: Option<String>,
+    rules: Rules,
+    paused: Arc<AtomicBool>,
+    config: Configuration,
+    queue: SpeechQueue,
+}
+
+/// Which clipboard text is worth speaking, so that copying a password or a whole
+/// page stays quiet.
+struct Rules {
+    min_length: usize,
+    max_length: usize,
+    max_words: usize,
+    /// Anchored, so that all of the text has to match.
+    allowed: Option<Regex>,
+    /// `clipboard_toggle_text`, trimmed.
+    toggle: Option<String>,
+}
+
+impl Rules {
+    fn new(config: &Configuration) -> Result<Self, String> {
+        let allowed = config
+            .clipboard_allowed_characters
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(&format!("^(?:{pattern})$"))
+                    .map_err(|e| format!("invalid clipboard_allowed_characters {pattern:?}: {e}"))
+            })
+            .transpose()?;
+        let toggle = match config.clipboard_toggle_text.as_deref().map(str::trim) {
+            Some("") => return Err("clipboard_toggle_text must not be empty".into()),
+            toggle => toggle.map(str::to_owned),
+        };
+        Ok(Self {
+            min_length: config.min_clipboard_length,
+            max_length: config.max_clipboard_length,
+            max_words: config.clipboard_word_count_max,
+            allowed,
+            toggle,
+        })
+    }
+
+    /// Whether `text`, already trimmed, pauses or resumes the watcher.
+    fn is_toggle(&self, text: &str) -> bool {
+        self.toggle.as_deref() == Some(text)
+    }
+
+    /// Why `text` is not spoken, if it isn't. The text itself is left out, as it
+    /// may be a password.
+    fn check(&self, text: &str) -> Result<(), String> {
+        let length = text.chars().count();
+        if length < self.min_length {
+            return Err(format!("{length} characters is below min_clipboard_length"));
+        }
+        if length > self.max_length {
+            return Err(format!("{length} characters is over max_clipboard_length"));
+        }
+        let words = text.split_whitespace().count();
+        if words > self.max_words {
+            return Err(format!("{words} words is over clipboard_word_count_max"));
+        }
+        if self
+            .allowed
+            .as_ref()
+            .is_some_and(|allowed| !allowed.is_match(text))
+        {
+            return Err("it does not match clipboard_allowed_characters".into());
+        }
+        Ok(())
+    }
+}
+
+impl ClipboardWatcher {
+    /// Starts watching on a background thread. The returned flag pauses the watcher while set.
+    pub fn start(config: Configuration, queue: SpeechQueue) -> Result<Arc<AtomicBool>, String> {
+        let rules = Rules::new(&config)?;
+        let mut clipboard =
+            arboard::Clipboard::new().map_err(|e| format!("could not open the clipboard: {e}"))?;
+        let paused = Arc::new(AtomicBool::new(false));
+        let mut watcher = Self {
+            // whatever is already on the clipboard isn't new
+            last_seen: clipboard.get_text().ok(),
+            last_spoken: None,
+            rules,
+            clipboard,
+            paused: paused.clone(),
+            config,
+            queue,
+        };
+        thread::spawn(move || loop {
+            sleep(Duration::from_millis(watcher.config.clipboard_poll_ms));
+            watcher.poll();
+        });
+        Ok(paused)
+    }
+
+    fn poll(&mut self) {
+        let Ok(text) = self.clipboard.get_text() else {
+            return;
+        };
+        if self.last_seen.as_ref() == Some(&text) {
+            return;
+        }
+        self.last_seen = Some(text.clone());
+        let text = text.trim();
+        if self.rules.is_toggle(text) {
+            let was_paused = self.paused.fetch_xor(true, Ordering::Relaxed);
+            log::info!(
+                "clipboard watch {}",
+                if was_paused { "resumed" } else { "paused" }
+            );
+            // or copying it again would not be new text
+            match self.clipboard.clear() {
+                Ok(()) => self.last_seen = None,
+                Err(e) => log::warn!("could not clear the clipboard: {e}"),
+            }
+            return;
+        }
+        if self.paused.load(Ordering::Relaxed) || text.is_empty() {
+            return;
+        }
+        if self.last_spoken.as_deref() == Some(text) {
+            log::debug!("skipped clipboard text: it was spoken last");
+            return;
+        }
+        if let Err(reason) = self.rules.check(text) {
+            log::debug!("skipped clipboard text: {reason}");
+            return;
+        }
+        self.last_spoken = Some(text.to_owned());
+        let Some(text) = self.queue.filter(text) else {
+            return;
+        };
+        let max = self.config.clipboard_max_chars;
+        if self.config.clipboard_chunking {
+            for chunk in chunking::chunk_text_by_sentences(&text, max) {
+                self.queue.enqueue(&chunk, Source::Clipboard);
+            }
+        } else {
+            self.queue
+                .enqueue(chunking::truncate_chars(&text, max), Source::Clipboard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(allowed: Option<&str>) -> Rules {
+        let config = Configuration {
+            min_clipboard_length: 2,
+            max_clipboard_length: 20,
+            clipboard_word_count_max: 3,
+            clipboard_allowed_characters: allowed.map(str::to_owned),
+            ..Default::default()
+        };
+        Rules::new(&config).unwrap()
+    }
+
+    #[test]
+    fn skips_text_outside_the_limits() {
+        let rules = rules(None);
+        assert_eq!(rules.check("hi there"), Ok(()));
+        assert!(rules
+            .check("x")
+            .unwrap_err()
+            .contains("min_clipboard_length"));
+        assert!(rules
+            .check("a rather long sentence")
+            .unwrap_err()
+            .contains("max_clipboard_length"));
+        assert_eq!(
+            rules.check("one two three four"),
+            Err("4 words is over clipboard_word_count_max".into())
+        );
+    }
+
+    #[test]
+    fn needs_all_of_the_text_to_match_the_allowed_characters() {
+        let rules = rules(Some(r"[\p{L} ]+"));
+        assert_eq!(rules.check("hello you"), Ok(()));
+        assert!(rules.check("hunter2!").is_err());
+        let config = Configuration {
+            clipboard_allowed_characters: Some("[".into()),
+            ..Default::default()
+        };
+        assert!(Rules::new(&config)
+            .err()
+            .unwrap()
+            .starts_with("invalid clipboard_allowed_characters"));
+    }
+
+    #[test]
+    fn recognizes_the_toggle_text() {
+        let config = Configuration {
+            clipboard_toggle_text: Some(" tts pause ".into()),
+            ..Default::default()
+        };
+        let rules = Rules::new(&config).unwrap();
+        assert!(rules.is_toggle("tts pause"));
+        assert!(!rules.is_toggle("tts pause now"));
+        assert!(!self::rules(None).is_toggle(""));
+        let config = Configuration {
+            clipboard_toggle_text: Some("  ".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Rules::new(&config).err(),
+            Some("clipboard_toggle_text must not be empty".into())
+        );
+    }
+}
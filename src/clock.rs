@@ -0,0 +1,286 @@
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Wall-clock time broken down in the local timezone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub date: Date,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+/// A calendar date, ordered chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    /// Parses a `YYYY-MM-DD` date.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        ((1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day))
+            .then_some(Self { year, month, day })
+    }
+
+    /// The day after this one.
+    pub fn next(self) -> Self {
+        from_days(days(self) + 1)
+    }
+}
+
+/// A `--from` or `--to` time: a whole day in the local timezone, or an RFC 3339
+/// timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeBound {
+    Day(Date),
+    At(u64),
+}
+
+impl TimeBound {
+    /// Parses `YYYY-MM-DD` or `YYYY-MM-DDTHH:MM:SS` with a `Z` or `±HH:MM` offset
+    /// and optional fractional seconds, which are dropped.
+    pub fn parse(s: &str) -> Option<Self> {
+        Date::parse(s)
+            .map(Self::Day)
+            .or_else(|| parse_rfc3339(s).map(Self::At))
+    }
+
+    /// The first second it covers, as a Unix timestamp.
+    pub fn start(self) -> u64 {
+        match self {
+            Self::Day(date) => local_midnight(date),
+            Self::At(secs) => secs,
+        }
+    }
+
+    /// The first second after it, as a Unix timestamp.
+    pub fn end(self) -> u64 {
+        match self {
+            Self::Day(date) => local_midnight(date.next()),
+            Self::At(secs) => secs + 1,
+        }
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let date = Date::parse(s.get(..10)?)?;
+    let rest = s[10..].strip_prefix(['T', 't', ' '])?;
+    let (time, offset) = match rest.find(['Z', 'z', '+', '-']) {
+        Some(at) => rest.split_at(at),
+        None => return None,
+    };
+    let mut fields = time.splitn(3, ':');
+    let hour: i64 = fields.next()?.parse().ok()?;
+    let minute: i64 = fields.next()?.parse().ok()?;
+    let second = fields.next()?;
+    let second: i64 = second
+        .split_once('.')
+        .map_or(second, |(whole, _)| whole)
+        .parse()
+        .ok()?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let offset = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = offset.split_at(1);
+            let (hours, minutes) = offset.split_once(':')?;
+            let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if sign == "-" {
+                -offset
+            } else {
+                offset
+            }
+        }
+    };
+    let secs = days(date) * 86_400 + hour * 3600 + minute * 60 + second - offset;
+    u64::try_from(secs).ok()
+}
+
+/// `unix_secs` as an RFC 3339 timestamp in UTC, like `2024-06-01T18:30:00Z`.
+pub fn rfc3339_utc(unix_secs: u64) -> String {
+    let secs = unix_secs as i64;
+    let date = from_days(secs.div_euclid(86_400));
+    let time = secs.rem_euclid(86_400);
+    format!(
+        "{date}T{:02}:{:02}:{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from 1970-01-01 to `date`, after Howard Hinnant's `days_from_civil`.
+fn days(date: Date) -> i64 {
+    let year = i64::from(date.year) - i64::from(date.month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(date.month);
+    let day_of_year =
+        (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + i64::from(date.day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date `days` after 1970-01-01, the inverse of [`days`].
+fn from_days(days: i64) -> Date {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u8;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u8;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    Date {
+        year: year as i32,
+        month,
+        day,
+    }
+}
+
+/// Unix time of the local midnight that starts `date`. The local offset is
+/// found by trial, since the standard library can't convert local time back.
+pub fn local_midnight(date: Date) -> u64 {
+    let target = days(date) * 86_400;
+    let mut guess = target;
+    // twice, in case the first guess lands on the other side of a DST change
+    for _ in 0..2 {
+        let local = local_at(guess.max(0) as u64);
+        let shown =
+            days(local.date) * 86_400 + i64::from(local.hour) * 3600 + i64::from(local.minute) * 60;
+        guess += target - shown;
+    }
+    guess.max(0) as u64
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+pub fn local_now() -> LocalTime {
+    local_at(unix_now())
+}
+
+/// Breaks a Unix timestamp down in the local timezone.
+pub fn local_at(unix_secs: u64) -> LocalTime {
+    let tm = local_tm(unix_secs as libc::time_t);
+    LocalTime {
+        date: Date {
+            year: tm.tm_year + 1900,
+            month: tm.tm_mon as u8 + 1,
+            day: tm.tm_mday as u8,
+        },
+        hour: tm.tm_hour as u8,
+        minute: tm.tm_min as u8,
+    }
+}
+
+#[cfg(unix)]
+fn local_tm(secs: libc::time_t) -> libc::tm {
+    // SAFETY: localtime_r only writes into the tm we hand it.
+    unsafe {
+        let mut tm = std::mem::zeroed();
+        libc::localtime_r(&secs, &mut tm);
+        tm
+    }
+}
+
+#[cfg(windows)]
+fn local_tm(secs: libc::time_t) -> libc::tm {
+    // SAFETY: localtime_s only writes into the tm we hand it.
+    unsafe {
+        let mut tm = std::mem::zeroed();
+        libc::localtime_s(&mut tm, &secs);
+        tm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> Date {
+        Date::parse(s).unwrap()
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        assert!(Date::parse("2024-02-29").is_some());
+        assert_eq!(Date::parse("2023-02-29"), None);
+        assert_eq!(Date::parse("2024-04-31"), None);
+        assert_eq!(date("2024-12-31").next(), date("2025-01-01"));
+        assert_eq!(date("2024-02-28").next(), date("2024-02-29"));
+    }
+
+    #[test]
+    fn parses_rfc3339() {
+        let at = |s| match TimeBound::parse(s) {
+            Some(TimeBound::At(secs)) => secs,
+            other => panic!("{s}: {other:?}"),
+        };
+        assert_eq!(at("2024-06-01T00:00:00Z"), 1_717_200_000);
+        assert_eq!(at("2024-06-01T02:30:00.250+02:30"), 1_717_200_000);
+        assert_eq!(at("2024-05-31t19:00:00-05:00"), 1_717_200_000);
+        assert_eq!(rfc3339_utc(1_717_200_000), "2024-06-01T00:00:00Z");
+        for bad in [
+            "2024-06-01T25:00:00Z",
+            "2024-06-01T12:00:00",
+            "2024-06-01 noon",
+            "yesterday",
+        ] {
+            assert_eq!(TimeBound::parse(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn bare_dates_cover_the_local_day() {
+        let day = TimeBound::parse("2024-06-01").unwrap();
+        assert_eq!(day, TimeBound::Day(date("2024-06-01")));
+        let start = local_at(day.start());
+        assert_eq!(
+            (start.date, start.hour, start.minute),
+            (date("2024-06-01"), 0, 0)
+        );
+        let end = local_at(day.end());
+        assert_eq!((end.date, end.hour, end.minute), (date("2024-06-02"), 0, 0));
+    }
+}
@@ -0,0 +1,161 @@
+use eframe::{
+    egui::{self, text::CCursor, text::CCursorRange, text_edit::TextEditOutput, RichText},
+    epaint::ahash::HashMap,
+};
+
+use crate::{
+    history,
+    hotkeys::{self, Hotkeys},
+    Configuration,
+};
+
+/// At most this many suggestions are offered.
+const SHOWN: usize = 3;
+
+/// Snippet previews are cut off after this many characters.
+const PREVIEW_CHARS: usize = 40;
+
+/// Tab completion of phrases from the quick replies and random phrase sets, and of
+/// snippet triggers.
+pub struct Completions {
+    library: Vec<String>,
+    /// Templates of the snippet triggers, shown next to them.
+    templates: HashMap<String, String>,
+    /// How often each phrase was spoken, from the message log.
+    usage: HashMap<String, u64>,
+    suggestions: Vec<String>,
+    /// The suggestion `complete` inserts; `next_completion` moves on to the next one.
+    selected: usize,
+}
+
+impl Completions {
+    pub fn new(config: &Configuration) -> Self {
+        let mut library: Vec<String> = config
+            .quick_replies
+            .iter()
+            .map(|reply| reply.text.clone())
+            .chain(config.random_phrase_sets.values().flatten().cloned())
+            .chain(config.snippets.keys().cloned())
+            .filter(|phrase| !phrase.trim().is_empty())
+            .collect();
+        library.sort();
+        library.dedup();
+        let mut usage = HashMap::default();
+        match history::read_spoken(&config.message_log) {
+            Ok(rows) => {
+                for (_, _, text) in rows {
+                    *usage.entry(text).or_default() += 1;
+                }
+            }
+            Err(e) => log::warn!("could not read {}: {e}", config.message_log.path),
+        }
+        Self {
+            library,
+            templates: config.snippets.clone(),
+            usage,
+            suggestions: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    /// Phrases starting with `typed`, ignoring case. Phrases matching more of it
+    /// exactly come first, then the ones spoken most often.
+    fn suggest(&mut self, typed: &str) {
+        self.selected = 0;
+        self.suggestions.clear();
+        if typed.trim().is_empty() {
+            return;
+        }
+        let lower = typed.to_lowercase();
+        let mut matches: Vec<(usize, u64, &String)> = self
+            .library
+            .iter()
+            .filter(|phrase| {
+                let phrase = phrase.to_lowercase();
+                phrase.starts_with(&lower) && phrase != lower
+            })
+            .map(|phrase| {
+                let exact = phrase
+                    .chars()
+                    .zip(typed.chars())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let used = self.usage.get(phrase).copied().unwrap_or(0);
+                (exact, used, phrase)
+            })
+            .collect();
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)));
+        self.suggestions = matches
+            .into_iter()
+            .take(SHOWN)
+            .map(|(.., phrase)| phrase.clone())
+            .collect();
+    }
+
+    /// Handles the completion hotkeys and shows the suggestions under the text box.
+    /// Returns the bottom edge of the suggestions, if any are shown.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        output: &mut TextEditOutput,
+        text: &mut String,
+        keys: &Hotkeys,
+    ) -> Option<f32> {
+        let complete = hotkeys::is_pressed(&keys.complete, ui.ctx());
+        let next = hotkeys::is_pressed(&keys.next_completion, ui.ctx());
+        // any other key dismisses the suggestions
+        let other = ui.input(|i| {
+            i.events
+                .iter()
+                .any(|event| matches!(event, egui::Event::Key { pressed: true, .. }))
+        });
+        if other {
+            self.suggestions.clear();
+        }
+        if output.response.changed() {
+            self.suggest(text);
+        }
+        if self.suggestions.is_empty() {
+            return None;
+        }
+        if next {
+            self.selected = (self.selected + 1) % self.suggestions.len();
+        }
+        if complete || next {
+            text.clone_from(&self.suggestions[self.selected]);
+            output
+                .state
+                .set_ccursor_range(Some(CCursorRange::one(CCursor::new(text.chars().count()))));
+            output.state.clone().store(ui.ctx(), output.response.id);
+            ui.ctx().request_repaint();
+        }
+        let area = egui::Area::new("completions")
+            .order(egui::Order::Foreground)
+            .fixed_pos(output.response.rect.left_bottom())
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (i, suggestion) in self.suggestions.iter().enumerate() {
+                        let label = match self.templates.get(suggestion) {
+                            Some(template) => format!("{suggestion} → {}", preview(template)),
+                            None => suggestion.clone(),
+                        };
+                        let label = RichText::new(label).size(14.);
+                        ui.label(if i == self.selected {
+                            label.strong()
+                        } else {
+                            label.weak()
+                        });
+                    }
+                });
+            });
+        Some(area.response.rect.bottom())
+    }
+}
+
+/// The start of a snippet template.
+fn preview(template: &str) -> String {
+    match template.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}…", &template[..end]),
+        None => template.to_owned(),
+    }
+}
@@ -0,0 +1,186 @@
+//! `--convert`: re-encodes a folder of audio files, e.g. a library of pre-rendered
+//! messages for other stream tools.
+
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use rodio::{source::UniformSourceIterator, Decoder, Source};
+
+use crate::audio;
+
+/// Extensions of the files that are converted.
+const INPUT_EXTENSIONS: &[&str] = &["wav", "mp3", "ogg"];
+/// Sample rates `--resample` accepts.
+const RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+
+/// What `--convert` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Ogg,
+}
+
+impl AudioFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Mp3 => "mp3",
+            Self::Ogg => "ogg",
+        }
+    }
+}
+
+/// Converts every WAV, MP3 and Ogg Vorbis file in `input` to `format` in `output`,
+/// printing progress to stderr. A file that fails is reported and skipped; the
+/// error at the end says how many did.
+pub fn run(
+    input: &Path,
+    output: &Path,
+    format: Option<AudioFormat>,
+    resample: Option<u32>,
+) -> Result<(), String> {
+    match format {
+        Some(AudioFormat::Wav) => {}
+        Some(format @ (AudioFormat::Mp3 | AudioFormat::Ogg)) => {
+            return Err(format!(
+                "no {} encoder is built in; convert to wav",
+                format.extension()
+            ))
+        }
+        None => return Err("--convert needs --format wav".into()),
+    }
+    if let Some(rate) = resample.filter(|rate| !RATES.contains(rate)) {
+        return Err(format!(
+            "--resample must be between {} and {} Hz, got {rate}",
+            RATES.start(),
+            RATES.end()
+        ));
+    }
+    let files =
+        audio_files(input).map_err(|e| format!("could not read {}: {e}", input.display()))?;
+    if files.is_empty() {
+        return Err(format!("no wav, mp3 or ogg files in {}", input.display()));
+    }
+    fs::create_dir_all(output)
+        .map_err(|e| format!("could not create {}: {e}", output.display()))?;
+    let mut failed = 0;
+    for (i, file) in files.iter().enumerate() {
+        eprintln!("{}/{} {}", i + 1, files.len(), file.display());
+        let target = output
+            .join(file.file_stem().unwrap_or_default())
+            .with_extension("wav");
+        let result = to_wav(file, resample)
+            .and_then(|wav| fs::write(&target, wav).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("could not convert {}: {e}", file.display());
+            failed += 1;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!(
+            "{n} of {} files could not be converted",
+            files.len()
+        )),
+    }
+}
+
+/// The audio files directly in `dir`, by name.
+fn audio_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|extension| {
+                    let extension = extension.to_string_lossy().to_lowercase();
+                    INPUT_EXTENSIONS.contains(&extension.as_str())
+                })
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Decodes `path` and encodes it as 16-bit WAV at `resample` Hz, or at its own rate.
+fn to_wav(path: &Path, resample: Option<u32>) -> Result<Vec<u8>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let channels = decoder.channels();
+    let rate = resample.unwrap_or(decoder.sample_rate());
+    let samples: Vec<i16> = UniformSourceIterator::new(decoder, channels, rate).collect();
+    Ok(audio::encode_wav(channels, rate, &samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, time::Duration};
+
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("convert-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn decode(wav: Vec<u8>) -> Decoder<Cursor<Vec<u8>>> {
+        Decoder::new_wav(Cursor::new(wav)).unwrap()
+    }
+
+    #[test]
+    fn resamples_every_audio_file() {
+        let dir = scratch("resample");
+        let (input, output) = (dir.join("in"), dir.join("out"));
+        fs::create_dir_all(&input).unwrap();
+        let second: Vec<i16> = (0..48_000).map(|i| (i % 100) as i16).collect();
+        fs::write(input.join("a.wav"), audio::encode_wav(2, 24_000, &second)).unwrap();
+        fs::write(
+            input.join("b.WAV"),
+            audio::encode_wav(1, 16_000, &second[..16_000]),
+        )
+        .unwrap();
+        fs::write(input.join("notes.txt"), "not audio").unwrap();
+        run(&input, &output, Some(AudioFormat::Wav), Some(48_000)).unwrap();
+        for (name, channels) in [("a.wav", 2), ("b.wav", 1)] {
+            let decoder = decode(fs::read(output.join(name)).unwrap());
+            assert_eq!(decoder.sample_rate(), 48_000, "{name}");
+            assert_eq!(decoder.channels(), channels, "{name}");
+            let length = decoder.total_duration().unwrap();
+            assert!(
+                length.abs_diff(Duration::from_secs(1)) < Duration::from_millis(5),
+                "{name}"
+            );
+        }
+        assert!(!output.join("notes.wav").exists());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn reports_files_it_cannot_decode() {
+        let dir = scratch("broken");
+        fs::write(dir.join("broken.mp3"), "not an mp3").unwrap();
+        fs::write(dir.join("fine.wav"), audio::encode_wav(1, 8_000, &[0; 80])).unwrap();
+        let error = run(&dir, &dir.join("out"), Some(AudioFormat::Wav), None).unwrap_err();
+        assert_eq!(error, "1 of 2 files could not be converted");
+        assert_eq!(
+            decode(fs::read(dir.join("out/fine.wav")).unwrap()).sample_rate(),
+            8_000
+        );
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn refuses_formats_without_an_encoder() {
+        let dir = scratch("formats");
+        let error = run(&dir, &dir, Some(AudioFormat::Mp3), None).unwrap_err();
+        assert_eq!(error, "no mp3 encoder is built in; convert to wav");
+        assert!(run(&dir, &dir, None, None).is_err());
+        assert!(run(&dir, &dir, Some(AudioFormat::Wav), Some(5)).is_err());
+        _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,147 @@
+//! IDs that tie log lines together: one for the whole run of the program, and one
+//! for each message from when it is submitted until it has been spoken. Within a
+//! message, spans name the phase a log line comes from.
+
+use std::{cell::RefCell, sync::OnceLock, time::Instant};
+
+thread_local! {
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+    static SPANS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A random (version 4) UUID.
+pub fn new_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// The ID of this run of the program.
+pub fn session_id() -> &'static str {
+    static SESSION_ID: OnceLock<String> = OnceLock::new();
+    SESSION_ID.get_or_init(new_id)
+}
+
+/// The ID of the message this thread is handling, if any.
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.with(|id| id.borrow().clone())
+}
+
+/// Runs `f` with `id` as the request ID of this thread.
+pub fn in_request<R>(id: &str, f: impl FnOnce() -> R) -> R {
+    let outer = REQUEST_ID.with(|current| current.replace(Some(id.to_owned())));
+    // put back even if `f` panics, as the thread lives on
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REQUEST_ID.with(|current| *current.borrow_mut() = self.0.take());
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
+/// A phase of handling a message, such as the HTTP request, nested in the spans
+/// entered before it on this thread until it is dropped. Leaving it logs how long
+/// it took at debug level.
+#[must_use = "the span is left right away unless it is kept"]
+pub struct Span {
+    name: &'static str,
+    start: Instant,
+}
+
+pub fn span(name: &'static str) -> Span {
+    SPANS.with(|spans| spans.borrow_mut().push(name));
+    Span {
+        name,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        log::debug!("{} took {} ms", self.name, self.start.elapsed().as_millis());
+        SPANS.with(|spans| {
+            let mut spans = spans.borrow_mut();
+            if let Some(at) = spans.iter().rposition(|name| *name == self.name) {
+                spans.truncate(at);
+            }
+        });
+    }
+}
+
+/// The spans this thread is in, outermost first.
+pub fn spans() -> Vec<&'static str> {
+    SPANS.with(|spans| spans.borrow().clone())
+}
+
+/// `session=<id>`, with `request=<id>` added inside a request and `span=<a>/<b>`
+/// inside spans, for log lines.
+pub fn tag() -> String {
+    let mut tag = format!("session={}", session_id());
+    if let Some(request) = request_id() {
+        tag += &format!(" request={request}");
+    }
+    let spans = spans();
+    if !spans.is_empty() {
+        tag += &format!(" span={}", spans.join("/"));
+    }
+    tag
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn makes_version_4_uuids() {
+        let id = new_id();
+        let groups: Vec<usize> = id.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(id.as_bytes()[14], b'4');
+        assert!(
+            matches!(id.as_bytes()[19], b'8' | b'9' | b'a' | b'b'),
+            "{id}"
+        );
+        assert_ne!(id, new_id());
+    }
+
+    #[test]
+    fn scopes_the_request_id_to_the_call() {
+        assert_eq!(request_id(), None);
+        let tag = in_request("outer", || {
+            in_request("inner", || {
+                assert_eq!(request_id().as_deref(), Some("inner"))
+            });
+            assert_eq!(request_id().as_deref(), Some("outer"));
+            tag()
+        });
+        assert_eq!(tag, format!("session={} request=outer", session_id()));
+        assert_eq!(request_id(), None);
+        assert_eq!(session_id(), session_id());
+    }
+
+    #[test]
+    fn nests_spans_until_they_are_dropped() {
+        let synthesize = span("synthesize");
+        {
+            let _http = span("http");
+            assert_eq!(spans(), ["synthesize", "http"]);
+            assert!(tag().ends_with(" span=synthesize/http"), "{}", tag());
+        }
+        let _decode = span("base64_decode");
+        // leaving an outer span leaves the ones in it too
+        drop(synthesize);
+        assert!(spans().is_empty());
+        assert_eq!(tag(), format!("session={}", session_id()));
+    }
+}
@@ -0,0 +1,208 @@
+use rodio::{cpal::traits::HostTrait, Device, DeviceTrait};
+use serde::Serialize;
+
+use crate::stats::OutputFormat;
+
+/// Sample rates checked against each device's supported ranges.
+const COMMON_RATES: &[u32] = &[
+    8_000, 11_025, 16_000, 22_050, 24_000, 32_000, 44_100, 48_000, 88_200, 96_000, 176_400, 192_000,
+];
+
+/// Name fragments of virtual audio cables and loopback drivers.
+const VIRTUAL_HINTS: &[&str] = &[
+    "virtual",
+    "vb-audio",
+    "cable",
+    "voicemeeter",
+    "loopback",
+    "blackhole",
+    "soundflower",
+    "null",
+];
+
+/// An output device, as `--devices` lists it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    /// Common sample rates the device can be opened at.
+    pub supported_rates: Vec<u32>,
+}
+
+impl DeviceInfo {
+    fn new(device: &Device, default_name: Option<&str>) -> Option<Self> {
+        let name = device.name().ok()?;
+        let ranges: Vec<_> = device
+            .supported_output_configs()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let supported_rates = COMMON_RATES
+            .iter()
+            .copied()
+            .filter(|rate| {
+                ranges.iter().any(|range| {
+                    (range.min_sample_rate().0..=range.max_sample_rate().0).contains(rate)
+                })
+            })
+            .collect();
+        Some(Self {
+            is_default: default_name == Some(name.as_str()),
+            name,
+            supported_rates,
+        })
+    }
+
+    /// Whether the name looks like a virtual cable, which is usually what a
+    /// stream or voice chat should capture.
+    pub fn is_likely_virtual(&self) -> bool {
+        let name = self.name.to_lowercase();
+        VIRTUAL_HINTS.iter().any(|hint| name.contains(hint))
+    }
+}
+
+/// The output devices of the default audio host.
+pub fn list() -> Result<Vec<DeviceInfo>, String> {
+    let host = rodio::cpal::default_host();
+    let default_name = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+    let devices = host
+        .output_devices()
+        .map_err(|e| format!("could not list output devices: {e}"))?;
+    Ok(devices
+        .filter_map(|device| DeviceInfo::new(&device, default_name.as_deref()))
+        .collect())
+}
+
+/// Prints the output devices for `--devices`.
+pub fn run(format: OutputFormat) -> Result<(), String> {
+    let devices = list()?;
+    match format {
+        OutputFormat::Text => print!("{}", render_text(&devices)),
+        OutputFormat::Json => println!("{}", render_json(&devices)?),
+        OutputFormat::Csv => print!("{}", render_csv(&devices)),
+    }
+    Ok(())
+}
+
+fn render_text(devices: &[DeviceInfo]) -> String {
+    let mut out = String::new();
+    for device in devices {
+        let rates: Vec<String> = device.supported_rates.iter().map(u32::to_string).collect();
+        out += &format!(
+            "{} {}{}{}\n",
+            if device.is_default { "*" } else { " " },
+            device.name,
+            if device.is_likely_virtual() {
+                " [virtual]"
+            } else {
+                ""
+            },
+            if rates.is_empty() {
+                String::new()
+            } else {
+                format!(" ({} Hz)", rates.join(", "))
+            }
+        );
+    }
+    out
+}
+
+fn render_json(devices: &[DeviceInfo]) -> Result<String, String> {
+    let rows = devices
+        .iter()
+        .map(|device| {
+            let mut row = serde_json::to_value(device)?;
+            row["is_virtual"] = device.is_likely_virtual().into();
+            Ok(row)
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()
+        .map_err(|e| e.to_string())?;
+    serde_json::to_string_pretty(&rows).map_err(|e| e.to_string())
+}
+
+fn render_csv(devices: &[DeviceInfo]) -> String {
+    let mut out = "name,is_default,is_virtual,supported_rates\n".to_owned();
+    for device in devices {
+        let rates: Vec<String> = device.supported_rates.iter().map(u32::to_string).collect();
+        out += &format!(
+            "\"{}\",{},{},{}\n",
+            device.name.replace('"', "\"\""),
+            device.is_default,
+            device.is_likely_virtual(),
+            rates.join(";")
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn devices() -> Vec<DeviceInfo> {
+        vec![
+            DeviceInfo {
+                name: "Realtek HD Audio 2nd output".into(),
+                is_default: true,
+                supported_rates: vec![44_100, 48_000],
+            },
+            DeviceInfo {
+                name: "CABLE Input (VB-Audio Virtual Cable)".into(),
+                is_default: false,
+                supported_rates: vec![48_000],
+            },
+            DeviceInfo {
+                name: r#"Monitor "B""#.into(),
+                is_default: false,
+                supported_rates: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn spots_virtual_devices() {
+        let flags: Vec<bool> = devices()
+            .iter()
+            .map(DeviceInfo::is_likely_virtual)
+            .collect();
+        assert_eq!(flags, [false, true, false]);
+    }
+
+    #[test]
+    fn renders_text() {
+        assert_eq!(
+            render_text(&devices()),
+            "* Realtek HD Audio 2nd output (44100, 48000 Hz)\n  \
+             CABLE Input (VB-Audio Virtual Cable) [virtual] (48000 Hz)\n  \
+             Monitor \"B\"\n"
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let json: serde_json::Value =
+            serde_json::from_str(&render_json(&devices()).unwrap()).unwrap();
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "name": "Realtek HD Audio 2nd output",
+                "is_default": true,
+                "supported_rates": [44100, 48000],
+                "is_virtual": false,
+            })
+        );
+        assert_eq!(json[1]["is_virtual"], true);
+    }
+
+    #[test]
+    fn renders_csv() {
+        assert_eq!(
+            render_csv(&devices()),
+            "name,is_default,is_virtual,supported_rates\n\
+             \"Realtek HD Audio 2nd output\",true,false,44100;48000\n\
+             \"CABLE Input (VB-Audio Virtual Cable)\",false,true,48000\n\
+             \"Monitor \"\"B\"\"\",false,false,\n"
+        );
+    }
+}
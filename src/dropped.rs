@@ -0,0 +1,45 @@
+use std::{fs, path::Path};
+
+use eframe::egui::DroppedFile;
+
+/// Reads files dropped on the overlay, in the order they were dropped. Each one is
+/// either the file's text or an error to show.
+pub fn read_text_files(files: &[DroppedFile], max_bytes: usize) -> Vec<Result<String, String>> {
+    files.iter().map(|file| read(file, max_bytes)).collect()
+}
+
+fn read(file: &DroppedFile, max_bytes: usize) -> Result<String, String> {
+    let name = match &file.path {
+        Some(path) => path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        ),
+        None => file.name.clone(),
+    };
+    let is_txt = Path::new(&name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("txt"));
+    if !is_txt {
+        return Err(format!("{name} is not a .txt file"));
+    }
+    let bytes = match (&file.path, &file.bytes) {
+        (_, Some(bytes)) => bytes.to_vec(),
+        (Some(path), None) => {
+            let len = fs::metadata(path)
+                .map_err(|e| format!("could not read {name}: {e}"))?
+                .len();
+            if len > max_bytes as u64 {
+                return Err(format!("{name} is larger than {max_bytes} bytes"));
+            }
+            fs::read(path).map_err(|e| format!("could not read {name}: {e}"))?
+        }
+        (None, None) => return Err(format!("could not read {name}")),
+    };
+    if bytes.len() > max_bytes {
+        return Err(format!("{name} is larger than {max_bytes} bytes"));
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => Ok(text),
+        _ => Err(format!("{name} is not a text file")),
+    }
+}
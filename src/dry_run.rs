@@ -0,0 +1,39 @@
+//! `--dry-run --say "text"`: the synthesis requests a message turns into, printed
+//! instead of sent, to see what a rejected voice or SSML document looked like to
+//! the API.
+
+use crate::{
+    chunking,
+    filters::{Pipeline, Verdict},
+    overrides, snippets, speech, Configuration,
+};
+
+/// The requests for `text`, one per piece `max_chars` splits it into, after the
+/// steps a typed message goes through. Translation is left out, as it would send
+/// a request of its own.
+pub fn requests(text: &str, config: &Configuration) -> Result<Vec<String>, String> {
+    let (overrides, text) = overrides::parse(text, &config.voice_profiles);
+    let mut config = config.clone();
+    overrides.apply(&mut config);
+    let text = snippets::expand(&text, &config.snippets);
+    let text = match Pipeline::new(&config, true)?.apply(&text) {
+        Verdict::Speak(text) => text,
+        Verdict::Confirm { text, reason } => {
+            log::info!("the message {reason}; the overlay would ask before sending it");
+            text
+        }
+        Verdict::Drop { reason } => return Err(format!("the filters drop the message: {reason}")),
+        Verdict::Empty => return Err("nothing is left to speak after the filters".into()),
+    };
+    chunking::enforce_max_chars(&text, &config)?
+        .iter()
+        .map(|part| speech::describe_request(part, &config).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Prints the requests for `--dry-run`.
+pub fn run(text: &str, config: &Configuration) -> Result<(), String> {
+    let requests = requests(text, config)?;
+    println!("{}", requests.join("\n"));
+    Ok(())
+}
@@ -0,0 +1,275 @@
+//! Warnings and errors also go to `tts-overlay.log`, since the Windows release
+//! build has no console for env_logger to write to. With `log_format = "json"`,
+//! both get one JSON object per line for log collectors.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock, correlation};
+
+pub const FILE_NAME: &str = "tts-overlay.log";
+/// The log is rotated when it would grow past this size.
+const MAX_BYTES: u64 = 2_000_000;
+/// Rotated logs kept, as `tts-overlay.1.log` (the newest) to `tts-overlay.3.log`.
+const KEEP: usize = 3;
+
+static PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// How log lines are written, as `log_format`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `[time level target] session=… message`, for reading.
+    #[default]
+    Text,
+    /// One JSON object per line with the fields of the text format.
+    Json,
+}
+
+/// Switches the format of the lines logged from now on; lines logged before the
+/// configuration was read are text.
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+/// Installs env_logger with the error log alongside it. The log is created in the
+/// working directory, or the temporary directory if that is not writable; without
+/// either, only env_logger logs.
+pub fn init() {
+    let stderr = env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            if JSON.load(Ordering::Relaxed) {
+                return writeln!(buf, "{}", json_line(record));
+            }
+            writeln!(
+                buf,
+                "[{} {} {}] {} {}",
+                buf.timestamp(),
+                buf.default_styled_level(record.level()),
+                record.target(),
+                correlation::tag(),
+                record.args()
+            )
+        })
+        .build();
+    let working_dir = std::env::current_dir().unwrap_or_default();
+    let file = LogFile::open(&[
+        working_dir.join(FILE_NAME),
+        std::env::temp_dir().join(FILE_NAME),
+    ]);
+    _ = PATH.set(file.as_ref().map(|file| file.path.clone()));
+    log::set_max_level(stderr.filter().max(LevelFilter::Warn));
+    let logger = Logger {
+        stderr,
+        file: Mutex::new(file),
+    };
+    _ = log::set_boxed_logger(Box::new(logger));
+}
+
+/// Where warnings and errors are logged, once `init` found a writable place.
+pub fn path() -> Option<&'static Path> {
+    PATH.get()?.as_deref()
+}
+
+/// Where warnings and errors are logged, as a line for `--version` and
+/// `--verify-config`.
+pub fn describe() -> String {
+    match path() {
+        Some(path) => format!("Warnings and errors are logged to {}", path.display()),
+        None => "Warnings and errors are not logged to a file; no writable place for one".into(),
+    }
+}
+
+struct Logger {
+    stderr: env_logger::Logger,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LevelFilter::Warn || self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+        if record.level() > LevelFilter::Warn {
+            return;
+        }
+        let line = if JSON.load(Ordering::Relaxed) {
+            format!("{}\n", json_line(record))
+        } else {
+            format!(
+                "{} {} {}: {} {}\n",
+                clock::rfc3339_utc(clock::unix_now()),
+                record.level(),
+                record.target(),
+                correlation::tag(),
+                record.args()
+            )
+        };
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            // nowhere left to report a failure to
+            _ = file.write_line(&line);
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// A log line for `log_format = "json"`. `request` and `spans` are left out
+/// outside a request.
+fn json_line(record: &Record) -> String {
+    let mut line = serde_json::json!({
+        "time": clock::rfc3339_utc(clock::unix_now()),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "session": correlation::session_id(),
+        "message": record.args().to_string(),
+    });
+    if let Some(request) = correlation::request_id() {
+        line["request"] = request.into();
+    }
+    let spans = correlation::spans();
+    if !spans.is_empty() {
+        line["spans"] = spans.into();
+    }
+    line.to_string()
+}
+
+/// An append-only log file that is moved aside once it reaches `max_bytes`.
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+}
+
+impl LogFile {
+    /// Opens the first of `candidates` that can be written to.
+    fn open(candidates: &[PathBuf]) -> Option<Self> {
+        candidates.iter().find_map(|path| {
+            let file = append(path).ok()?;
+            let size = file.metadata().ok()?.len();
+            Some(Self {
+                path: path.clone(),
+                file,
+                size,
+                max_bytes: MAX_BYTES,
+            })
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts `tts-overlay.N.log` to `N + 1`, dropping the oldest, and starts a
+    /// new file.
+    fn rotate(&mut self) -> io::Result<()> {
+        _ = fs::remove_file(rotated_path(&self.path, KEEP));
+        for n in (1..KEEP).rev() {
+            _ = fs::rename(rotated_path(&self.path, n), rotated_path(&self.path, n + 1));
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        self.file = append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `tts-overlay.log` rotated `n` times ago is `tts-overlay.<n>.log`.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{n}.{}", extension.to_string_lossy()),
+        None => format!("{stem}.{n}"),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("error-log-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn falls_back_to_the_next_writable_place() {
+        let dir = temp_dir("fallback");
+        let unwritable = dir.join("missing").join(FILE_NAME);
+        let file = LogFile::open(&[unwritable.clone(), dir.join(FILE_NAME)]).unwrap();
+        assert_eq!(file.path, dir.join(FILE_NAME));
+        assert!(LogFile::open(&[unwritable]).is_none());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_last_files() {
+        let dir = temp_dir("rotate");
+        let path = dir.join(FILE_NAME);
+        let mut file = LogFile::open(std::slice::from_ref(&path)).unwrap();
+        file.max_bytes = 10;
+        for i in 0..6 {
+            file.write_line(&format!("line {i}\n")).unwrap();
+        }
+        let read = |n| fs::read_to_string(rotated_path(&path, n)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line 5\n");
+        assert_eq!(read(1), "line 4\n");
+        assert_eq!(read(3), "line 2\n");
+        assert!(!rotated_path(&path, 4).exists());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn writes_json_lines_with_the_request_and_spans() {
+        let line = |args| {
+            let record = Record::builder()
+                .level(log::Level::Warn)
+                .target("tts_overlay::speech")
+                .args(args)
+                .build();
+            serde_json::from_str::<serde_json::Value>(&json_line(&record)).unwrap()
+        };
+        let outside = line(format_args!("no \"request\""));
+        assert_eq!(outside["level"], "WARN");
+        assert_eq!(outside["target"], "tts_overlay::speech");
+        assert_eq!(outside["session"], correlation::session_id());
+        assert_eq!(outside["message"], "no \"request\"");
+        assert!(outside.get("request").is_none() && outside.get("spans").is_none());
+        let inside = correlation::in_request("r1", || {
+            let _span = correlation::span("http");
+            line(format_args!("retrying"))
+        });
+        assert_eq!(inside["request"], "r1");
+        assert_eq!(inside["spans"], serde_json::json!(["http"]));
+    }
+}
@@ -0,0 +1,118 @@
+//! `--export-queue`: renders a list of messages to one WAV file, e.g. to prepare a
+//! segment ahead of a stream.
+
+use std::{fs, io::Cursor, path::Path, time::Duration};
+
+use rodio::{source::UniformSourceIterator, Decoder, Source};
+
+use crate::{
+    audio,
+    filters::{Pipeline, Verdict},
+    speech, Configuration,
+};
+
+/// Synthesizes each line of `input` in the active voice, using the audio cache
+/// when it has the line, and writes the clips to `output` as one 16-bit mono WAV
+/// file with `silence_ms` of silence between them. The clips go through the same
+/// effects as when they are played, unless `save_raw_audio` is set. Lines go
+/// through the same filters as messages from the daemon's sources; dropped and
+/// empty ones are left out. Returns the length of the written file.
+pub fn run(
+    input: &Path,
+    output: &Path,
+    silence_ms: u64,
+    config: &Configuration,
+) -> Result<Duration, String> {
+    let pipeline = Pipeline::new(config, false)?;
+    let lines = fs::read_to_string(input)
+        .map_err(|e| format!("could not read {}: {e}", input.display()))?;
+    // numbered as in the file
+    let lines: Vec<(usize, &str)> = (1..)
+        .zip(lines.lines())
+        .filter(|(_, line)| !line.trim().is_empty())
+        .collect();
+    let mut clips = Vec::new();
+    let mut sample_rate = None;
+    for (i, &(number, line)) in lines.iter().enumerate() {
+        let text = match pipeline.apply(line) {
+            Verdict::Speak(text) | Verdict::Confirm { text, .. } => text,
+            Verdict::Drop { reason } => {
+                eprintln!("skipping line {number}: {reason}");
+                continue;
+            }
+            Verdict::Empty => continue,
+        };
+        eprintln!("{}/{} {text}", i + 1, lines.len());
+        let wav = speech::synthesize_cached(&text, config)
+            .map_err(|e| format!("could not synthesize line {number}: {e}"))?;
+        let decoder = Decoder::new_wav(Cursor::new(wav))
+            .map_err(|e| format!("could not decode line {number}: {e}"))?;
+        let clip: audio::Clip = if config.save_raw_audio {
+            Box::new(decoder.convert_samples())
+        } else {
+            audio::apply_effects(decoder.convert_samples(), config)
+        };
+        // the clips are joined at the rate of the first one
+        let rate = *sample_rate.get_or_insert(clip.sample_rate());
+        clips.push(UniformSourceIterator::<_, f32>::new(clip, 1, rate).collect());
+    }
+    let Some(sample_rate) = sample_rate else {
+        return Err(format!("nothing to speak in {}", input.display()));
+    };
+    let silence_frames = (sample_rate as u64 * silence_ms / 1000) as usize;
+    let clips = if config.declick {
+        declick(clips, sample_rate)
+    } else {
+        clips
+    };
+    let samples = interleave_silence(clips, silence_frames);
+    audio::write_wav(output, &samples, sample_rate, 1)
+        .map_err(|e| format!("could not write {}: {e}", output.display()))?;
+    Ok(Duration::from_secs_f64(
+        samples.len() as f64 / sample_rate as f64,
+    ))
+}
+
+/// Fades mono `clips` out and in where one ends and the next starts, and in
+/// before the first if it starts loud, for `declick`.
+fn declick(mut clips: Vec<Vec<f32>>, sample_rate: u32) -> Vec<Vec<f32>> {
+    let frames = audio::declick_frames(sample_rate);
+    if let Some(first) = clips.first_mut() {
+        *first = audio::ramp_in(std::mem::take(first), 1, audio::ramp_in_frames(sample_rate));
+    }
+    for i in 1..clips.len() {
+        let (a, b) = audio::declick_boundary(&clips[i - 1], &clips[i], frames);
+        (clips[i - 1], clips[i]) = (a, b);
+    }
+    if let Some(last) = clips.last_mut() {
+        (*last, _) = audio::declick_boundary(last, &[], frames);
+    }
+    clips
+}
+
+/// Joins mono `clips` with `silence_frames` zero samples between each two.
+pub fn interleave_silence(clips: Vec<Vec<f32>>, silence_frames: usize) -> Vec<f32> {
+    let length =
+        clips.iter().map(Vec::len).sum::<usize>() + silence_frames * clips.len().saturating_sub(1);
+    let mut joined = Vec::with_capacity(length);
+    for (i, clip) in clips.into_iter().enumerate() {
+        if i > 0 {
+            joined.resize(joined.len() + silence_frames, 0.);
+        }
+        joined.extend(clip);
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puts_silence_only_between_clips() {
+        let joined = interleave_silence(vec![vec![0.5, 0.5], vec![-0.5], vec![0.25]], 2);
+        assert_eq!(joined, [0.5, 0.5, 0., 0., -0.5, 0., 0., 0.25]);
+        assert_eq!(interleave_silence(vec![vec![0.5]], 3), [0.5]);
+        assert!(interleave_silence(Vec::new(), 3).is_empty());
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use crate::{chat::ChatFilter, history::Source, queue::SpeechQueue, Configuration};
+
+/// Follows a file like `tail -F`, queueing each appended line for speaking.
+pub struct FileWatcher {
+    path: PathBuf,
+    pattern: Option<Regex>,
+    chat: ChatFilter,
+    /// Bytes of the current file already processed.
+    position: u64,
+    #[cfg(unix)]
+    inode: Option<u64>,
+    /// Bytes after the last newline, waiting for the rest of their line. Kept
+    /// undecoded, as a read can end in the middle of a character.
+    partial: Vec<u8>,
+    limiter: RateLimiter,
+    poll_interval: Duration,
+    queue: SpeechQueue,
+    /// While set, lines are read but not spoken.
+    paused: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    /// Starts following `path` on a background thread. The returned flag pauses
+    /// the watcher while set.
+    pub fn start(
+        path: PathBuf,
+        config: &Configuration,
+        queue: SpeechQueue,
+    ) -> Result<Arc<AtomicBool>, String> {
+        let mut watcher = Self::new(path, config, queue)?;
+        let paused = watcher.paused.clone();
+        thread::spawn(move || loop {
+            sleep(watcher.poll_interval);
+            watcher.poll();
+        });
+        Ok(paused)
+    }
+
+    fn new(path: PathBuf, config: &Configuration, queue: SpeechQueue) -> Result<Self, String> {
+        let pattern = config
+            .file_watch_pattern
+            .as_deref()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| format!("invalid file_watch_pattern {pattern:?}: {e}"))
+            })
+            .transpose()?;
+        let chat = ChatFilter::new(&config.chat)?;
+        Ok(Self {
+            // lines written before we started aren't news
+            position: fs::metadata(&path).map_or(0, |meta| meta.len()),
+            #[cfg(unix)]
+            inode: inode(&path),
+            path,
+            pattern,
+            chat,
+            partial: Vec::new(),
+            limiter: RateLimiter::new(config.file_watch_max_per_minute),
+            poll_interval: Duration::from_millis(config.file_watch_poll_ms),
+            queue,
+            paused: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    fn poll(&mut self) {
+        let lines = self.read_lines();
+        // lines written while paused aren't news once resumed
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        for line in lines {
+            if let Some(text) = self.message(&line) {
+                self.queue.say(&text, Source::File);
+            }
+        }
+    }
+
+    /// The lines completed since the last call.
+    fn read_lines(&mut self) -> Vec<String> {
+        let Ok(meta) = fs::metadata(&self.path) else {
+            // not created yet, or mid-rotation; start from the top once it appears
+            self.position = 0;
+            self.partial.clear();
+            return Vec::new();
+        };
+        if meta.len() < self.position || self.rotated() {
+            log::debug!("{} was truncated or replaced", self.path.display());
+            self.position = 0;
+            self.partial.clear();
+        }
+        if meta.len() == self.position {
+            return Vec::new();
+        }
+        let mut appended = Vec::new();
+        let read = File::open(&self.path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(self.position))?;
+            file.read_to_end(&mut appended)
+        });
+        match read {
+            Ok(n) => self.position += n as u64,
+            Err(e) => {
+                log::warn!("could not read {}: {e}", self.path.display());
+                return Vec::new();
+            }
+        }
+        self.partial.extend(appended);
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_owned());
+        }
+        lines
+    }
+
+    #[cfg(unix)]
+    fn rotated(&mut self) -> bool {
+        let current = inode(&self.path);
+        std::mem::replace(&mut self.inode, current) != current
+    }
+
+    #[cfg(not(unix))]
+    fn rotated(&mut self) -> bool {
+        false
+    }
+
+    /// What to say for `line`, if it matches `file_watch_pattern`, passes the chat
+    /// filter and is within the rate limit.
+    fn message(&mut self, line: &str) -> Option<String> {
+        let (user, text) = match &self.pattern {
+            Some(pattern) => match pattern.captures(line) {
+                Some(captures) => (
+                    captures.name("user").map(|m| m.as_str()),
+                    captures
+                        .name("message")
+                        .or(captures.get(1))
+                        .or(captures.get(0))
+                        .map_or("", |m| m.as_str()),
+                ),
+                None => return None,
+            },
+            None => (None, line),
+        };
+        let text = self.chat.apply(user, text.trim())?;
+        if self.limiter.allow() {
+            Some(text)
+        } else {
+            log::debug!("rate limit reached, dropping line: {text}");
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|meta| meta.ino())
+}
+
+/// Allows at most `per_minute` events in any sliding minute.
+struct RateLimiter {
+    per_minute: u32,
+    recent: Vec<Instant>,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            recent: Vec::new(),
+        }
+    }
+
+    fn allow(&mut self) -> bool {
+        let now = Instant::now();
+        self.recent
+            .retain(|t| now.duration_since(*t) < Duration::from_secs(60));
+        if self.recent.len() < self.per_minute as usize {
+            self.recent.push(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write, sync::Arc};
+
+    use super::*;
+    use crate::filters::Pipeline;
+
+    fn watcher(path: &Path, config: Configuration) -> FileWatcher {
+        let pipeline = Arc::new(Pipeline::new(&config, false).unwrap());
+        let queue = SpeechQueue::start(config.clone(), pipeline);
+        FileWatcher::new(path.to_owned(), &config, queue).unwrap()
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("watched-{name}-{}.log", std::process::id()));
+        _ = fs::remove_file(&path);
+        path
+    }
+
+    fn append(path: &Path, bytes: &[u8]) {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    #[test]
+    fn follows_the_file_through_truncation_and_rotation() {
+        let path = scratch("rotation");
+        append(&path, b"already there\n");
+        let mut watcher = watcher(&path, Configuration::default());
+        assert!(watcher.read_lines().is_empty());
+        append(&path, b"one\ntw");
+        assert_eq!(watcher.read_lines(), ["one"]);
+        append(&path, b"o\n");
+        assert_eq!(watcher.read_lines(), ["two"]);
+        fs::write(&path, b"new\n").unwrap();
+        assert_eq!(watcher.read_lines(), ["new"]);
+        let rotated = path.with_extension("log.1");
+        fs::rename(&path, &rotated).unwrap();
+        assert!(watcher.read_lines().is_empty());
+        fs::write(&path, b"after rotation\n").unwrap();
+        assert_eq!(watcher.read_lines(), ["after rotation"]);
+        _ = fs::remove_file(path);
+        _ = fs::remove_file(rotated);
+    }
+
+    #[test]
+    fn keeps_a_character_split_across_reads_whole() {
+        let path = scratch("utf8");
+        let mut watcher = watcher(&path, Configuration::default());
+        let line = "café ☕\n".as_bytes();
+        append(&path, &line[..4]);
+        assert!(watcher.read_lines().is_empty());
+        append(&path, &line[4..line.len() - 2]);
+        assert!(watcher.read_lines().is_empty());
+        append(&path, &line[line.len() - 2..]);
+        assert_eq!(watcher.read_lines(), ["café ☕"]);
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn waits_for_a_file_that_does_not_exist_yet() {
+        let path = scratch("missing");
+        let mut watcher = watcher(&path, Configuration::default());
+        assert!(watcher.read_lines().is_empty());
+        append(&path, b"first\n");
+        assert_eq!(watcher.read_lines(), ["first"]);
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn speaks_what_the_pattern_extracts() {
+        let path = scratch("pattern");
+        let mut watcher = watcher(
+            &path,
+            Configuration {
+                file_watch_pattern: Some(r"^\[[\d:]+\] <(?<user>\w+)> (?<message>.+)$".into()),
+                file_watch_max_per_minute: 60,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            watcher.message("[12:00] <sky> hello there"),
+            Some("hello there".into())
+        );
+        assert_eq!(watcher.message("* sky joined"), None);
+        let mut fallback = self::watcher(
+            &path,
+            Configuration {
+                file_watch_pattern: Some(r"said: (.+)".into()),
+                file_watch_max_per_minute: 60,
+                ..Default::default()
+            },
+        );
+        assert_eq!(fallback.message("sky said: hi"), Some("hi".into()));
+    }
+
+    #[test]
+    fn drops_lines_over_the_rate_limit() {
+        let path = scratch("limit");
+        let mut watcher = watcher(
+            &path,
+            Configuration {
+                file_watch_max_per_minute: 2,
+                ..Default::default()
+            },
+        );
+        let spoken: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|line| watcher.message(line))
+            .collect();
+        assert_eq!(spoken, [Some("a".into()), Some("b".into()), None]);
+    }
+}
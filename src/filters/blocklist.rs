@@ -0,0 +1,73 @@
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+
+use super::{Filter, Verdict};
+use crate::history;
+
+/// What happens to a message matching `phrase_filter_regex`. In config.toml this is
+/// `"block"`, `"log"` or `{ replace = { with = "..." } }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// The message is not spoken.
+    #[default]
+    Block,
+    /// Every match is replaced and the rest of the message is spoken.
+    Replace { with: String },
+    /// The message is spoken unchanged.
+    Log,
+}
+
+/// Blocks, censors or just notes messages matching any of a set of patterns.
+pub struct Blocklist {
+    patterns: Vec<Regex>,
+    action: FilterAction,
+    log_filtered: bool,
+}
+
+impl Blocklist {
+    pub fn new(
+        patterns: &[String],
+        action: FilterAction,
+        log_filtered: bool,
+    ) -> Result<Self, String> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|e| format!("invalid phrase_filter_regex {pattern:?}: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            patterns,
+            action,
+            log_filtered,
+        })
+    }
+}
+
+impl Filter for Blocklist {
+    fn name(&self) -> &'static str {
+        "blocklist"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let Some(pattern) = self.patterns.iter().find(|regex| regex.is_match(&text)) else {
+            return Verdict::Speak(text);
+        };
+        let reason = format!("matches {:?}", pattern.as_str());
+        log::info!("message {reason}");
+        if self.log_filtered {
+            history::record_filtered(&reason, &text);
+        }
+        match &self.action {
+            FilterAction::Block => Verdict::Drop { reason },
+            FilterAction::Replace { with } => {
+                Verdict::Speak(self.patterns.iter().fold(text, |text, regex| {
+                    regex.replace_all(&text, NoExpand(with)).into_owned()
+                }))
+            }
+            FilterAction::Log => Verdict::Speak(text),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::ops::Range;
+
+use eframe::epaint::ahash::HashSet;
+use serde::{Deserialize, Serialize};
+
+use super::{expansions::DEFAULT_EXPANSIONS, Filter, Verdict};
+use crate::Configuration;
+
+/// Acronyms that stay in capitals, besides the chat abbreviations from the
+/// expansion table.
+const ACRONYMS: &[&str] = &[
+    "AFAIK", "ASAP", "CEO", "CPU", "DIY", "EU", "FAQ", "FIFA", "FYI", "GPU", "HTML", "HTTP",
+    "HTTPS", "IMAX", "JSON", "LGBT", "LGBTQ", "NASA", "NATO", "NBA", "NFL", "NHL", "NYC", "RSVP",
+    "SCOTUS", "UEFA", "UFO", "UK", "UNESCO", "UNICEF", "USA", "USB", "WIFI",
+];
+
+/// What happens to words written in capitals.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CapsMode {
+    /// Words are spoken as written.
+    #[default]
+    Off,
+    /// Words are lowercased, keeping a capital at the start of a sentence.
+    Lowercase,
+    /// Words are stressed with SSML where the provider supports it, and lowercased
+    /// otherwise.
+    Emphasis,
+}
+
+/// Lowercases shouted words: words of `caps_min_length` or more letters, all in
+/// capitals, that are not known acronyms.
+pub struct Caps {
+    min_len: usize,
+    /// Uppercase.
+    exceptions: HashSet<String>,
+}
+
+impl Caps {
+    pub fn new(config: &Configuration) -> Self {
+        let exceptions = ACRONYMS
+            .iter()
+            .map(|acronym| acronym.to_string())
+            .chain(
+                DEFAULT_EXPANSIONS
+                    .iter()
+                    .map(|(short, _)| short.to_uppercase()),
+            )
+            .chain(config.expansions.keys().map(|short| short.to_uppercase()))
+            .chain(
+                config
+                    .caps_exceptions
+                    .iter()
+                    .map(|word| word.to_uppercase()),
+            )
+            .collect();
+        Self {
+            min_len: config.caps_min_length,
+            exceptions,
+        }
+    }
+
+    /// Byte ranges of the shouted words in `text`.
+    pub fn find(&self, text: &str) -> Vec<Range<usize>> {
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '\'' || c == '’';
+        let mut found = Vec::new();
+        let mut start = None;
+        // the extra space ends a word at the end of the text
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (start, is_word_char(c)) {
+                (None, true) => start = Some(i),
+                (Some(from), false) => {
+                    let word = text[from..i].trim_matches(['\'', '’']);
+                    let from = from + text[from..i].find(word).unwrap_or(0);
+                    if self.is_shouted(word) {
+                        found.push(from..from + word.len());
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        found
+    }
+
+    fn is_shouted(&self, word: &str) -> bool {
+        let letters = word.chars().filter(|c| c.is_alphabetic()).count();
+        letters >= self.min_len.max(2)
+            && word
+                .chars()
+                .all(|c| c.is_uppercase() || c == '\'' || c == '’')
+            && !self.exceptions.contains(word)
+    }
+}
+
+/// The word at `range` in lowercase, capitalized if it starts a sentence.
+pub fn tame(text: &str, range: Range<usize>) -> String {
+    let word = text[range.clone()].to_lowercase();
+    let before = text[..range.start]
+        .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '“' | '‘' | '('));
+    if before.is_empty() || before.ends_with(['.', '!', '?']) {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    } else {
+        word
+    }
+}
+
+impl Filter for Caps {
+    fn name(&self) -> &'static str {
+        "caps"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let found = self.find(&text);
+        if found.is_empty() {
+            return Verdict::Speak(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for range in found {
+            out += &text[last..range.start];
+            out += &tame(&text, range.clone());
+            last = range.end;
+        }
+        out += &text[last..];
+        Verdict::Speak(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn caps() -> Caps {
+        let mut config = Configuration {
+            caps_min_length: 4,
+            ..Default::default()
+        };
+        config.caps_exceptions.push("Twitch".into());
+        Caps::new(&config)
+    }
+
+    fn tame_all(text: &str) -> String {
+        match caps().apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn lowercase_text_is_unchanged() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = testing::text(&mut rng, 100).to_lowercase();
+            assert_eq!(tame_all(&text), text);
+        }
+    }
+
+    #[test]
+    fn lowercases_shouted_words() {
+        assert_eq!(tame_all("THIS IS AMAZING"), "This IS amazing");
+        assert_eq!(tame_all("wow. WHAT a play!"), "wow. What a play!");
+        assert_eq!(tame_all("i said STOP!! LISTEN"), "i said stop!! Listen");
+        assert_eq!(tame_all("DON'T do it"), "Don't do it");
+        assert_eq!(tame_all("ok \"WHAT\""), "ok \"what\"");
+        assert_eq!(tame_all("(WHAT) now"), "(What) now");
+        assert_eq!(tame_all("ÜBER cool"), "Über cool");
+    }
+
+    #[test]
+    fn keeps_acronyms_and_mixed_case() {
+        for text in [
+            "the USA and NASA",
+            "GG everyone, AFK now",
+            "watching on TWITCH",
+            "LOL that was fun",
+            "iPhone McDONALD",
+            "MP3 files",
+            "I am here",
+        ] {
+            assert_eq!(tame_all(text), text);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use eframe::epaint::ahash::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use super::{Filter, Verdict};
+
+const NAMES: &str = include_str!("emoji.tsv");
+
+const ZWJ: char = '\u{200D}';
+const KEYCAP: char = '\u{20E3}';
+
+/// How emoji in a message are spoken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmojiMode {
+    /// Emoji are left for the voice to handle.
+    #[default]
+    Keep,
+    /// Emoji are removed.
+    Strip,
+    /// Each distinct emoji is named once, e.g. "😂😂🔥😂" becomes
+    /// "face with tears of joy fire".
+    Name,
+    /// Runs of the same emoji are counted, e.g. "🔥🔥🔥" becomes "three fire emoji".
+    Collapse,
+}
+
+/// A piece of a message: plain text, or one emoji with its name.
+enum Piece<'a> {
+    Text(&'a str),
+    Emoji { text: &'a str, name: String },
+}
+
+pub struct Emoji {
+    mode: EmojiMode,
+    names: HashMap<char, &'static str>,
+}
+
+impl Emoji {
+    pub fn new(mode: EmojiMode) -> Self {
+        let names = NAMES
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let (code, name) = line.split_once('\t')?;
+                Some((char::from_u32(u32::from_str_radix(code, 16).ok()?)?, name))
+            })
+            .collect();
+        Self { mode, names }
+    }
+
+    /// Splits `text` into plain text and emoji. A ZWJ sequence, a flag or an emoji
+    /// with a skin tone is one emoji, named after its first character.
+    fn pieces<'a>(&self, text: &'a str) -> Vec<Piece<'a>> {
+        let mut pieces = Vec::new();
+        let mut chars = text.char_indices().peekable();
+        let mut plain = 0;
+        while let Some((start, c)) = chars.next() {
+            let next = chars.peek().map(|&(_, next)| next);
+            let name = if is_regional_indicator(c) && next.is_some_and(is_regional_indicator) {
+                chars.next();
+                "flag".to_owned()
+            } else if (c.is_ascii_digit() || c == '#' || c == '*')
+                && matches!(next, Some('\u{FE0F}' | KEYCAP))
+            {
+                chars.next_if(|&(_, c)| c == '\u{FE0F}');
+                if chars.next_if(|&(_, c)| c == KEYCAP).is_none() {
+                    continue;
+                }
+                format!("keycap {c}")
+            } else if let Some(name) = self.names.get(&c) {
+                // skin tones, presentation selectors, tags and joined emoji
+                while let Some(&(_, next)) = chars.peek() {
+                    if is_modifier(next) {
+                        chars.next();
+                    } else if next == ZWJ {
+                        chars.next();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                name.to_string()
+            } else {
+                continue;
+            };
+            let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+            pieces.push(Piece::Text(&text[plain..start]));
+            pieces.push(Piece::Emoji {
+                text: &text[start..end],
+                name,
+            });
+            plain = end;
+        }
+        pieces.push(Piece::Text(&text[plain..]));
+        pieces
+    }
+}
+
+impl Filter for Emoji {
+    fn name(&self) -> &'static str {
+        "emoji"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let pieces = self.pieces(&text);
+        if pieces.len() == 1 {
+            return Verdict::Speak(text);
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut seen = HashSet::default();
+        let mut i = 0;
+        while i < pieces.len() {
+            let (emoji, name) = match &pieces[i] {
+                Piece::Text(plain) => {
+                    out.push_str(plain);
+                    i += 1;
+                    continue;
+                }
+                Piece::Emoji { text, name } => (text, name),
+            };
+            // the same emoji again, possibly after some spaces
+            let mut run = 1;
+            while let (Some(Piece::Text(gap)), Some(Piece::Emoji { name: next, .. })) =
+                (pieces.get(i + 2 * run - 1), pieces.get(i + 2 * run))
+            {
+                if !gap.trim().is_empty() || next != name {
+                    break;
+                }
+                run += 1;
+            }
+            let spoken = match self.mode {
+                EmojiMode::Keep => Some(emoji.to_string()),
+                EmojiMode::Strip => None,
+                EmojiMode::Name => seen.insert(name).then(|| name.clone()),
+                EmojiMode::Collapse if run == 1 => Some(name.clone()),
+                EmojiMode::Collapse => Some(format!("{} {name} emoji", count_word(run))),
+            };
+            if let Some(spoken) = spoken {
+                out.push(' ');
+                out.push_str(&spoken);
+                out.push(' ');
+            }
+            i += match self.mode {
+                EmojiMode::Collapse => 2 * run - 1,
+                _ => 1,
+            };
+        }
+        // left to the pipeline to skip if nothing is left
+        Verdict::Speak(out.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// Characters that change how the emoji before them looks.
+fn is_modifier(c: char) -> bool {
+    matches!(c, '\u{FE0E}' | '\u{FE0F}' | KEYCAP)
+        || ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+        || ('\u{E0020}'..='\u{E007F}').contains(&c)
+}
+
+fn count_word(n: usize) -> String {
+    const WORDS: [&str; 11] = [
+        "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    ];
+    WORDS
+        .get(n.wrapping_sub(2))
+        .map_or_else(|| n.to_string(), |word| word.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(mode: EmojiMode, text: &str) -> String {
+        match Emoji::new(mode).apply(text.into()) {
+            Verdict::Speak(text) => text,
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    fn check(mode: EmojiMode, cases: &[(&str, &str)]) {
+        for &(text, spoken) in cases {
+            assert_eq!(apply(mode, text), spoken, "{mode:?} {text:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_emoji_apart_from_words() {
+        check(
+            EmojiMode::Keep,
+            &[
+                ("no emoji here", "no emoji here"),
+                ("nice👍🏽", "nice 👍🏽"),
+                (
+                    "👨\u{200D}👩\u{200D}👧 family",
+                    "👨\u{200D}👩\u{200D}👧 family",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn strips_whole_sequences() {
+        check(
+            EmojiMode::Strip,
+            &[
+                ("nice 👍🏽 job 🇯🇵", "nice job"),
+                ("the 👨\u{200D}👩\u{200D}👧 is here", "the is here"),
+                ("press 1\u{FE0F}\u{20E3}", "press"),
+                ("🔥🔥🔥", ""),
+            ],
+        );
+    }
+
+    #[test]
+    fn names_each_distinct_emoji_once() {
+        check(
+            EmojiMode::Name,
+            &[
+                ("😂😂🔥😂", "face with tears of joy fire"),
+                // a ZWJ sequence is named after its first emoji
+                ("👨\u{200D}👩\u{200D}👧", "man"),
+                // whatever the skin tone
+                ("👍🏽 and 👍🏻", "thumbs up sign and"),
+                ("🇯🇵🇫🇷", "flag"),
+                (
+                    "press 1\u{FE0F}\u{20E3} or #\u{20E3}",
+                    "press keycap 1 or keycap #",
+                ),
+                // digits on their own are not keycaps
+                ("route 66", "route 66"),
+            ],
+        );
+    }
+
+    #[test]
+    fn counts_runs_of_the_same_emoji() {
+        check(
+            EmojiMode::Collapse,
+            &[
+                ("🔥🔥🔥", "three fire emoji"),
+                ("🔥 🔥 wow 🔥", "two fire emoji wow fire"),
+                ("👍🏽👍🏻", "two thumbs up sign emoji"),
+                (
+                    "😂🔥😂",
+                    "face with tears of joy fire face with tears of joy",
+                ),
+                (&"🔥".repeat(13), "13 fire emoji"),
+            ],
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use eframe::epaint::ahash::HashMap;
+
+use super::{urls, Filter, Verdict};
+
+/// Chat abbreviations that are spelled out or mispronounced otherwise.
+pub(super) const DEFAULT_EXPANSIONS: &[(&str, &str)] = &[
+    ("afaik", "as far as I know"),
+    ("afk", "away from keyboard"),
+    ("asap", "as soon as possible"),
+    ("brb", "be right back"),
+    ("btw", "by the way"),
+    ("dm", "direct message"),
+    ("fr", "for real"),
+    ("fyi", "for your information"),
+    ("g2g", "got to go"),
+    ("gg", "good game"),
+    ("glhf", "good luck, have fun"),
+    ("gtg", "got to go"),
+    ("hbu", "how about you"),
+    ("idk", "I don't know"),
+    ("ig", "I guess"),
+    ("iirc", "if I remember correctly"),
+    ("ikr", "I know, right"),
+    ("imho", "in my humble opinion"),
+    ("imo", "in my opinion"),
+    ("irl", "in real life"),
+    ("istg", "I swear to god"),
+    ("jk", "just kidding"),
+    ("ngl", "not gonna lie"),
+    ("np", "no problem"),
+    ("nvm", "never mind"),
+    ("ofc", "of course"),
+    ("omg", "oh my god"),
+    ("omw", "on my way"),
+    ("pls", "please"),
+    ("plz", "please"),
+    ("rn", "right now"),
+    ("smh", "shaking my head"),
+    ("tbh", "to be honest"),
+    ("thx", "thanks"),
+    ("ttyl", "talk to you later"),
+    ("ty", "thank you"),
+    ("tyvm", "thank you very much"),
+    ("wdym", "what do you mean"),
+    ("wp", "well played"),
+    ("wyd", "what are you doing"),
+];
+
+/// Replaces whole-word abbreviations, ignoring case, outside of links, so that
+/// "example.com/fr/dm" stays a working link even when links are read out.
+pub struct Expansions(HashMap<String, String>);
+
+impl Expansions {
+    /// The built-in table, if `builtin` is set, plus `extra`, minus `ignore`.
+    pub fn new(builtin: bool, extra: &HashMap<String, String>, ignore: &[String]) -> Self {
+        let builtin = DEFAULT_EXPANSIONS
+            .iter()
+            .filter(|_| builtin)
+            .map(|(short, long)| (short.to_string(), long.to_string()));
+        let mut table: HashMap<_, _> = builtin
+            .chain(
+                extra
+                    .iter()
+                    .map(|(short, long)| (short.to_lowercase(), long.clone())),
+            )
+            .collect();
+        for short in ignore {
+            table.remove(&short.to_lowercase());
+        }
+        Self(table)
+    }
+}
+
+impl Filter for Expansions {
+    fn name(&self) -> &'static str {
+        "expansions"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let links = urls::find_urls(&text);
+        let mut out = String::with_capacity(text.len());
+        let mut word_start = None;
+        // the extra space flushes a word at the end of the text
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (word_start, c.is_alphanumeric()) {
+                (None, true) => word_start = Some(i),
+                (Some(start), false) => {
+                    let word = &text[start..i];
+                    let in_link = links.iter().any(|link| link.contains(&start));
+                    match self.0.get(&word.to_lowercase()) {
+                        Some(long) if !in_link => out.push_str(long),
+                        _ => out.push_str(word),
+                    }
+                    word_start = None;
+                }
+                _ => {}
+            }
+            if word_start.is_none() && i < text.len() {
+                out.push(c);
+            }
+        }
+        Verdict::Speak(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn expand(expansions: &Expansions, text: &str) -> String {
+        match expansions.apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn text_without_abbreviations_is_unchanged() {
+        let expansions = Expansions::new(true, &HashMap::default(), &[]);
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = testing::text(&mut rng, 100);
+            let is_short = |word: &str| expansions.0.contains_key(&word.to_lowercase());
+            if text.split(|c: char| !c.is_alphanumeric()).any(is_short) {
+                continue;
+            }
+            let once = expand(&expansions, &text);
+            assert_eq!(once, text);
+            assert_eq!(expand(&expansions, &once), once);
+        }
+    }
+
+    #[test]
+    fn expands_whole_words_only() {
+        let expansions = Expansions::new(true, &HashMap::default(), &["gg".into()]);
+        assert_eq!(
+            expand(&expansions, "BRB, idk!"),
+            "be right back, I don't know!"
+        );
+        assert_eq!(expand(&expansions, "brbx tyvm"), "brbx thank you very much");
+        assert_eq!(expand(&expansions, "gg"), "gg");
+    }
+
+    #[test]
+    fn leaves_links_alone() {
+        let expansions = Expansions::new(true, &HashMap::default(), &[]);
+        assert_eq!(
+            expand(&expansions, "fr see example.com/fr/dm, dm me"),
+            "for real see example.com/fr/dm, direct message me"
+        );
+        assert_eq!(
+            expand(&expansions, "(https://btw.example.org/?q=idk) btw"),
+            "(https://btw.example.org/?q=idk) by the way"
+        );
+    }
+}
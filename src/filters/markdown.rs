@@ -0,0 +1,372 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Filter, Verdict};
+
+/// Paired inline markers, longest first so `**` is not taken for two `*`.
+const EMPHASIS: &[&str] = &["**", "__", "~~", "||", "*", "_"];
+
+/// How bullet points are spoken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BulletMode {
+    /// Each item ends with a pause.
+    #[default]
+    Pause,
+    /// Items after the first start with "next,".
+    Next,
+}
+
+/// How fenced code blocks are spoken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeBlockMode {
+    /// The block is left out.
+    Drop,
+    /// The block is replaced with "code snippet".
+    #[default]
+    Describe,
+}
+
+/// Removes Markdown and Discord formatting, keeping the formatted text.
+pub struct Markdown {
+    bullets: BulletMode,
+    code_blocks: CodeBlockMode,
+}
+
+impl Markdown {
+    pub fn new(bullets: BulletMode, code_blocks: CodeBlockMode) -> Self {
+        Self {
+            bullets,
+            code_blocks,
+        }
+    }
+
+    /// Handles code blocks, quotes, headings, rules and bullets, then the inline
+    /// formatting of each line.
+    fn blocks(&self, text: &str) -> String {
+        let mut lines = Vec::new();
+        let mut fence: Option<&str> = None;
+        let mut in_list = false;
+        for line in text.lines() {
+            let trimmed = line.trim_start();
+            if let Some(open) = fence {
+                if trimmed.trim_end() == open {
+                    fence = None;
+                }
+                continue;
+            }
+            if let Some(open) = ["```", "~~~"].into_iter().find(|f| trimmed.starts_with(f)) {
+                // a closing fence on the same line makes it inline code
+                if !trimmed[3..].contains(open) {
+                    fence = Some(open);
+                    if self.code_blocks == CodeBlockMode::Describe {
+                        lines.push("code snippet.".to_owned());
+                    }
+                    in_list = false;
+                    continue;
+                }
+            }
+            let mut line = line;
+            if let Some(rest) = trimmed.strip_prefix('>') {
+                // not `>_<`
+                if rest.is_empty() || rest.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+                    line = trimmed.trim_start_matches(|c: char| c == '>' || c.is_whitespace());
+                }
+            }
+            let trimmed = line.trim();
+            if is_rule(trimmed) {
+                continue;
+            }
+            if let Some(heading) = heading(trimmed) {
+                lines.push(end_sentence(&inline(heading)));
+                in_list = false;
+                continue;
+            }
+            match trimmed
+                .strip_prefix(['-', '*', '+'])
+                .filter(|item| item.starts_with(char::is_whitespace))
+                .map(str::trim)
+            {
+                Some(item) if !item.is_empty() => {
+                    let item = end_sentence(&inline(item));
+                    lines.push(match self.bullets {
+                        BulletMode::Next if in_list => format!("next, {item}"),
+                        _ => item,
+                    });
+                    in_list = true;
+                }
+                _ => {
+                    lines.push(inline(line));
+                    in_list = false;
+                }
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Filter for Markdown {
+    fn name(&self) -> &'static str {
+        "markdown"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        Verdict::Speak(self.blocks(&text))
+    }
+}
+
+/// A line made only of three or more `-`, `*` or `_`.
+fn is_rule(line: &str) -> bool {
+    ['-', '*', '_'].into_iter().any(|mark| {
+        line.chars().filter(|&c| c == mark).count() >= 3
+            && line.chars().all(|c| c == mark || c == ' ')
+    })
+}
+
+/// The text of an ATX heading such as `## Title ##`.
+fn heading(line: &str) -> Option<&str> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let title = &line[level..];
+    ((1..=6).contains(&level) && title.starts_with(char::is_whitespace))
+        .then(|| title.trim().trim_end_matches('#').trim_end())
+        .filter(|title| !title.is_empty())
+}
+
+/// `text` with a full stop, unless it already ends with a pause.
+fn end_sentence(text: &str) -> String {
+    if text.ends_with(['.', '!', '?', ',', ';', ':']) {
+        text.to_owned()
+    } else {
+        format!("{text}.")
+    }
+}
+
+/// Removes backslash escapes, code span backticks and paired emphasis markers.
+fn inline(text: &str) -> String {
+    // characters that must be kept as they are
+    let mut chars: Vec<(char, bool)> = Vec::with_capacity(text.len());
+    for word in text.split_inclusive(char::is_whitespace) {
+        // links are left to the url filter
+        if word.contains("://") {
+            chars.extend(word.chars().map(|c| (c, true)));
+            continue;
+        }
+        let mut rest = word.chars().peekable();
+        while let Some(c) = rest.next() {
+            match rest.peek() {
+                Some(&next) if c == '\\' && next.is_ascii_punctuation() => {
+                    chars.push((next, true));
+                    rest.next();
+                }
+                _ => chars.push((c, false)),
+            }
+        }
+    }
+    code_spans(&mut chars);
+    for marker in EMPHASIS {
+        emphasis(&mut chars, marker);
+    }
+    chars.into_iter().map(|(c, _)| c).collect()
+}
+
+/// Length of the run of unescaped `mark` starting at `at`.
+fn run(chars: &[(char, bool)], at: usize, mark: char) -> usize {
+    chars[at..]
+        .iter()
+        .take_while(|&&(c, literal)| c == mark && !literal)
+        .count()
+}
+
+/// Replaces `` `code` `` with its text. The closing run of backticks must be as long
+/// as the opening one; unmatched backticks stay.
+fn code_spans(chars: &mut Vec<(char, bool)>) {
+    let mut i = 0;
+    while i < chars.len() {
+        let open = run(chars, i, '`');
+        if open == 0 {
+            i += 1;
+            continue;
+        }
+        let mut j = i + open;
+        let close = loop {
+            if j >= chars.len() {
+                break None;
+            }
+            match run(chars, j, '`') {
+                0 => j += 1,
+                len if len == open => break Some(j),
+                len => j += len,
+            }
+        };
+        match close {
+            Some(j) if j > i + open => {
+                for c in &mut chars[i + open..j] {
+                    c.1 = true;
+                }
+                chars.drain(j..j + open);
+                chars.drain(i..i + open);
+                i = j - open;
+            }
+            _ => i += open,
+        }
+    }
+}
+
+/// Removes pairs of `marker` around text. An opening marker must not follow a letter
+/// or digit and must be followed by text; a closing one must follow text and must not
+/// be followed by a letter or digit. So `*this*` is stripped but `2*3*4`, `a * b * c`
+/// and `snake_case_name` are not.
+fn emphasis(chars: &mut Vec<(char, bool)>, marker: &str) {
+    let mark = marker.chars().next().unwrap();
+    let len = marker.len();
+    // `**` may be the inside of `***`, but `*` must stand alone
+    let fits = |run: usize| if len == 1 { run == 1 } else { run >= len };
+    let mut i = 0;
+    while i < chars.len() {
+        let open = run(chars, i, mark);
+        if open == 0 {
+            i += 1;
+            continue;
+        }
+        let before = i.checked_sub(1).map(|at| chars[at].0);
+        let after = chars.get(i + open).map(|&(c, _)| c);
+        let opens = fits(open)
+            && !before.is_some_and(char::is_alphanumeric)
+            && after.is_some_and(|c| !c.is_whitespace());
+        if !opens {
+            i += open;
+            continue;
+        }
+        // the marker next to the text, so `***a***` becomes `*a*`
+        let start = i + open - len;
+        let mut j = i + open;
+        let mut close = None;
+        while j < chars.len() {
+            let found = run(chars, j, mark);
+            if found == 0 {
+                j += 1;
+                continue;
+            }
+            let before = chars[j - 1].0;
+            let after = chars.get(j + found).map(|&(c, _)| c);
+            if fits(found) && !before.is_whitespace() && !after.is_some_and(char::is_alphanumeric) {
+                close = Some(j);
+                break;
+            }
+            j += found;
+        }
+        match close {
+            Some(j) => {
+                chars.drain(j..j + len);
+                chars.drain(start..start + len);
+                i = start;
+            }
+            None => i += open,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn strip(text: &str) -> String {
+        strip_with(BulletMode::Pause, CodeBlockMode::Describe, text)
+    }
+
+    fn strip_with(bullets: BulletMode, code_blocks: CodeBlockMode, text: &str) -> String {
+        match Markdown::new(bullets, code_blocks).apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn text_without_markdown_is_unchanged() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = testing::text(&mut rng, 100).replace('\n', " ");
+            if text.trim_start().starts_with('-') {
+                continue;
+            }
+            assert_eq!(strip(&text), text);
+        }
+    }
+
+    #[test]
+    fn strips_inline_formatting() {
+        for (text, spoken) in [
+            ("this is **important**", "this is important"),
+            ("*really* and _truly_", "really and truly"),
+            ("~~old~~ new", "old new"),
+            ("__underlined__ ||spoiler||", "underlined spoiler"),
+            ("run `cargo test` now", "run cargo test now"),
+            ("``a `tick` inside``", "a `tick` inside"),
+            ("**bold *and italic* text**", "bold and italic text"),
+            ("***both***", "both"),
+            ("**bold** and *italic*!", "bold and italic!"),
+            (r"\*not italic\*", "*not italic*"),
+        ] {
+            assert_eq!(strip(text), spoken, "{text}");
+        }
+    }
+
+    #[test]
+    fn leaves_unpaired_markers() {
+        for text in [
+            "2*3*4 = 24",
+            "a * b * c",
+            "x ** 2",
+            "snake_case_name",
+            "**bold*",
+            "*dangling",
+            "`unclosed code",
+            "5 * (3 + 4)*",
+            "the *-key",
+            "see https://example.com/_path_",
+            "||",
+        ] {
+            assert_eq!(strip(text), text, "{text}");
+        }
+    }
+
+    #[test]
+    fn strips_quotes_headings_and_rules() {
+        assert_eq!(strip("> quoted\n>> nested"), "quoted\nnested");
+        assert_eq!(strip(">_< oops"), ">_< oops");
+        assert_eq!(strip("> > twice"), "twice");
+        assert_eq!(strip("## Title ##\ntext"), "Title.\ntext");
+        assert_eq!(strip("# **Big** news"), "Big news.");
+        assert_eq!(strip("#hashtag"), "#hashtag");
+        assert_eq!(strip("above\n---\nbelow"), "above\nbelow");
+        assert_eq!(strip("5 > 3"), "5 > 3");
+    }
+
+    #[test]
+    fn reads_bullets() {
+        let list = "Shopping:\n- eggs\n* **milk**\n+ bread!";
+        assert_eq!(strip(list), "Shopping:\neggs.\nmilk.\nbread!");
+        assert_eq!(
+            strip_with(BulletMode::Next, CodeBlockMode::Describe, list),
+            "Shopping:\neggs.\nnext, milk.\nnext, bread!"
+        );
+        assert_eq!(strip("-5 degrees"), "-5 degrees");
+    }
+
+    #[test]
+    fn replaces_code_blocks() {
+        let text = "look:\n```rust\nfn main() {}\n```\nneat";
+        assert_eq!(strip(text), "look:\ncode snippet.\nneat");
+        assert_eq!(
+            strip_with(BulletMode::Pause, CodeBlockMode::Drop, text),
+            "look:\nneat"
+        );
+        // an unclosed block runs to the end
+        assert_eq!(
+            strip_with(BulletMode::Pause, CodeBlockMode::Drop, "a\n~~~\nb *c*"),
+            "a"
+        );
+        assert_eq!(strip("```inline```"), "inline");
+    }
+}
@@ -0,0 +1,202 @@
+//! Text transformations applied to every message before it is synthesized.
+
+pub mod blocklist;
+pub mod caps;
+pub mod emoji;
+pub mod expansions;
+pub mod markdown;
+mod numbers;
+pub mod profanity;
+pub mod repetition;
+pub mod replacements;
+mod sanitize;
+pub mod urls;
+
+use crate::Configuration;
+
+/// What to do with a message once it has been filtered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Speak(String),
+    /// Must not be spoken.
+    Drop {
+        reason: String,
+    },
+    /// May only be spoken once the user confirms it.
+    Confirm {
+        text: String,
+        reason: String,
+    },
+    /// Nothing speakable is left, so there is nothing to synthesize.
+    Empty,
+}
+
+impl Verdict {
+    /// The text that would still be spoken, if any.
+    fn text(&self) -> Option<&str> {
+        match self {
+            Self::Speak(text) | Self::Confirm { text, .. } => Some(text),
+            Self::Drop { .. } | Self::Empty => None,
+        }
+    }
+}
+
+/// One stage of the pipeline.
+trait Filter: Send + Sync {
+    /// Names the filter in logs.
+    fn name(&self) -> &'static str;
+    fn apply(&self, text: String) -> Verdict;
+}
+
+/// The configured filters, in the order they run.
+pub struct Pipeline {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl Pipeline {
+    /// Builds the pipeline for typed messages if `interactive` is set, or for messages
+    /// from the daemon's sources otherwise. Fails if any configured pattern is invalid.
+    pub fn new(config: &Configuration, interactive: bool) -> Result<Self, String> {
+        let mut filters: Vec<Box<dyn Filter>> = Vec::new();
+        if config.sanitize_text {
+            filters.push(Box::new(sanitize::Sanitize::new(config.ascii_punctuation)));
+        }
+        filters.push(Box::new(replacements::Replacements::new(
+            &config.replacements,
+        )?));
+        if config.strip_markdown {
+            filters.push(Box::new(markdown::Markdown::new(
+                config.markdown_bullets,
+                config.markdown_code_blocks,
+            )));
+        }
+        if config.max_repeated_chars > 0
+            || config.repeated_syllables != repetition::SyllableRepeats::Off
+        {
+            filters.push(Box::new(repetition::Repetition::new(config)));
+        }
+        let emoji_mode = if interactive {
+            config.emoji_mode
+        } else {
+            config.external_emoji_mode
+        };
+        if emoji_mode != emoji::EmojiMode::Keep {
+            filters.push(Box::new(emoji::Emoji::new(emoji_mode)));
+        }
+        if config.url_mode != urls::UrlMode::Keep {
+            filters.push(Box::new(urls::Urls::new(config.url_mode)));
+        }
+        filters.push(Box::new(expansions::Expansions::new(
+            config.expand_abbreviations,
+            &config.expansions,
+            &config.expansion_ignore,
+        )));
+        // with SSML, `Input` stresses the words instead
+        let caps = match config.caps_mode {
+            caps::CapsMode::Off => false,
+            caps::CapsMode::Lowercase => true,
+            caps::CapsMode::Emphasis => !config.provider.supports_ssml(),
+        };
+        if caps {
+            filters.push(Box::new(caps::Caps::new(config)));
+        }
+        if config.number_words {
+            match numbers::Numbers::new(&config.gcloud_language, config.number_words_max_digits) {
+                Some(numbers) => filters.push(Box::new(numbers)),
+                None => log::warn!(
+                    "number_words does not support {:?} yet; numbers are left as they are",
+                    config.gcloud_language
+                ),
+            }
+        }
+        if config.profanity_filter.enabled {
+            filters.push(Box::new(profanity::Profanity::new(
+                &config.profanity_filter,
+            )));
+        }
+        if !config.phrase_filter_regex.is_empty() {
+            filters.push(Box::new(blocklist::Blocklist::new(
+                &config.phrase_filter_regex,
+                config.filter_action.clone(),
+                config.log_filtered,
+            )?));
+        }
+        Ok(Self { filters })
+    }
+
+    /// Runs every filter in turn. A dropped message skips the remaining filters; one
+    /// that needs confirmation keeps going so later filters can still drop it.
+    /// Whether anything is left to speak is judged at the end, as filters such as
+    /// the emoji one can turn text with no words into some.
+    pub fn apply(&self, text: &str) -> Verdict {
+        let mut verdict = Verdict::Speak(text.to_owned());
+        // the filter that left nothing to speak, if one did
+        let mut emptied_by = None;
+        for filter in &self.filters {
+            let was_speakable = verdict.text().is_some_and(is_speakable);
+            verdict = match verdict {
+                Verdict::Speak(text) => filter.apply(text),
+                Verdict::Confirm { text, reason } => match filter.apply(text) {
+                    Verdict::Speak(text) | Verdict::Confirm { text, .. } => {
+                        Verdict::Confirm { text, reason }
+                    }
+                    drop => drop,
+                },
+                done @ (Verdict::Drop { .. } | Verdict::Empty) => return done,
+            };
+            log::trace!("after the {} filter: {verdict:?}", filter.name());
+            match verdict.text().map(is_speakable) {
+                Some(false) if was_speakable => emptied_by = Some(filter.name()),
+                Some(true) => emptied_by = None,
+                _ => {}
+            }
+        }
+        match verdict {
+            Verdict::Speak(text) | Verdict::Confirm { text, .. } if !is_speakable(&text) => {
+                match emptied_by {
+                    Some(filter) => log::debug!(
+                        "skipping message with nothing to speak after the {filter} filter: {text:?}"
+                    ),
+                    None => log::debug!("skipping message with nothing to speak: {text:?}"),
+                }
+                Verdict::Empty
+            }
+            verdict => verdict,
+        }
+    }
+}
+
+/// Whether `text` has anything to say besides whitespace, punctuation and emoji.
+pub fn is_speakable(text: &str) -> bool {
+    text.chars().any(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(emoji_mode: emoji::EmojiMode) -> Pipeline {
+        let config = Configuration {
+            sanitize_text: true,
+            emoji_mode,
+            ..crate::config::valid_config()
+        };
+        Pipeline::new(&config, true).unwrap()
+    }
+
+    #[test]
+    fn speaks_emoji_only_messages_the_emoji_filter_names() {
+        assert_eq!(
+            pipeline(emoji::EmojiMode::Name).apply("🔥🔥🔥"),
+            Verdict::Speak("fire".into())
+        );
+        assert_eq!(
+            pipeline(emoji::EmojiMode::Strip).apply("🔥🔥🔥"),
+            Verdict::Empty
+        );
+        assert_eq!(
+            pipeline(emoji::EmojiMode::Keep).apply(" ... "),
+            Verdict::Empty
+        );
+    }
+}
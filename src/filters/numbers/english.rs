@@ -0,0 +1,173 @@
+use super::Speller;
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const SCALES: [&str; 7] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+pub struct English;
+
+impl English {
+    /// Spells out 0 to 999.
+    fn hundreds(n: u64) -> String {
+        let (hundreds, rest) = (n / 100, n % 100);
+        let rest = match rest {
+            0 => String::new(),
+            1..=19 => ONES[rest as usize].to_owned(),
+            _ if rest % 10 == 0 => TENS[(rest / 10) as usize].to_owned(),
+            _ => format!(
+                "{}-{}",
+                TENS[(rest / 10) as usize],
+                ONES[(rest % 10) as usize]
+            ),
+        };
+        match (hundreds, rest.is_empty()) {
+            (0, _) => rest,
+            (_, true) => format!("{} hundred", ONES[hundreds as usize]),
+            (_, false) => format!("{} hundred {rest}", ONES[hundreds as usize]),
+        }
+    }
+}
+
+impl Speller for English {
+    fn cardinal(&self, mut n: u64) -> String {
+        if n == 0 {
+            return ONES[0].to_owned();
+        }
+        let mut groups = Vec::new();
+        for scale in SCALES {
+            if n == 0 {
+                break;
+            }
+            let group = n % 1000;
+            if group != 0 {
+                let words = Self::hundreds(group);
+                groups.push(if scale.is_empty() {
+                    words
+                } else {
+                    format!("{words} {scale}")
+                });
+            }
+            n /= 1000;
+        }
+        groups.reverse();
+        groups.join(" ")
+    }
+
+    fn ordinal(&self, n: u64) -> String {
+        let cardinal = self.cardinal(n);
+        let split = cardinal.rfind([' ', '-']).map_or(0, |i| i + 1);
+        let (head, last) = cardinal.split_at(split);
+        let last = match last {
+            "one" => "first".to_owned(),
+            "two" => "second".to_owned(),
+            "three" => "third".to_owned(),
+            "five" => "fifth".to_owned(),
+            "eight" => "eighth".to_owned(),
+            "nine" => "ninth".to_owned(),
+            "twelve" => "twelfth".to_owned(),
+            _ => match last.strip_suffix('y') {
+                Some(stem) => format!("{stem}ieth"),
+                None => format!("{last}th"),
+            },
+        };
+        format!("{head}{last}")
+    }
+
+    fn digit(&self, d: u8) -> &'static str {
+        ONES[usize::from(d % 10)]
+    }
+
+    fn decimal_point(&self) -> &'static str {
+        "point"
+    }
+
+    fn negative(&self) -> &'static str {
+        "negative"
+    }
+
+    fn scientific(&self, mantissa: &str, power: &str) -> String {
+        format!("{mantissa} times ten to the {power}")
+    }
+
+    fn year(&self, year: u64) -> String {
+        let (century, rest) = (year / 100, year % 100);
+        match (century, rest) {
+            // "two thousand", "two thousand five"
+            _ if !(1000..=9999).contains(&year)
+                || year.is_multiple_of(1000)
+                || (year / 1000 == 2 && rest < 10) =>
+            {
+                self.cardinal(year)
+            }
+            (_, 0) => format!("{} hundred", self.cardinal(century)),
+            (_, 1..=9) => format!("{} oh {}", self.cardinal(century), ONES[rest as usize]),
+            _ => format!("{} {}", self.cardinal(century), self.cardinal(rest)),
+        }
+    }
+
+    fn date(&self, year: u64, month: u8, day: u8) -> String {
+        format!(
+            "{} {}, {}",
+            MONTHS[usize::from(month - 1)],
+            self.ordinal(day.into()),
+            self.year(year)
+        )
+    }
+
+    fn time(&self, hour: u8, minute: u8) -> String {
+        let hour = self.cardinal(hour.into());
+        match minute {
+            0 => format!("{hour} o'clock"),
+            1..=9 => format!("{hour} oh {}", ONES[usize::from(minute)]),
+            _ => format!("{hour} {}", self.cardinal(minute.into())),
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Spells out numbers, dates and times for voices that read them badly.
+//!
+//! Decimals read the way people say them: "3.14" is "three point fourteen", but
+//! fractions of more than two digits, or starting with zero, go digit by digit.
+
+mod english;
+
+use regex::{Captures, Regex};
+
+use super::{Filter, Verdict};
+
+/// Number words for one language.
+trait Speller: Send + Sync {
+    fn cardinal(&self, n: u64) -> String;
+    fn ordinal(&self, n: u64) -> String;
+    fn digit(&self, d: u8) -> &'static str;
+    fn decimal_point(&self) -> &'static str;
+    /// The word before a number below zero.
+    fn negative(&self) -> &'static str;
+    /// `mantissa` times ten to the `power`, which is an ordinal.
+    fn scientific(&self, mantissa: &str, power: &str) -> String;
+    fn year(&self, year: u64) -> String;
+    fn date(&self, year: u64, month: u8, day: u8) -> String;
+    fn time(&self, hour: u8, minute: u8) -> String;
+}
+
+/// The speller for a BCP 47 language code such as "en-US", if there is one.
+fn speller(language: &str) -> Option<Box<dyn Speller>> {
+    match language
+        .split(['-', '_'])
+        .next()?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "en" => Some(Box::new(english::English)),
+        _ => None,
+    }
+}
+
+pub struct Numbers {
+    speller: Box<dyn Speller>,
+    /// Numbers with more digits than this, like phone numbers, are left alone.
+    max_digits: usize,
+    pattern: Regex,
+}
+
+impl Numbers {
+    /// Returns `None` if there are no number words for `language`.
+    pub fn new(language: &str, max_digits: usize) -> Option<Self> {
+        let pattern = Regex::new(concat!(
+            r"\b(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})\b",
+            r"|\b(?P<hour>\d{1,2}):(?P<minute>\d{2})\b",
+            r"|\b(?P<ordinal>\d+)(?:st|nd|rd|th)\b",
+            r"|(?P<minus>-)?\b(?P<int>\d{1,3}(?:,\d{3})+|\d+)(?:\.(?P<fraction>\d+))?",
+            r"(?:[eE](?P<exponent>[-+]?\d{1,3}))?\b",
+        ))
+        .unwrap();
+        Some(Self {
+            speller: speller(language)?,
+            max_digits,
+            pattern,
+        })
+    }
+
+    fn spell(&self, caps: &Captures) -> Option<String> {
+        let speller = &self.speller;
+        let number = |name| caps.name(name).map(|m| m.as_str());
+        if let (Some(year), Some(month), Some(day)) =
+            (number("year"), number("month"), number("day"))
+        {
+            let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+            if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+                return None;
+            }
+            return Some(speller.date(year.parse().ok()?, month, day));
+        }
+        if let (Some(hour), Some(minute)) = (number("hour"), number("minute")) {
+            let (hour, minute) = (hour.parse().ok()?, minute.parse().ok()?);
+            return (hour < 24 && minute < 60).then(|| speller.time(hour, minute));
+        }
+        if let Some(ordinal) = number("ordinal") {
+            if ordinal.len() > self.max_digits {
+                return None;
+            }
+            return Some(speller.ordinal(ordinal.parse().ok()?));
+        }
+        let int = number("int")?.replace(',', "");
+        if int.len() > self.max_digits {
+            return None;
+        }
+        let mut words = speller.cardinal(int.parse().ok()?);
+        if let Some(fraction) = number("fraction") {
+            words.push(' ');
+            words.push_str(speller.decimal_point());
+            if fraction.len() <= 2 && !fraction.starts_with('0') {
+                words.push(' ');
+                words.push_str(&speller.cardinal(fraction.parse().ok()?));
+            } else {
+                for digit in fraction.bytes() {
+                    words.push(' ');
+                    words.push_str(speller.digit(digit - b'0'));
+                }
+            }
+        }
+        if number("minus").is_some() {
+            words = format!("{} {words}", speller.negative());
+        }
+        if let Some(exponent) = number("exponent") {
+            let power = match exponent.strip_prefix('-') {
+                Some(exponent) => format!(
+                    "{} {}",
+                    speller.negative(),
+                    speller.ordinal(exponent.parse().ok()?)
+                ),
+                None => speller.ordinal(exponent.trim_start_matches('+').parse().ok()?),
+            };
+            words = speller.scientific(&words, &power);
+        }
+        Some(words)
+    }
+}
+
+impl Filter for Numbers {
+    fn name(&self) -> &'static str {
+        "number words"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let spelled = self.pattern.replace_all(&text, |caps: &Captures| {
+            let whole = caps.get(0).unwrap();
+            let original = whole.as_str().to_owned();
+            // a minus sign after a word is a hyphen, as in "555-1234"
+            let start = caps.name("int").map_or(whole.start(), |int| int.start());
+            if in_url_or_code(&text, start, whole.end()) {
+                return original;
+            }
+            self.spell(caps).unwrap_or(original)
+        });
+        Verdict::Speak(spelled.into_owned())
+    }
+}
+
+/// Whether the match at `start..end` is part of a link, a version number, a phone
+/// number or similar, where spelling it out would hurt.
+fn in_url_or_code(text: &str, start: usize, end: usize) -> bool {
+    let word_start = text[..start]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + 1);
+    let word_end = text[end..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |i| end + i);
+    let word = &text[word_start..word_end];
+    if word.contains("://") || word.starts_with("www.") || word.contains('@') {
+        return true;
+    }
+    let joins = |c: char| matches!(c, '.' | ',' | ':' | '/' | '-' | '_');
+    let mut before = text[..start].chars().rev();
+    let mut after = text[end..].chars();
+    // "1.2.3", "555-1234", "$5" but not "5." at the end of a sentence
+    let glued_before = match (before.next(), before.next()) {
+        (Some('$' | '#' | '@' | '%'), _) => true,
+        (Some(c), Some(d)) => joins(c) && d.is_alphanumeric(),
+        _ => false,
+    };
+    let glued_after = match (after.next(), after.next()) {
+        (Some('%'), _) => true,
+        (Some(c), Some(d)) => joins(c) && d.is_alphanumeric(),
+        _ => false,
+    };
+    glued_before || glued_after
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use rand::Rng;
+
+    use super::*;
+    use crate::testing;
+
+    fn spell(text: &str) -> String {
+        static NUMBERS: OnceLock<Numbers> = OnceLock::new();
+        let numbers = NUMBERS.get_or_init(|| Numbers::new("en-US", 6).unwrap());
+        match numbers.apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn spells_out_numbers_dates_and_times() {
+        for (text, spoken) in [
+            ("1337", "one thousand three hundred thirty-seven"),
+            ("0", "zero"),
+            ("3.14", "three point fourteen"),
+            ("1,000 people", "one thousand people"),
+            ("the 21st", "the twenty-first"),
+            ("on 2024-06-01", "on June first, twenty twenty-four"),
+            ("1999-12-31", "December thirty-first, nineteen ninety-nine"),
+            ("at 9:05", "at nine oh five"),
+            ("at 12:00", "at twelve o'clock"),
+            ("14:30", "fourteen thirty"),
+            ("it is 12.", "it is twelve."),
+        ] {
+            assert_eq!(spell(text), spoken, "{text}");
+        }
+    }
+
+    #[test]
+    fn reads_decimals_negatives_and_scientific_notation() {
+        for (text, spoken) in [
+            ("0.5", "zero point five"),
+            ("2.50", "two point fifty"),
+            ("99.99", "ninety-nine point ninety-nine"),
+            ("0.10", "zero point ten"),
+            ("1.0", "one point zero"),
+            ("3.05", "three point zero five"),
+            ("10.01", "ten point zero one"),
+            ("3.142", "three point one four two"),
+            ("0.001", "zero point zero zero one"),
+            (
+                "pi is 3.14159.",
+                "pi is three point one four one five nine.",
+            ),
+            ("1,234.5", "one thousand two hundred thirty-four point five"),
+            ("-3.14", "negative three point fourteen"),
+            ("-0.5", "negative zero point five"),
+            ("-7", "negative seven"),
+            (
+                "it fell -2.5 degrees",
+                "it fell negative two point five degrees",
+            ),
+            ("1.5e-3", "one point five times ten to the negative third"),
+            (
+                "-1.5e-3",
+                "negative one point five times ten to the negative third",
+            ),
+            ("1e6", "one times ten to the sixth"),
+            (
+                "6.02E23",
+                "six point zero two times ten to the twenty-third",
+            ),
+            ("2.5e+2", "two point five times ten to the second"),
+            ("555-1234", "555-1234"),
+            ("well-5.5", "well-5.5"),
+            ("1.5em", "1.5em"),
+            ("3.14.15", "3.14.15"),
+            ("0x1e3", "0x1e3"),
+        ] {
+            assert_eq!(spell(text), spoken, "{text}");
+        }
+    }
+
+    #[test]
+    fn leaves_links_versions_and_long_numbers_alone() {
+        for text in [
+            "4k video",
+            "v1.2.3",
+            "call 5551234567",
+            "555-1234",
+            "https://example.com/123",
+            "$5",
+            "50%",
+            "2024-13-01",
+            "25:00",
+        ] {
+            assert_eq!(spell(text), text);
+        }
+    }
+
+    #[test]
+    fn short_numbers_between_words_become_words() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let words: Vec<String> = (0..rng.gen_range(1..8))
+                .map(|_| match rng.gen_bool(0.5) {
+                    true => rng.gen_range(0..1_000_000u64).to_string(),
+                    false => testing::word(&mut rng),
+                })
+                .collect();
+            let text = words.join(" ");
+            let spoken = spell(&text);
+            assert!(
+                !spoken.contains(|c: char| c.is_ascii_digit()),
+                "{text:?} -> {spoken:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn no_long_run_of_digits_is_spelled_out() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let digits = rng.gen_range(7..16);
+            let number: String = (0..digits).map(|_| rng.gen_range('0'..='9')).collect();
+            let text = format!("call {number} now");
+            assert_eq!(spell(&text), text);
+        }
+    }
+}
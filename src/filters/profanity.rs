@@ -0,0 +1,292 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Range,
+};
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{Filter, Verdict};
+use crate::history;
+
+const DEFAULT_WORDS: &str = include_str!("profanity.txt");
+
+/// The only ending matched without being listed, as stripping -y, -ed or -er from
+/// any word would flag "spicy", "spiced" and "cocker". Other inflections are
+/// listed with their word.
+const SUFFIX: &str = "s";
+
+/// What happens to a message containing a listed word.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfanityAction {
+    /// Each matching word is replaced with `replacement`.
+    #[default]
+    Replace,
+    /// The whole message is dropped and logged to `dropped.tsv`.
+    Drop,
+    /// The overlay asks for a second Enter before speaking. Daemon mode has no one
+    /// to ask, so it drops the message instead.
+    Confirm,
+}
+
+/// The `[profanity_filter]` table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ProfanityConfig {
+    pub enabled: bool,
+    pub action: ProfanityAction,
+    pub replacement: String,
+    /// Words to match on top of the built-in list, each optionally followed by
+    /// its inflections, like "frack fracking fracked".
+    pub add: Vec<String>,
+    /// Built-in words that should not be matched, with their inflections.
+    pub remove: Vec<String>,
+}
+
+impl Default for ProfanityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: ProfanityAction::default(),
+            replacement: "bleep".into(),
+            add: Vec::new(),
+            remove: Vec::new(),
+        }
+    }
+}
+
+pub struct Profanity {
+    /// Each form to match, with the list entry it is a form of.
+    words: HashMap<String, String>,
+    action: ProfanityAction,
+    replacement: String,
+    /// Where dropped messages go, `history::record_dropped` outside of tests.
+    record_dropped: fn(&str, &str),
+}
+
+impl Profanity {
+    pub fn new(config: &ProfanityConfig) -> Self {
+        let removed: HashSet<_> = config.remove.iter().map(|word| normalize(word)).collect();
+        let mut words = HashMap::new();
+        for line in DEFAULT_WORDS
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .chain(config.add.iter().map(String::as_str))
+        {
+            let mut forms = line.split_whitespace().map(normalize);
+            let Some(word) = forms.next().filter(|word| !word.is_empty()) else {
+                continue;
+            };
+            if removed.contains(&word) {
+                continue;
+            }
+            for form in forms.filter(|form| !form.is_empty()) {
+                words.insert(form, word.clone());
+            }
+            words.insert(word.clone(), word);
+        }
+        Self {
+            words,
+            action: config.action,
+            replacement: config.replacement.clone(),
+            record_dropped: history::record_dropped,
+        }
+    }
+
+    /// Finds listed words in `text`, returning the byte range to censor and the
+    /// list entry it matched.
+    fn find(&self, text: &str) -> Vec<(Range<usize>, String)> {
+        words(text)
+            .filter_map(|range| {
+                let token = &text[range.clone()];
+                // "sh!t!" only matches once the trailing "!" is dropped, "a$$" only with it
+                let trimmed = token.trim_end_matches(|c: char| !c.is_alphanumeric());
+                for (candidate, len) in [(token, token.len()), (trimmed, trimmed.len())] {
+                    if let Some(word) = self.lookup(&normalize(candidate)) {
+                        return Some((range.start..range.start + len, word));
+                    }
+                }
+                None
+            })
+            .collect()
+    }
+
+    fn lookup(&self, word: &str) -> Option<String> {
+        self.words
+            .get(word)
+            .or_else(|| self.words.get(word.strip_suffix(SUFFIX)?))
+            .cloned()
+    }
+}
+
+impl Filter for Profanity {
+    fn name(&self) -> &'static str {
+        "profanity"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let found = self.find(&text);
+        if found.is_empty() {
+            return Verdict::Speak(text);
+        }
+        // the reason ends up in the overlay and in dropped.tsv, so it doesn't
+        // repeat the words
+        let matched: BTreeSet<_> = found.iter().map(|(_, word)| word).collect();
+        let reason = match matched.len() {
+            1 => "contains a filtered word".to_owned(),
+            n => format!("contains {n} filtered words"),
+        };
+        match self.action {
+            ProfanityAction::Replace => {
+                let mut out = String::with_capacity(text.len());
+                let mut end = 0;
+                for (range, _) in &found {
+                    out.push_str(&text[end..range.start]);
+                    out.push_str(&self.replacement);
+                    end = range.end;
+                }
+                out.push_str(&text[end..]);
+                Verdict::Speak(out)
+            }
+            ProfanityAction::Drop => {
+                log::info!("dropped message that {reason}");
+                (self.record_dropped)(&reason, &text);
+                Verdict::Drop { reason }
+            }
+            ProfanityAction::Confirm => Verdict::Confirm { text, reason },
+        }
+    }
+}
+
+/// Splits `text` into words. Dots, dashes and symbols used in leetspeak stay inside
+/// a word so "f.u.c.k" and "$h1t" are checked whole; matching whole words keeps
+/// "Scunthorpe" and "classic" from being flagged.
+fn words(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let is_boundary = |c: char| c.is_whitespace() || ",;:/()[]{}<>\"?".contains(c);
+    let mut start = None;
+    text.char_indices()
+        .chain([(text.len(), ' ')])
+        .filter_map(move |(i, c)| match (start, is_boundary(c)) {
+            (None, false) => {
+                start = Some(i);
+                None
+            }
+            (Some(s), true) => {
+                start = None;
+                Some(s..i)
+            }
+            _ => None,
+        })
+}
+
+/// Folds case, accents, compatibility forms and leetspeak, then keeps only letters.
+fn normalize(word: &str) -> String {
+    word.nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' | '|' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' | '+' => 't',
+            '8' => 'b',
+            c => c,
+        })
+        .filter(|c| c.is_alphabetic())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(action: ProfanityAction) -> Profanity {
+        Profanity {
+            // not into dropped.tsv in the working directory
+            record_dropped: |_, _| {},
+            ..Profanity::new(&ProfanityConfig {
+                enabled: true,
+                action,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn replaced(text: &str) -> String {
+        match filter(ProfanityAction::Replace).apply(text.into()) {
+            Verdict::Speak(text) => text,
+            other => panic!("{text}: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn leaves_words_that_only_contain_a_listed_word() {
+        for text in [
+            "Scunthorpe United",
+            "a classic assessment",
+            "spicy food, spiced rum",
+            "the cocker spaniel",
+            "Dickens and Cockburn",
+            "shiitake",
+        ] {
+            assert_eq!(replaced(text), text);
+        }
+    }
+
+    #[test]
+    fn matches_leetspeak_and_punctuation() {
+        assert_eq!(replaced("$h1t!"), "bleep!");
+        assert_eq!(replaced("f.u.c.k this"), "bleep this");
+        assert_eq!(replaced("what a$$"), "what bleep");
+        assert_eq!(replaced("SHÍT"), "bleep");
+    }
+
+    #[test]
+    fn matches_listed_inflections_and_plurals() {
+        assert_eq!(replaced("fucking shits, bitches"), "bleep bleep, bleep");
+        assert_eq!(replaced("so shitty"), "so bleep");
+        assert_eq!(replaced("pricks"), "bleep");
+        // inflections that are not listed are left alone
+        assert_eq!(replaced("spics spicing"), "bleep spicing");
+    }
+
+    #[test]
+    fn add_and_remove_work_on_whole_entries() {
+        let filter = Profanity::new(&ProfanityConfig {
+            enabled: true,
+            add: vec!["frack fracking".into()],
+            remove: vec!["shit".into()],
+            ..Default::default()
+        });
+        assert_eq!(
+            filter.apply("fracking shitty shit".into()),
+            Verdict::Speak("bleep shitty shit".into())
+        );
+    }
+
+    #[test]
+    fn drops_or_asks_without_repeating_the_words() {
+        let text = "fuck, FUCK and shit";
+        assert_eq!(
+            filter(ProfanityAction::Confirm).apply(text.into()),
+            Verdict::Confirm {
+                text: text.into(),
+                reason: "contains 2 filtered words".into()
+            }
+        );
+        assert_eq!(
+            filter(ProfanityAction::Drop).apply("oh fuck fuck".into()),
+            Verdict::Drop {
+                reason: "contains a filtered word".into()
+            }
+        );
+        assert_eq!(
+            filter(ProfanityAction::Drop).apply("fine".into()),
+            Verdict::Speak("fine".into())
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use eframe::epaint::ahash::HashSet;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+
+use super::{Filter, Verdict};
+use crate::Configuration;
+
+const ZWJ: char = '\u{200d}';
+
+/// How eagerly repeated syllables such as "hahahaha" are shortened.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SyllableRepeats {
+    /// Syllables are left alone.
+    Off,
+    /// Syllables of two or three characters repeated more than three times are
+    /// spoken twice.
+    #[default]
+    Low,
+    /// Syllables of up to four characters repeated more than twice are spoken twice.
+    High,
+}
+
+impl SyllableRepeats {
+    /// Longest syllable and how often it may repeat before it is shortened.
+    fn limits(self) -> Option<(usize, usize)> {
+        match self {
+            Self::Off => None,
+            Self::Low => Some((3, 3)),
+            Self::High => Some((4, 2)),
+        }
+    }
+}
+
+/// Shortens drawn-out words like "noooooo" and "hahahahaha".
+pub struct Repetition {
+    max_run: usize,
+    syllables: SyllableRepeats,
+    /// Lowercase words that are spelled that way on purpose.
+    protected: HashSet<String>,
+}
+
+impl Repetition {
+    pub fn new(config: &Configuration) -> Self {
+        let replaced = config.replacements.iter().flat_map(|replacement| {
+            let literal = regex::escape(&replacement.pattern) == replacement.pattern;
+            let pattern = literal.then_some(replacement.pattern.as_str());
+            replacement.replace.split_whitespace().chain(pattern)
+        });
+        let protected = config
+            .pronunciations
+            .keys()
+            .map(String::as_str)
+            .chain(replaced)
+            .map(str::to_lowercase)
+            .collect();
+        Self {
+            max_run: config.max_repeated_chars,
+            syllables: config.repeated_syllables,
+            protected,
+        }
+    }
+
+    fn shorten(&self, word: &str) -> String {
+        let mut graphemes = graphemes(word);
+        if self.max_run > 0 {
+            graphemes = collapse_runs(&graphemes, self.max_run);
+        }
+        if let Some((max_len, max_repeats)) = self.syllables.limits() {
+            graphemes = collapse_syllables(&graphemes, max_len, max_repeats);
+        }
+        graphemes.concat()
+    }
+}
+
+impl Filter for Repetition {
+    fn name(&self) -> &'static str {
+        "repetition"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        let mut out = String::with_capacity(text.len());
+        for token in text.split_inclusive(char::is_whitespace) {
+            let word = token.trim_end();
+            let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+            let is_link = word.contains("://") || word.starts_with("www.");
+            if is_link || self.protected.contains(&core.to_lowercase()) {
+                out += token;
+            } else {
+                out += &self.shorten(word);
+                out += &token[word.len()..];
+            }
+        }
+        Verdict::Speak(out)
+    }
+}
+
+/// Splits `text` into user-perceived characters: a character with its combining
+/// marks, variation selectors and skin tones, emoji joined with zero width
+/// joiners, and flags. A close approximation of extended grapheme clusters.
+fn graphemes(text: &str) -> Vec<&str> {
+    let is_flag_half = |c: char| ('\u{1f1e6}'..='\u{1f1ff}').contains(&c);
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    // regional indicators since the last cluster break, which pair up into flags
+    let mut flag_halves = 0;
+    for (i, c) in text.char_indices() {
+        let extends = prev.is_some_and(|prev| {
+            is_combining_mark(c)
+                || c == ZWJ
+                || prev == ZWJ
+                || matches!(c, '\u{fe00}'..='\u{fe0f}' | '\u{1f3fb}'..='\u{1f3ff}' | '\u{e0020}'..='\u{e007f}')
+                || (is_flag_half(c) && is_flag_half(prev) && flag_halves % 2 == 1)
+        });
+        if !extends && i > 0 {
+            out.push(&text[start..i]);
+            start = i;
+        }
+        flag_halves = if is_flag_half(c) { flag_halves + 1 } else { 0 };
+        prev = Some(c);
+    }
+    if start < text.len() {
+        out.push(&text[start..]);
+    }
+    out
+}
+
+/// Shortens runs of the same grapheme to `max`. Digits are left alone, so
+/// 1000000 stays a million.
+fn collapse_runs<'a>(graphemes: &[&'a str], max: usize) -> Vec<&'a str> {
+    let mut out: Vec<&str> = Vec::with_capacity(graphemes.len());
+    let mut run = 0;
+    for (i, grapheme) in graphemes.iter().enumerate() {
+        run = match i.checked_sub(1) {
+            Some(prev) if graphemes[prev] == *grapheme => run + 1,
+            _ => 1,
+        };
+        if run <= max || grapheme.chars().all(|c| c.is_ascii_digit()) {
+            out.push(grapheme);
+        }
+    }
+    out
+}
+
+/// Shortens a syllable of two to `max_len` graphemes, repeated more than
+/// `max_repeats` times in a row, to two repetitions.
+fn collapse_syllables<'a>(
+    graphemes: &[&'a str],
+    max_len: usize,
+    max_repeats: usize,
+) -> Vec<&'a str> {
+    let mut out = Vec::with_capacity(graphemes.len());
+    let mut i = 0;
+    'outer: while i < graphemes.len() {
+        for len in 2..=max_len {
+            let Some(syllable) = graphemes.get(i..i + len) else {
+                break;
+            };
+            let has_letter = syllable
+                .iter()
+                .any(|grapheme| grapheme.chars().any(char::is_alphabetic));
+            if !has_letter || syllable.iter().all(|grapheme| *grapheme == syllable[0]) {
+                continue;
+            }
+            let repeats = graphemes[i..]
+                .chunks_exact(len)
+                .take_while(|chunk| *chunk == syllable)
+                .count();
+            if repeats > max_repeats {
+                out.extend_from_slice(syllable);
+                out.extend_from_slice(syllable);
+                i += repeats * len;
+                continue 'outer;
+            }
+        }
+        out.push(graphemes[i]);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filters::replacements::Replacement, pronunciations::Pronunciation, testing};
+
+    fn filter(syllables: SyllableRepeats) -> Repetition {
+        let mut config = Configuration {
+            max_repeated_chars: 3,
+            repeated_syllables: syllables,
+            ..Default::default()
+        };
+        config
+            .pronunciations
+            .insert("Aaaaron".into(), Pronunciation::Ipa("ˈɛərən".into()));
+        config.replacements.push(Replacement {
+            pattern: "yes".into(),
+            replace: "yesssss".into(),
+            case_insensitive: false,
+        });
+        Repetition::new(&config)
+    }
+
+    fn shorten(syllables: SyllableRepeats, text: &str) -> String {
+        match filter(syllables).apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn text_without_repetition_is_unchanged() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let words: Vec<String> = (0..8).map(|_| testing::word(&mut rng)).collect();
+            let text = words.join(" ");
+            let repeats = graphemes(&text)
+                .windows(4)
+                .any(|window| window.iter().all(|g| *g == window[0]));
+            if repeats {
+                continue;
+            }
+            assert_eq!(shorten(SyllableRepeats::Off, &text), text);
+        }
+    }
+
+    #[test]
+    fn shortens_long_runs() {
+        for (text, shortened) in [
+            ("noooooooo", "nooo"),
+            ("NOOOOO!!!!!!", "NOOO!!!"),
+            ("soooo gooood", "sooo goood"),
+            ("дааааааа", "дааа"),
+            ("😂😂😂😂😂😂", "😂😂😂"),
+            ("👍🏽👍🏽👍🏽👍🏽👍🏽", "👍🏽👍🏽👍🏽"),
+            ("🇺🇸🇺🇸🇺🇸🇺🇸🇺🇸", "🇺🇸🇺🇸🇺🇸"),
+            ("👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧", "👨‍👩‍👧👨‍👩‍👧👨‍👩‍👧"),
+            (
+                "e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}",
+                "e\u{301}e\u{301}e\u{301}",
+            ),
+            ("1000000 views", "1000000 views"),
+            ("too", "too"),
+        ] {
+            assert_eq!(shorten(SyllableRepeats::Off, text), shortened, "{text}");
+        }
+    }
+
+    #[test]
+    fn shortens_repeated_syllables() {
+        for (text, low, high) in [
+            ("hahahahahahahahahaha", "haha", "haha"),
+            ("hahaha", "hahaha", "haha"),
+            ("ахахахахахах", "ахах", "ахах"),
+            ("lololololol", "lolol", "lolol"),
+            ("bananas", "bananas", "bananas"),
+            ("121212121212", "121212121212", "121212121212"),
+            ("xdxdxdxd lmao", "xdxd lmao", "xdxd lmao"),
+        ] {
+            assert_eq!(shorten(SyllableRepeats::Low, text), low, "{text}");
+            assert_eq!(shorten(SyllableRepeats::High, text), high, "{text}");
+        }
+    }
+
+    #[test]
+    fn skips_links_and_dictionary_words() {
+        for text in [
+            "https://example.com/aaaaaa",
+            "www.heeeeey.com",
+            "Aaaaron!",
+            "yesssss",
+        ] {
+            assert_eq!(shorten(SyllableRepeats::High, text), text);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{Filter, Verdict};
+
+/// A `[[replacements]]` entry. `replace` may refer to capture groups as `$1` or `${name}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Replacement {
+    pub pattern: String,
+    pub replace: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+}
+
+/// Applies each replacement in turn, so later patterns see earlier results.
+pub struct Replacements(Vec<(Regex, String)>);
+
+impl Replacements {
+    pub fn new(replacements: &[Replacement]) -> Result<Self, String> {
+        replacements
+            .iter()
+            .map(|r| {
+                RegexBuilder::new(&r.pattern)
+                    .case_insensitive(r.case_insensitive)
+                    .build()
+                    .map(|regex| (regex, r.replace.clone()))
+                    .map_err(|e| format!("invalid replacement pattern {:?}: {e}", r.pattern))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl Filter for Replacements {
+    fn name(&self) -> &'static str {
+        "replacements"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        Verdict::Speak(self.0.iter().fold(text, |text, (regex, replace)| {
+            regex.replace_all(&text, replace.as_str()).into_owned()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacement(pattern: &str, replace: &str) -> Replacement {
+        Replacement {
+            pattern: pattern.into(),
+            replace: replace.into(),
+            case_insensitive: false,
+        }
+    }
+
+    fn replaced(replacements: &[Replacement], text: &str) -> String {
+        match Replacements::new(replacements).unwrap().apply(text.into()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn applies_replacements_in_order() {
+        let replacements = [
+            replacement("lol", "laughing out loud"),
+            replacement("loud", "LOUD"),
+        ];
+        assert_eq!(replaced(&replacements, "lol"), "laughing out LOUD");
+        let reversed = [replacements[1].clone(), replacements[0].clone()];
+        assert_eq!(replaced(&reversed, "lol"), "laughing out loud");
+    }
+
+    #[test]
+    fn substitutes_capture_groups() {
+        assert_eq!(
+            replaced(&[replacement(r"(\d+)c", "$1 degrees")], "it is 30c"),
+            "it is 30 degrees"
+        );
+        assert_eq!(
+            replaced(
+                &[replacement(r"@(?<user>\w+)", "at ${user}")],
+                "hi @sky and @ren"
+            ),
+            "hi at sky and at ren"
+        );
+    }
+
+    #[test]
+    fn matches_case_only_when_asked_to() {
+        let mut kotlin = replacement("kt", "Kotlin");
+        assert_eq!(replaced(&[kotlin.clone()], "kt KT"), "Kotlin KT");
+        kotlin.case_insensitive = true;
+        assert_eq!(replaced(&[kotlin], "kt KT"), "Kotlin Kotlin");
+    }
+
+    #[test]
+    fn names_the_invalid_pattern() {
+        let error = Replacements::new(&[replacement("fine", ""), replacement("(oops", "")])
+            .err()
+            .unwrap();
+        assert!(
+            error.starts_with("invalid replacement pattern \"(oops\": "),
+            "{error}"
+        );
+    }
+}
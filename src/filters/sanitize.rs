@@ -0,0 +1,216 @@
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::{Filter, Verdict};
+
+const ZWJ: char = '\u{200d}';
+
+/// Typographic punctuation and the ASCII it is spoken as. None of these are longer
+/// in UTF-8 than what they replace.
+const PUNCTUATION: &[(char, &str)] = &[
+    ('\u{2018}', "'"),
+    ('\u{2019}', "'"),
+    ('\u{201a}', "'"),
+    ('\u{201b}', "'"),
+    ('\u{2032}', "'"),
+    ('\u{201c}', "\""),
+    ('\u{201d}', "\""),
+    ('\u{201e}', "\""),
+    ('\u{201f}', "\""),
+    ('\u{2033}', "\""),
+    ('\u{ab}', "\""),
+    ('\u{bb}', "\""),
+    ('\u{2010}', "-"),
+    ('\u{2011}', "-"),
+    ('\u{2012}', "-"),
+    ('\u{2013}', " - "),
+    ('\u{2014}', " - "),
+    ('\u{2015}', " - "),
+    ('\u{2212}', "-"),
+    ('\u{2026}', "..."),
+];
+
+/// Cleans up text pasted from PDFs and web pages: normalizes it to NFC, removes
+/// invisible formatting characters, optionally replaces typographic punctuation
+/// with ASCII, and collapses whitespace.
+pub struct Sanitize {
+    ascii_punctuation: bool,
+}
+
+impl Sanitize {
+    pub fn new(ascii_punctuation: bool) -> Self {
+        Self { ascii_punctuation }
+    }
+
+    fn clean(&self, text: &str) -> String {
+        let mut visible = String::with_capacity(text.len());
+        for c in text.chars() {
+            if c == '\n' || c == ZWJ {
+                visible.push(c);
+            } else if c.is_whitespace() {
+                visible.push(' ');
+            } else if is_invisible(c) || c.is_control() {
+                continue;
+            } else {
+                match PUNCTUATION.iter().find(|(from, _)| *from == c) {
+                    Some((_, to)) if self.ascii_punctuation => visible += to,
+                    _ => visible.push(c),
+                }
+            }
+        }
+        let chars: Vec<char> = visible.chars().collect();
+        let is_emoji = |c: Option<&char>| {
+            c.is_some_and(|&c| {
+                !c.is_ascii()
+                    && !c.is_alphanumeric()
+                    && !c.is_whitespace()
+                    && !is_combining_mark(c)
+                    && c != ZWJ
+            })
+        };
+        let mut joined = String::with_capacity(visible.len());
+        for (i, &c) in chars.iter().enumerate() {
+            // joins emoji like 👨‍👩‍👧, but nothing else worth keeping
+            let joins = i > 0 && is_emoji(chars.get(i - 1)) && is_emoji(chars.get(i + 1));
+            if c != ZWJ || joins {
+                joined.push(c);
+            }
+        }
+        // normalized last, so marks split from their letter by a removed character
+        // are composed too
+        collapse_whitespace(&joined).nfc().collect()
+    }
+}
+
+impl Filter for Sanitize {
+    fn name(&self) -> &'static str {
+        "sanitize"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        Verdict::Speak(self.clean(&text))
+    }
+}
+
+/// Zero-width characters, soft hyphens and bidirectional controls.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{ad}'
+            | '\u{61c}'
+            | '\u{180e}'
+            | '\u{200b}'..='\u{200f}'
+            | '\u{202a}'..='\u{202e}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{feff}'
+    )
+}
+
+/// Joins runs of spaces into one, drops spaces at the ends of lines and more than
+/// one blank line in a row, and trims the text.
+fn collapse_whitespace(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut blank = false;
+    for line in text.split('\n') {
+        let line = line
+            .split(' ')
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        if line.is_empty() {
+            if !blank && !lines.is_empty() {
+                lines.push(String::new());
+            }
+            blank = true;
+        } else {
+            lines.push(line.join(" "));
+            blank = false;
+        }
+    }
+    if blank {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+
+    use super::*;
+    use crate::testing;
+
+    fn clean(text: &str) -> String {
+        Sanitize::new(true).clean(text)
+    }
+
+    /// Text with typographic punctuation, invisible characters and odd whitespace
+    /// mixed in.
+    fn messy(rng: &mut StdRng) -> String {
+        const MESS: &[char] = &[
+            '\u{a0}', '\u{ad}', '\u{200b}', '\u{200d}', '\u{200e}', '\u{202e}', '\u{2066}',
+            '\u{feff}', '\u{2019}', '\u{201c}', '\u{2014}', '\u{2026}', '\u{301}', '\u{3000}',
+            '\r', '\u{7}', '👍', 'e',
+        ];
+        let mut chars: Vec<char> = testing::text(rng, 60).chars().collect();
+        for _ in 0..rng.gen_range(0..10) {
+            let at = rng.gen_range(0..=chars.len());
+            chars.insert(at, *MESS.choose(rng).unwrap());
+        }
+        chars.into_iter().collect()
+    }
+
+    #[test]
+    fn output_is_clean_stable_and_no_longer() {
+        let mut rng = testing::rng();
+        for _ in 0..testing::cases() {
+            let text = messy(&mut rng);
+            let cleaned = clean(&text);
+            assert!(
+                !cleaned.chars().any(|c| is_invisible(c) && c != ZWJ
+                    || (c.is_control() && c != '\n')
+                    || (c.is_whitespace() && c != ' ' && c != '\n')),
+                "{text:?} -> {cleaned:?}"
+            );
+            assert!(
+                !cleaned.contains("  ") && cleaned.trim() == cleaned,
+                "{cleaned:?}"
+            );
+            assert_eq!(clean(&cleaned), cleaned, "{text:?}");
+            assert!(
+                cleaned.len() <= text.nfc().collect::<String>().len(),
+                "{text:?} -> {cleaned:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn maps_typographic_punctuation() {
+        assert_eq!(
+            clean("\u{201c}It\u{2019}s fine\u{201d}\u{2026} they said\u{2014}twice"),
+            "\"It's fine\"... they said - twice"
+        );
+        assert_eq!(
+            Sanitize::new(false).clean("\u{201c}quoted\u{201d}"),
+            "\u{201c}quoted\u{201d}"
+        );
+    }
+
+    #[test]
+    fn removes_invisible_characters() {
+        assert_eq!(
+            clean("soft\u{ad}ware zero\u{200b}width"),
+            "software zerowidth"
+        );
+        assert_eq!(clean("\u{feff}\u{202e}reversed\u{202c}"), "reversed");
+        assert_eq!(clean("a\u{200d}b"), "ab");
+        assert_eq!(clean("👨\u{200d}👩\u{200d}👧"), "👨\u{200d}👩\u{200d}👧");
+    }
+
+    #[test]
+    fn normalizes_and_collapses_whitespace() {
+        assert_eq!(clean("cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(clean("  one\u{a0}\u{a0}two\t three  "), "one two three");
+        assert_eq!(clean("line \r\n\n\n\nnext\n"), "line\n\nnext");
+        assert_eq!(clean("- item\n- item"), "- item\n- item");
+    }
+}
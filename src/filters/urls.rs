@@ -0,0 +1,221 @@
+use std::ops::Range;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::{Host, Url};
+
+use super::{Filter, Verdict};
+
+/// Top-level domains recognized without a scheme, so "example.com" is a link
+/// but "e.g." and "notes.txt" are not.
+const BARE_TLDS: &[&str] = &[
+    "app", "be", "ca", "co", "com", "de", "dev", "edu", "eu", "fm", "fr", "gg", "gov", "io", "jp",
+    "ly", "me", "net", "nl", "org", "ru", "sh", "tv", "uk", "us", "xyz",
+];
+
+/// How links in a message are spoken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlMode {
+    /// Links are read out as written.
+    #[default]
+    Keep,
+    /// Links are removed.
+    Strip,
+    /// Each link is replaced with the word "link".
+    Link,
+    /// Each link is replaced with "a link to" and its hostname, e.g. "a link to youtube dot com".
+    Host,
+}
+
+pub struct Urls {
+    mode: UrlMode,
+    /// Matches "[text](url)".
+    markdown_link: Regex,
+}
+
+impl Urls {
+    pub fn new(mode: UrlMode) -> Self {
+        Self {
+            mode,
+            markdown_link: Regex::new(r"\[([^\]]*)\]\(([^)\s]+)\)").unwrap(),
+        }
+    }
+
+    fn describe(&self, original: &str, url: &Url) -> String {
+        match self.mode {
+            UrlMode::Keep => original.to_owned(),
+            UrlMode::Strip => String::new(),
+            UrlMode::Link => "link".into(),
+            UrlMode::Host => match url.host() {
+                Some(Host::Domain(domain)) => format!(
+                    "a link to {}",
+                    domain.trim_start_matches("www.").replace('.', " dot ")
+                ),
+                _ => "a link".into(),
+            },
+        }
+    }
+}
+
+impl Filter for Urls {
+    fn name(&self) -> &'static str {
+        "urls"
+    }
+
+    fn apply(&self, text: String) -> Verdict {
+        // "[text](url)" keeps its text, followed by the link if it is still spoken
+        let markdown =
+            self.markdown_link
+                .replace_all(&text, |caps: &regex::Captures| match find_url(&caps[2]) {
+                    Some((range, url)) if range == (0..caps[2].len()) => {
+                        format!("{} {}", &caps[1], self.describe(&caps[2], &url))
+                    }
+                    _ => caps[0].to_owned(),
+                });
+        let mut out = String::with_capacity(markdown.len());
+        let mut rest = markdown.as_ref();
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            let (space, word) = rest.split_at(start);
+            let end = word.find(char::is_whitespace).unwrap_or(word.len());
+            let (word, tail) = word.split_at(end);
+            out.push_str(space);
+            match find_url(word) {
+                Some((range, url)) => {
+                    let (before, after) = (&word[..range.start], &word[range.end..]);
+                    let spoken = self.describe(&word[range.clone()], &url);
+                    if spoken.is_empty() {
+                        // don't leave "()" behind
+                        out.push_str(before.trim_end_matches(['(', '[', '<']));
+                        out.push_str(after.trim_start_matches([')', ']', '>']));
+                    } else {
+                        out.push_str(before);
+                        out.push_str(&spoken);
+                        out.push_str(after);
+                    }
+                }
+                None => out.push_str(word),
+            }
+            rest = tail;
+        }
+        out.push_str(rest);
+        if self.mode == UrlMode::Strip {
+            out = out.split_whitespace().collect::<Vec<_>>().join(" ");
+            for mark in [".", ",", "!", "?", ";", ":"] {
+                out = out.replace(&format!(" {mark}"), mark);
+            }
+        }
+        Verdict::Speak(out)
+    }
+}
+
+/// The byte ranges of the links in `text`, for filters that must leave them be.
+pub(super) fn find_urls(text: &str) -> Vec<Range<usize>> {
+    text.split_whitespace()
+        .filter_map(|word| {
+            let (range, _) = find_url(word)?;
+            let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+            Some(offset + range.start..offset + range.end)
+        })
+        .collect()
+}
+
+/// Finds a link in a whitespace-free `word`, leaving out surrounding quotes,
+/// brackets and sentence punctuation.
+fn find_url(word: &str) -> Option<(Range<usize>, Url)> {
+    let start = word.len() - word.trim_start_matches(['(', '[', '<', '"', '\'']).len();
+    let mut end = word.len();
+    loop {
+        let candidate = &word[start..end];
+        let trimmed = candidate.trim_end_matches(['.', ',', ';', ':', '!', '?', '"', '\'', '>']);
+        // a closing bracket belongs to the link only if the link opened it
+        let trimmed = match trimmed.chars().last() {
+            Some(')') if trimmed.matches('(').count() < trimmed.matches(')').count() => {
+                &trimmed[..trimmed.len() - 1]
+            }
+            Some(']') if trimmed.matches('[').count() < trimmed.matches(']').count() => {
+                &trimmed[..trimmed.len() - 1]
+            }
+            _ => trimmed,
+        };
+        if trimmed.len() == candidate.len() {
+            break;
+        }
+        end = start + trimmed.len();
+    }
+    let candidate = &word[start..end];
+    let url = if candidate.starts_with("http://") || candidate.starts_with("https://") {
+        Url::parse(candidate).ok()?
+    } else {
+        let host = candidate.split(['/', '?', '#', ':']).next()?;
+        let tld = host.rsplit('.').next()?.to_ascii_lowercase();
+        let labels_ok = host.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+        if !host.contains('.') || !labels_ok || !BARE_TLDS.contains(&tld.as_str()) {
+            return None;
+        }
+        Url::parse(&format!("http://{candidate}")).ok()?
+    };
+    matches!(
+        url.host(),
+        Some(Host::Domain(_) | Host::Ipv4(_) | Host::Ipv6(_))
+    )
+    .then_some((start..end, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spoken(mode: UrlMode, text: &str) -> String {
+        match Urls::new(mode).apply(text.to_owned()) {
+            Verdict::Speak(text) => text,
+            verdict => panic!("unexpected {verdict:?}"),
+        }
+    }
+
+    #[test]
+    fn speaks_links_as_configured() {
+        let text =
+            "see [the docs](https://docs.rs/regex), example.com/path or (https://www.a.io/x).";
+        for (mode, expected) in [
+            (
+                UrlMode::Keep,
+                "see the docs https://docs.rs/regex, example.com/path or (https://www.a.io/x).",
+            ),
+            (UrlMode::Strip, "see the docs, or."),
+            (UrlMode::Link, "see the docs link, link or (link)."),
+            (
+                UrlMode::Host,
+                "see the docs a link to docs dot rs, a link to example dot com or (a link to a dot io).",
+            ),
+        ] {
+            assert_eq!(spoken(mode, text), expected, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_trailing_punctuation_out_of_the_link() {
+        for (text, expected) in [
+            ("example.com/path).", "link)."),
+            ("(example.com/path),", "(link),"),
+            ("https://en.wikipedia.org/wiki/Rust_(language).", "link."),
+            ("example.com.", "link."),
+        ] {
+            assert_eq!(spoken(UrlMode::Link, text), expected, "{text}");
+        }
+        assert_eq!(spoken(UrlMode::Strip, "(example.com/path),"), ",");
+    }
+
+    #[test]
+    fn does_not_take_other_dotted_words_for_links() {
+        for text in ["e.g. this", "notes.txt", "[a](not a link)", "3.14"] {
+            assert_eq!(spoken(UrlMode::Link, text), text);
+        }
+        assert_eq!(
+            find_urls("at example.com/fr/dm, or a.io ok"),
+            [3..20, 25..29]
+        );
+    }
+}
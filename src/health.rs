@@ -0,0 +1,276 @@
+//! Whether the provider can be used, checked in the background when the program
+//! starts and, with `--daemon`, every `health_check_interval_secs`, so that an
+//! expired key or a dead network shows before a message fails. The overlay and
+//! `GET /status` show the result. With `prewarm`, the check goes through the
+//! shared client, so the connection it opens is there for the first message.
+
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use eframe::epaint::Color32;
+use reqwest::blocking::Client;
+use serde::Serialize;
+
+use crate::{
+    auth, http_log, metrics::METRICS, provider::ProviderConfig, proxy, speech::SynthesisError,
+    Configuration,
+};
+
+/// How long a check waits for the provider.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Checks closer together than this reuse the last result.
+const MIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The outcome of the last check.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", content = "reason", rename_all = "lowercase")]
+pub enum Health {
+    /// Not checked yet.
+    Unknown,
+    Ok,
+    /// Works for now, but messages may fail or be slow, e.g. with the quota used
+    /// up.
+    Degraded(String),
+    /// Messages will fail, e.g. because the key was rejected or the network is
+    /// down.
+    Down(String),
+}
+
+impl Health {
+    /// Green, amber or red; grey before the first check.
+    pub fn color(&self) -> Color32 {
+        match self {
+            Self::Unknown => Color32::GRAY,
+            Self::Ok => Color32::LIGHT_GREEN,
+            Self::Degraded(_) => Color32::from_rgb(255, 160, 80),
+            Self::Down(_) => Color32::RED,
+        }
+    }
+
+    /// `● google`, with the reason added when it is not OK.
+    pub fn line(&self, provider: &str) -> String {
+        match self {
+            Self::Unknown => format!("● {provider}: checking"),
+            Self::Ok => format!("● {provider}"),
+            Self::Degraded(reason) | Self::Down(reason) => format!("● {provider}: {reason}"),
+        }
+    }
+}
+
+struct State {
+    health: Health,
+    checked_at: Option<Instant>,
+    /// Whether the last check could not reach the provider at all.
+    unreachable: bool,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    health: Health::Unknown,
+    checked_at: None,
+    unreachable: false,
+});
+
+/// The result of the last check, and how long ago it was made.
+pub fn current() -> (Health, Option<Duration>) {
+    let state = STATE.lock().unwrap();
+    (
+        state.health.clone(),
+        state.checked_at.map(|at| at.elapsed()),
+    )
+}
+
+/// Whether the last check could not reach the provider at all, so that a
+/// message sent now would be held until it can.
+pub fn unreachable() -> bool {
+    STATE.lock().unwrap().unreachable
+}
+
+/// Checks the provider, unless it was checked less than half a minute ago, and
+/// returns the result. With `prewarm`, the connection is kept for messages.
+pub fn check(config: &Configuration, prewarm: bool) -> Health {
+    {
+        let mut state = STATE.lock().unwrap();
+        if state
+            .checked_at
+            .is_some_and(|at| at.elapsed() < MIN_INTERVAL)
+        {
+            return state.health.clone();
+        }
+        // claimed before probing, so that callers meanwhile don't probe too
+        state.checked_at = Some(Instant::now());
+    }
+    let result = try_probe(config, prewarm);
+    let unreachable = result.as_ref().is_err_and(SynthesisError::is_offline);
+    let health = match result {
+        Ok(()) => Health::Ok,
+        Err(e) => from_error(&e),
+    };
+    let mut state = STATE.lock().unwrap();
+    if health != state.health {
+        match &health {
+            Health::Ok => log::info!("{} is reachable", config.provider.name()),
+            Health::Degraded(reason) | Health::Down(reason) => {
+                log::warn!("{} health check: {reason}", config.provider.name())
+            }
+            Health::Unknown => {}
+        }
+    }
+    state.health = health.clone();
+    state.checked_at = Some(Instant::now());
+    state.unreachable = unreachable;
+    health
+}
+
+/// Checks the provider on a thread of its own now, and then every
+/// `health_check_interval_secs` if `repeat` is set and that is not 0.
+pub fn start(config: Configuration, repeat: bool, prewarm: bool) {
+    thread::spawn(move || {
+        check(&config, prewarm);
+        if !repeat || config.health_check_interval_secs == 0 {
+            return;
+        }
+        loop {
+            thread::sleep(Duration::from_secs(config.health_check_interval_secs));
+            check(&config, prewarm);
+        }
+    });
+}
+
+/// Asks the provider whether it would take a message now, by listing the voices
+/// for `gcloud_language` with the configured credentials. With `prewarm`, this
+/// goes through the client messages use, leaving its connection open for them.
+pub fn probe(config: &Configuration, prewarm: bool) -> Health {
+    match try_probe(config, prewarm) {
+        Ok(()) => Health::Ok,
+        Err(e) => from_error(&e),
+    }
+}
+
+fn try_probe(config: &Configuration, prewarm: bool) -> Result<(), SynthesisError> {
+    match config.provider {
+        ProviderConfig::Google { .. } => {}
+        ProviderConfig::Mock { .. } => return Ok(()),
+    }
+    let client = if prewarm {
+        http_log::client(config)
+    } else {
+        http_log::client_builder(config).build()
+    };
+    let result = client
+        .map_err(SynthesisError::Network)
+        .and_then(|client| list_voices(config, &client));
+    if result.is_ok() && prewarm {
+        log::debug!("the connection to {} is open", config.gcloud_endpoint);
+        METRICS.prewarmed.set(1);
+    }
+    result
+}
+
+fn list_voices(config: &Configuration, client: &Client) -> Result<(), SynthesisError> {
+    let url = format!("{}/v1/voices", config.gcloud_endpoint.trim_end_matches('/'));
+    let through = url::Url::parse(&url)
+        .ok()
+        .and_then(|url| proxy::for_url(config, &url));
+    let request = client
+        .get(url)
+        .query(&[("languageCode", &config.gcloud_language)])
+        .timeout(TIMEOUT);
+    let request = auth::authorize(request, config).map_err(SynthesisError::Auth)?;
+    let response = http_log::send(client, request).map_err(|e| match through {
+        Some(proxy) if e.is_connect() => SynthesisError::Proxy { proxy, source: e },
+        _ => SynthesisError::Network(e),
+    })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(SynthesisError::Http {
+            status: status.as_u16(),
+            body: response.text().unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+/// What a failed check means for messages, with Google's own explanation when
+/// it gave one.
+fn from_error(e: &SynthesisError) -> Health {
+    match e {
+        SynthesisError::Network(source) if source.is_timeout() => Health::Degraded(e.to_string()),
+        SynthesisError::Http { status, body } => {
+            let reason = match google_message(body) {
+                Some(message) => format!("{e}: {message}"),
+                None => e.to_string(),
+            };
+            match status {
+                429 | 500.. => Health::Degraded(reason),
+                _ => Health::Down(reason),
+            }
+        }
+        _ => Health::Down(e.to_string()),
+    }
+}
+
+/// `error.message` of a Google error response.
+fn google_message(body: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    Some(body["error"]["message"].as_str()?.trim().to_owned()).filter(|m| !m.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http(status: u16, body: &str) -> Health {
+        from_error(&SynthesisError::Http {
+            status,
+            body: body.into(),
+        })
+    }
+
+    #[test]
+    fn tells_a_rejected_key_from_a_struggling_service() {
+        assert_eq!(
+            http(
+                400,
+                r#"{"error": {"code": 400, "message": "API key expired. Please renew the API key."}}"#
+            ),
+            Health::Down(
+                "400 from Google — check voice and language: API key expired. Please renew the API key."
+                    .into()
+            )
+        );
+        assert_eq!(
+            http(403, "Forbidden"),
+            Health::Down("403 from Google — check API key".into())
+        );
+        assert_eq!(
+            http(429, "{}"),
+            Health::Degraded("429 from Google — quota exceeded, try again later".into())
+        );
+        assert_eq!(http(503, ""), Health::Degraded("503 from Google".into()));
+        assert_eq!(
+            from_error(&SynthesisError::Auth("no credentials".into())),
+            Health::Down("could not authenticate: no credentials".into())
+        );
+    }
+
+    #[test]
+    fn shows_the_reason_unless_it_is_ok() {
+        assert_eq!(Health::Ok.line("google"), "● google");
+        assert_eq!(
+            Health::Down("network error: could not connect to the speech service".into())
+                .line("google"),
+            "● google: network error: could not connect to the speech service"
+        );
+        assert_eq!(
+            serde_json::to_value(Health::Degraded("slow".into())).unwrap(),
+            serde_json::json!({"status": "degraded", "reason": "slow"})
+        );
+        assert_eq!(
+            serde_json::to_value(Health::Ok).unwrap(),
+            serde_json::json!({"status": "ok"})
+        );
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{self, unix_now};
+
+pub const DROPPED_PATH: &str = "dropped.tsv";
+/// How much of the end of a log `last_spoken` reads, a few hundred messages.
+const TAIL_BYTES: u64 = 64 * 1024;
+pub const FILTERED_PATH: &str = "filtered.log";
+
+/// The `[message_log]` table: the JSON-lines record of every message spoken, which
+/// replay, completions and `--stats` read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct MessageLogConfig {
+    pub enabled: bool,
+    pub path: String,
+    pub rotation: Rotation,
+    /// Size at which the log is rotated with `rotation = "size"`.
+    pub max_bytes: u64,
+}
+
+impl Default for MessageLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "messages.jsonl".into(),
+            rotation: Rotation::default(),
+            max_bytes: 10_000_000,
+        }
+    }
+}
+
+/// When the message log is moved aside to start a new one. Old logs keep the name
+/// with the Unix time of the rotation added, like `messages.1760000000.jsonl`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Never,
+    /// On the first message of a new day.
+    Daily,
+    /// Once it would grow past `max_bytes`.
+    #[default]
+    Size,
+}
+
+/// Where a message came from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Overlay,
+    Http,
+    Clipboard,
+    File,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+/// A line of the message log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub timestamp: u64,
+    pub source: Source,
+    /// The text as it was sent to the provider, after filters and translation.
+    pub text: String,
+    pub provider: String,
+    pub voice: String,
+    pub characters: usize,
+    /// Time the provider took; missing for audio from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synthesis_ms: Option<u64>,
+    /// From taking the message on to sending its first sample to the device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_audio_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub playback_ms: Option<u64>,
+    pub status: Status,
+    /// Why the message could not be spoken, as shown to the user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Key of the audio in the cache, so a replay can find it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_key: Option<String>,
+    /// Name of the audio in the archive, when `archive.enabled` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_hash: Option<String>,
+}
+
+impl Message {
+    /// A message from `source` that is about to be spoken.
+    pub fn new(source: Source, text: &str, provider: &str, voice: &str) -> Self {
+        Self {
+            timestamp: unix_now(),
+            source,
+            text: text.to_owned(),
+            provider: provider.to_owned(),
+            voice: voice.to_owned(),
+            characters: text.chars().count(),
+            synthesis_ms: None,
+            first_audio_ms: None,
+            playback_ms: None,
+            status: Status::Ok,
+            error: None,
+            audio_key: None,
+            audio_hash: None,
+        }
+    }
+
+    /// How long the message took to start playing, like `820 ms to speak (640 ms
+    /// synthesis)`, if it was played.
+    pub fn latency(&self) -> Option<String> {
+        let first_audio = self.first_audio_ms?;
+        Some(match self.synthesis_ms {
+            Some(synthesis) => format!("{first_audio} ms to speak ({synthesis} ms synthesis)"),
+            None => format!("{first_audio} ms to speak (cached)"),
+        })
+    }
+}
+
+/// Appends `message` to the message log, rotating it first if it is due.
+pub fn record_message(config: &MessageLogConfig, message: &Message) {
+    if !config.enabled {
+        return;
+    }
+    let line = match serde_json::to_string(message) {
+        Ok(line) => line,
+        Err(e) => {
+            log::warn!("could not serialize message for {}: {e}", config.path);
+            return;
+        }
+    };
+    if let Err(e) = rotate(config, line.len() as u64 + 1) {
+        log::warn!("could not rotate {}: {e}", config.path);
+    }
+    append(&config.path, &line);
+}
+
+/// Moves the log aside if adding `incoming` bytes is due to rotate it.
+fn rotate(config: &MessageLogConfig, incoming: u64) -> io::Result<()> {
+    let metadata = match fs::metadata(&config.path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let due = match config.rotation {
+        Rotation::Never => false,
+        Rotation::Daily => {
+            let modified = metadata.modified()?.duration_since(UNIX_EPOCH);
+            let modified = modified.map_or(0, |since| since.as_secs());
+            clock::local_at(modified).date != clock::local_now().date
+        }
+        Rotation::Size => metadata.len() > 0 && metadata.len() + incoming > config.max_bytes,
+    };
+    if due {
+        fs::rename(&config.path, rotated_path(&config.path, unix_now()))?;
+    }
+    Ok(())
+}
+
+/// `messages.jsonl` rotated at `unix_secs` becomes `messages.<unix_secs>.jsonl`.
+/// If it already rotated in that second, the next free second is used, which
+/// keeps the logs in order.
+fn rotated_path(path: &str, unix_secs: u64) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (unix_secs..)
+        .map(|secs| {
+            let name = match path.extension() {
+                Some(extension) => format!("{stem}.{secs}.{}", extension.to_string_lossy()),
+                None => format!("{stem}.{secs}"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|rotated| !rotated.exists())
+        .unwrap()
+}
+
+/// The rotated logs of `path`, oldest first, then `path` itself.
+fn log_files(path: &str) -> io::Result<Vec<PathBuf>> {
+    let current = Path::new(path);
+    let dir = match current.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let stem = current.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = current
+        .extension()
+        .map(|extension| extension.to_string_lossy());
+    let mut rotated: Vec<(u64, PathBuf)> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let rest = name.strip_prefix(&*stem)?.strip_prefix('.')?;
+                let stamp = match &extension {
+                    Some(extension) => rest.strip_suffix(&**extension)?.strip_suffix('.')?,
+                    None => rest,
+                };
+                Some((stamp.parse().ok()?, dir.join(name)))
+            })
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    rotated.sort();
+    let mut files: Vec<PathBuf> = rotated.into_iter().map(|(_, path)| path).collect();
+    files.push(current.to_owned());
+    Ok(files)
+}
+
+/// Calls `f` with every message in the log and its rotated predecessors, oldest
+/// first, one line at a time. Lines that don't parse are skipped with a warning;
+/// returns how many were.
+pub fn each_message(config: &MessageLogConfig, mut f: impl FnMut(Message)) -> io::Result<u64> {
+    let mut skipped = 0;
+    for path in log_files(&config.path)? {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(message) => f(message),
+                Err(e) => {
+                    log::warn!("skipping line {} of {}: {e}", number + 1, path.display());
+                    skipped += 1;
+                }
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+/// Reads every message in the log and its rotated predecessors, oldest first.
+pub fn read_messages(config: &MessageLogConfig) -> io::Result<Vec<Message>> {
+    let mut messages = Vec::new();
+    each_message(config, |message| messages.push(message))?;
+    Ok(messages)
+}
+
+/// The messages that were spoken, oldest first, as `(timestamp, provider, text)`.
+pub fn read_spoken(config: &MessageLogConfig) -> io::Result<Vec<(u64, String, String)>> {
+    let mut spoken = Vec::new();
+    each_message(config, |message| {
+        if message.status == Status::Ok {
+            spoken.push((message.timestamp, message.provider, message.text));
+        }
+    })?;
+    Ok(spoken)
+}
+
+/// Removes `message` from whichever log file holds it, rewriting that file without
+/// its line. Returns whether it was found.
+pub fn delete_message(config: &MessageLogConfig, message: &Message) -> io::Result<bool> {
+    for path in log_files(&config.path)? {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut found = false;
+        let kept: Vec<&str> = contents
+            .lines()
+            .filter(|line| {
+                let matches = !found
+                    && serde_json::from_str::<Message>(line).is_ok_and(|logged| logged == *message);
+                found |= matches;
+                !matches
+            })
+            .collect();
+        if found {
+            let temporary = path.with_extension("tmp");
+            let mut rewritten = kept.join("\n");
+            if !rewritten.is_empty() {
+                rewritten.push('\n');
+            }
+            fs::write(&temporary, rewritten)?;
+            fs::rename(&temporary, &path)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The message that was spoken last, for replays, from the end of the current
+/// log, so that opening the overlay does not read the whole history. Right after
+/// a rotation, the end of the newest rotated log is read instead.
+pub fn last_spoken(config: &MessageLogConfig) -> io::Result<Option<Message>> {
+    for path in log_files(&config.path)?.iter().rev().take(2) {
+        if let Some(message) = last_spoken_in(path)? {
+            return Ok(Some(message));
+        }
+    }
+    Ok(None)
+}
+
+/// The last spoken message in the last `TAIL_BYTES` of the log at `path`.
+fn last_spoken_in(path: &Path) -> io::Result<Option<Message>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let start = file.metadata()?.len().saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    let mut lines = tail.lines();
+    if start > 0 {
+        // most likely cut off
+        lines.next();
+    }
+    Ok(lines
+        .rev()
+        .filter_map(|line| serde_json::from_str::<Message>(line).ok())
+        .find(|message| message.status == Status::Ok))
+}
+
+/// Appends a message that was filtered out to `dropped.tsv` as `timestamp\treason\ttext`.
+pub fn record_dropped(reason: &str, text: &str) {
+    append(
+        DROPPED_PATH,
+        &format!("{}\t{}\t{}", unix_now(), one_line(reason), one_line(text)),
+    );
+}
+
+/// Appends a message that matched `phrase_filter_regex` to `filtered.log` as
+/// `timestamp\treason\ttext`.
+pub fn record_filtered(reason: &str, text: &str) {
+    append(
+        FILTERED_PATH,
+        &format!("{}\t{}\t{}", unix_now(), one_line(reason), one_line(text)),
+    );
+}
+
+/// Replaces characters that would break a TSV row with spaces.
+fn one_line(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c == '\t' || c == '\n' || c == '\r' {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn append(path: &str, line: &str) {
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{line}"));
+    if let Err(e) = result {
+        log::warn!("could not write to {path}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_in(name: &str) -> (PathBuf, MessageLogConfig) {
+        let dir = std::env::temp_dir().join(format!("message-log-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = MessageLogConfig {
+            path: dir.join("messages.jsonl").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        (dir, config)
+    }
+
+    fn message(text: &str) -> Message {
+        Message::new(Source::Overlay, text, "google", "en-US-Standard-A")
+    }
+
+    #[test]
+    fn reads_back_what_it_records() {
+        let (dir, config) = log_in("roundtrip");
+        let mut failed = message("second");
+        failed.status = Status::Error;
+        failed.error = Some("401 from Google — check API key".into());
+        record_message(&config, &message("first\nline"));
+        record_message(&config, &failed);
+        let messages = read_messages(&config).unwrap();
+        assert_eq!(messages, [message("first\nline"), failed]);
+        let spoken = read_spoken(&config).unwrap();
+        assert_eq!(spoken.len(), 1);
+        assert_eq!(spoken[0].2, "first\nline");
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn deletes_one_message() {
+        let (dir, config) = log_in("delete");
+        let mut repeated = message("again");
+        repeated.timestamp = 1;
+        for message in [&repeated, &message("keep"), &repeated] {
+            record_message(&config, message);
+        }
+        assert!(delete_message(&config, &repeated).unwrap());
+        let texts: Vec<String> = read_messages(&config)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        assert_eq!(texts, ["keep", "again"]);
+        assert!(!delete_message(&config, &message("never logged")).unwrap());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotates_by_size_and_reads_across_files() {
+        let (dir, mut config) = log_in("rotation");
+        let line = serde_json::to_string(&message("one")).unwrap();
+        config.max_bytes = line.len() as u64 + 2;
+        record_message(&config, &message("one"));
+        record_message(&config, &message("two"));
+        let files = log_files(&config.path).unwrap();
+        assert_eq!(files.len(), 2, "{files:?}");
+        let texts: Vec<String> = read_messages(&config)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        assert_eq!(texts, ["one", "two"]);
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn finds_the_last_spoken_message_at_the_end_of_the_log() {
+        let (dir, mut config) = log_in("last");
+        config.rotation = Rotation::Never;
+        for i in 0..1000 {
+            record_message(&config, &message(&format!("earlier message {i}")));
+        }
+        assert!(fs::metadata(&config.path).unwrap().len() > TAIL_BYTES);
+        let mut failed = message("failed");
+        failed.status = Status::Error;
+        record_message(&config, &message("last"));
+        record_message(&config, &failed);
+        assert_eq!(last_spoken(&config).unwrap().unwrap().text, "last");
+        // a fresh log after a rotation has nothing spoken yet
+        fs::rename(&config.path, rotated_path(&config.path, 1)).unwrap();
+        record_message(&config, &failed);
+        assert_eq!(last_spoken(&config).unwrap().unwrap().text, "last");
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn keeps_every_log_rotated_within_a_second() {
+        let (dir, mut config) = log_in("quick-rotation");
+        config.max_bytes = 1;
+        for text in ["one", "two", "three"] {
+            record_message(&config, &message(text));
+        }
+        assert_eq!(log_files(&config.path).unwrap().len(), 3);
+        let texts: Vec<String> = read_messages(&config)
+            .unwrap()
+            .into_iter()
+            .map(|message| message.text)
+            .collect();
+        assert_eq!(texts, ["one", "two", "three"]);
+        let first = rotated_path(&config.path, 7);
+        assert!(first.ends_with("messages.7.jsonl"), "{first:?}");
+        fs::write(&first, "").unwrap();
+        assert!(rotated_path(&config.path, 7).ends_with("messages.8.jsonl"));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn can_be_turned_off() {
+        let (dir, mut config) = log_in("disabled");
+        config.enabled = false;
+        record_message(&config, &message("quiet"));
+        assert!(read_messages(&config).unwrap().is_empty());
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn names_rotated_logs_after_the_time() {
+        assert_eq!(
+            rotated_path("logs/messages.jsonl", 42),
+            Path::new("logs/messages.42.jsonl")
+        );
+        assert_eq!(rotated_path("messages", 42), Path::new("messages.42"));
+    }
+
+    #[test]
+    fn describes_how_long_a_message_took_to_start() {
+        let mut spoken = message("hi");
+        assert_eq!(spoken.latency(), None);
+        spoken.first_audio_ms = Some(35);
+        assert_eq!(spoken.latency().unwrap(), "35 ms to speak (cached)");
+        spoken.synthesis_ms = Some(640);
+        spoken.first_audio_ms = Some(820);
+        assert_eq!(
+            spoken.latency().unwrap(),
+            "820 ms to speak (640 ms synthesis)"
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use eframe::egui::{self, Key, Modifiers, RichText, Sense, TextEdit};
+
+use crate::{
+    clock,
+    history::{self, Message, MessageLogConfig, Status},
+    hotkeys::{self, HotkeyBinding, Hotkeys},
+};
+
+/// At most this many entries are listed at once.
+const SHOWN: usize = 8;
+
+/// What the browser wants done with the overlay.
+pub enum Action {
+    /// Puts the text in the text box.
+    Insert(String),
+    /// Speaks the text right away.
+    Send(String),
+    /// Goes back to the text box.
+    Close,
+}
+
+/// Searchable list of the messages spoken before, newest first, which replaces the
+/// text box while it is open.
+pub struct HistoryBrowser {
+    /// Spoken messages, newest first.
+    messages: Vec<Message>,
+    query: String,
+    /// Indices into `messages` of the entries matching `query`, best first.
+    matches: Vec<usize>,
+    selected: usize,
+    /// Why the log could not be read, or an entry deleted.
+    error: Option<String>,
+}
+
+impl HistoryBrowser {
+    pub fn open(config: &MessageLogConfig) -> Self {
+        let (mut messages, error) = match history::read_messages(config) {
+            Ok(messages) => (messages, None),
+            Err(e) => (
+                Vec::new(),
+                Some(format!("Could not read {}: {e}", config.path)),
+            ),
+        };
+        messages.retain(|message| message.status == Status::Ok);
+        messages.reverse();
+        let mut browser = Self {
+            messages,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+            error,
+        };
+        browser.search();
+        browser
+    }
+
+    /// Lists the messages matching `query`, best match first and newest first
+    /// among equal ones.
+    fn search(&mut self) {
+        let mut scored: Vec<(u32, usize)> = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter_map(|(i, message)| Some((fuzzy_score(&self.query, &message.text)?, i)))
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    fn selected(&self) -> Option<&Message> {
+        self.matches.get(self.selected).map(|&i| &self.messages[i])
+    }
+
+    /// Deletes the selected entry from the message log.
+    fn delete_selected(&mut self, config: &MessageLogConfig) {
+        let Some(&i) = self.matches.get(self.selected) else {
+            return;
+        };
+        match history::delete_message(config, &self.messages[i]) {
+            Ok(_) => {
+                self.messages.remove(i);
+                self.search();
+            }
+            Err(e) => self.error = Some(format!("Could not delete the message: {e}")),
+        }
+    }
+
+    /// Handles the browser's keys and shows the search box with the matching
+    /// entries. Returns the bottom edge of the list and what the overlay should do.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        config: &MessageLogConfig,
+        keys: &Hotkeys,
+    ) -> (f32, Option<Action>) {
+        let ctx = ui.ctx().clone();
+        // handled before the search box sees them, so Enter or Delete don't edit it
+        if hotkeys::is_pressed(&keys.cancel, &ctx) || hotkeys::is_pressed(&keys.history, &ctx) {
+            return (ui.min_rect().bottom(), Some(Action::Close));
+        }
+        let resend = hotkeys::is_pressed(&keys.resend, &ctx);
+        let insert = hotkeys::is_pressed(&keys.submit, &ctx);
+        if let Some(message) = self.selected().filter(|_| resend || insert) {
+            let text = message.text.clone();
+            let action = if resend {
+                Action::Send(text)
+            } else {
+                Action::Insert(text)
+            };
+            return (ui.min_rect().bottom(), Some(action));
+        }
+        if hotkeys::is_pressed(&keys.delete_entry, &ctx) {
+            self.delete_selected(config);
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowDown)) {
+            self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1));
+        }
+        if ctx.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowUp)) {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        let search = ui.add(
+            TextEdit::singleline(&mut self.query)
+                .hint_text("Search history")
+                .font(egui::FontId::proportional(24.))
+                .desired_width(f32::INFINITY)
+                .lock_focus(true),
+        );
+        search.request_focus();
+        if search.changed() {
+            self.selected = 0;
+            self.search();
+        }
+        if let Some(error) = &self.error {
+            ui.label(RichText::new(error).size(14.).color(egui::Color32::RED));
+        }
+        if self.matches.is_empty() {
+            let empty = if self.messages.is_empty() {
+                "Nothing spoken yet"
+            } else {
+                "No messages match"
+            };
+            ui.weak(RichText::new(empty).size(14.));
+        }
+        let first = (self.selected + 1).saturating_sub(SHOWN);
+        let mut clicked = None;
+        for (row, &i) in self.matches.iter().enumerate().skip(first).take(SHOWN) {
+            let message = &self.messages[i];
+            let time = clock::local_at(message.timestamp);
+            let label = format!(
+                "{} {:02}:{:02}  {}  {}",
+                time.date, time.hour, time.minute, message.voice, message.text
+            );
+            let label = RichText::new(label).size(14.);
+            let label = if row == self.selected {
+                label.strong()
+            } else {
+                label.weak()
+            };
+            if ui
+                .add(egui::Label::new(label).truncate(true).sense(Sense::click()))
+                .clicked()
+            {
+                clicked = Some(message.text.clone());
+            }
+        }
+        ui.weak(
+            RichText::new(format!(
+                "{} insert · {} speak · {} delete · {} back",
+                shortcut(&keys.submit),
+                shortcut(&keys.resend),
+                shortcut(&keys.delete_entry),
+                shortcut(&keys.cancel)
+            ))
+            .size(12.),
+        );
+        (ui.min_rect().bottom(), clicked.map(Action::Insert))
+    }
+}
+
+fn shortcut(binding: &HotkeyBinding) -> &str {
+    binding
+        .as_deref()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("(unbound)")
+}
+
+/// How well `text` matches `query` when the characters of `query` appear in it in
+/// order, ignoring case, or `None` if they don't. Characters found right after the
+/// previous match or at the start of a word score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut text = text.chars().flat_map(char::to_lowercase).enumerate();
+    let mut last_match: Option<usize> = None;
+    // the character before the one looked at next
+    let mut before: Option<char> = None;
+    for wanted in query.chars().flat_map(char::to_lowercase) {
+        if wanted.is_whitespace() {
+            continue;
+        }
+        let at = loop {
+            let (at, c) = text.next()?;
+            if c == wanted {
+                break at;
+            }
+            before = Some(c);
+        };
+        score += 1;
+        if before.is_none_or(|c| !c.is_alphanumeric()) {
+            score += 2;
+        }
+        if last_match.is_some_and(|last| last + 1 == at) {
+            score += 3;
+        }
+        last_match = Some(at);
+        before = Some(wanted);
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_characters_in_order() {
+        assert!(fuzzy_score("hlo", "Hello").is_some());
+        assert!(fuzzy_score("gg wp", "good game, well played").is_some());
+        assert_eq!(fuzzy_score("olleh", "hello"), None);
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn prefers_runs_and_word_starts() {
+        let score = |text| fuzzy_score("brb", text).unwrap();
+        assert!(score("brb") > score("be right back"));
+        assert!(score("be right back") > score("subrubric"));
+    }
+}
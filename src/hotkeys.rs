@@ -0,0 +1,198 @@
+use eframe::egui::{self, Key, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// A shortcut such as `Ctrl+Shift+V`, or `None` (or `""`) for no shortcut.
+pub type HotkeyBinding = Option<String>;
+
+/// Keys that can be bound, looked up by their egui name.
+const KEYS: &[Key] = &[
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::Enter,
+    Key::Escape,
+    Key::Tab,
+    Key::Space,
+    Key::Backspace,
+    Key::Delete,
+    Key::Insert,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::ArrowUp,
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::Minus,
+    Key::PlusEquals,
+];
+
+/// Keyboard shortcuts of the overlay, one per action.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Hotkeys {
+    /// Speaks the text box.
+    pub submit: HotkeyBinding,
+    /// Closes the overlay without speaking.
+    pub cancel: HotkeyBinding,
+    /// Speaks the last message in the message log again.
+    pub replay: HotkeyBinding,
+    /// Speaks the text on the clipboard.
+    pub speak_clipboard: HotkeyBinding,
+    /// Switches to the next voice profile for this message.
+    pub next_profile: HotkeyBinding,
+    /// Inserts the highlighted completion.
+    pub complete: HotkeyBinding,
+    /// Highlights and inserts the next completion.
+    pub next_completion: HotkeyBinding,
+    /// Opens the history browser, or closes it.
+    pub history: HotkeyBinding,
+    /// Speaks the entry selected in the history browser; `submit` copies it into
+    /// the text box instead.
+    pub resend: HotkeyBinding,
+    /// Deletes the entry selected in the history browser from the message log.
+    pub delete_entry: HotkeyBinding,
+    /// Takes back the last edit of the text box, such as a completion, in one go.
+    pub undo: HotkeyBinding,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let bind = |shortcut: &str| Some(shortcut.to_owned());
+        Self {
+            submit: bind("Enter"),
+            cancel: bind("Escape"),
+            replay: bind("Ctrl+R"),
+            speak_clipboard: bind("Ctrl+Shift+V"),
+            next_profile: bind("Ctrl+P"),
+            complete: bind("Tab"),
+            next_completion: bind("Shift+Tab"),
+            history: bind("Ctrl+H"),
+            resend: bind("Ctrl+Enter"),
+            delete_entry: bind("Shift+Delete"),
+            undo: bind("Ctrl+Z"),
+        }
+    }
+}
+
+impl Hotkeys {
+    /// Each action with its binding, in the order they are declared.
+    pub fn bindings(&self) -> [(&'static str, &HotkeyBinding); 11] {
+        [
+            ("submit", &self.submit),
+            ("cancel", &self.cancel),
+            ("replay", &self.replay),
+            ("speak_clipboard", &self.speak_clipboard),
+            ("next_profile", &self.next_profile),
+            ("complete", &self.complete),
+            ("next_completion", &self.next_completion),
+            ("history", &self.history),
+            ("resend", &self.resend),
+            ("delete_entry", &self.delete_entry),
+            ("undo", &self.undo),
+        ]
+    }
+
+    /// Rejects bindings that name an unknown key or modifier.
+    pub fn validate(&self) -> Result<(), String> {
+        for (action, binding) in self.bindings() {
+            if let Some(shortcut) = binding.as_deref().filter(|s| !s.is_empty()) {
+                parse(shortcut)
+                    .map_err(|e| format!("invalid hotkeys.{action} {shortcut:?}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints every action and its binding, for `--list-hotkeys`.
+    pub fn print(&self) {
+        for (action, binding) in self.bindings() {
+            let shortcut = binding.as_deref().filter(|s| !s.is_empty());
+            println!("{action:<16} {}", shortcut.unwrap_or("(unbound)"));
+        }
+    }
+}
+
+/// Whether `binding` was pressed this frame. The key press is consumed, so text
+/// boxes and other shortcuts don't see it.
+pub fn is_pressed(binding: &HotkeyBinding, ctx: &egui::Context) -> bool {
+    match binding.as_deref().map(parse) {
+        Some(Ok((modifiers, key))) => ctx.input_mut(|i| i.consume_key(modifiers, key)),
+        _ => false,
+    }
+}
+
+/// Parses `Ctrl+Shift+V` style shortcuts. Modifiers and keys ignore case.
+fn parse(shortcut: &str) -> Result<(Modifiers, Key), String> {
+    let mut parts: Vec<&str> = shortcut.split('+').map(str::trim).collect();
+    // "Ctrl++" binds the plus key
+    if shortcut.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("Plus");
+    }
+    let name = parts
+        .pop()
+        .filter(|name| !name.is_empty())
+        .ok_or("missing key")?;
+    let mut modifiers = Modifiers::NONE;
+    for modifier in parts {
+        match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => modifiers.ctrl = true,
+            "cmd" | "command" => modifiers = modifiers.plus(Modifiers::COMMAND),
+            "alt" | "option" => modifiers.alt = true,
+            "shift" => modifiers.shift = true,
+            _ => return Err(format!("unknown modifier `{modifier}`")),
+        }
+    }
+    let key = KEYS
+        .iter()
+        .find(|key| key.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown key `{name}`"))?;
+    Ok((modifiers, *key))
+}
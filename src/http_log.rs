@@ -0,0 +1,231 @@
+//! Sends the requests to the synthesis and translation backends, logging each one
+//! at debug level with its credentials masked, so a `RUST_LOG=debug` log can be
+//! shared when asking for help. The clients come from here too, so that each one
+//! has the proxy and TLS settings.
+
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::blocking::{Client, ClientBuilder, Request, RequestBuilder, Response};
+use serde_json::Value;
+use url::Url;
+
+use crate::{proxy, tls, Configuration};
+
+/// Parts of a header, field or query parameter name that mark its value as secret.
+const SECRET_NAMES: [&str; 5] = ["key", "token", "secret", "auth", "password"];
+
+/// A client builder with the configured proxy and TLS settings.
+pub fn client_builder(config: &Configuration) -> ClientBuilder {
+    tls::apply(proxy::apply(Client::builder(), config), config)
+}
+
+/// What the shared client was built with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Settings {
+    proxy_url: Option<String>,
+    ca_bundle_path: Option<String>,
+    danger_accept_invalid_certs: bool,
+    request_timeout_secs: u64,
+}
+
+impl Settings {
+    fn of(config: &Configuration) -> Self {
+        Self {
+            proxy_url: config.proxy_url.clone(),
+            ca_bundle_path: config.ca_bundle_path.clone(),
+            danger_accept_invalid_certs: config.danger_accept_invalid_certs,
+            request_timeout_secs: config.request_timeout_secs,
+        }
+    }
+}
+
+static SHARED: Mutex<Option<(Settings, Client)>> = Mutex::new(None);
+
+/// The client with the configured proxy, TLS settings and `request_timeout_secs`.
+/// It is one client for the whole process, so its pool keeps connections open and
+/// each message after the first skips connecting and the TLS handshake; it is
+/// only built again when those settings change.
+pub fn client(config: &Configuration) -> reqwest::Result<Client> {
+    let settings = Settings::of(config);
+    let mut shared = SHARED.lock().unwrap();
+    if let Some((built_with, client)) = &*shared {
+        if *built_with == settings {
+            return Ok(client.clone());
+        }
+    }
+    let client = client_builder(config)
+        .timeout(Duration::from_secs(settings.request_timeout_secs))
+        .build()?;
+    *shared = Some((settings, client.clone()));
+    Ok(client)
+}
+
+/// Sends the request built by `builder`, logging it and the response status.
+pub fn send(client: &Client, builder: RequestBuilder) -> reqwest::Result<Response> {
+    let request = builder.build()?;
+    let target = format!("{} {}", request.method(), redact_url(request.url()));
+    if log::log_enabled!(log::Level::Debug) {
+        log::debug!("{}", describe(&request));
+    }
+    let start = Instant::now();
+    let response = client.execute(request);
+    let ms = start.elapsed().as_millis();
+    match &response {
+        Ok(response) => log::debug!("{target}: {} in {ms} ms", response.status()),
+        Err(_) => log::debug!("{target}: no response after {ms} ms"),
+    }
+    response
+}
+
+/// `request` as `--dry-run` prints it: the method and URL, a line per header and
+/// the pretty-printed JSON body, with credentials masked.
+pub fn render(request: &Request) -> String {
+    let mut out = format!("{} {}\n", request.method(), redact_url(request.url()));
+    for (name, value) in request.headers() {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        out += &format!("{name}: {}\n", redact(name.as_str(), &value));
+    }
+    if let Some(bytes) = request.body().and_then(|body| body.as_bytes()) {
+        out.push('\n');
+        match serde_json::from_slice::<Value>(bytes) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                out += &serde_json::to_string_pretty(&json).unwrap_or_default();
+                out.push('\n');
+            }
+            Err(_) => out += &format!("{} bytes\n", bytes.len()),
+        }
+    }
+    out
+}
+
+/// The method, URL, headers and body of `request`, with credentials masked.
+fn describe(request: &Request) -> String {
+    let headers: BTreeMap<&str, String> = request
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (name.as_str(), redact(name.as_str(), &value))
+        })
+        .collect();
+    let body = match request.body().and_then(|body| body.as_bytes()) {
+        None => "none".to_owned(),
+        Some(bytes) => match serde_json::from_slice::<Value>(bytes) {
+            Ok(mut json) => {
+                redact_json(&mut json);
+                json.to_string()
+            }
+            Err(_) => format!("{} bytes", bytes.len()),
+        },
+    };
+    format!(
+        "{} {} headers={headers:?} body={body}",
+        request.method(),
+        redact_url(request.url())
+    )
+}
+
+fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_NAMES.iter().any(|secret| name.contains(secret))
+}
+
+/// `value` if `name` is not secret, or `***` and its last 4 characters when it is
+/// long enough for that to give little away.
+fn redact(name: &str, value: &str) -> String {
+    if !is_secret(name) {
+        return value.to_owned();
+    }
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= 8 {
+        return "***".to_owned();
+    }
+    let last: String = chars[chars.len() - 4..].iter().collect();
+    format!("***{last}")
+}
+
+fn redact_url(url: &Url) -> String {
+    if url.query().is_none() {
+        return url.to_string();
+    }
+    let mut url = url.clone();
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| {
+            let value = redact(&name, &value);
+            (name.into_owned(), value)
+        })
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url.to_string()
+}
+
+/// Masks the string values of secret fields, at any depth.
+fn redact_json(json: &mut Value) {
+    match json {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                match value {
+                    Value::String(s) if is_secret(name) => *s = redact(name, s),
+                    _ => redact_json(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{header::AUTHORIZATION, Method};
+    use serde_json::json;
+
+    use super::*;
+
+    const TOKEN: &str = "AIzaSyD-secret-token-9f3k";
+
+    #[test]
+    fn never_logs_the_credentials() {
+        let client = Client::new();
+        let request = client
+            .post(format!(
+                "https://example.com/v1/text:synthesize?key={TOKEN}"
+            ))
+            .header("X-goog-api-key", TOKEN)
+            .header(AUTHORIZATION, format!("Bearer {TOKEN}"))
+            .header("X-Proxy-Auth", "hunter2")
+            .json(&json!({
+                "input": { "text": "hello" },
+                "api_key": TOKEN,
+                "nested": [{ "token": TOKEN }],
+            }))
+            .build()
+            .unwrap();
+        let logged = describe(&request);
+        assert!(!logged.contains("secret-token"), "{logged}");
+        assert!(!logged.contains("hunter2"), "{logged}");
+        assert!(
+            logged.contains(r#""x-goog-api-key": "***9f3k""#),
+            "{logged}"
+        );
+        assert!(logged.contains(r#""authorization": "***9f3k""#), "{logged}");
+        assert!(logged.contains(r#""x-proxy-auth": "***""#), "{logged}");
+        assert!(logged.contains(r#""input":{"text":"hello"}"#), "{logged}");
+        assert!(logged.starts_with("POST https://example.com/v1/text:synthesize?key=***9f3k "));
+    }
+
+    #[test]
+    fn keeps_everything_else() {
+        let request = Request::new(Method::GET, "https://example.com/".parse().unwrap());
+        assert_eq!(
+            describe(&request),
+            "GET https://example.com/ headers={} body=none"
+        );
+    }
+}
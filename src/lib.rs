@@ -0,0 +1,63 @@
+//! Text-to-speech overlay: the configuration, filters, synthesis and the sources
+//! that feed it. The binary adds the overlay window and the command line.
+
+pub mod archive;
+pub mod audio;
+pub mod audition;
+pub mod auth;
+pub mod bench;
+pub mod cache;
+pub mod chat;
+pub mod chunking;
+pub mod cli;
+pub mod clipboard;
+pub mod clock;
+pub mod completion;
+pub mod convert;
+pub mod correlation;
+pub mod devices;
+pub mod dropped;
+pub mod dry_run;
+pub mod error_log;
+pub mod export;
+pub mod filewatch;
+pub mod filters;
+pub mod health;
+pub mod history;
+pub mod history_browser;
+pub mod hotkeys;
+pub mod http_log;
+pub mod metrics;
+pub mod migrate;
+pub mod notify;
+pub mod offline;
+pub mod overrides;
+pub mod phonetics;
+pub mod phrases;
+pub mod preview;
+pub mod pronunciations;
+pub mod provider;
+pub mod proxy;
+pub mod queue;
+pub mod quick_replies;
+pub mod schedule;
+pub mod server;
+pub mod session;
+pub mod snippets;
+pub mod speech;
+pub mod stats;
+pub mod subtitles;
+pub mod text_menu;
+pub mod tls;
+pub mod transcript;
+pub mod translate;
+pub mod undo;
+pub mod verify;
+pub mod voices;
+pub mod worker;
+
+mod config;
+#[cfg(test)]
+mod testing;
+
+pub use config::{Configuration, QuickReply, VoiceProfile};
@@ -1,28 +1,241 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use std::{
-    fs,
-    io::Cursor,
-    thread::{self, sleep},
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
     time::{Duration, Instant},
 };
 
-use base64::Engine;
 use eframe::{
-    egui::{self, Frame, TextEdit},
-    epaint::{ahash::HashMap, Color32, FontId},
+    egui::{self, Frame, RichText, Stroke, TextEdit},
+    epaint::{Color32, FontId},
+};
+use tts_overlay::{
+    archive, audition, auth, bench, cache, chunking,
+    cli::Args,
+    clipboard::ClipboardWatcher,
+    clock,
+    completion::Completions,
+    convert, correlation, devices, dropped, dry_run, error_log, export,
+    filewatch::FileWatcher,
+    filters::{Pipeline, Verdict},
+    health, history,
+    history_browser::{self, HistoryBrowser},
+    hotkeys, metrics,
+    notify::Notifier,
+    offline,
+    overrides::{self, Overrides},
+    phonetics::{self, Phonetics},
+    phrases,
+    preview::Preview,
+    provider::ProviderConfig,
+    queue::SpeechQueue,
+    quick_replies, server,
+    session::{Session, SessionMetrics},
+    snippets, speech, stats, subtitles,
+    text_menu::TextMenu,
+    transcript,
+    undo::TextHistory,
+    verify, voices,
+    worker::{self, WorkerResult},
+    Configuration,
 };
-use reqwest::header::ACCEPT;
-use rodio::{cpal::traits::HostTrait, DeviceTrait, Source};
-use serde::{Deserialize, Serialize};
-use serde_json::json;
 
 fn main() -> Result<(), eframe::Error> {
-    env_logger::init();
-    let config: Configuration =
-        toml::from_str(&fs::read_to_string("config.toml").unwrap()).unwrap();
+    error_log::init();
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    });
+    if args.version {
+        println!("tts-overlay {}", env!("CARGO_PKG_VERSION"));
+        println!("{}", error_log::describe());
+        return Ok(());
+    }
+    if args.emit_metrics_once {
+        print!("{}", metrics::METRICS.render());
+        return Ok(());
+    }
+    if let Some((input, output)) = &args.convert {
+        if let Err(e) = convert::run(input, output, args.audio_format, args.resample) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.devices {
+        if let Err(e) = devices::run(args.format) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.clear_cache {
+        if let Err(e) = cache::clear() {
+            eprintln!("error: could not clear cache: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.cache_stats {
+        if let Err(e) = cache::print_stats() {
+            eprintln!("error: could not read cache index: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let mut config = Configuration::load(Path::new("config.toml")).unwrap_or_else(|e| {
+        eprintln!("error: could not load config.toml: {e}");
+        std::process::exit(1);
+    });
+    error_log::set_format(config.log_format);
+    if args.stats {
+        if let Err(e) = stats::run(args.format, args.since, &config.message_log) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.export {
+        if let Err(e) = transcript::run(
+            args.format,
+            args.export_filter,
+            &config.message_log,
+            &config.archive,
+        ) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.use_mock_provider && !matches!(config.provider, ProviderConfig::Mock { .. }) {
+        config.provider = ProviderConfig::Mock {
+            response_delay_ms: 0,
+            audio_fixture_path: None,
+            panic_message: None,
+        };
+    }
+    if let Err(e) = config.read_secret_files().and_then(|()| config.validate()) {
+        eprintln!("error: invalid config.toml: {e}");
+        std::process::exit(1);
+    }
+    if config.danger_accept_invalid_certs {
+        log::warn!(
+            "danger_accept_invalid_certs is on: TLS certificates are not checked, so anyone on the network can read and change requests, including the API key"
+        );
+    }
+    if let ProviderConfig::Google { .. } = config.provider {
+        log::info!(
+            "authenticating to Google with {}",
+            auth::Credentials::resolve(&config).describe()
+        );
+    }
+    if args.list_hotkeys {
+        config.hotkeys.print();
+        return Ok(());
+    }
+    if args.list_voices {
+        if let Err(e) = voices::run(&config, args.language.as_deref(), args.format) {
+            eprintln!("error: could not list voices: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.dry_run || args.say.is_some() {
+        let Some(text) = args.say.as_deref().filter(|_| args.dry_run) else {
+            eprintln!("error: --dry-run and --say go together: --dry-run --say \"text\"");
+            std::process::exit(2);
+        };
+        if let Err(e) = dry_run::run(text, &config) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.bench {
+        let text = args.text.as_deref().unwrap_or(bench::DEFAULT_TEXT);
+        if let Err(e) = bench::run(&config, args.runs, text, args.format) {
+            eprintln!("error: benchmark failed: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.audition {
+        let text = args.text.as_deref().unwrap_or(audition::DEFAULT_TEXT);
+        if let Err(e) = audition::run(&config, args.language.as_deref(), text) {
+            eprintln!("error: audition failed: {e}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.archive_prune {
+        match archive::prune(&config.archive) {
+            Ok(pruned) => println!("Removed {pruned} from {}", config.archive.dir),
+            Err(e) => {
+                eprintln!("error: could not prune {}: {e}", config.archive.dir);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if args.verify_config {
+        std::process::exit(if verify::run(&config) { 0 } else { 1 });
+    }
+    if let Some((input, output)) = &args.export_queue {
+        match export::run(input, output, args.silence_ms, &config) {
+            Ok(duration) => println!(
+                "Wrote {} ({:.1} s)",
+                output.display(),
+                duration.as_secs_f32()
+            ),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if config.archive.enabled {
+        match archive::prune(&config.archive) {
+            Ok(pruned) if pruned.clips > 0 => log::info!("pruned {pruned} from the archive"),
+            Ok(_) => {}
+            Err(e) => log::warn!("could not prune {}: {e}", config.archive.dir),
+        }
+    }
+    if let Some(port) = config.metrics_port {
+        if let Err(e) = server::start_metrics(port) {
+            eprintln!("error: could not serve metrics on port {port}: {e}");
+            std::process::exit(1);
+        }
+    }
+    let pipeline = Arc::new(
+        Pipeline::new(&config, !args.headless()).unwrap_or_else(|e| {
+            eprintln!("error: invalid config.toml: {e}");
+            std::process::exit(1);
+        }),
+    );
+    // in the background, so that a slow network does not hold up the overlay
+    let prewarm = config.prewarm.unwrap_or(args.headless());
+    health::start(config.clone(), args.headless(), prewarm);
+    if args.headless() {
+        run_daemon(config, pipeline, &args);
+    }
+    // nothing is on screen once the message is sent, so don't hold it long
+    config.offline_max_age_secs = config
+        .offline_max_age_secs
+        .min(offline::ONE_SHOT_MAX_AGE_SECS);
+    match speech::verify_audio_device(&config.output_device) {
+        Ok(name) => println!("Using audio device: {name}"),
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    }
     let options = eframe::NativeOptions {
+        // the app name given to `run_native` stays the same, as it is also the
+        // app ID desktops group windows by
         viewport: egui::ViewportBuilder::default()
+            .with_title(config.title())
             .with_inner_size([config.width, 1.0])
             .with_position([config.x, config.y])
             .with_active(true)
@@ -32,141 +245,1081 @@ fn main() -> Result<(), eframe::Error> {
         ..Default::default()
     };
     let (send, recv) = oneshot::channel();
+    let mut notifier = Notifier::new(&config);
+    let subtitles = config.subtitle_overlay.then(|| config.clone());
+    let max_age = config.offline_max_age_secs;
     eframe::run_native(
         "TTS Overlay",
         options,
-        Box::new(|_cc| Box::new(OverlayApp::new(config, send))),
+        Box::new(|_cc| {
+            Box::new(OverlayApp::new(
+                config,
+                pipeline,
+                send,
+                Session::load(),
+                Box::new(Workers),
+            ))
+        }),
     )?;
-    _ = recv.recv();
+    let result = match subtitles {
+        Some(config) => subtitles::run(config, recv)?,
+        None => wait_for(recv, max_age),
+    };
+    if let Some(Err(message)) = result {
+        notifier.failure(&message);
+        eprintln!("error: {message}");
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
-struct Configuration {
-    font_size: f32,
-    width: f32,
-    x: f32,
-    y: f32,
-    gcloud_token: String,
-    gcloud_language: String,
-    gcloud_voice: String,
-    output_device: String,
+/// Waits for the message sent from the overlay to be spoken, saying so on
+/// stderr if that is held up for up to `max_age` seconds by the service being
+/// unreachable.
+fn wait_for(recv: oneshot::Receiver<WorkerResult>, max_age: u64) -> Option<WorkerResult> {
+    let mut told = false;
+    loop {
+        match recv.recv_timeout(Duration::from_millis(250)) {
+            Ok(result) => return Some(result),
+            Err(oneshot::RecvTimeoutError::Timeout) => {
+                if !told && offline::offline_for().is_some() {
+                    eprintln!("The speech service can't be reached; retrying for up to {max_age}s");
+                    told = true;
+                }
+            }
+            Err(oneshot::RecvTimeoutError::Disconnected) => return None,
+        }
+    }
 }
 
+/// Runs without a window, speaking whatever the enabled sources produce.
+fn run_daemon(config: Configuration, pipeline: Arc<Pipeline>, args: &Args) -> ! {
+    if config.http_listen.is_none() && !args.watch_clipboard && args.watch_file.is_none() {
+        eprintln!(
+            "error: nothing to do; set http_listen in config.toml or pass --watch-clipboard or --watch-file"
+        );
+        std::process::exit(1);
+    }
+    // looked up now rather than when the first message is spoken
+    let device = config.output_device.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = speech::verify_audio_device(&device) {
+            log::warn!("{e}");
+        }
+    });
+    let queue = SpeechQueue::start(config.clone(), pipeline);
+    let chat_reader_paused = args.watch_file.as_ref().map(|path| {
+        FileWatcher::start(path.clone(), &config, queue.clone()).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        })
+    });
+    let clipboard_paused = if args.watch_clipboard {
+        match ClipboardWatcher::start(config.clone(), queue.clone()) {
+            Ok(paused) => Some(paused),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(addr) = &config.http_listen {
+        let state = server::State {
+            queue,
+            clipboard_paused,
+            chat_reader_paused,
+            quick_replies: config.quick_replies.clone(),
+            token: config.http_token.clone(),
+        };
+        if let Err(e) = server::start(addr, state) {
+            eprintln!("error: could not listen on {addr}: {e}");
+            std::process::exit(1);
+        }
+    }
+    loop {
+        std::thread::park();
+    }
+}
+
+/// What the overlay does beyond its own window, so that tests can watch it
+/// instead.
+trait Effects {
+    /// Speaks `parts` in order with `config`, telling `waiter` when done.
+    fn speak(
+        &mut self,
+        parts: Vec<String>,
+        config: Configuration,
+        waiter: oneshot::Sender<WorkerResult>,
+    );
+    /// Speaks `last` again with `config`, telling `waiter` when done.
+    fn replay(
+        &mut self,
+        last: history::Message,
+        config: Configuration,
+        waiter: oneshot::Sender<WorkerResult>,
+    );
+    fn save_session(&mut self, session: &Session);
+}
+
+/// Speech on worker threads, and the session in its file.
+struct Workers;
+
+impl Effects for Workers {
+    fn speak(
+        &mut self,
+        parts: Vec<String>,
+        config: Configuration,
+        waiter: oneshot::Sender<WorkerResult>,
+    ) {
+        let request_id = correlation::new_id();
+        worker::spawn(waiter, move || {
+            correlation::in_request(&request_id, || {
+                let cancel = AtomicBool::new(false);
+                parts
+                    .into_iter()
+                    .try_for_each(|part| {
+                        speech::speak(part, config.clone(), history::Source::Overlay, &cancel)
+                    })
+                    .map_err(|e| e.to_string())
+            })
+        });
+    }
+
+    fn replay(
+        &mut self,
+        last: history::Message,
+        config: Configuration,
+        waiter: oneshot::Sender<WorkerResult>,
+    ) {
+        let request_id = correlation::new_id();
+        worker::spawn(waiter, move || {
+            correlation::in_request(&request_id, || {
+                let cancel = AtomicBool::new(false);
+                speech::replay(&last, config, history::Source::Overlay, &cancel)
+                    .map_err(|e| e.to_string())
+            })
+        });
+    }
+
+    fn save_session(&mut self, session: &Session) {
+        session.save();
+    }
+}
+
+const HINT: &str = "What do you want to say?";
+
+/// Space around the overlay's content.
+const PANEL_MARGIN: f32 = 4.;
+
+/// The text box's margins, and room for a character typed before the window
+/// catches up.
+const TEXT_PADDING: f32 = 2. * 4. + 24.;
+
+/// The latency of the last message is shown on open only if it was sent this
+/// many seconds ago or less, as older ones are not news.
+const LATENCY_TOAST_SECS: u64 = 120;
+
 struct OverlayApp {
     text: String,
     grace_period: Instant,
     config: Configuration,
-    waiter: Option<oneshot::Sender<()>>,
+    pipeline: Arc<Pipeline>,
+    session: Session,
+    metrics: SessionMetrics,
+    /// Last size requested for the window, to avoid resizing every frame.
+    window_width: f32,
+    window_height: f32,
+    /// Shown under the text box, e.g. why a message was not spoken.
+    notice: Option<String>,
+    /// Input the user was asked to confirm; pressing Enter again on it speaks it.
+    confirming: Option<String>,
+    /// The window shakes until then after a message was refused for being too long.
+    shake_until: Option<Instant>,
+    preview: Option<Preview>,
+    phonetics: Option<Phonetics>,
+    /// Voice profile picked with the `next_profile` hotkey, if any.
+    profile: Option<String>,
+    completions: Completions,
+    menu: TextMenu,
+    /// Earlier text for the `undo` hotkey.
+    undo: TextHistory,
+    /// Shown instead of the text box while it is open.
+    history: Option<HistoryBrowser>,
+    /// The text box takes the focus back from the history browser on the next frame.
+    refocus: bool,
+    toast: Option<Toast>,
+    /// The window closes then, once a toast about dropped files has been seen.
+    close_at: Option<Instant>,
+    waiter: Option<oneshot::Sender<WorkerResult>>,
+    effects: Box<dyn Effects>,
+}
+
+/// A message shown under the text box for a couple of seconds.
+struct Toast {
+    text: String,
+    error: bool,
+    until: Instant,
+}
+
+impl Toast {
+    fn new(text: impl Into<String>, error: bool) -> Self {
+        Self {
+            text: text.into(),
+            error,
+            until: Instant::now() + Duration::from_millis(1500),
+        }
+    }
 }
 
 impl OverlayApp {
-    fn new(config: Configuration, waiter: oneshot::Sender<()>) -> Self {
+    fn new(
+        config: Configuration,
+        pipeline: Arc<Pipeline>,
+        waiter: oneshot::Sender<WorkerResult>,
+        session: Session,
+        effects: Box<dyn Effects>,
+    ) -> Self {
+        let metrics = SessionMetrics::restore(
+            session.metrics.as_ref(),
+            Duration::from_secs(config.session_max_age_hours * 3600),
+        );
+        let preview = config.split_screen.then(Preview::new);
+        let phonetics = phonetics::enabled(&config).then(Phonetics::new);
+        let completions = Completions::new(&config);
+        // the overlay closes when a message is sent, so the last one is shown on
+        // the next open
+        let toast = config
+            .show_latency
+            .then(|| history::last_spoken(&config.message_log).ok().flatten())
+            .flatten()
+            .filter(|last| clock::unix_now().saturating_sub(last.timestamp) <= LATENCY_TOAST_SECS)
+            .and_then(|last| last.latency())
+            .map(|latency| Toast::new(latency, false));
+        let window_width = config.width;
         Self {
             text: String::new(),
             grace_period: Instant::now() + Duration::from_millis(500),
             config,
+            pipeline,
+            session,
+            metrics,
+            window_width,
+            window_height: 1.0,
+            notice: None,
+            confirming: None,
+            shake_until: None,
+            preview,
+            phonetics,
+            profile: None,
+            completions,
+            menu: TextMenu::default(),
+            undo: TextHistory::new(),
+            history: None,
+            refocus: false,
+            toast,
+            close_at: None,
             waiter: Some(waiter),
+            effects,
+        }
+    }
+
+    /// Turns the input into the pieces to speak and the settings to speak them with,
+    /// or the notice to show instead.
+    fn prepare(&mut self, input: &str) -> Result<(Vec<String>, Overrides), String> {
+        let (mut overrides, text) = overrides::parse(input, &self.config.voice_profiles);
+        let profile = self
+            .profile
+            .as_ref()
+            .and_then(|name| self.config.voice_profiles.get(name));
+        if let Some(profile) = profile.filter(|_| overrides.voice.is_none()) {
+            overrides.voice = Some(profile.gcloud_voice.clone());
+            overrides.language = Some(profile.gcloud_language.clone());
+        }
+        let text = snippets::expand(&text, &self.config.snippets);
+        let text = phrases::pick_random(&text, &self.config, &mut self.session).unwrap_or(text);
+        let text = match self.pipeline.apply(&text) {
+            Verdict::Speak(text) => text,
+            Verdict::Confirm { text, .. } if self.confirming.as_deref() == Some(input) => text,
+            Verdict::Confirm { reason, .. } => {
+                self.confirming = Some(input.to_owned());
+                return Err(format!("Message {reason}; press Enter to speak it anyway"));
+            }
+            Verdict::Drop { reason } => return Err(format!("Phrase filtered: {reason}")),
+            Verdict::Empty => return Ok((Vec::new(), overrides)),
+        };
+        let parts = chunking::enforce_max_chars(&text, &self.config).inspect_err(|_| {
+            self.shake_until = Some(Instant::now() + Duration::from_millis(400));
+        })?;
+        Ok((parts, overrides))
+    }
+
+    /// Starts speaking `input` on a worker thread, or returns the notice to show instead.
+    fn submit(&mut self, input: &str) -> Result<(), String> {
+        let (parts, overrides) = self.prepare(input)?;
+        if parts.is_empty() {
+            if let Some(waiter) = self.waiter.take() {
+                _ = waiter.send(Ok(()));
+            }
+            return Ok(());
+        }
+        self.speak(parts, &overrides);
+        Ok(())
+    }
+
+    /// Speaks `parts` in order, unless something was spoken already.
+    fn speak(&mut self, parts: Vec<String>, overrides: &Overrides) -> bool {
+        let Some(waiter) = self.waiter.take() else {
+            return false;
+        };
+        self.metrics.record(&parts.concat());
+        self.session.metrics = Some(self.metrics.to_stored());
+        self.effects.save_session(&self.session);
+        let mut config = self.config.clone();
+        overrides.apply(&mut config);
+        self.effects.speak(parts, config, waiter);
+        true
+    }
+
+    /// Shows the history browser and does what it asks.
+    fn show_history(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let Some(browser) = &mut self.history else {
+            return;
+        };
+        let (bottom, action) = browser.show(ui, &self.config.message_log, &self.config.hotkeys);
+        self.fit_height(ctx, bottom + 8.);
+        let Some(action) = action else {
+            return;
+        };
+        self.history = None;
+        self.refocus = true;
+        match action {
+            history_browser::Action::Insert(text) => {
+                self.undo.replaced(&self.text, &text);
+                self.text = text;
+            }
+            history_browser::Action::Send(text) => match self.submit(&text) {
+                Ok(()) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                Err(notice) => self.notice = Some(notice),
+            },
+            history_browser::Action::Close => {}
+        }
+        ctx.request_repaint();
+    }
+
+    /// Speaks the message spoken last again, or says why not.
+    /// Returns whether it did.
+    fn replay(&mut self) -> bool {
+        let last = match history::last_spoken(&self.config.message_log) {
+            Ok(Some(last)) => last,
+            Ok(None) => {
+                self.notice = Some("Nothing to replay yet".into());
+                return false;
+            }
+            Err(e) => {
+                self.notice = Some(format!("Could not read the message log: {e}"));
+                return false;
+            }
+        };
+        let Some(waiter) = self.waiter.take() else {
+            return false;
+        };
+        self.effects.replay(last, self.config.clone(), waiter);
+        true
+    }
+
+    /// Speaks the text files dropped on the window, chunked like clipboard text.
+    fn load_dropped(&mut self, files: &[egui::DroppedFile]) {
+        let max = self
+            .config
+            .max_chars
+            .unwrap_or(self.config.clipboard_max_chars);
+        let mut parts = Vec::new();
+        let mut loaded = 0;
+        let mut error = None;
+        for file in dropped::read_text_files(files, self.config.drag_drop_max_bytes) {
+            let text = file.and_then(|text| match self.pipeline.apply(&text) {
+                Verdict::Speak(text) => Ok(text),
+                Verdict::Drop { reason } | Verdict::Confirm { reason, .. } => {
+                    Err(format!("Phrase filtered: {reason}"))
+                }
+                Verdict::Empty => Err("Nothing to speak".into()),
+            });
+            match text {
+                Ok(text) => {
+                    parts.extend(chunking::chunk_text_by_sentences(&text, max));
+                    loaded += 1;
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        if loaded > 0 && self.speak(parts, &Overrides::default()) {
+            let text = match loaded {
+                1 => "📄 File loaded".to_owned(),
+                n => format!("📄 {n} files loaded"),
+            };
+            self.toast = Some(Toast::new(text, false));
+            self.close_at = Some(Instant::now() + Duration::from_millis(1500));
+        }
+        if let Some(e) = error {
+            self.toast = Some(Toast::new(e, true));
+        }
+    }
+
+    /// Switches to the next voice profile by name, then back to the configured voice.
+    fn next_profile(&mut self) {
+        let mut names: Vec<&String> = self.config.voice_profiles.keys().collect();
+        names.sort();
+        let next = match &self.profile {
+            Some(current) => names.iter().skip_while(|name| **name != current).nth(1),
+            None => names.first(),
+        };
+        self.profile = next.map(|name| name.to_string());
+        let text = format!("Voice: {}", self.profile.as_deref().unwrap_or("default"));
+        self.toast = Some(Toast::new(text, false));
+    }
+
+    /// Applies the cursor and selection settings to the text box. egui draws a
+    /// steady cursor, so it is hidden for the second half of each blink here.
+    fn style_text_box(&self, ui: &mut egui::Ui) {
+        let rate = f64::from(self.config.cursor_blink_rate_hz);
+        let mut cursor = ui.visuals().text_cursor.color;
+        if rate > 0. {
+            let phase = (ui.input(|i| i.time) * rate).fract();
+            if phase >= 0.5 {
+                cursor = Color32::TRANSPARENT;
+            }
+            let until_toggle = (0.5 - phase % 0.5) / rate;
+            ui.ctx()
+                .request_repaint_after(Duration::from_secs_f64(until_toggle));
+        }
+        let [r, g, b, a] = self.config.select_background_color;
+        let visuals = ui.visuals_mut();
+        visuals.text_cursor = Stroke::new(self.config.cursor_width, cursor);
+        visuals.selection.bg_fill = Color32::from_rgba_unmultiplied(r, g, b, a);
+    }
+
+    /// Wiggles the window sideways while `shake_until` is in the future.
+    fn shake(&mut self, ctx: &egui::Context) {
+        let Some(until) = self.shake_until else {
+            return;
+        };
+        let left = until
+            .saturating_duration_since(Instant::now())
+            .as_secs_f32();
+        if left == 0. {
+            self.shake_until = None;
+        }
+        let offset = (left * 60.).sin() * 8.;
+        ctx.send_viewport_cmd(egui::ViewportCommand::OuterPosition(
+            [self.config.x + offset, self.config.y].into(),
+        ));
+        ctx.request_repaint();
+    }
+
+    /// Whether losing focus speaks the text, with `speak_on_focus_loss`.
+    fn speaks_on_focus_loss(&self) -> bool {
+        self.config.speak_on_focus_loss
+            && self.text.trim().chars().count() >= self.config.min_text_length_to_speak.max(1)
+    }
+
+    /// Grows or shrinks the window to fit content taller than a single text box.
+    fn fit_height(&mut self, ctx: &egui::Context, height: f32) {
+        self.resize(ctx, self.window_width, height);
+    }
+
+    /// With `auto_width`, narrows the window to the text, or the hint while there
+    /// is none, within `min_width` and `width`.
+    fn fit_width(&mut self, ctx: &egui::Context) {
+        let shown = if self.text.is_empty() {
+            HINT
+        } else {
+            &self.text
+        };
+        let text_width = ctx.fonts(|fonts| {
+            fonts
+                .layout_no_wrap(shown.to_owned(), FontId::proportional(24.), Color32::WHITE)
+                .size()
+                .x
+        });
+        let width = (text_width + TEXT_PADDING + 2. * PANEL_MARGIN)
+            .clamp(self.config.min_width, self.config.width);
+        self.resize(ctx, width, self.window_height);
+    }
+
+    fn resize(&mut self, ctx: &egui::Context, width: f32, height: f32) {
+        if (width - self.window_width).abs() > 0.5 || (height - self.window_height).abs() > 0.5 {
+            self.window_width = width;
+            self.window_height = height;
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize([width, height].into()));
         }
     }
 }
 
 impl eframe::App for OverlayApp {
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
-        [0.; 4]
+        // needs the viewport's `with_transparent(true)` to show through
+        let [r, g, b] = self.config.background_color_rgb;
+        let alpha = (self.config.background_alpha * 255.).round() as u8;
+        Color32::from_rgba_unmultiplied(r, g, b, alpha).to_normalized_gamma_f32()
     }
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.show(ctx);
+    }
+}
+
+impl OverlayApp {
+    /// Draws a frame and handles its input. Apart from [`Effects`], what it does
+    /// shows in the frame's output, so tests drive this instead of
+    /// [`eframe::App::update`].
+    fn show(&mut self, ctx: &egui::Context) {
+        self.shake(ctx);
+        if self.config.drag_drop_enabled {
+            let files = ctx.input(|i| i.raw.dropped_files.clone());
+            if !files.is_empty() {
+                self.load_dropped(&files);
+            }
+        }
+        if let Some(at) = self.close_at {
+            if at <= Instant::now() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            } else {
+                ctx.request_repaint_after(at - Instant::now());
+            }
+        }
+        if let Some(toast) = &self.toast {
+            if toast.until <= Instant::now() {
+                self.toast = None;
+            } else {
+                ctx.request_repaint_after(toast.until - Instant::now());
+            }
+        }
         egui::CentralPanel::default()
             .frame(
                 Frame::central_panel(&ctx.style())
                     .fill(Color32::TRANSPARENT)
-                    .inner_margin(4.),
+                    .inner_margin(PANEL_MARGIN),
             )
             .show(ctx, |ui| {
+                if self.history.is_some() {
+                    self.show_history(ctx, ui);
+                    return;
+                }
+                self.style_text_box(ui);
+                // before the text box sees it, as egui's own undo would
+                if hotkeys::is_pressed(&self.config.hotkeys.undo, ctx) {
+                    if let Some(previous) = self.undo.undo(&self.text) {
+                        self.text = previous;
+                    }
+                }
+                // as wide as the window was last asked to be
+                let width = if self.config.auto_width {
+                    self.window_width - 2. * PANEL_MARGIN
+                } else {
+                    f32::INFINITY
+                };
+                let before = self.text.clone();
+                let [r, g, b, a] = self.config.hint_color;
                 let textbox = TextEdit::singleline(&mut self.text)
-                    .hint_text("What do you want to say?")
+                    .hint_text(
+                        RichText::new(HINT).color(Color32::from_rgba_unmultiplied(r, g, b, a)),
+                    )
                     .font(FontId::proportional(24.))
-                    .desired_width(f32::INFINITY);
-                let textbox = ui.add(textbox);
-                if !textbox.has_focus() && self.grace_period <= Instant::now() {
-                    if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
-                        if let Some(waiter) = self.waiter.take() {
-                            let text = self.text.clone();
-                            let config = self.config.clone();
-                            thread::spawn(move || {
-                                let client = reqwest::blocking::Client::new();
-                                if let Ok(resp) = client
-                                    .post("https://texttospeech.googleapis.com/v1/text:synthesize")
-                                    .json(&json!({
-                                      "input": {
-                                        "text": text
-                                      },
-                                      "voice": {
-                                        "languageCode": config.gcloud_language,
-                                        "name": config.gcloud_voice
-                                      },
-                                      "audioConfig": {
-                                        "audioEncoding": "LINEAR16"
-                                      }
-                                    }))
-                                    .header("X-goog-api-key", config.gcloud_token)
-                                    .header(ACCEPT, "application/json")
-                                    .send()
-                                {
-                                    if let Ok(value) = resp.json::<HashMap<String, String>>() {
-                                        if let Some(encoded) = value.get("audioContent") {
-                                            if let Ok(wav) =
-                                                base64::engine::general_purpose::STANDARD
-                                                    .decode(encoded)
-                                            {
-                                                let host = rodio::cpal::default_host();
-                                                if let Ok(devices) = host.output_devices() {
-                                                    for device in devices {
-                                                        if let Ok(name) = device.name() {
-                                                            if name.contains(&config.output_device)
-                                                            {
-                                                                if let Ok((_, handle)) =
-                                                                    rodio::OutputStream::try_from_device(&device)
-                                                                {
-                                                                    if let Ok(decoder) =
-                                                                        rodio::Decoder::new_wav(Cursor::new(wav))
-                                                                    {
-                                                                        if let Some(duration) =
-                                                                            decoder.total_duration()
-                                                                        {
-                                                                            if let Ok(()) = handle
-                                                                                .play_raw(decoder.convert_samples())
-                                                                            {
-                                                                                // for good measure
-                                                                                sleep(
-                                                                                    duration
-                                                                                        + Duration::from_millis(
-                                                                                            500,
-                                                                                        ),
-                                                                                );
-                                                                            };
-                                                                        }
-                                                                    };
-                                                                }
-                                                                break;
-                                                            }
-                                                        }
-                                                    }
-                                                };
-                                            }
-                                        }
-                                    }
-                                }
-                                _ = waiter.send(());
-                            });
-                        };
-                    } else if let Some(waiter) = self.waiter.take() {
-                        _ = waiter.send(());
+                    .desired_width(width)
+                    .lock_focus(true);
+                let mut output = textbox.show(ui);
+                if self.text != before {
+                    self.undo.typed(&before, Instant::now());
+                }
+                let typed = self.text.clone();
+                let completions =
+                    self.completions
+                        .show(ui, &mut output, &mut self.text, &self.config.hotkeys);
+                let menu =
+                    self.menu
+                        .show(ui, &mut output, &mut self.text, &self.config.quick_replies);
+                if self.text != typed {
+                    self.undo.replaced(&typed, &self.text);
+                }
+                if self.config.auto_width {
+                    self.fit_width(ctx);
+                }
+                let textbox = output.response;
+                if std::mem::take(&mut self.refocus) {
+                    textbox.request_focus();
+                }
+                if let Some(phonetics) = &mut self.phonetics {
+                    let (language, _) = self.config.active_voice();
+                    phonetics.update(
+                        ctx,
+                        &self.text,
+                        language,
+                        textbox.changed(),
+                        textbox.lost_focus(),
+                    );
+                    phonetics.show(ui);
+                }
+                let strip = (self.config.show_quick_replies
+                    && !self.config.quick_replies.is_empty())
+                .then(|| quick_replies::show(ui, &self.config.quick_replies));
+                let picked = strip
+                    .as_ref()
+                    .and_then(|strip| strip.picked)
+                    .map(|i| self.config.quick_replies[i].clone());
+                let too_long = self
+                    .config
+                    .max_chars
+                    .map(|max| (self.text.chars().count(), max))
+                    .filter(|(count, max)| count > max);
+                if let Some((count, max)) = too_long {
+                    ui.label(
+                        RichText::new(format!("{count} / {max}"))
+                            .size(14.)
+                            .color(Color32::RED),
+                    );
+                }
+                if let Some(notice) = &self.notice {
+                    ui.label(
+                        RichText::new(notice)
+                            .size(14.)
+                            .color(Color32::from_rgb(255, 160, 80)),
+                    );
+                }
+                if let Some(toast) = &self.toast {
+                    let color = if toast.error {
+                        Color32::RED
+                    } else {
+                        Color32::LIGHT_GREEN
+                    };
+                    ui.label(RichText::new(&toast.text).size(14.).color(color));
+                }
+                if self.config.show_session_stats {
+                    ui.label(RichText::new(self.metrics.status_line()).size(14.));
+                }
+                let (health, _) = health::current();
+                if health == health::Health::Unknown {
+                    ctx.request_repaint_after(Duration::from_millis(500));
+                }
+                // problems always show; all being well only with the other stats
+                let show_health = match health {
+                    health::Health::Ok | health::Health::Unknown => self.config.show_session_stats,
+                    _ => true,
+                };
+                if show_health {
+                    ui.label(
+                        RichText::new(health.line(self.config.provider.name()))
+                            .size(14.)
+                            .color(health.color()),
+                    );
+                }
+                let offline = health::unreachable();
+                if offline {
+                    ui.label(
+                        RichText::new(offline::notice(&self.config))
+                            .size(14.)
+                            .color(Color32::from_rgb(255, 160, 80)),
+                    );
+                }
+                if let Some(preview) = &mut self.preview {
+                    let max = self
+                        .config
+                        .max_chars
+                        .unwrap_or(self.config.clipboard_max_chars);
+                    preview.update(ctx, &self.text, max, textbox.changed());
+                    preview.show(ui);
+                }
+                if too_long.is_some()
+                    || self.notice.is_some()
+                    || self.toast.is_some()
+                    || show_health
+                    || offline
+                    || self.preview.is_some()
+                    || self.phonetics.is_some()
+                    || menu.bottom.is_some()
+                    || completions.is_some()
+                    || strip.is_some()
+                {
+                    let bottom = menu
+                        .bottom
+                        .into_iter()
+                        .chain(completions)
+                        .chain(strip.as_ref().and_then(|strip| strip.bottom))
+                        .fold(ui.min_rect().bottom(), f32::max);
+                    self.fit_height(ctx, bottom + 8.);
+                }
+                let keys = &self.config.hotkeys;
+                let submit = hotkeys::is_pressed(&keys.submit, ctx);
+                let cancel = hotkeys::is_pressed(&keys.cancel, ctx);
+                let replay = hotkeys::is_pressed(&keys.replay, ctx);
+                let speak_clipboard = hotkeys::is_pressed(&keys.speak_clipboard, ctx);
+                if hotkeys::is_pressed(&keys.next_profile, ctx) {
+                    self.next_profile();
+                }
+                if hotkeys::is_pressed(&self.config.hotkeys.history, ctx) {
+                    self.history = Some(HistoryBrowser::open(&self.config.message_log));
+                    ctx.request_repaint();
+                    return;
+                }
+                if cancel {
+                    if let Some(waiter) = self.waiter.take() {
+                        _ = waiter.send(Ok(()));
+                    }
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    return;
+                }
+                if let Some(reply) = &picked {
+                    *self
+                        .session
+                        .quick_reply_clicks
+                        .entry(reply.label.clone())
+                        .or_default() += 1;
+                }
+                let input = match (menu.speak, picked) {
+                    (Some(selection), _) => Some(selection),
+                    (None, Some(reply)) => Some(reply.text),
+                    (None, None) if submit => Some(self.text.clone()),
+                    (None, None) if replay && self.grace_period <= Instant::now() => {
+                        if self.replay() {
+                            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                            return;
+                        }
+                        None
+                    }
+                    (None, None) if speak_clipboard => {
+                        match arboard::Clipboard::new().and_then(|mut c| c.get_text()) {
+                            Ok(text) => Some(text),
+                            Err(e) => {
+                                self.notice = Some(format!("Could not read the clipboard: {e}"));
+                                None
+                            }
+                        }
+                    }
+                    (None, None) => None,
+                };
+                if let Some(input) = input.filter(|_| self.grace_period <= Instant::now()) {
+                    if let Err(notice) = self.submit(&input) {
+                        self.notice = Some(notice);
+                        textbox.request_focus();
+                        return;
                     }
                     ctx.send_viewport_cmd(egui::ViewportCommand::Close)
-                } else {
+                } else if menu.bottom.is_some()
+                    || menu.acted
+                    || strip.is_some_and(|strip| strip.hovered)
+                {
+                    textbox.request_focus();
+                } else if !textbox.has_focus() && self.grace_period <= Instant::now() {
+                    if self.speaks_on_focus_loss() {
+                        let text = self.text.clone();
+                        if let Err(notice) = self.submit(&text) {
+                            self.notice = Some(notice);
+                            textbox.request_focus();
+                            return;
+                        }
+                    }
+                    if let Some(waiter) = self.waiter.take() {
+                        _ = waiter.send(Ok(()));
+                    }
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close)
+                } else if !textbox.has_focus() {
+                    // only then, as asking again would undo `lock_focus` and let
+                    // Tab move the focus away
                     textbox.request_focus();
                 }
             });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use eframe::egui::{Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect};
+
+    use super::*;
+
+    /// What the overlay tests start from. `Default` leaves the fields serde fills
+    /// in at zero, and the library's `valid_config` isn't built for this crate.
+    fn config() -> Configuration {
+        Configuration {
+            speaking_rate: 1.0,
+            volume: 1.0,
+            ..Default::default()
+        }
+    }
+
+    /// Notes what the overlay asks to speak instead of speaking it.
+    struct Recorder(mpsc::Sender<Vec<String>>);
+
+    impl Effects for Recorder {
+        fn speak(
+            &mut self,
+            parts: Vec<String>,
+            _config: Configuration,
+            waiter: oneshot::Sender<WorkerResult>,
+        ) {
+            _ = self.0.send(parts);
+            _ = waiter.send(Ok(()));
+        }
+
+        fn replay(
+            &mut self,
+            last: history::Message,
+            _config: Configuration,
+            waiter: oneshot::Sender<WorkerResult>,
+        ) {
+            _ = self.0.send(vec![last.text]);
+            _ = waiter.send(Ok(()));
+        }
+
+        fn save_session(&mut self, _session: &Session) {}
+    }
+
+    /// The overlay in a headless egui context, fed input a frame at a time.
+    struct Harness {
+        ctx: egui::Context,
+        app: OverlayApp,
+        spoken: mpsc::Receiver<Vec<String>>,
+        done: oneshot::Receiver<WorkerResult>,
+        closed: bool,
+        /// The last size the overlay asked the window to be.
+        size: Option<egui::Vec2>,
+    }
+
+    impl Harness {
+        /// The overlay as it opens, after the first frames gave the text box focus.
+        fn open() -> Self {
+            Self::with_config(config())
+        }
+
+        fn with_config(config: Configuration) -> Self {
+            let pipeline = Arc::new(Pipeline::new(&config, true).unwrap());
+            let (waiter, done) = oneshot::channel();
+            let (spoke, spoken) = mpsc::channel();
+            let app = OverlayApp::new(
+                config,
+                pipeline,
+                waiter,
+                Session::default(),
+                Box::new(Recorder(spoke)),
+            );
+            let mut harness = Self {
+                ctx: egui::Context::default(),
+                app,
+                spoken,
+                done,
+                closed: false,
+                size: None,
+            };
+            // one to ask for the focus, one for the text box to lock it
+            harness.frame(Vec::new());
+            harness.frame(Vec::new());
+            harness
+        }
+
+        /// Ends the grace period, as if the overlay had been open for a while.
+        fn settle(self) -> Self {
+            let mut harness = self;
+            harness.app.grace_period = Instant::now();
+            harness
+        }
+
+        fn frame(&mut self, events: Vec<Event>) {
+            let input = RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, [400., 300.].into())),
+                events,
+                ..Default::default()
+            };
+            let output = self.ctx.run(input, |ctx| self.app.show(ctx));
+            for command in output
+                .viewport_output
+                .values()
+                .flat_map(|viewport| &viewport.commands)
+            {
+                match command {
+                    egui::ViewportCommand::Close => self.closed = true,
+                    egui::ViewportCommand::InnerSize(size) => self.size = Some(*size),
+                    _ => {}
+                }
+            }
+        }
+
+        fn type_text(&mut self, text: &str) {
+            self.frame(vec![Event::Text(text.into())]);
+        }
+
+        fn press(&mut self, key: Key) {
+            self.press_with(Modifiers::NONE, key);
+        }
+
+        fn press_with(&mut self, modifiers: Modifiers, key: Key) {
+            let event = |pressed| Event::Key {
+                key,
+                pressed,
+                repeat: false,
+                modifiers,
+            };
+            self.frame(vec![event(true), event(false)]);
+        }
+
+        /// Clicks below the text box, which takes the focus away from it.
+        fn click_elsewhere(&mut self) {
+            let pos = Pos2::new(200., 250.);
+            let button = |pressed| Event::PointerButton {
+                pos,
+                button: PointerButton::Primary,
+                pressed,
+                modifiers: Modifiers::NONE,
+            };
+            self.frame(vec![Event::PointerMoved(pos), button(true)]);
+            self.frame(vec![button(false)]);
+        }
+
+        fn spoken(&self) -> Vec<Vec<String>> {
+            self.spoken.try_iter().collect()
+        }
+    }
+
+    #[test]
+    fn enter_speaks_the_text_once_and_closes() {
+        let mut overlay = Harness::open().settle();
+        overlay.type_text("hello there");
+        assert!(!overlay.closed);
+        overlay.press(Key::Enter);
+        overlay.press(Key::Enter);
+        assert_eq!(overlay.spoken(), [["hello there"]]);
+        assert!(overlay.closed);
+        assert_eq!(overlay.done.try_recv(), Ok(Ok(())));
+    }
+
+    #[test]
+    fn escape_closes_without_speaking() {
+        let mut overlay = Harness::open().settle();
+        overlay.type_text("never mind");
+        overlay.press(Key::Escape);
+        assert!(overlay.spoken().is_empty());
+        assert!(overlay.closed);
+        assert_eq!(overlay.done.try_recv(), Ok(Ok(())));
+    }
+
+    #[test]
+    fn stays_open_through_the_grace_period() {
+        let mut overlay = Harness::open();
+        overlay.type_text("early");
+        overlay.click_elsewhere();
+        overlay.press(Key::Enter);
+        assert!(overlay.spoken().is_empty());
+        assert!(!overlay.closed);
+        // the text box took the focus back
+        overlay.app.grace_period = Instant::now();
+        overlay.press(Key::Enter);
+        assert_eq!(overlay.spoken(), [["early"]]);
+        assert!(overlay.closed);
+    }
+
+    #[test]
+    fn losing_focus_closes_without_speaking() {
+        let mut overlay = Harness::open().settle();
+        overlay.type_text("half a thought");
+        overlay.click_elsewhere();
+        assert!(overlay.spoken().is_empty());
+        assert!(overlay.closed);
+        assert_eq!(overlay.done.try_recv(), Ok(Ok(())));
+    }
+
+    #[test]
+    fn shows_the_latency_of_a_recent_message_only() {
+        let path = std::env::temp_dir().join(format!("latency-{}.jsonl", std::process::id()));
+        let mut config = Configuration {
+            show_latency: true,
+            ..config()
+        };
+        config.message_log.path = path.to_string_lossy().into_owned();
+        let mut message =
+            history::Message::new(history::Source::Overlay, "hi", "google", "en-US-Standard-A");
+        message.first_audio_ms = Some(820);
+        message.synthesis_ms = Some(640);
+        let toast = |message: &history::Message| {
+            _ = std::fs::remove_file(&path);
+            history::record_message(&config.message_log, message);
+            Harness::with_config(config.clone())
+                .app
+                .toast
+                .map(|toast| toast.text)
+        };
+        assert_eq!(
+            toast(&message).as_deref(),
+            Some("820 ms to speak (640 ms synthesis)")
+        );
+        message.timestamp -= LATENCY_TOAST_SECS + 1;
+        assert_eq!(toast(&message), None);
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn speaks_on_focus_loss_when_asked_to() {
+        let config = Configuration {
+            speak_on_focus_loss: true,
+            min_text_length_to_speak: 3,
+            ..config()
+        };
+        let mut overlay = Harness::with_config(config.clone()).settle();
+        overlay.type_text("half a thought");
+        overlay.click_elsewhere();
+        assert_eq!(overlay.spoken(), [["half a thought"]]);
+        assert!(overlay.closed);
+        for short in ["", " ok "] {
+            let mut overlay = Harness::with_config(config.clone()).settle();
+            overlay.type_text(short);
+            overlay.click_elsewhere();
+            assert!(overlay.spoken().is_empty(), "{short:?}");
+            assert!(overlay.closed);
+        }
+    }
+
+    #[test]
+    fn undoes_a_completion_in_one_step() {
+        let mut overlay = Harness::with_config(Configuration {
+            snippets: [("!hi".to_owned(), "Hello everyone!".to_owned())]
+                .into_iter()
+                .collect(),
+            ..config()
+        })
+        .settle();
+        overlay.type_text("!h");
+        overlay.press(Key::Tab);
+        assert_eq!(overlay.app.text, "!hi");
+        overlay.type_text(" :)");
+        // as eframe reports Ctrl outside macOS
+        let ctrl = Modifiers {
+            ctrl: true,
+            command: true,
+            ..Modifiers::NONE
+        };
+        for expected in ["!hi", "!h", ""] {
+            overlay.press_with(ctrl, Key::Z);
+            assert_eq!(overlay.app.text, expected);
+        }
+        assert!(!overlay.closed);
+    }
+
+    #[test]
+    fn auto_width_follows_the_text_within_its_limits() {
+        let mut overlay = Harness::with_config(Configuration {
+            width: 600.,
+            auto_width: true,
+            min_width: 100.,
+            ..config()
+        })
+        .settle();
+        let width = |overlay: &Harness| overlay.size.unwrap().x;
+        // the hint shows in full
+        let empty = width(&overlay);
+        assert!((100.0..600.).contains(&empty), "{empty}");
+        overlay.type_text("hi");
+        assert_eq!(width(&overlay), 100.);
+        overlay.type_text(" there, this is a message long enough to need the full width");
+        assert_eq!(width(&overlay), 600.);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::offline;
+
+/// Process-wide metrics, exposed in the Prometheus text format by the HTTP server.
+pub static METRICS: MetricsRegistry = MetricsRegistry::new();
+
+/// Upper bounds (in seconds) of the histogram buckets.
+const BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60.];
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    const fn new() -> Self {
+        Self(AtomicI64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counters keyed by a label value, or a tuple of them for several labels.
+pub struct LabeledCounter<K = &'static str>(Mutex<BTreeMap<K, u64>>);
+
+impl<K: Ord + Clone> LabeledCounter<K> {
+    const fn new() -> Self {
+        Self(Mutex::new(BTreeMap::new()))
+    }
+
+    pub fn add(&self, label: K, n: u64) {
+        if let Ok(mut map) = self.0.lock() {
+            *map.entry(label).or_default() += n;
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<K, u64> {
+        self.0.lock().map(|map| map.clone()).unwrap_or_default()
+    }
+}
+
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+    last_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+            last_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            if secs <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.last_micros
+            .store(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// The latest observation, from whichever thread made it; `--bench` reads it
+    /// after each run so that its numbers are the ones `/metrics` sees.
+    pub fn last(&self) -> Duration {
+        Duration::from_micros(self.last_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Runs `f`, recording how long it took.
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.observe(start.elapsed());
+        result
+    }
+}
+
+pub struct MetricsRegistry {
+    pub messages_spoken: Counter,
+    /// Synthesis requests by provider and outcome (`ok` or the error category).
+    pub requests: LabeledCounter<(&'static str, &'static str)>,
+    /// Synthesis requests to any provider, where `synthesis_latency` is Google's HTTP
+    /// round trip alone.
+    pub request_duration: Histogram,
+    /// Milliseconds of audio played.
+    pub audio_duration_ms: Counter,
+    pub characters_synthesized: LabeledCounter,
+    pub synthesis_latency: Histogram,
+    /// Decoding synthesized WAV audio before it is played.
+    pub decode_duration: Histogram,
+    pub time_to_first_audio: Histogram,
+    /// `time_to_first_audio` of the first message alone, which connects to the
+    /// provider unless the connection was prewarmed.
+    pub first_message_audio: Histogram,
+    /// 1 once the startup check left a connection to the provider open.
+    pub prewarmed: Gauge,
+    pub playback_duration: Histogram,
+    pub queue_depth: Gauge,
+    pub errors: LabeledCounter,
+    pub cache_hits: Counter,
+    pub cache_misses: Counter,
+}
+
+impl MetricsRegistry {
+    const fn new() -> Self {
+        Self {
+            messages_spoken: Counter::new(),
+            requests: LabeledCounter::new(),
+            request_duration: Histogram::new(),
+            audio_duration_ms: Counter::new(),
+            characters_synthesized: LabeledCounter::new(),
+            synthesis_latency: Histogram::new(),
+            decode_duration: Histogram::new(),
+            time_to_first_audio: Histogram::new(),
+            first_message_audio: Histogram::new(),
+            prewarmed: Gauge::new(),
+            playback_duration: Histogram::new(),
+            queue_depth: Gauge::new(),
+            errors: LabeledCounter::new(),
+            cache_hits: Counter::new(),
+            cache_misses: Counter::new(),
+        }
+    }
+
+    pub fn error(&self, category: &'static str) {
+        self.errors.add(category, 1);
+    }
+
+    pub fn request(&self, provider: &'static str, status: &'static str) {
+        self.requests.add((provider, status), 1);
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        counter(
+            &mut out,
+            "tts_messages_spoken_total",
+            "Messages played to completion.",
+            self.messages_spoken.get(),
+        );
+        _ = writeln!(
+            out,
+            "# HELP tts_requests_total Synthesis requests by provider and outcome."
+        );
+        _ = writeln!(out, "# TYPE tts_requests_total counter");
+        for ((provider, status), n) in self.requests.snapshot() {
+            _ = writeln!(
+                out,
+                "tts_requests_total{{provider=\"{provider}\",status=\"{status}\"}} {n}"
+            );
+        }
+        histogram(
+            &mut out,
+            "tts_request_duration_seconds",
+            "Time taken by a synthesis request, from any provider.",
+            &self.request_duration,
+        );
+        _ = writeln!(out, "# HELP tts_audio_duration_seconds_total Audio played.");
+        _ = writeln!(out, "# TYPE tts_audio_duration_seconds_total counter");
+        _ = writeln!(
+            out,
+            "tts_audio_duration_seconds_total {}",
+            self.audio_duration_ms.get() as f64 / 1e3
+        );
+        labeled(
+            &mut out,
+            "tts_characters_synthesized_total",
+            "Characters sent for synthesis.",
+            "backend",
+            &self.characters_synthesized,
+        );
+        histogram(
+            &mut out,
+            "tts_synthesis_latency_seconds",
+            "Time spent waiting for the synthesis request.",
+            &self.synthesis_latency,
+        );
+        histogram(
+            &mut out,
+            "tts_decode_duration_seconds",
+            "Time spent decoding audio before playing it.",
+            &self.decode_duration,
+        );
+        histogram(
+            &mut out,
+            "tts_time_to_first_audio_seconds",
+            "Time from starting a message to handing audio to the device.",
+            &self.time_to_first_audio,
+        );
+        histogram(
+            &mut out,
+            "tts_first_message_time_to_first_audio_seconds",
+            "Time to first audio of the first message since startup.",
+            &self.first_message_audio,
+        );
+        _ = writeln!(
+            out,
+            "# HELP tts_connection_prewarmed Whether a connection to the provider was opened at startup."
+        );
+        _ = writeln!(out, "# TYPE tts_connection_prewarmed gauge");
+        _ = writeln!(out, "tts_connection_prewarmed {}", self.prewarmed.get());
+        histogram(
+            &mut out,
+            "tts_playback_duration_seconds",
+            "Length of the played audio.",
+            &self.playback_duration,
+        );
+        _ = writeln!(out, "# HELP tts_queue_depth Messages waiting to be spoken.");
+        _ = writeln!(out, "# TYPE tts_queue_depth gauge");
+        _ = writeln!(out, "tts_queue_depth {}", self.queue_depth.get());
+        _ = writeln!(
+            out,
+            "# HELP tts_offline_waiting Messages held until the speech service is reachable."
+        );
+        _ = writeln!(out, "# TYPE tts_offline_waiting gauge");
+        _ = writeln!(out, "tts_offline_waiting {}", offline::waiting());
+        labeled(
+            &mut out,
+            "tts_errors_total",
+            "Failed messages by the stage that failed.",
+            "category",
+            &self.errors,
+        );
+        counter(
+            &mut out,
+            "tts_cache_hits_total",
+            "Messages played from the audio cache.",
+            self.cache_hits.get(),
+        );
+        counter(
+            &mut out,
+            "tts_cache_misses_total",
+            "Messages that had to be synthesized despite the audio cache.",
+            self.cache_misses.get(),
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} counter");
+    _ = writeln!(out, "{name} {value}");
+}
+
+fn labeled(out: &mut String, name: &str, help: &str, label: &str, counter: &LabeledCounter) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} counter");
+    for (value, n) in counter.snapshot() {
+        _ = writeln!(out, "{name}{{{label}=\"{value}\"}} {n}");
+    }
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} histogram");
+    for (bucket, bound) in histogram.buckets.iter().zip(BUCKETS) {
+        let n = bucket.load(Ordering::Relaxed);
+        _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {n}");
+    }
+    let count = histogram.count.load(Ordering::Relaxed);
+    let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+    _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+    _ = writeln!(out, "{name}_sum {sum}");
+    _ = writeln!(out, "{name}_count {count}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_requests_by_provider_and_status() {
+        let metrics = MetricsRegistry::new();
+        metrics.request("google", "ok");
+        metrics.request("google", "ok");
+        metrics.request("google", "http");
+        metrics.request("mock", "ok");
+        metrics.audio_duration_ms.add(1500);
+        metrics.request_duration.observe(Duration::from_millis(200));
+        let rendered = metrics.render();
+        for line in [
+            r#"tts_requests_total{provider="google",status="http"} 1"#,
+            r#"tts_requests_total{provider="google",status="ok"} 2"#,
+            r#"tts_requests_total{provider="mock",status="ok"} 1"#,
+            r#"tts_request_duration_seconds_bucket{le="0.25"} 1"#,
+            r#"tts_request_duration_seconds_bucket{le="0.1"} 0"#,
+            "tts_audio_duration_seconds_total 1.5",
+            "tts_queue_depth 0",
+            "tts_connection_prewarmed 0",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "no {line} in\n{rendered}"
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Upgrades a `config.toml` written for an older version, so that renamed or
+//! removed settings keep working instead of failing to parse. Each config carries
+//! `config_version`; a file without one is version 1. When the upgrade changed a
+//! setting, the file is written back, with the original kept next to it, as the
+//! rewrite loses its comments.
+
+use std::{fs, path::Path};
+
+use toml::{value::Table, Value};
+
+/// The `config_version` this version of the program writes.
+pub const CURRENT_VERSION: u32 = 2;
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// The migration from version `i + 1` to `i + 2`.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize - 1] = [v1_to_v2];
+
+/// The `config_version` of `raw`.
+pub fn version(raw: &Value) -> Result<u32, String> {
+    match raw.get("config_version") {
+        None => Ok(1),
+        Some(Value::Integer(version)) if *version >= 1 => u32::try_from(*version)
+            .map_err(|_| format!("config_version {version} is not a version")),
+        Some(version) => Err(format!(
+            "config_version must be a whole number from 1, got {version}"
+        )),
+    }
+}
+
+/// Applies the migrations from `from_version` on and sets `config_version` to
+/// the current one.
+pub fn migrate_config(mut raw: Value, from_version: u32) -> Result<Value, String> {
+    if from_version > CURRENT_VERSION {
+        return Err(format!(
+            "config_version {from_version} is from a newer version of tts-overlay, which writes up to {CURRENT_VERSION}"
+        ));
+    }
+    for migration in MIGRATIONS.iter().skip(from_version as usize - 1) {
+        raw = migration(raw)?;
+    }
+    table(&mut raw)?.insert(
+        "config_version".into(),
+        Value::Integer(CURRENT_VERSION.into()),
+    );
+    Ok(raw)
+}
+
+/// Reads the config at `path`, upgrading it first if it is from an older version.
+/// A file the migrations leave alone but for `config_version` is not rewritten.
+pub fn load(path: &Path) -> Result<Value, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let raw: Value = toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+    let from_version = version(&raw)?;
+    if from_version == CURRENT_VERSION {
+        return Ok(raw);
+    }
+    let mut stamped = raw.clone();
+    let raw = migrate_config(raw, from_version)?;
+    table(&mut stamped)?.insert(
+        "config_version".into(),
+        Value::Integer(CURRENT_VERSION.into()),
+    );
+    if raw == stamped {
+        return Ok(raw);
+    }
+    let backup = path.with_extension(format!("toml.v{from_version}"));
+    let written = toml::to_string(&raw)
+        .map_err(|e| e.to_string())
+        .and_then(|migrated| {
+            fs::write(&backup, &text).map_err(|e| format!("{}: {e}", backup.display()))?;
+            fs::write(path, migrated).map_err(|e| format!("{}: {e}", path.display()))
+        });
+    match written {
+        Ok(()) => log::info!(
+            "upgraded {} from config_version {from_version} to {CURRENT_VERSION}; the original is in {}",
+            path.display(),
+            backup.display()
+        ),
+        // the upgraded settings are still used, the file is just upgraded again
+        // next time
+        Err(e) => log::warn!(
+            "could not write the upgraded config back, so it is upgraded on each start: {e}"
+        ),
+    }
+    Ok(raw)
+}
+
+fn table(raw: &mut Value) -> Result<&mut Table, String> {
+    raw.as_table_mut()
+        .ok_or_else(|| "the config is not a table of settings".to_owned())
+}
+
+/// `show_phonetics = true` became `phonetics_backend = "espeak-ng"` when there
+/// came to be a choice of backend.
+fn v1_to_v2(mut raw: Value) -> Result<Value, String> {
+    let config = table(&mut raw)?;
+    match config.remove("show_phonetics") {
+        None | Some(Value::Boolean(false)) => {}
+        Some(Value::Boolean(true)) => {
+            config
+                .entry("phonetics_backend")
+                .or_insert_with(|| "espeak-ng".into());
+        }
+        Some(other) => return Err(format!("show_phonetics must be true or false, got {other}")),
+    }
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toml(text: &str) -> Value {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn replaces_show_phonetics_with_the_backend() {
+        for (before, after) in [
+            (
+                "font_size = 24.0\nshow_phonetics = true\n",
+                "font_size = 24.0\nphonetics_backend = \"espeak-ng\"\n",
+            ),
+            (
+                "font_size = 24.0\nshow_phonetics = false\n",
+                "font_size = 24.0\n",
+            ),
+            (
+                "show_phonetics = true\nphonetics_backend = \"disabled\"\n",
+                "phonetics_backend = \"disabled\"\n",
+            ),
+            ("font_size = 24.0\n", "font_size = 24.0\n"),
+        ] {
+            assert_eq!(v1_to_v2(toml(before)).unwrap(), toml(after), "{before}");
+        }
+        assert_eq!(
+            v1_to_v2(toml("show_phonetics = \"yes\"")).unwrap_err(),
+            "show_phonetics must be true or false, got \"yes\""
+        );
+    }
+
+    #[test]
+    fn migrates_to_the_current_version() {
+        let raw = toml("show_phonetics = true\n");
+        assert_eq!(version(&raw), Ok(1));
+        let migrated = migrate_config(raw, 1).unwrap();
+        assert_eq!(
+            migrated,
+            toml("phonetics_backend = \"espeak-ng\"\nconfig_version = 2\n")
+        );
+        assert_eq!(version(&migrated), Ok(CURRENT_VERSION));
+        assert_eq!(migrate_config(migrated.clone(), 2), Ok(migrated));
+        let error = migrate_config(toml("config_version = 3"), 3).unwrap_err();
+        assert!(
+            error.starts_with("config_version 3 is from a newer"),
+            "{error}"
+        );
+        assert!(version(&toml("config_version = 0")).is_err());
+    }
+
+    #[test]
+    fn writes_the_upgrade_back_and_keeps_the_original() {
+        let path = std::env::temp_dir().join(format!("config-{}.toml", std::process::id()));
+        let original = "# mine\nshow_phonetics = true\n";
+        fs::write(&path, original).unwrap();
+        let migrated = load(&path).unwrap();
+        assert_eq!(version(&migrated), Ok(CURRENT_VERSION));
+        assert_eq!(
+            toml(&fs::read_to_string(&path).unwrap()),
+            toml("phonetics_backend = \"espeak-ng\"\nconfig_version = 2\n")
+        );
+        let backup = path.with_extension("toml.v1");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        // already current, so left alone
+        assert_eq!(load(&path).unwrap(), migrated);
+        _ = fs::remove_file(path);
+        _ = fs::remove_file(backup);
+    }
+
+    #[test]
+    fn leaves_a_file_with_nothing_to_upgrade_alone() {
+        let path = std::env::temp_dir().join(format!("config-current-{}.toml", std::process::id()));
+        let original = "# mine\nfont_size = 24.0 # big\n";
+        fs::write(&path, original).unwrap();
+        assert_eq!(
+            load(&path).unwrap(),
+            toml("font_size = 24.0\nconfig_version = 2\n")
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert!(!path.with_extension("toml.v1").exists());
+        _ = fs::remove_file(path);
+    }
+}
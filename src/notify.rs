@@ -0,0 +1,89 @@
+use std::{
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use crate::Configuration;
+
+const TITLE: &str = "TTS Overlay";
+
+/// Reports failed messages as desktop notifications, at most once per interval.
+pub struct Notifier {
+    enabled: bool,
+    interval: Duration,
+    last: Option<Instant>,
+    /// Never shown in a notification, even if an error message happens to contain it.
+    secret: String,
+}
+
+impl Notifier {
+    pub fn new(config: &Configuration) -> Self {
+        Self {
+            enabled: config.desktop_notifications,
+            interval: Duration::from_secs(config.notification_interval_secs),
+            last: None,
+            secret: config.gcloud_token.clone(),
+        }
+    }
+
+    /// Logs `message` and, unless one was shown recently, notifies the user about it.
+    pub fn failure(&mut self, message: &str) {
+        let mut body = format!("TTS failed: {message}");
+        if !self.secret.is_empty() {
+            body = body.replace(&self.secret, "***");
+        }
+        log::error!("{body}");
+        if !self.enabled || self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        if let Err(e) = show(&body) {
+            log::warn!("could not show desktop notification: {e}");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn show(body: &str) -> std::io::Result<()> {
+    Command::new("notify-send").args([TITLE, body]).spawn()?;
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn show(body: &str) -> std::io::Result<()> {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let script = format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(TITLE)
+    );
+    Command::new("osascript").args(["-e", &script]).spawn()?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn show(body: &str) -> std::io::Result<()> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let script = format!(
+        "[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+         $xml = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+         $text = $xml.GetElementsByTagName('text'); \
+         $text.Item(0).AppendChild($xml.CreateTextNode({title})) > $null; \
+         $text.Item(1).AppendChild($xml.CreateTextNode({body})) > $null; \
+         [Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier({title}).Show([Windows.UI.Notifications.ToastNotification]::new($xml))",
+        title = quote(TITLE),
+        body = quote(body),
+    );
+    Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .creation_flags(CREATE_NO_WINDOW)
+        .spawn()?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn show(_body: &str) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,225 @@
+//! Riding out short network outages: a message whose synthesis fails because the
+//! service can't be reached is tried again with backoff until it goes through or
+//! is `offline_max_age_secs` old. Messages queued behind it wait their turn, and
+//! those that grew too old while waiting are dropped rather than spoken late.
+//! The overlay says when a message sent now would be held, and holds it for
+//! less long, as nothing is on screen after sending.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use crate::{metrics::METRICS, speech::SynthesisError, Configuration};
+
+/// Wait before the first retry, doubled after each failed one.
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// How often a backoff checks whether the message was cancelled.
+const CANCEL_CHECK: Duration = Duration::from_millis(100);
+/// Cap on `offline_max_age_secs` for the overlay, which is gone once the
+/// message is sent and leaves nothing on screen while it is retried.
+pub const ONE_SHOT_MAX_AGE_SECS: u64 = 15;
+
+struct State {
+    /// When the current outage started, if there is one.
+    since: Option<Instant>,
+    /// When the last outage ended.
+    ended: Option<Instant>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    since: None,
+    ended: None,
+});
+
+/// How long the service has been unreachable, if it is.
+pub fn offline_for() -> Option<Duration> {
+    STATE.lock().unwrap().since.map(|since| since.elapsed())
+}
+
+/// Messages waiting for the service to be reachable again: the one being retried
+/// and those queued behind it.
+pub fn waiting() -> u64 {
+    match offline_for() {
+        Some(_) => METRICS.queue_depth.get().max(0) as u64 + 1,
+        None => 0,
+    }
+}
+
+/// What the overlay says about a message sent while the service can't be
+/// reached.
+pub fn notice(config: &Configuration) -> String {
+    if config.drop_when_offline {
+        "Offline: a message sent now fails".into()
+    } else {
+        format!(
+            "Offline: a message sent now is retried for up to {}s",
+            config.offline_max_age_secs
+        )
+    }
+}
+
+/// Whether a message submitted at `submitted_at` waited out an outage and is now
+/// too old to be worth speaking.
+pub fn is_stale(submitted_at: Instant, config: &Configuration) -> bool {
+    let state = STATE.lock().unwrap();
+    let outage = state.since.is_some() || state.ended.is_some_and(|ended| ended > submitted_at);
+    outage && submitted_at.elapsed() > Duration::from_secs(config.offline_max_age_secs)
+}
+
+/// Runs `attempt` until it succeeds, fails for another reason than connectivity,
+/// `cancel` is set or `offline_max_age_secs` have passed. With
+/// `drop_when_offline`, it runs once.
+pub fn retrying<T>(
+    config: &Configuration,
+    cancel: &AtomicBool,
+    attempt: impl FnMut() -> Result<T, SynthesisError>,
+) -> Result<T, SynthesisError> {
+    if config.drop_when_offline {
+        let mut attempt = attempt;
+        return attempt();
+    }
+    retrying_with(
+        Duration::from_secs(config.offline_max_age_secs),
+        FIRST_BACKOFF,
+        cancel,
+        attempt,
+        SynthesisError::is_offline,
+    )
+}
+
+fn retrying_with<T, E: std::fmt::Display>(
+    max_age: Duration,
+    first_backoff: Duration,
+    cancel: &AtomicBool,
+    mut attempt: impl FnMut() -> Result<T, E>,
+    is_offline: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let mut backoff = first_backoff;
+    loop {
+        match attempt() {
+            Ok(result) => {
+                back_online();
+                return Ok(result);
+            }
+            Err(e) if is_offline(&e) => {
+                went_offline(&e);
+                if start.elapsed() + backoff > max_age {
+                    log::warn!("giving up on a message after {:?} offline", start.elapsed());
+                    return Err(e);
+                }
+                if !wait(backoff, cancel) {
+                    return Err(e);
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn went_offline(e: &impl std::fmt::Display) {
+    let mut state = STATE.lock().unwrap();
+    if state.since.is_none() {
+        log::warn!("offline, holding messages until the service can be reached: {e}");
+        state.since = Some(Instant::now());
+    }
+}
+
+fn back_online() {
+    let mut state = STATE.lock().unwrap();
+    if let Some(since) = state.since.take() {
+        log::info!("back online after {:?}", since.elapsed());
+        state.ended = Some(Instant::now());
+    }
+}
+
+/// Sleeps for `duration`, returning `false` early if `cancel` is set.
+fn wait(duration: Duration, cancel: &AtomicBool) -> bool {
+    let until = Instant::now() + duration;
+    while Instant::now() < until {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        sleep(CANCEL_CHECK.min(until - Instant::now()));
+    }
+    !cancel.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    const SHORT: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn retries_until_the_service_is_back() {
+        let attempts = Cell::new(0);
+        let result = retrying_with(
+            Duration::from_secs(5),
+            SHORT,
+            &AtomicBool::new(false),
+            || {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 {
+                    Err("offline".to_owned())
+                } else {
+                    Ok("spoken")
+                }
+            },
+            |e| e == "offline",
+        );
+        assert_eq!(result, Ok("spoken"));
+        assert_eq!(attempts.get(), 3);
+        assert!(STATE.lock().unwrap().ended.is_some());
+    }
+
+    #[test]
+    fn gives_up_on_other_errors_old_messages_and_cancels() {
+        let attempts = Cell::new(0);
+        let fail = |error: &str| {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(error.to_owned())
+        };
+        let is_offline = |e: &String| e == "offline";
+        let keep_going = AtomicBool::new(false);
+        let long = Duration::from_secs(5);
+        assert!(retrying_with(long, SHORT, &keep_going, || fail("403"), is_offline).is_err());
+        assert_eq!(attempts.replace(0), 1);
+        assert!(retrying_with(
+            SHORT * 5,
+            SHORT,
+            &keep_going,
+            || fail("offline"),
+            is_offline
+        )
+        .is_err());
+        assert!((2..=4).contains(&attempts.replace(0)));
+        let cancelled = AtomicBool::new(true);
+        assert!(retrying_with(long, SHORT, &cancelled, || fail("offline"), is_offline).is_err());
+        assert_eq!(attempts.get(), 1);
+        back_online();
+    }
+
+    #[test]
+    fn tells_what_happens_to_a_message_sent_while_offline() {
+        let mut config = Configuration {
+            offline_max_age_secs: ONE_SHOT_MAX_AGE_SECS,
+            ..Default::default()
+        };
+        assert_eq!(
+            notice(&config),
+            "Offline: a message sent now is retried for up to 15s"
+        );
+        config.drop_when_offline = true;
+        assert_eq!(notice(&config), "Offline: a message sent now fails");
+    }
+}
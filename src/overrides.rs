@@ -0,0 +1,233 @@
+use eframe::epaint::ahash::HashMap;
+
+use crate::{Configuration, VoiceProfile};
+
+/// Google accepts speaking rates in this range.
+pub const RATES: std::ops::RangeInclusive<f64> = 0.25..=4.0;
+
+/// Volume multipliers are clamped to this range.
+pub const VOLUMES: std::ops::RangeInclusive<f32> = 0.01..=5.0;
+
+/// Voice settings for a single message, given as a leading
+/// `[voice=en-GB-News-K lang=en-GB rate=1.3 tr=es vol=1.5]`, or `[vol:1.5]` for
+/// the volume alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overrides {
+    pub voice: Option<String>,
+    pub language: Option<String>,
+    pub rate: Option<f64>,
+    /// Language to translate the message to before it is spoken.
+    pub translate_to: Option<String>,
+    /// Multiplies the configured `volume`.
+    pub volume: Option<f32>,
+}
+
+impl Overrides {
+    /// Changes `config` so that it speaks with these settings.
+    pub fn apply(&self, config: &mut Configuration) {
+        if self.voice.is_some() || self.language.is_some() {
+            let (language, voice) = config.active_voice();
+            let (mut language, mut voice) = (language.to_owned(), voice.to_owned());
+            if let Some(new_voice) = &self.voice {
+                language = language_of(new_voice).unwrap_or(language);
+                voice = new_voice.clone();
+            }
+            if let Some(new_language) = &self.language {
+                // let Google pick a voice for the language rather than fail on a mismatch
+                if self.voice.is_none() && language_of(&voice).as_ref() != Some(new_language) {
+                    voice.clear();
+                }
+                language = new_language.clone();
+            }
+            // a scheduled profile would win over the top-level voice otherwise
+            config.voice_schedule.clear();
+            config.default_profile.clear();
+            config.gcloud_language = language;
+            config.gcloud_voice = voice;
+        }
+        if let Some(rate) = self.rate {
+            config.speaking_rate = rate;
+        }
+        if let Some(target) = &self.translate_to {
+            config.translation.target.clone_from(target);
+        }
+        if let Some(volume) = self.volume {
+            config.volume *= volume;
+        }
+    }
+}
+
+/// Splits a leading `[vol:1.5]` off `text`, returning the volume multiplier,
+/// clamped to `VOLUMES`, and the rest. Without one the multiplier is 1.0 and
+/// `text` is returned as it is.
+pub fn parse_volume_modifier(text: &str) -> (f32, &str) {
+    let volume = text
+        .trim_start()
+        .strip_prefix("[vol:")
+        .and_then(|inner| inner.split_once(']'))
+        .and_then(|(value, rest)| match value.trim().parse::<f32>() {
+            Ok(volume) if volume.is_finite() => Some((volume, rest)),
+            _ => {
+                log::warn!("ignoring [vol:{value}]: expected a number");
+                None
+            }
+        });
+    match volume {
+        Some((volume, rest)) => (
+            volume.clamp(*VOLUMES.start(), *VOLUMES.end()),
+            rest.trim_start(),
+        ),
+        None => (1.0, text),
+    }
+}
+
+/// Splits a leading `[vol:...]`, then a `[key=value ...]` or language tag such as
+/// `[de]` off `text`, looking the tag up in `profiles`. Text with an unknown tag
+/// or with brackets that don't hold `key=value` pairs is returned as it is. Text
+/// that starts with `\[` is too, without the backslash that escaped the bracket.
+pub fn parse(text: &str, profiles: &HashMap<String, VoiceProfile>) -> (Overrides, String) {
+    let (volume, rest) = parse_volume_modifier(text);
+    if rest.len() != text.len() {
+        let (mut overrides, text) = parse_settings(rest, profiles);
+        overrides.volume = Some(volume);
+        return (overrides, text);
+    }
+    parse_settings(text, profiles)
+}
+
+fn parse_settings(text: &str, profiles: &HashMap<String, VoiceProfile>) -> (Overrides, String) {
+    let trimmed = text.trim_start();
+    if let Some(escaped) = trimmed.strip_prefix("\\[") {
+        return (Overrides::default(), format!("[{escaped}"));
+    }
+    let Some((inner, rest)) = trimmed
+        .strip_prefix('[')
+        .and_then(|inner| inner.split_once(']'))
+    else {
+        return (Overrides::default(), text.to_owned());
+    };
+    if let Some(profile) = language_profile(inner, profiles) {
+        let overrides = Overrides {
+            voice: Some(profile.gcloud_voice.clone()),
+            language: Some(profile.gcloud_language.clone()),
+            ..Default::default()
+        };
+        return (overrides, rest.trim_start().to_owned());
+    }
+    let pairs: Option<Vec<(&str, &str)>> = inner
+        .split_whitespace()
+        .map(|pair| {
+            pair.split_once('=')
+                .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+        })
+        .collect();
+    let pairs = match pairs {
+        Some(pairs) if !pairs.is_empty() => pairs,
+        _ => return (Overrides::default(), text.to_owned()),
+    };
+    let mut overrides = Overrides::default();
+    for (key, value) in pairs {
+        match key.to_ascii_lowercase().as_str() {
+            "voice" => overrides.voice = Some(value.to_owned()),
+            "lang" | "language" => overrides.language = Some(value.to_owned()),
+            "tr" | "translate" => overrides.translate_to = Some(value.to_owned()),
+            "rate" => match value.parse() {
+                Ok(rate) if RATES.contains(&rate) => overrides.rate = Some(rate),
+                _ => log::warn!(
+                    "ignoring rate={value}: expected a number from {} to {}",
+                    RATES.start(),
+                    RATES.end()
+                ),
+            },
+            "vol" | "volume" => match value.parse::<f32>() {
+                Ok(volume) if volume.is_finite() => {
+                    overrides.volume = Some(volume.clamp(*VOLUMES.start(), *VOLUMES.end()))
+                }
+                _ => log::warn!("ignoring vol={value}: expected a number"),
+            },
+            _ => log::warn!("ignoring unknown message setting {key}={value}"),
+        }
+    }
+    (overrides, rest.trim_start().to_owned())
+}
+
+/// The voice profile for a language tag such as "de": the profile named after it,
+/// or else the first profile by name whose language is that language.
+pub fn language_profile<'a>(
+    tag: &str,
+    profiles: &'a HashMap<String, VoiceProfile>,
+) -> Option<&'a VoiceProfile> {
+    if tag.len() != 2 || !tag.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    if let Some(profile) = profiles.get(tag) {
+        return Some(profile);
+    }
+    let mut matching: Vec<(&String, &VoiceProfile)> = profiles
+        .iter()
+        .filter(|(_, profile)| {
+            let language = profile.gcloud_language.split(['-', '_']).next();
+            language.is_some_and(|language| language.eq_ignore_ascii_case(tag))
+        })
+        .collect();
+    matching.sort_by_key(|(name, _)| *name);
+    matching.first().map(|(_, profile)| *profile)
+}
+
+/// The language code at the start of a voice name, e.g. "en-GB" for "en-GB-News-K".
+fn language_of(voice: &str) -> Option<String> {
+    let mut parts = voice.splitn(3, '-');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(language), Some(region), Some(_)) if !language.is_empty() && !region.is_empty() => {
+            Some(format!("{language}-{region}"))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_defaults_to_one() {
+        assert_eq!(parse_volume_modifier("hello"), (1.0, "hello"));
+        assert_eq!(parse_volume_modifier(""), (1.0, ""));
+        assert_eq!(parse_volume_modifier("[vol:abc] hi"), (1.0, "[vol:abc] hi"));
+        assert_eq!(parse_volume_modifier("[vol:2 hi"), (1.0, "[vol:2 hi"));
+        assert_eq!(parse_volume_modifier(r"\[vol:2] hi"), (1.0, r"\[vol:2] hi"));
+    }
+
+    #[test]
+    fn volume_prefix_is_split_off() {
+        assert_eq!(parse_volume_modifier("[vol:1.5] alert!"), (1.5, "alert!"));
+        assert_eq!(parse_volume_modifier("  [vol: 0.3 ]psst"), (0.3, "psst"));
+    }
+
+    #[test]
+    fn volume_is_clamped() {
+        assert_eq!(parse_volume_modifier("[vol:10] a"), (5.0, "a"));
+        assert_eq!(parse_volume_modifier("[vol:0] a"), (0.01, "a"));
+        assert_eq!(parse_volume_modifier("[vol:-3] a"), (0.01, "a"));
+        assert_eq!(parse_volume_modifier("[vol:inf] a"), (1.0, "[vol:inf] a"));
+    }
+
+    #[test]
+    fn volume_only_counts_at_the_start() {
+        let text = "say [vol:2] this";
+        assert_eq!(parse_volume_modifier(text), (1.0, text));
+        let (overrides, rest) = parse(text, &HashMap::default());
+        assert_eq!((overrides.volume, rest.as_str()), (None, text));
+    }
+
+    #[test]
+    fn volume_combines_with_other_settings() {
+        let (overrides, rest) = parse("[vol:2] [rate=1.5] hi", &HashMap::default());
+        assert_eq!(overrides.volume, Some(2.0));
+        assert_eq!(overrides.rate, Some(1.5));
+        assert_eq!(rest, "hi");
+        let (overrides, rest) = parse("[rate=1.5 vol=0.5] hi", &HashMap::default());
+        assert_eq!(overrides.volume, Some(0.5));
+        assert_eq!(rest, "hi");
+    }
+}
@@ -0,0 +1,255 @@
+//! The IPA transcription shown under the text box with `phonetics_backend`, so
+//! the pronunciation can be checked before a message is spoken. Google's API has
+//! no way to return one, so it comes from `espeak-ng --ipa`, run in the
+//! background.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{self, FontId, RichText};
+use serde::{Deserialize, Serialize};
+
+use crate::Configuration;
+
+const PROGRAM: &str = "espeak-ng";
+
+/// Typing pauses this long, or the text box loses focus, before the
+/// transcription catches up, so espeak is not started for every key.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Transcriptions kept, so that going back to earlier text does not run espeak
+/// again.
+const CACHE_ENTRIES: usize = 200;
+
+/// Where the transcription under the text box comes from, as `phonetics_backend`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PhoneticsBackend {
+    /// No transcription.
+    #[default]
+    Disabled,
+    /// `espeak-ng --ipa`, which has to be on the `PATH`.
+    EspeakNg,
+    /// Not available: Google's API does not return pronunciations.
+    GoogleApi,
+}
+
+impl PhoneticsBackend {
+    pub fn validate(self) -> Result<(), String> {
+        match self {
+            Self::GoogleApi => Err(
+                "phonetics_backend = \"google-api\" is not available, as Google's API does not return pronunciations; use \"espeak-ng\"".into(),
+            ),
+            Self::Disabled | Self::EspeakNg => Ok(()),
+        }
+    }
+}
+
+/// Whether the overlay shows a transcription.
+pub fn enabled(config: &Configuration) -> bool {
+    config.phonetics_backend == PhoneticsBackend::EspeakNg
+}
+
+/// Transcriptions by text and language, with when each was last used.
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<(String, String), (String, u64)>,
+    uses: u64,
+}
+
+impl Cache {
+    fn get(&mut self, key: &(String, String)) -> Option<String> {
+        self.uses += 1;
+        let (ipa, used) = self.entries.get_mut(key)?;
+        *used = self.uses;
+        Some(ipa.clone())
+    }
+
+    /// Adds `ipa`, forgetting the least recently used transcription when full.
+    fn put(&mut self, key: (String, String), ipa: String) {
+        if self.entries.len() >= CACHE_ENTRIES && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.uses += 1;
+        self.entries.insert(key, (ipa, self.uses));
+    }
+}
+
+/// The dimmed line under the text box.
+pub struct Phonetics {
+    /// Text and language the shown or pending transcription is for.
+    source: (String, String),
+    /// The transcription, or why there is none.
+    shown: Option<Result<String, String>>,
+    pending: Option<Receiver<Result<String, String>>>,
+    edited_at: Instant,
+    cache: Cache,
+}
+
+impl Default for Phonetics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Phonetics {
+    pub fn new() -> Self {
+        Self {
+            source: Default::default(),
+            shown: None,
+            pending: None,
+            edited_at: Instant::now(),
+            cache: Cache::default(),
+        }
+    }
+
+    /// Transcribes `text` in `language` once it has not changed for the debounce
+    /// period or the text box has lost focus, and picks up a finished
+    /// transcription.
+    pub fn update(
+        &mut self,
+        ctx: &egui::Context,
+        text: &str,
+        language: &str,
+        edited: bool,
+        lost_focus: bool,
+    ) {
+        if edited {
+            self.edited_at = Instant::now();
+        }
+        if let Some(pending) = &self.pending {
+            match pending.try_recv() {
+                Ok(result) => {
+                    if let Ok(ipa) = &result {
+                        self.cache.put(self.source.clone(), ipa.clone());
+                    }
+                    self.shown = Some(result);
+                    self.pending = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.pending = None,
+            }
+        }
+        let key = (text.trim().to_owned(), language.to_owned());
+        if key == self.source {
+            return;
+        }
+        let idle = self.edited_at.elapsed();
+        if idle < DEBOUNCE && !lost_focus {
+            ctx.request_repaint_after(DEBOUNCE - idle);
+            return;
+        }
+        self.source = key.clone();
+        self.pending = None;
+        if key.0.is_empty() {
+            self.shown = None;
+            return;
+        }
+        if let Some(ipa) = self.cache.get(&key) {
+            self.shown = Some(Ok(ipa));
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            _ = sender.send(text_to_ipa(&key.0, &key.1));
+            ctx.request_repaint();
+        });
+        self.pending = Some(receiver);
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui) {
+        let line = match &self.shown {
+            Some(Ok(ipa)) => format!("/{ipa}/"),
+            Some(Err(e)) => e.clone(),
+            None => return,
+        };
+        ui.label(RichText::new(line).font(FontId::proportional(14.)).weak());
+    }
+}
+
+/// The IPA transcription of `text` in the BCP 47 `lang`, falling back to the
+/// language without its region, as espeak-ng has no voice for most regions.
+pub fn text_to_ipa(text: &str, lang: &str) -> Result<String, String> {
+    let voice = lang.to_lowercase().replace('_', "-");
+    let primary = voice.split('-').next().unwrap_or_default().to_owned();
+    match espeak(text, &voice) {
+        Err(_) if primary != voice => espeak(text, &primary),
+        result => result,
+    }
+}
+
+fn espeak(text: &str, voice: &str) -> Result<String, String> {
+    let not_run = |e: io::Error| match e.kind() {
+        io::ErrorKind::NotFound => {
+            format!("{PROGRAM} is not installed, so there is no transcription")
+        }
+        _ => format!("could not run {PROGRAM}: {e}"),
+    };
+    // on stdin, so that text starting with `-` is not taken for an option
+    let mut child = Command::new(PROGRAM)
+        .args(["--ipa", "-q", "--stdin", "-v", voice])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(not_run)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(not_run)?;
+    }
+    let output = child.wait_with_output().map_err(not_run)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !stderr.trim().is_empty() {
+        return Err(format!("{PROGRAM}: {}", stderr.trim()));
+    }
+    Ok(to_line(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// espeak-ng puts each clause on its own line.
+fn to_line(ipa: &str) -> String {
+    ipa.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> (String, String) {
+        (text.to_owned(), "en-US".to_owned())
+    }
+
+    #[test]
+    fn forgets_the_least_recently_used_transcription() {
+        let mut cache = Cache::default();
+        for i in 0..CACHE_ENTRIES {
+            cache.put(key(&i.to_string()), format!("ipa {i}"));
+        }
+        assert_eq!(cache.get(&key("0")).as_deref(), Some("ipa 0"));
+        cache.put(key("new"), "nˈuː".into());
+        assert_eq!(cache.entries.len(), CACHE_ENTRIES);
+        cache.put(key("new"), "nˈuː".into());
+        assert_eq!(cache.entries.len(), CACHE_ENTRIES);
+        assert_eq!(cache.get(&key("1")), None);
+        assert_eq!(cache.get(&key("0")).as_deref(), Some("ipa 0"));
+        assert_eq!(cache.get(&key("new")).as_deref(), Some("nˈuː"));
+        assert_eq!(cache.get(&("new".into(), "de-DE".into())), None);
+    }
+
+    #[test]
+    fn joins_clauses_on_one_line() {
+        assert_eq!(to_line(" həlˈəʊ\n wˈɜːld\n"), "həlˈəʊ wˈɜːld");
+    }
+}
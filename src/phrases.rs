@@ -0,0 +1,84 @@
+use rand::seq::SliceRandom;
+
+use crate::{session::Session, Configuration};
+
+/// If `text` is a random phrase trigger, picks a phrase from the matching set.
+pub fn pick_random(text: &str, config: &Configuration, session: &mut Session) -> Option<String> {
+    let text = text.trim();
+    let (name, set) = config
+        .random_phrase_sets
+        .iter()
+        .find(|(name, _)| config.random_phrase_trigger.replace("{set}", name) == text)?;
+    let last = session.random_last.get(name);
+    let candidates: Vec<&String> = set
+        .iter()
+        .filter(|phrase| !(config.random_no_repeat && set.len() > 1 && Some(*phrase) == last))
+        .collect();
+    let phrase = (*candidates.choose(&mut rand::thread_rng())?).clone();
+    session.random_last.insert(name.clone(), phrase.clone());
+    Some(phrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(no_repeat: bool) -> Configuration {
+        Configuration {
+            random_phrase_trigger: "[random:{set}]".into(),
+            random_phrase_sets: [
+                (
+                    "hi".to_owned(),
+                    vec!["Hello!".to_owned(), "Hey there!".to_owned()],
+                ),
+                ("bye".to_owned(), vec!["See you!".to_owned()]),
+            ]
+            .into_iter()
+            .collect(),
+            random_no_repeat: no_repeat,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn picks_from_the_set_the_trigger_names() {
+        let (config, mut session) = (config(true), Session::default());
+        assert_eq!(
+            pick_random(" [random:bye] ", &config, &mut session),
+            Some("See you!".into())
+        );
+        assert_eq!(session.random_last["bye"], "See you!");
+        for text in ["[random:nope]", "[random:bye] now", "hello"] {
+            assert_eq!(pick_random(text, &config, &mut session), None, "{text}");
+        }
+        assert!(!session.random_last.contains_key("nope"));
+    }
+
+    #[test]
+    fn never_picks_the_same_phrase_twice_in_a_row() {
+        let (config, mut session) = (config(true), Session::default());
+        let mut last = pick_random("[random:hi]", &config, &mut session).unwrap();
+        for _ in 0..50 {
+            let phrase = pick_random("[random:hi]", &config, &mut session).unwrap();
+            assert_ne!(phrase, last);
+            assert_eq!(session.random_last["hi"], phrase);
+            last = phrase;
+        }
+        // unless the set has nothing else to pick
+        for _ in 0..3 {
+            assert_eq!(
+                pick_random("[random:bye]", &config, &mut session),
+                Some("See you!".into())
+            );
+        }
+    }
+
+    #[test]
+    fn may_repeat_without_random_no_repeat() {
+        let (config, mut session) = (config(false), Session::default());
+        let picks: Vec<_> = (0..100)
+            .map(|_| pick_random("[random:hi]", &config, &mut session).unwrap())
+            .collect();
+        assert!(picks.windows(2).any(|pair| pair[0] == pair[1]));
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use eframe::{
+    egui::{self, RichText, ScrollArea},
+    epaint::Color32,
+};
+
+use crate::chunking;
+
+/// Typing pauses this long before the preview catches up.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// More chunks than this scroll instead of growing the window.
+const VISIBLE_CHUNKS: f32 = 5.;
+
+const ROW_HEIGHT: f32 = 20.;
+
+/// The split-screen pane showing how the input will be chunked.
+pub struct Preview {
+    /// Input the chunks were computed from.
+    source: String,
+    chunks: Vec<String>,
+    edited_at: Instant,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preview {
+    pub fn new() -> Self {
+        Self {
+            source: String::new(),
+            chunks: Vec::new(),
+            edited_at: Instant::now(),
+        }
+    }
+
+    /// Re-chunks `text` once it has not changed for the debounce period.
+    pub fn update(&mut self, ctx: &egui::Context, text: &str, max_chars: usize, edited: bool) {
+        if edited {
+            self.edited_at = Instant::now();
+        }
+        if text == self.source {
+            return;
+        }
+        let idle = self.edited_at.elapsed();
+        if idle < DEBOUNCE {
+            ctx.request_repaint_after(DEBOUNCE - idle);
+            return;
+        }
+        self.source = text.to_owned();
+        self.chunks = chunking::chunk_text_by_sentences(text, max_chars);
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui) {
+        ui.separator();
+        ScrollArea::vertical()
+            .max_height(ROW_HEIGHT * VISIBLE_CHUNKS)
+            .auto_shrink([false, true])
+            .show(ui, |ui| {
+                if self.chunks.is_empty() {
+                    ui.label(RichText::new("Nothing to speak yet").size(14.).weak());
+                }
+                for (i, chunk) in self.chunks.iter().enumerate() {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(
+                            RichText::new(format!("{}.", i + 1))
+                                .size(14.)
+                                .color(Color32::GRAY),
+                        );
+                        ui.label(RichText::new(chunk.trim()).size(14.));
+                    });
+                }
+            });
+    }
+}
@@ -0,0 +1,356 @@
+use std::ops::Range;
+
+use eframe::epaint::ahash::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    filters::caps::{self, Caps, CapsMode},
+    Configuration,
+};
+
+/// How a word from `[pronunciations]` is said.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Pronunciation {
+    /// `word = "IPA"`
+    Ipa(String),
+    /// `word = { ipa = "IPA", respelling = "..." }`; the respelling is spoken by
+    /// providers without SSML support.
+    Full {
+        ipa: String,
+        #[serde(default)]
+        respelling: Option<String>,
+    },
+}
+
+impl Pronunciation {
+    fn ipa(&self) -> &str {
+        match self {
+            Self::Ipa(ipa) | Self::Full { ipa, .. } => ipa,
+        }
+    }
+
+    fn respelling(&self) -> Option<&str> {
+        match self {
+            Self::Ipa(_) => None,
+            Self::Full { respelling, .. } => respelling.as_deref(),
+        }
+    }
+}
+
+/// What is sent to the provider for a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Text(String),
+    /// A complete `<speak>` document.
+    Ssml(String),
+}
+
+impl Input {
+    /// `text` with the configured pronunciations: as SSML with `<phoneme>` tags if
+    /// the provider supports it, otherwise with the respellings. With SSML and
+    /// `caps_mode = "emphasis"`, shouted words are stressed too, and with
+    /// `subtitle_overlay` every word gets a `<mark/>` named after its index so the
+    /// provider reports when it is spoken. Text without any of this is sent as is.
+    pub fn new(text: &str, config: &Configuration) -> Self {
+        let ssml = config.provider.supports_ssml();
+        let mut marks: Vec<(Range<usize>, Markup)> = find(text, &config.pronunciations)
+            .into_iter()
+            .map(|(range, pronunciation)| (range, Markup::Phoneme(pronunciation)))
+            .collect();
+        if ssml && config.caps_mode == CapsMode::Emphasis {
+            for word in Caps::new(config).find(text) {
+                let overlaps = |(range, _): &(Range<usize>, Markup)| {
+                    range.start < word.end && word.start < range.end
+                };
+                if !marks.iter().any(overlaps) {
+                    marks.push((word, Markup::Emphasis));
+                }
+            }
+            marks.sort_by_key(|(range, _)| range.start);
+        }
+        let subtitles = ssml && config.subtitle_overlay;
+        if marks.is_empty() && !subtitles {
+            return Self::Text(text.to_owned());
+        }
+        let words = if subtitles {
+            word_starts(text)
+        } else {
+            Vec::new()
+        };
+        let mut out = String::with_capacity(text.len() * 2);
+        let mut last = 0;
+        for (range, markup) in marks {
+            let (before, word) = (&text[last..range.start], &text[range.clone()]);
+            match markup {
+                Markup::Phoneme(pronunciation) if ssml => {
+                    out += &escape_marked(text, last..range.start, &words);
+                    out += &word_marks(&words, range.clone());
+                    out += &format!(
+                        r#"<phoneme alphabet="ipa" ph="{}">{}</phoneme>"#,
+                        escape(pronunciation.ipa()),
+                        escape(word)
+                    );
+                }
+                Markup::Phoneme(pronunciation) => {
+                    out += before;
+                    out += pronunciation.respelling().unwrap_or(word);
+                }
+                Markup::Emphasis => {
+                    out += &escape_marked(text, last..range.start, &words);
+                    out += &word_marks(&words, range.clone());
+                    out += &format!(
+                        r#"<emphasis level="strong">{}</emphasis>"#,
+                        escape(&caps::tame(text, range.clone()))
+                    );
+                }
+            }
+            last = range.end;
+        }
+        if ssml {
+            let rest = escape_marked(text, last..text.len(), &words);
+            Self::Ssml(format!("<speak>{out}{rest}</speak>"))
+        } else {
+            Self::Text(out + &text[last..])
+        }
+    }
+
+    /// The text or document, which identifies the audio in the cache.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Text(text) | Self::Ssml(text) => text,
+        }
+    }
+}
+
+/// How a part of the message is marked up.
+enum Markup<'a> {
+    Phoneme(&'a Pronunciation),
+    Emphasis,
+}
+
+/// Whole-word occurrences of the configured words in `text`, ignoring case, as
+/// byte ranges with their pronunciation.
+fn find<'a>(
+    text: &str,
+    pronunciations: &'a HashMap<String, Pronunciation>,
+) -> Vec<(Range<usize>, &'a Pronunciation)> {
+    if pronunciations.is_empty() {
+        return Vec::new();
+    }
+    let by_word: HashMap<String, &Pronunciation> = pronunciations
+        .iter()
+        .map(|(word, pronunciation)| (word.to_lowercase(), pronunciation))
+        .collect();
+    let mut words: Vec<&String> = by_word.keys().filter(|word| !word.is_empty()).collect();
+    // longest first, so "van gogh" wins over "van"
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    let alternatives: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+    let pattern = Regex::new(&format!("(?i){}", alternatives.join("|"))).unwrap();
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let mut found = Vec::new();
+    let mut at = 0;
+    while let Some(m) = pattern.find_at(text, at) {
+        let before = text[..m.start()].chars().next_back();
+        let after = text[m.end()..].chars().next();
+        let pronunciation = by_word.get(&m.as_str().to_lowercase());
+        match pronunciation {
+            Some(pronunciation) if !is_word(before) && !is_word(after) => {
+                found.push((m.start()..m.end(), *pronunciation));
+                at = m.end();
+            }
+            _ => at = m.start() + text[m.start()..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    found
+}
+
+/// Byte offsets of the words in `text`, in the order `split_whitespace` yields them.
+fn word_starts(text: &str) -> Vec<usize> {
+    let mut after_space = true;
+    text.char_indices()
+        .filter_map(|(i, c)| {
+            let starts = after_space && !c.is_whitespace();
+            after_space = c.is_whitespace();
+            starts.then_some(i)
+        })
+        .collect()
+}
+
+/// A `<mark/>` for each word starting in `range`, named after its index.
+fn word_marks(words: &[usize], range: Range<usize>) -> String {
+    words
+        .iter()
+        .enumerate()
+        .filter(|(_, start)| range.contains(start))
+        .map(|(i, _)| format!(r#"<mark name="{i}"/>"#))
+        .collect()
+}
+
+/// `text[range]` escaped, with a `<mark/>` before each word.
+fn escape_marked(text: &str, range: Range<usize>, words: &[usize]) -> String {
+    let mut out = String::with_capacity(range.len());
+    let mut last = range.start;
+    for (i, &start) in words.iter().enumerate() {
+        if range.contains(&start) {
+            out += &escape(&text[last..start]);
+            out += &format!(r#"<mark name="{i}"/>"#);
+            last = start;
+        }
+    }
+    out + &escape(&text[last..range.end])
+}
+
+/// Escapes text for use in SSML, including inside attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            '\'' => out += "&apos;",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::ProviderConfig;
+
+    fn config(ssml: bool) -> Configuration {
+        let mut config = Configuration::default();
+        if !ssml {
+            config.provider = ProviderConfig::Mock {
+                response_delay_ms: 0,
+                audio_fixture_path: None,
+                panic_message: None,
+            };
+        }
+        config.pronunciations.insert(
+            "Nguyen".into(),
+            Pronunciation::Full {
+                ipa: "ŋwiən".into(),
+                respelling: Some("win".into()),
+            },
+        );
+        config
+            .pronunciations
+            .insert("Siobhan".into(), Pronunciation::Ipa("ʃɪˈvɔːn".into()));
+        config
+            .pronunciations
+            .insert("van Gogh".into(), Pronunciation::Ipa("væn ˈɡɒx".into()));
+        config
+    }
+
+    fn phoneme(ipa: &str, word: &str) -> String {
+        format!(r#"<phoneme alphabet="ipa" ph="{ipa}">{word}</phoneme>"#)
+    }
+
+    #[test]
+    fn text_without_the_words_is_sent_as_is() {
+        let config = config(true);
+        for text in ["hello <there> & \"you\"", "Nguyens", "xSiobhan", "van"] {
+            assert_eq!(Input::new(text, &config), Input::Text(text.into()));
+        }
+    }
+
+    #[test]
+    fn wraps_whole_words_ignoring_case() {
+        let config = config(true);
+        assert_eq!(
+            Input::new("hi NGUYEN, meet siobhan", &config),
+            Input::Ssml(format!(
+                "<speak>hi {}, meet {}</speak>",
+                phoneme("ŋwiən", "NGUYEN"),
+                phoneme("ʃɪˈvɔːn", "siobhan")
+            ))
+        );
+        assert_eq!(
+            Input::new("Vincent van Gogh", &config),
+            Input::Ssml(format!(
+                "<speak>Vincent {}</speak>",
+                phoneme("væn ˈɡɒx", "van Gogh")
+            ))
+        );
+    }
+
+    #[test]
+    fn escapes_the_surrounding_text() {
+        let config = config(true);
+        assert_eq!(
+            Input::new(r#"<b>Nguyen</b> & "Siobhan's" 1 > 0"#, &config),
+            Input::Ssml(format!(
+                "<speak>&lt;b&gt;{}&lt;/b&gt; &amp; &quot;{}&apos;s&quot; 1 &gt; 0</speak>",
+                phoneme("ŋwiən", "Nguyen"),
+                phoneme("ʃɪˈvɔːn", "Siobhan")
+            ))
+        );
+    }
+
+    #[test]
+    fn escapes_the_ipa() {
+        let mut config = config(true);
+        config
+            .pronunciations
+            .insert("quote".into(), Pronunciation::Ipa(r#"a"b<c"#.into()));
+        assert_eq!(
+            Input::new("quote", &config),
+            Input::Ssml(format!(
+                "<speak>{}</speak>",
+                phoneme("a&quot;b&lt;c", "quote")
+            ))
+        );
+    }
+
+    #[test]
+    fn stresses_shouted_words() {
+        let mut config = config(true);
+        config.caps_mode = CapsMode::Emphasis;
+        config.caps_min_length = 4;
+        let emphasis = |word: &str| format!(r#"<emphasis level="strong">{word}</emphasis>"#);
+        assert_eq!(
+            Input::new("STOP it, NGUYEN & USA", &config),
+            Input::Ssml(format!(
+                "<speak>{} it, {} &amp; USA</speak>",
+                emphasis("Stop"),
+                phoneme("ŋwiən", "NGUYEN")
+            ))
+        );
+        config.caps_mode = CapsMode::Lowercase;
+        assert_eq!(
+            Input::new("STOP it", &config),
+            Input::Text("STOP it".into())
+        );
+    }
+
+    #[test]
+    fn marks_every_word_for_subtitles() {
+        let mut config = config(true);
+        config.subtitle_overlay = true;
+        assert_eq!(
+            Input::new("say  hi to Vincent van Gogh", &config),
+            Input::Ssml(format!(
+                r#"<speak><mark name="0"/>say  <mark name="1"/>hi <mark name="2"/>to <mark name="3"/>Vincent <mark name="4"/><mark name="5"/>{}</speak>"#,
+                phoneme("væn ˈɡɒx", "van Gogh")
+            ))
+        );
+        let mut config = self::config(false);
+        config.subtitle_overlay = true;
+        assert_eq!(Input::new("say hi", &config), Input::Text("say hi".into()));
+    }
+
+    #[test]
+    fn respells_without_ssml() {
+        let config = config(false);
+        assert_eq!(
+            Input::new("Nguyen & Siobhan <3", &config),
+            Input::Text("win & Siobhan <3".into())
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::{f32::consts::TAU, fs, thread::sleep, time::Duration};
+
+use eframe::epaint::ahash::HashMap;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+
+use crate::{audio, clock::unix_now, correlation, speech::SynthesisError};
+
+/// Sample rate of the audio the mock provider generates.
+const MOCK_SAMPLE_RATE: u32 = 24_000;
+/// Length of generated audio per byte of text.
+const MOCK_MS_PER_BYTE: u64 = 50;
+
+/// The `[provider]` table: which backend synthesizes speech.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProviderConfig {
+    /// Google Cloud Text-to-Speech at `gcloud_endpoint`.
+    Google {
+        /// Headers sent with every request, e.g. for a proxy in front of the API.
+        /// `{timestamp}` in a value becomes the Unix time, and `{session_id}` an ID
+        /// that stays the same until the program exits.
+        #[serde(default)]
+        extra_headers: HashMap<String, String>,
+        /// Header that carries the ID of the message being spoken, as in the logs,
+        /// or a new random UUID outside of one.
+        #[serde(default)]
+        request_id_header: Option<String>,
+    },
+    /// Answers locally without network access or credentials, for development.
+    /// Returns the WAV file at `audio_fixture_path`, or a 1 kHz tone as long as
+    /// the text, after waiting `response_delay_ms`.
+    Mock {
+        #[serde(default)]
+        response_delay_ms: u64,
+        #[serde(default)]
+        audio_fixture_path: Option<String>,
+        /// Panics with this message instead of answering, to see how a crashing
+        /// backend is reported.
+        #[serde(default)]
+        panic_message: Option<String>,
+    },
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self::Google {
+            extra_headers: HashMap::default(),
+            request_id_header: None,
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// Name of the backend, as recorded in metrics and history.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Google { .. } => "google",
+            Self::Mock { .. } => "mock",
+        }
+    }
+
+    /// Whether the backend accepts SSML, which `[pronunciations]` needs.
+    pub fn supports_ssml(&self) -> bool {
+        matches!(self, Self::Google { .. })
+    }
+
+    /// Rejects extra headers that are not valid HTTP.
+    pub fn validate(&self) -> Result<(), String> {
+        let Self::Google {
+            extra_headers,
+            request_id_header,
+        } = self
+        else {
+            return Ok(());
+        };
+        for name in extra_headers.keys().chain(request_id_header) {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("{name:?} is not a valid header name"))?;
+        }
+        for (name, value) in extra_headers {
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("extra_headers.{name} has characters a header can't"))?;
+        }
+        Ok(())
+    }
+
+    /// The headers `extra_headers` and `request_id_header` add to a request, with
+    /// the placeholders filled in.
+    pub fn extra_headers(&self) -> Vec<(String, String)> {
+        let Self::Google {
+            extra_headers,
+            request_id_header,
+        } = self
+        else {
+            return Vec::new();
+        };
+        let session_id = correlation::session_id();
+        let timestamp = unix_now().to_string();
+        extra_headers
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .replace("{timestamp}", &timestamp)
+                    .replace("{session_id}", session_id);
+                (name.clone(), value)
+            })
+            .chain(request_id_header.iter().map(|name| {
+                let id = correlation::request_id().unwrap_or_else(correlation::new_id);
+                (name.clone(), id)
+            }))
+            .collect()
+    }
+}
+
+/// Voice gender asked of Google, for when the voice name leaves it open.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SsmlGender {
+    #[serde(alias = "male")]
+    Male,
+    #[serde(alias = "female")]
+    Female,
+    #[serde(alias = "neutral")]
+    Neutral,
+}
+
+/// What the mock provider returns for `text`.
+pub fn mock(
+    text: &str,
+    response_delay_ms: u64,
+    audio_fixture_path: Option<&str>,
+    panic_message: Option<&str>,
+) -> Result<Vec<u8>, SynthesisError> {
+    sleep(Duration::from_millis(response_delay_ms));
+    if let Some(message) = panic_message {
+        panic!("{message}");
+    }
+    match audio_fixture_path {
+        Some(path) => fs::read(path)
+            .map_err(|e| SynthesisError::AudioDecode(format!("could not read {path}: {e}"))),
+        None => Ok(tone(Duration::from_millis(
+            MOCK_MS_PER_BYTE * text.len() as u64,
+        ))),
+    }
+}
+
+/// A 16-bit mono WAV file of a 1 kHz sine wave.
+fn tone(duration: Duration) -> Vec<u8> {
+    let frames = (duration.as_secs_f32() * MOCK_SAMPLE_RATE as f32) as u32;
+    let samples: Vec<i16> = (0..frames)
+        .map(|frame| {
+            let phase = TAU * 1000. * frame as f32 / MOCK_SAMPLE_RATE as f32;
+            (phase.sin() * 0.2 * i16::MAX as f32) as i16
+        })
+        .collect();
+    audio::encode_wav(1, MOCK_SAMPLE_RATE, &samples)
+}
@@ -0,0 +1,333 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    chunking, correlation,
+    filters::{Pipeline, Verdict},
+    history::{self, Source},
+    metrics::METRICS,
+    notify::Notifier,
+    offline,
+    overrides::{self, Overrides},
+    snippets, speech, worker, Configuration,
+};
+
+/// A message waiting to be spoken, with how to speak it.
+pub struct PhraseEntry {
+    /// Number of the entry in this run, for `SpeechQueue::cancel`.
+    pub id: u64,
+    pub text: String,
+    pub source: Source,
+    /// Higher is more urgent. Entries are still spoken in the order they came in.
+    pub priority: i32,
+    /// Name of the voice profile to speak with instead of the scheduled one.
+    pub voice_profile: Option<String>,
+    /// Volume to speak at instead of `volume`.
+    pub volume_override: Option<f32>,
+    /// Settings from a leading `[voice=... rate=...]`, applied after the others.
+    pub overrides: Overrides,
+    /// Set to skip the entry, or to stop it while it is spoken.
+    pub cancel_token: Arc<AtomicBool>,
+    pub submitted_at: Instant,
+    /// Ties the log lines about this entry together.
+    pub request_id: String,
+    /// A message from the log, spoken again instead of `text`.
+    replay: Option<history::Message>,
+}
+
+impl PhraseEntry {
+    pub fn builder(text: impl Into<String>, source: Source) -> PhraseEntryBuilder {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        PhraseEntryBuilder(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            text: text.into(),
+            source,
+            priority: 0,
+            voice_profile: None,
+            volume_override: None,
+            overrides: Overrides::default(),
+            cancel_token: Arc::new(AtomicBool::new(false)),
+            submitted_at: Instant::now(),
+            request_id: correlation::new_id(),
+            replay: None,
+        })
+    }
+
+    /// The configuration to speak this entry with.
+    fn config(&self, config: &Configuration) -> Configuration {
+        let mut config = config.clone();
+        if let Some(profile) = &self.voice_profile {
+            // a scheduled profile would win otherwise
+            config.voice_schedule.clear();
+            config.default_profile.clone_from(profile);
+        }
+        if let Some(volume) = self.volume_override {
+            config.volume = volume;
+        }
+        self.overrides.apply(&mut config);
+        config
+    }
+}
+
+pub struct PhraseEntryBuilder(PhraseEntry);
+
+impl PhraseEntryBuilder {
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.0.priority = priority;
+        self
+    }
+
+    pub fn voice_profile(mut self, name: impl Into<String>) -> Self {
+        self.0.voice_profile = Some(name.into());
+        self
+    }
+
+    pub fn volume_override(mut self, volume: f32) -> Self {
+        self.0.volume_override = Some(volume);
+        self
+    }
+
+    pub fn overrides(mut self, overrides: Overrides) -> Self {
+        self.0.overrides = overrides;
+        self
+    }
+
+    fn replay(mut self, message: history::Message) -> Self {
+        self.0.replay = Some(message);
+        self
+    }
+
+    pub fn build(self) -> PhraseEntry {
+        self.0
+    }
+}
+
+/// Speaks queued messages one after another on a dedicated thread.
+#[derive(Clone)]
+pub struct SpeechQueue {
+    sender: mpsc::Sender<PhraseEntry>,
+    /// Cancel tokens of the entries not yet spoken to the end, by ID.
+    pending: Arc<Mutex<HashMap<u64, Arc<AtomicBool>>>>,
+    /// Cancel token of the entry being spoken right now.
+    current: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    pipeline: Arc<Pipeline>,
+    config: Arc<Configuration>,
+}
+
+impl SpeechQueue {
+    pub fn start(config: Configuration, pipeline: Arc<Pipeline>) -> Self {
+        let (sender, receiver) = mpsc::channel::<PhraseEntry>();
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+        let current = Arc::new(Mutex::new(None));
+        let (worker_pending, worker_current) = (pending.clone(), current.clone());
+        let shared_config = Arc::new(config.clone());
+        thread::spawn(move || {
+            let mut notifier = Notifier::new(&config);
+            for entry in receiver {
+                METRICS.queue_depth.dec();
+                let cancel = entry.cancel_token.clone();
+                if cancel.load(Ordering::Relaxed) {
+                    log::debug!("request {} was cancelled while queued", entry.request_id);
+                    worker_pending.lock().unwrap().remove(&entry.id);
+                    continue;
+                }
+                if offline::is_stale(entry.submitted_at, &config) {
+                    log::info!(
+                        "dropped request {}: it waited {:?} for the service to be reachable",
+                        entry.request_id,
+                        entry.submitted_at.elapsed()
+                    );
+                    worker_pending.lock().unwrap().remove(&entry.id);
+                    continue;
+                }
+                *worker_current.lock().unwrap() = Some(cancel.clone());
+                let (id, request_id) = (entry.id, entry.request_id.clone());
+                let job_config = entry.config(&config);
+                let result = correlation::in_request(&request_id, || {
+                    log::debug!("waited {:?} in the queue", entry.submitted_at.elapsed());
+                    worker::catching(move || {
+                        match entry.replay {
+                            Some(last) => speech::replay(&last, job_config, entry.source, &cancel),
+                            None => speech::speak(entry.text, job_config, entry.source, &cancel),
+                        }
+                        .map_err(|e| e.to_string())
+                    })
+                });
+                *worker_current.lock().unwrap() = None;
+                worker_pending.lock().unwrap().remove(&id);
+                if let Err(message) = result {
+                    notifier.failure(&message);
+                }
+            }
+        });
+        Self {
+            sender,
+            pending,
+            current,
+            pipeline,
+            config: shared_config,
+        }
+    }
+
+    /// Runs `text` through the filter pipeline, returning `None` if it must not be spoken.
+    pub fn filter(&self, text: &str) -> Option<String> {
+        match self.pipeline.apply(text) {
+            Verdict::Speak(text) => Some(text),
+            Verdict::Drop { .. } | Verdict::Empty => None,
+            Verdict::Confirm { text, reason } => {
+                log::info!("dropped message that {reason}; there is no overlay to confirm it");
+                history::record_dropped(&reason, &text);
+                None
+            }
+        }
+    }
+
+    /// Expands snippets, filters `text` and queues the result, returning the IDs
+    /// of the queued entries, none if nothing was queued. A leading
+    /// `[voice=... rate=...]` applies to this message only.
+    pub fn say(&self, text: &str, source: Source) -> Vec<u64> {
+        let (overrides, text) = overrides::parse(text, &self.config.voice_profiles);
+        let text = snippets::expand(&text, &self.config.snippets);
+        match self.filter(&text) {
+            Some(text) => self.enqueue_with(&text, &overrides, source),
+            None => Vec::new(),
+        }
+    }
+
+    /// Queues text that has already been filtered, applying `max_chars`.
+    pub fn enqueue(&self, text: &str, source: Source) -> Vec<u64> {
+        self.enqueue_with(text, &Overrides::default(), source)
+    }
+
+    fn enqueue_with(&self, text: &str, overrides: &Overrides, source: Source) -> Vec<u64> {
+        match chunking::enforce_max_chars(text, &self.config) {
+            Ok(parts) => parts
+                .into_iter()
+                .map(|part| {
+                    let entry = PhraseEntry::builder(part, source)
+                        .overrides(overrides.clone())
+                        .build();
+                    self.push(entry)
+                })
+                .collect(),
+            Err(reason) => {
+                log::info!("dropped message: {reason}");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Queues the message spoken last to be spoken again, after whatever is queued
+    /// already. Returns whether there was one.
+    pub fn replay(&self, source: Source) -> bool {
+        match history::last_spoken(&self.config.message_log) {
+            Ok(Some(last)) => {
+                let entry = PhraseEntry::builder(last.text.clone(), source)
+                    .replay(last)
+                    .build();
+                self.push(entry);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                log::warn!("could not read the message log: {e}");
+                false
+            }
+        }
+    }
+
+    /// Queues `entry` as it is, returning its ID.
+    pub fn push(&self, entry: PhraseEntry) -> u64 {
+        let id = entry.id;
+        log::debug!(
+            "queued request {} from {:?}",
+            entry.request_id,
+            entry.source
+        );
+        self.pending
+            .lock()
+            .unwrap()
+            .insert(id, entry.cancel_token.clone());
+        METRICS.queue_depth.inc();
+        if self.sender.send(entry).is_err() {
+            METRICS.queue_depth.dec();
+            self.pending.lock().unwrap().remove(&id);
+        }
+        id
+    }
+
+    /// Cancels the entry with `id`: it is skipped if still queued and stopped if
+    /// being spoken. Returns whether it was queued or being spoken.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.pending.lock().unwrap().get(&id) {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops the message being spoken, returning whether there was one.
+    pub fn skip(&self) -> bool {
+        match &*self.current.lock().unwrap() {
+            Some(cancel) => {
+                cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_entries_with_their_own_ids_and_tokens() {
+        let first = PhraseEntry::builder("hello", Source::Http).build();
+        let second = PhraseEntry::builder("again", Source::Clipboard)
+            .priority(5)
+            .voice_profile("narrator")
+            .volume_override(0.5)
+            .build();
+        assert!(second.id > first.id);
+        assert_ne!(first.request_id, second.request_id);
+        assert!(!Arc::ptr_eq(&first.cancel_token, &second.cancel_token));
+        assert_eq!(
+            (first.priority, first.voice_profile, first.volume_override),
+            (0, None, None)
+        );
+        assert_eq!(second.text, "again");
+        assert_eq!(second.priority, 5);
+        assert_eq!(second.voice_profile.as_deref(), Some("narrator"));
+    }
+
+    #[test]
+    fn speaks_with_the_profile_then_the_overrides() {
+        let config = Configuration {
+            volume: 1.0,
+            default_profile: "default".into(),
+            ..Default::default()
+        };
+        let entry = PhraseEntry::builder("hello", Source::Http)
+            .voice_profile("narrator")
+            .volume_override(0.5)
+            .overrides(Overrides {
+                volume: Some(0.5),
+                ..Default::default()
+            })
+            .build();
+        let config = entry.config(&config);
+        assert_eq!(config.default_profile, "narrator");
+        assert_eq!(config.volume, 0.25);
+    }
+}
@@ -0,0 +1,51 @@
+use eframe::{egui, epaint::Color32};
+
+use crate::QuickReply;
+
+/// Quick replies past this many are listed under a "…" button instead.
+const BUTTONS: usize = 10;
+
+/// What the strip did this frame.
+#[derive(Default)]
+pub struct StripResult {
+    /// Bottom edge of the overflow list, if it is open.
+    pub bottom: Option<f32>,
+    /// The pointer is on the strip, so the text box losing focus should not close the window.
+    pub hovered: bool,
+    /// Index of the quick reply that was clicked.
+    pub picked: Option<usize>,
+}
+
+/// The row of quick reply buttons under the text box.
+pub fn show(ui: &mut egui::Ui, replies: &[QuickReply]) -> StripResult {
+    let mut result = StripResult::default();
+    let row = ui.horizontal(|ui| {
+        for (i, reply) in replies.iter().take(BUTTONS).enumerate() {
+            if button(ui, reply).clicked() {
+                result.picked = Some(i);
+            }
+        }
+        if replies.len() > BUTTONS {
+            let overflow = ui.menu_button("…", |ui| {
+                for (i, reply) in replies.iter().enumerate().skip(BUTTONS) {
+                    if button(ui, reply).clicked() {
+                        result.picked = Some(i);
+                        ui.close_menu();
+                    }
+                }
+                ui.min_rect().bottom()
+            });
+            result.bottom = overflow.inner;
+        }
+    });
+    result.hovered = result.bottom.is_some() || ui.rect_contains_pointer(row.response.rect);
+    result
+}
+
+fn button(ui: &mut egui::Ui, reply: &QuickReply) -> egui::Response {
+    let mut button = egui::Button::new(&reply.label);
+    if let Some([r, g, b, a]) = reply.color {
+        button = button.fill(Color32::from_rgba_unmultiplied(r, g, b, a));
+    }
+    ui.add(button).on_hover_text(&reply.text)
+}
@@ -0,0 +1,137 @@
+use eframe::epaint::ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::VoiceProfile;
+
+/// Uses `profile_name` from `start_hour` up to (but not including) `end_hour`.
+/// Entries whose end is before their start wrap around midnight, and entries
+/// whose start and end are the same cover the whole day. An empty
+/// `profile_name` means the top-level voice.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct VoiceScheduleEntry {
+    pub start_hour: u8,
+    pub end_hour: u8,
+    pub profile_name: String,
+}
+
+impl VoiceScheduleEntry {
+    fn contains(&self, hour: u8) -> bool {
+        if self.start_hour == self.end_hour {
+            true
+        } else if self.start_hour < self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+/// Checks that each entry's hours are on the clock and that it names one of
+/// `profiles`.
+pub fn validate(
+    schedule: &[VoiceScheduleEntry],
+    profiles: &HashMap<String, VoiceProfile>,
+) -> Result<(), String> {
+    for entry in schedule {
+        if entry.start_hour > 23 || entry.end_hour > 24 {
+            return Err(format!(
+                "voice_schedule hours must be from 0 to 23, or 24 for end_hour, got {}-{}",
+                entry.start_hour, entry.end_hour
+            ));
+        }
+        if !entry.profile_name.is_empty() && !profiles.contains_key(&entry.profile_name) {
+            return Err(format!(
+                "voice_schedule names {:?}, which is not one of voice_profiles",
+                entry.profile_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Picks the voice profile scheduled for `hour`, falling back to `default`.
+pub fn active_profile_at<'a>(
+    hour: u8,
+    schedule: &'a [VoiceScheduleEntry],
+    default: &'a str,
+) -> &'a str {
+    match schedule.iter().find(|entry| entry.contains(hour)) {
+        Some(entry) => {
+            log::debug!(
+                "using voice profile {:?}: scheduled for {}:00-{}:00",
+                entry.profile_name,
+                entry.start_hour,
+                entry.end_hour
+            );
+            &entry.profile_name
+        }
+        None => {
+            log::debug!("using voice profile {default:?}: no schedule entry covers {hour}:00");
+            default
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(start_hour: u8, end_hour: u8, profile_name: &str) -> VoiceScheduleEntry {
+        VoiceScheduleEntry {
+            start_hour,
+            end_hour,
+            profile_name: profile_name.into(),
+        }
+    }
+
+    #[test]
+    fn picks_the_first_entry_covering_the_hour() {
+        let schedule = [entry(9, 17, "work"), entry(22, 6, "night")];
+        for (hour, expected) in [
+            (8, "default"),
+            (9, "work"),
+            (16, "work"),
+            (17, "default"),
+            (21, "default"),
+            (22, "night"),
+            (0, "night"),
+            (5, "night"),
+            (6, "default"),
+        ] {
+            assert_eq!(
+                active_profile_at(hour, &schedule, "default"),
+                expected,
+                "{hour}"
+            );
+        }
+        assert_eq!(active_profile_at(12, &[], "default"), "default");
+    }
+
+    #[test]
+    fn equal_bounds_cover_the_whole_day() {
+        let schedule = [entry(9, 17, "work"), entry(7, 7, "always")];
+        for hour in [0, 7, 8, 17, 23] {
+            assert_eq!(active_profile_at(hour, &schedule, ""), "always", "{hour}");
+        }
+        assert_eq!(active_profile_at(10, &schedule, ""), "work");
+        assert_eq!(active_profile_at(23, &[entry(0, 24, "all")], ""), "all");
+    }
+
+    #[test]
+    fn rejects_unknown_profiles_and_hours() {
+        let profiles: HashMap<_, _> = [("work".to_owned(), VoiceProfile::default())]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            validate(&[entry(9, 17, "work"), entry(0, 24, "")], &profiles),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&[entry(9, 17, "wrok")], &profiles),
+            Err("voice_schedule names \"wrok\", which is not one of voice_profiles".into())
+        );
+        assert!(validate(&[entry(24, 2, "work")], &profiles)
+            .unwrap_err()
+            .starts_with("voice_schedule hours"));
+    }
+}
@@ -0,0 +1,287 @@
+//! Minimal HTTP control interface for daemon mode.
+//!
+//! All routes except `/metrics` answer with JSON, and errors look like
+//! `{"error": "..."}`. When `http_token` is set, every request must carry
+//! `Authorization: Bearer <token>`:
+//!
+//! ```text
+//! curl -X POST -H 'Authorization: Bearer hunter2' -d 'hello chat' localhost:7878/speak
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/phrase/0
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/skip
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/cancel/3
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/replay
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/clipboard/toggle
+//! curl -X POST -H 'Authorization: Bearer hunter2' localhost:7878/toggle-chat-reader
+//! curl -H 'Authorization: Bearer hunter2' localhost:7878/phrase-list
+//! curl -H 'Authorization: Bearer hunter2' localhost:7878/status
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::{health, history::Source, metrics::METRICS, offline, queue::SpeechQueue, QuickReply};
+
+/// Requests with a larger body are rejected.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Everything the routes can act on.
+#[derive(Clone)]
+pub struct State {
+    pub queue: SpeechQueue,
+    /// Pause flag of the clipboard watcher, if it is running.
+    pub clipboard_paused: Option<Arc<AtomicBool>>,
+    /// Pause flag of the `--watch-file` chat reader, if it is running.
+    pub chat_reader_paused: Option<Arc<AtomicBool>>,
+    pub quick_replies: Vec<QuickReply>,
+    /// Bearer token required on every request, if set.
+    pub token: Option<String>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: String,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, json!({ "error": message.into() }))
+    }
+}
+
+/// Binds `addr` and serves requests on background threads, returning the address
+/// bound, which tells the port when `addr` asks for any.
+pub fn start(addr: &str, state: State) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    log::info!("HTTP server listening on {addr}");
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let state = state.clone();
+            thread::spawn(move || handle(stream, &state));
+        }
+    });
+    Ok(addr)
+}
+
+/// Serves only `GET /metrics`, without a token, on all interfaces at `port`, for a
+/// Prometheus scraper that should not be able to speak.
+pub fn start_metrics(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    log::info!("serving metrics on port {port}");
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            thread::spawn(move || {
+                let response = match read_request(&stream) {
+                    Ok(request)
+                        if (request.method.as_str(), request.path.as_str())
+                            == ("GET", "/metrics") =>
+                    {
+                        metrics()
+                    }
+                    Ok(_) => Response::error(404, "not found"),
+                    Err(e) => Response::error(400, e.to_string()),
+                };
+                respond(&mut stream, response);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, state: &State) {
+    let response = match read_request(&stream) {
+        Ok(request) => route(request, state),
+        Err(e) => Response::error(400, e.to_string()),
+    };
+    respond(&mut stream, response);
+}
+
+fn respond(stream: &mut TcpStream, response: Response) {
+    _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+}
+
+fn route(request: Request, state: &State) -> Response {
+    if let Some(token) = &state.token {
+        let expected = format!("Bearer {token}");
+        if request.authorization.as_deref() != Some(expected.as_str()) {
+            return Response::error(401, "missing or wrong bearer token");
+        }
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => metrics(),
+        ("POST", "/speak") => {
+            if request.body.trim().is_empty() {
+                return Response::error(400, "nothing to say");
+            }
+            let ids = state.queue.say(&request.body, Source::Http);
+            Response::json(202, json!({ "queued": !ids.is_empty(), "ids": ids }))
+        }
+        ("POST", "/replay") => {
+            if state.queue.replay(Source::Http) {
+                Response::json(202, json!({ "queued": true }))
+            } else {
+                Response::json(
+                    200,
+                    json!({ "queued": false, "reason": "nothing to replay yet" }),
+                )
+            }
+        }
+        ("GET", "/status") => {
+            let (backend, checked) = health::current();
+            Response::json(
+                200,
+                json!({
+                    "queued": METRICS.queue_depth.get(),
+                    "offline": offline::offline_for().is_some(),
+                    "offline_secs": offline::offline_for().map(|offline| offline.as_secs()),
+                    "waiting": offline::waiting(),
+                    "backend": backend,
+                    "backend_checked_secs": checked.map(|checked| checked.as_secs()),
+                }),
+            )
+        }
+        ("POST", "/skip") => Response::json(200, json!({ "skipped": state.queue.skip() })),
+        ("POST", path) if path.starts_with("/cancel/") => {
+            let id = &path["/cancel/".len()..];
+            match id.parse() {
+                Ok(id) => Response::json(200, json!({ "cancelled": state.queue.cancel(id) })),
+                Err(_) => Response::error(404, format!("no queued message with id {id:?}")),
+            }
+        }
+        ("GET", "/phrase-list") => {
+            let phrases: Vec<_> = state
+                .quick_replies
+                .iter()
+                .enumerate()
+                .map(|(index, reply)| json!({ "index": index, "label": reply.label }))
+                .collect();
+            Response::json(200, Value::Array(phrases))
+        }
+        ("POST", path) if path.starts_with("/phrase/") => {
+            let index = &path["/phrase/".len()..];
+            match index
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| state.quick_replies.get(i))
+            {
+                Some(reply) => {
+                    let ids = state.queue.say(&reply.text, Source::Http);
+                    Response::json(
+                        202,
+                        json!({ "queued": !ids.is_empty(), "ids": ids, "label": reply.label }),
+                    )
+                }
+                None => Response::error(404, format!("no quick reply at index {index:?}")),
+            }
+        }
+        ("POST", "/clipboard/toggle") => match &state.clipboard_paused {
+            Some(paused) => {
+                let was_paused = paused.fetch_xor(true, Ordering::Relaxed);
+                Response::json(200, json!({ "paused": !was_paused }))
+            }
+            None => Response::error(404, "clipboard watch is not running"),
+        },
+        ("POST", "/toggle-chat-reader") => match &state.chat_reader_paused {
+            Some(paused) => {
+                let was_paused = paused.fetch_xor(true, Ordering::Relaxed);
+                Response::json(200, json!({ "paused": !was_paused }))
+            }
+            None => Response::error(404, "chat reader is not running"),
+        },
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn metrics() -> Response {
+    Response {
+        status: 200,
+        content_type: "text/plain; version=0.0.4",
+        body: METRICS.render(),
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "malformed request line",
+        ));
+    };
+    let (method, path) = (method.to_owned(), path.to_owned());
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_owned());
+            }
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8(body)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "body is not UTF-8"))?;
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "",
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use eframe::epaint::ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::unix_now;
+
+const SESSION_PATH: &str = "session.toml";
+
+/// State that survives between launches, stored next to `config.toml`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Session {
+    /// Last phrase picked from each random phrase set.
+    #[serde(default)]
+    pub random_last: HashMap<String, String>,
+    #[serde(default)]
+    pub metrics: Option<StoredMetrics>,
+    /// How often each quick reply button was clicked, by label.
+    #[serde(default)]
+    pub quick_reply_clicks: HashMap<String, u64>,
+}
+
+/// `SessionMetrics` as written to disk, with the start time as a Unix timestamp.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct StoredMetrics {
+    pub phrase_count: u64,
+    pub char_count: u64,
+    pub started_at: u64,
+}
+
+/// Running totals for the current streaming session.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionMetrics {
+    pub phrase_count: u64,
+    pub char_count: u64,
+    pub session_start: Instant,
+}
+
+impl SessionMetrics {
+    /// Continues the stored session if it started less than `max_age` ago.
+    pub fn restore(stored: Option<&StoredMetrics>, max_age: Duration) -> Self {
+        let now = unix_now();
+        match stored {
+            Some(stored) if now.saturating_sub(stored.started_at) < max_age.as_secs() => Self {
+                phrase_count: stored.phrase_count,
+                char_count: stored.char_count,
+                session_start: Instant::now()
+                    .checked_sub(Duration::from_secs(now - stored.started_at))
+                    .unwrap_or_else(Instant::now),
+            },
+            _ => Self {
+                phrase_count: 0,
+                char_count: 0,
+                session_start: Instant::now(),
+            },
+        }
+    }
+
+    pub fn record(&mut self, text: &str) {
+        self.phrase_count += 1;
+        self.char_count += text.chars().count() as u64;
+    }
+
+    pub fn to_stored(self) -> StoredMetrics {
+        StoredMetrics {
+            phrase_count: self.phrase_count,
+            char_count: self.char_count,
+            started_at: unix_now().saturating_sub(self.session_start.elapsed().as_secs()),
+        }
+    }
+
+    /// Status line such as `Session: 42 phrases • 1,234 chars • 1h23m`.
+    pub fn status_line(&self) -> String {
+        format!(
+            "Session: {} phrases • {} chars • {}",
+            fmt_thousands(self.phrase_count),
+            fmt_thousands(self.char_count),
+            fmt_duration(self.session_start.elapsed())
+        )
+    }
+}
+
+/// Formats a duration compactly, e.g. `42s`, `7m`, or `1h23m`.
+pub fn fmt_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    match (secs / 3600, secs / 60 % 60) {
+        (0, 0) => format!("{secs}s"),
+        (0, m) => format!("{m}m"),
+        (h, m) => format!("{h}h{m:02}m"),
+    }
+}
+
+/// Formats `n` with comma thousands separators.
+pub fn fmt_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Session {
+    pub fn load() -> Self {
+        fs::read_to_string(SESSION_PATH)
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Ok(s) = toml::to_string(self) {
+            _ = fs::write(SESSION_PATH, s);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use eframe::epaint::ahash::HashMap;
+use regex::{Captures, Regex};
+
+use crate::{
+    clock::{self, Date},
+    metrics::METRICS,
+};
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Replaces every trigger from `snippets` that stands on its own in `text` with its
+/// template. `{time}`, `{date}` and `{queue_len}` in templates are filled in now.
+pub fn expand(text: &str, snippets: &HashMap<String, String>) -> String {
+    if snippets.is_empty() {
+        return text.to_owned();
+    }
+    let now = clock::local_now();
+    let queue_len = METRICS.queue_depth.get();
+    expand_with(text, snippets, |name| match name {
+        "time" => Some(spoken_time(now.hour, now.minute)),
+        "date" => Some(spoken_date(now.date)),
+        "queue_len" => Some(queue_len.to_string()),
+        _ => None,
+    })
+}
+
+/// Expands triggers, looking variables up with `variable`. Templates are not
+/// searched for triggers again, and unknown variables are left as written.
+fn expand_with(
+    text: &str,
+    snippets: &HashMap<String, String>,
+    variable: impl Fn(&str) -> Option<String>,
+) -> String {
+    let variables = Regex::new(r"\{(\w+)\}").unwrap();
+    let mut out = String::with_capacity(text.len());
+    for word in text.split_inclusive(char::is_whitespace) {
+        let trimmed = word.trim_end();
+        // ";raid!" still triggers ";raid"
+        let trigger = trimmed.trim_end_matches(['.', ',', '!', '?']);
+        match snippets.get(trigger) {
+            Some(template) => {
+                let expanded = variables.replace_all(template, |caps: &Captures| {
+                    variable(&caps[1]).unwrap_or_else(|| {
+                        log::warn!("snippet {trigger} uses unknown variable {}", &caps[0]);
+                        caps[0].to_owned()
+                    })
+                });
+                out += &expanded;
+                out += &word[trigger.len()..];
+            }
+            None => out += word,
+        }
+    }
+    out
+}
+
+/// "3 PM" or "3:05 PM".
+fn spoken_time(hour: u8, minute: u8) -> String {
+    let suffix = if hour < 12 { "AM" } else { "PM" };
+    let hour = match hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    match minute {
+        0 => format!("{hour} {suffix}"),
+        minute => format!("{hour}:{minute:02} {suffix}"),
+    }
+}
+
+/// "October 15".
+fn spoken_date(date: Date) -> String {
+    format!("{} {}", MONTHS[date.month as usize - 1], date.day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippets() -> HashMap<String, String> {
+        [
+            (";raid", "Thank you for the raid, enjoy your stay!"),
+            (";time", "{time}"),
+            (";loop", "see ;raid"),
+            (";odd", "{queue_len} left, {nope} and {}"),
+        ]
+        .into_iter()
+        .map(|(trigger, template)| (trigger.to_owned(), template.to_owned()))
+        .collect()
+    }
+
+    fn expand(text: &str) -> String {
+        expand_with(text, &snippets(), |name| match name {
+            "time" => Some("3:05 PM".into()),
+            "queue_len" => Some("2".into()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn expands_standalone_triggers() {
+        assert_eq!(expand(";raid"), "Thank you for the raid, enjoy your stay!");
+        assert_eq!(expand("it's ;time!"), "it's 3:05 PM!");
+        assert_eq!(expand("a\t;time\nb"), "a\t3:05 PM\nb");
+    }
+
+    #[test]
+    fn leaves_triggers_inside_words() {
+        for text in ["x;raid", ";raids", ";RAID", "; raid"] {
+            assert_eq!(expand(text), text);
+        }
+    }
+
+    #[test]
+    fn expands_only_once() {
+        assert_eq!(expand(";loop"), "see ;raid");
+    }
+
+    #[test]
+    fn keeps_unknown_variables() {
+        assert_eq!(expand(";odd"), "2 left, {nope} and {}");
+    }
+
+    #[test]
+    fn speaks_times_and_dates() {
+        assert_eq!(spoken_time(0, 0), "12 AM");
+        assert_eq!(spoken_time(12, 30), "12:30 PM");
+        assert_eq!(spoken_time(15, 5), "3:05 PM");
+        let date = Date {
+            year: 2024,
+            month: 10,
+            day: 15,
+        };
+        assert_eq!(spoken_date(date), "October 15");
+    }
+}
@@ -0,0 +1,765 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    io::Cursor,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use base64::Engine;
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    header::{ACCEPT, ETAG, IF_NONE_MATCH},
+    StatusCode,
+};
+use rodio::{cpal::traits::HostTrait, Device, DeviceTrait, Source};
+use serde::Deserialize;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    archive,
+    audio::{self, Output},
+    auth,
+    cache::{self, AudioBytes},
+    correlation, filters, history, http_log,
+    metrics::METRICS,
+    offline,
+    pronunciations::Input,
+    provider::{self, ProviderConfig},
+    proxy,
+    subtitles::{self, Timepoint},
+    translate, Configuration,
+};
+
+const AUDIO_ENCODING: &str = "LINEAR16";
+
+/// At most this many responses are kept for conditional requests.
+const ETAG_RESPONSES: usize = 64;
+
+/// Why a message could not be spoken. The `Display` text is meant for the user;
+/// `Debug` keeps the underlying error for the logs.
+#[derive(Debug)]
+pub enum SynthesisError {
+    /// The speech service could not be reached.
+    Network(reqwest::Error),
+    /// The proxy the request went through could not be reached or refused it.
+    Proxy {
+        proxy: String,
+        source: reqwest::Error,
+    },
+    /// No access token could be had for Google.
+    Auth(String),
+    /// The speech service answered with an error status.
+    Http {
+        status: u16,
+        body: String,
+    },
+    /// The response was not the JSON the API documents.
+    JsonParse(serde_json::Error),
+    /// The response had no audio in it.
+    MissingAudio,
+    Base64Decode(base64::DecodeError),
+    /// The audio could not be read or is not a playable WAV file.
+    AudioDecode(String),
+    /// No output device has `output_device` in its name.
+    DeviceNotFound(String),
+    PlaybackError(String),
+}
+
+impl SynthesisError {
+    /// Whether the service could not be reached at all, which trying again later
+    /// may fix. DNS failures count as failing to connect.
+    pub fn is_offline(&self) -> bool {
+        match self {
+            Self::Network(e) => e.is_connect() || e.is_timeout(),
+            Self::Proxy { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Label of the error in `tts_errors_total`.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::Network(_) => "network",
+            Self::Proxy { .. } => "proxy",
+            Self::Auth(_) | Self::Http { .. } => "http",
+            Self::JsonParse(_) | Self::MissingAudio => "response",
+            Self::Base64Decode(_) | Self::AudioDecode(_) => "decode",
+            Self::DeviceNotFound(_) => "device",
+            Self::PlaybackError(_) => "playback",
+        }
+    }
+}
+
+impl fmt::Display for SynthesisError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Network(e) if e.is_timeout() => {
+                write!(
+                    f,
+                    "network error: the speech service did not answer in time"
+                )
+            }
+            Self::Network(e) if e.is_connect() => {
+                write!(f, "network error: could not connect to the speech service")
+            }
+            Self::Network(_) => write!(f, "network error: the request did not go through"),
+            Self::Proxy { proxy, .. } => {
+                write!(f, "proxy error: could not get through {proxy}")
+            }
+            Self::Http { status, .. } => {
+                let hint = match status {
+                    401 | 403 => " — check API key",
+                    429 => " — quota exceeded, try again later",
+                    407 => " — the proxy wants credentials in proxy_url",
+                    400 => " — check voice and language",
+                    _ => "",
+                };
+                write!(f, "{status} from Google{hint}")
+            }
+            Self::Auth(reason) => write!(f, "could not authenticate: {reason}"),
+            Self::JsonParse(_) => write!(f, "unexpected response: not the JSON the API sends"),
+            Self::MissingAudio => write!(f, "response contained no audio"),
+            Self::Base64Decode(_) => write!(f, "invalid audio data: the audio was corrupted"),
+            Self::AudioDecode(reason) => write!(f, "unplayable audio: {reason}"),
+            Self::DeviceNotFound(name) => {
+                write!(f, "no audio device matches output_device {name:?}")
+            }
+            Self::PlaybackError(reason) => write!(f, "playback failed: {reason}"),
+        }
+    }
+}
+
+impl Error for SynthesisError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Network(e) | Self::Proxy { source: e, .. } => Some(e),
+            Self::JsonParse(e) => Some(e),
+            Self::Base64Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Synthesizes `text` with the configured provider and plays it on the configured
+/// device, translating it first if `translation.target` is set. Setting `cancel`
+/// stops playback early. The outcome is recorded in the message log as coming
+/// from `source`.
+pub fn speak(
+    text: String,
+    mut config: Configuration,
+    source: history::Source,
+    cancel: &AtomicBool,
+) -> Result<(), SynthesisError> {
+    if !filters::is_speakable(&text) {
+        log::debug!("skipping synthesis, nothing to speak: {text:?}");
+        return Ok(());
+    }
+    if config.dry_run {
+        // before translation, which would send a request of its own
+        log::warn!(
+            "dry_run is set, so this was not sent:\n{}",
+            describe_request(&text, &config)?
+        );
+        return Ok(());
+    }
+    let text = translate::localize(text, &mut config);
+    let (_, voice) = config.active_voice();
+    let mut message = history::Message::new(source, &text, config.provider.name(), voice);
+    let result = speak_logged(&text, &config, cancel, &mut message);
+    record(message, &config, result)
+}
+
+/// Plays `last` again, from the audio cache if it still has the clip, or else by
+/// synthesizing its text again. The text is not translated again: it was logged
+/// after translation. The replay is logged as coming from `source`.
+pub fn replay(
+    last: &history::Message,
+    config: Configuration,
+    source: history::Source,
+    cancel: &AtomicBool,
+) -> Result<(), SynthesisError> {
+    let cached = last
+        .audio_key
+        .as_ref()
+        .and_then(|key| Some((key, cache::open(&config)?.get(key)?)));
+    let Some((key, wav)) = cached else {
+        let (_, voice) = config.active_voice();
+        let mut message = history::Message::new(source, &last.text, config.provider.name(), voice);
+        let result = speak_logged(&last.text, &config, cancel, &mut message);
+        return record(message, &config, result);
+    };
+    METRICS.cache_hits.inc();
+    let mut message = history::Message::new(source, &last.text, &last.provider, &last.voice);
+    message.audio_key = Some(key.clone());
+    let result = play_logged(
+        &last.text,
+        wav,
+        &[],
+        Instant::now(),
+        &config,
+        cancel,
+        &mut message,
+    );
+    record(message, &config, result)
+}
+
+/// Adds `message` to the message log with the outcome of speaking it.
+fn record(
+    mut message: history::Message,
+    config: &Configuration,
+    result: Result<(), SynthesisError>,
+) -> Result<(), SynthesisError> {
+    if let Err(e) = &result {
+        message.status = history::Status::Error;
+        message.error = Some(e.to_string());
+    }
+    history::record_message(&config.message_log, &message);
+    result
+}
+
+/// Does the work of [`speak`], noting timings in `message`.
+fn speak_logged(
+    text: &str,
+    config: &Configuration,
+    cancel: &AtomicBool,
+    message: &mut history::Message,
+) -> Result<(), SynthesisError> {
+    let start = Instant::now();
+    let (language, voice) = config.active_voice();
+    let input = Input::new(text, config);
+    let key = audio_key(&input, language, voice, config);
+    message.audio_key = Some(key.clone());
+    let (wav, timepoints, synthesis) =
+        cached_or_synthesized(&input, &key, language, voice, config, cancel)?;
+    message.synthesis_ms = synthesis.map(|synthesis| synthesis.as_millis() as u64);
+    play_logged(text, wav, &timepoints, start, config, cancel, message)
+}
+
+/// Plays `wav`, the audio of `text`, until it ends or `cancel` is set, noting its
+/// length in `message`. `start` is when the message started being handled.
+fn play_logged(
+    text: &str,
+    wav: AudioBytes,
+    timepoints: &[Timepoint],
+    start: Instant,
+    config: &Configuration,
+    cancel: &AtomicBool,
+    message: &mut history::Message,
+) -> Result<(), SynthesisError> {
+    if config.archive.enabled {
+        let cached = message
+            .audio_key
+            .as_ref()
+            .and_then(|key| cache::clip_file(config, key));
+        match archive::store(&config.archive, wav.as_ref(), cached.as_deref()) {
+            Ok(hash) => message.audio_hash = Some(hash),
+            Err(e) => log::warn!("could not archive the audio in {}: {e}", config.archive.dir),
+        }
+    }
+    let (_output, duration) = play(wav, config).inspect_err(|e| METRICS.error(e.category()))?;
+    let first_audio = start.elapsed();
+    if METRICS.time_to_first_audio.count() == 0 {
+        METRICS.first_message_audio.observe(first_audio);
+    }
+    METRICS.time_to_first_audio.observe(first_audio);
+    message.first_audio_ms = Some(first_audio.as_millis() as u64);
+    if let Some(latency) = message.latency() {
+        log::debug!("{latency}");
+    }
+    message.playback_ms = Some(duration.as_millis() as u64);
+    if config.subtitle_overlay {
+        subtitles::start(text, timepoints, duration);
+    }
+    // for good measure
+    wait(duration + Duration::from_millis(500), cancel);
+    if config.subtitle_overlay {
+        subtitles::finish();
+    }
+    METRICS.playback_duration.observe(duration);
+    METRICS.audio_duration_ms.add(duration.as_millis() as u64);
+    METRICS.messages_spoken.inc();
+    Ok(())
+}
+
+/// The audio for `input` from the cache, or synthesized and added to the cache
+/// along with how long synthesis took. Cached audio has no timepoints, so its
+/// subtitles are estimated.
+fn cached_or_synthesized(
+    input: &Input,
+    key: &str,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+    cancel: &AtomicBool,
+) -> Result<(AudioBytes, Vec<Timepoint>, Option<Duration>), SynthesisError> {
+    let mut cache = cache::open(config);
+    if let Some(wav) = cache.as_mut().and_then(|cache| cache.get(key)) {
+        METRICS.cache_hits.inc();
+        return Ok((wav, Vec::new(), None));
+    }
+    if cache.is_some() {
+        METRICS.cache_misses.inc();
+    }
+    let mut start = Instant::now();
+    let (wav, timepoints) = offline::retrying(config, cancel, || {
+        start = Instant::now();
+        synthesize_input(input, language, voice, config)
+    })?;
+    let synthesis = start.elapsed();
+    if let Some(Err(e)) = cache.as_mut().map(|cache| cache.put(key, &wav)) {
+        log::warn!("could not write to audio cache: {e}");
+    }
+    Ok((AudioBytes::Owned(wav), timepoints, Some(synthesis)))
+}
+
+/// Key of the audio for `input` in the cache.
+fn audio_key(input: &Input, language: &str, voice: &str, config: &Configuration) -> String {
+    // keys without a gender stay as they were
+    let voice = match config.preferred_gender {
+        Some(gender) => format!("{voice}/{gender:?}"),
+        None => voice.to_owned(),
+    };
+    cache::key(
+        input.as_str(),
+        config.provider.name(),
+        language,
+        &voice,
+        config.speaking_rate,
+        AUDIO_ENCODING,
+    )
+}
+
+/// The WAV bytes of `text` in the active voice, from the audio cache when it has
+/// them, without playing them.
+pub fn synthesize_cached(text: &str, config: &Configuration) -> Result<AudioBytes, SynthesisError> {
+    let (language, voice) = config.active_voice();
+    let input = Input::new(text, config);
+    let key = audio_key(&input, language, voice, config);
+    let cancel = AtomicBool::new(false);
+    cached_or_synthesized(&input, &key, language, voice, config, &cancel).map(|(wav, ..)| wav)
+}
+
+/// Starts playing `wav` on the configured device, returning the open output, which
+/// must be kept until playback ends, and the length of the clip.
+fn play(wav: AudioBytes, config: &Configuration) -> Result<(Output, Duration), SynthesisError> {
+    let output = open_device(config)?;
+    let decoder = decode(wav)?;
+    let duration = decoder
+        .total_duration()
+        .ok_or_else(|| SynthesisError::AudioDecode("unknown length".into()))?;
+    let clip = audio::apply_effects(decoder.convert_samples(), config);
+    let clip = if config.declick {
+        audio::declick(clip)
+    } else {
+        clip
+    };
+    output.play(clip).map_err(SynthesisError::PlaybackError)?;
+    Ok((output, duration))
+}
+
+/// Plays `wav` on the configured device, returning once it has been played.
+pub fn play_to_end(wav: AudioBytes, config: &Configuration) -> Result<Duration, SynthesisError> {
+    let (_output, duration) = play(wav, config)?;
+    wait(
+        duration + Duration::from_millis(500),
+        &AtomicBool::new(false),
+    );
+    Ok(duration)
+}
+
+/// A decoder for the samples of `wav`.
+pub fn decode(wav: AudioBytes) -> Result<rodio::Decoder<Cursor<AudioBytes>>, SynthesisError> {
+    let _span = correlation::span("audio_decode");
+    METRICS
+        .decode_duration
+        .time(|| rodio::Decoder::new_wav(Cursor::new(wav)))
+        .map_err(|e| SynthesisError::AudioDecode(e.to_string()))
+}
+
+/// The device `output_device` was last found to be, so that each message need
+/// not enumerate the devices again; `find_device` logs how long that takes.
+/// It is looked up again when `output_device` changes and when the device fails
+/// to open, e.g. once unplugged, and forgotten when no device matches. A device
+/// that starts to match later, while this one still opens, is not picked up
+/// until then.
+static DEVICE: Mutex<Option<(String, Arc<Device>)>> = Mutex::new(None);
+
+/// Opens the configured output device, looking it up again if the one found
+/// before fails to open, as it may have been unplugged or replaced since.
+fn open_device(config: &Configuration) -> Result<Output, SynthesisError> {
+    let cached = match &*DEVICE.lock().unwrap() {
+        Some((name_substr, device)) if *name_substr == config.output_device => Some(device.clone()),
+        _ => None,
+    };
+    if let Some(device) = cached {
+        match audio::open_output(&device, config) {
+            Ok(output) => return Ok(output),
+            Err(e) => log::info!("the output device failed, so looking it up again: {e}"),
+        }
+    }
+    let device = find_device(&config.output_device)?;
+    audio::open_output(&device, config).map_err(SynthesisError::PlaybackError)
+}
+
+/// The first output device with `name_substr` in its name, which is kept for
+/// the messages after.
+fn find_device(name_substr: &str) -> Result<Arc<Device>, SynthesisError> {
+    let _span = correlation::span("device_lookup");
+    let start = Instant::now();
+    let not_found = || SynthesisError::DeviceNotFound(name_substr.to_owned());
+    let found = rodio::cpal::default_host()
+        .output_devices()
+        .map_err(|_| not_found())?
+        .find(|device| device.name().is_ok_and(|name| name.contains(name_substr)));
+    log::debug!("looked up the output device in {:?}", start.elapsed());
+    let Some(device) = found.map(Arc::new) else {
+        *DEVICE.lock().unwrap() = None;
+        return Err(not_found());
+    };
+    *DEVICE.lock().unwrap() = Some((name_substr.to_owned(), device.clone()));
+    Ok(device)
+}
+
+/// Full name of the device `output_device = name_substr` plays on, to check the
+/// setting at startup. The device is kept, so the first message need not look
+/// it up.
+pub fn verify_audio_device(name_substr: &str) -> Result<String, SynthesisError> {
+    find_device(name_substr)?
+        .name()
+        .map_err(|_| SynthesisError::DeviceNotFound(name_substr.to_owned()))
+}
+
+/// Requests `text` from the configured provider, returning the WAV bytes. Words
+/// in `pronunciations` are sent as SSML if the provider supports it.
+pub fn synthesize(
+    text: &str,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+) -> Result<Vec<u8>, SynthesisError> {
+    synthesize_input(&Input::new(text, config), language, voice, config).map(|(wav, _)| wav)
+}
+
+/// Requests `input` from the configured provider, returning the WAV bytes and when
+/// the marked words are spoken, if the provider reports it.
+fn synthesize_input(
+    input: &Input,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+) -> Result<(Vec<u8>, Vec<Timepoint>), SynthesisError> {
+    let _span = correlation::span("synthesize");
+    log::debug!(
+        "provider={} voice={voice} characters={}",
+        config.provider.name(),
+        input.as_str().chars().count()
+    );
+    METRICS.characters_synthesized.add(
+        config.provider.name(),
+        input.as_str().chars().count() as u64,
+    );
+    let result = METRICS.request_duration.time(|| match &config.provider {
+        ProviderConfig::Google { .. } => request(input, language, voice, config),
+        ProviderConfig::Mock {
+            response_delay_ms,
+            audio_fixture_path,
+            panic_message,
+        } => provider::mock(
+            input.as_str(),
+            *response_delay_ms,
+            audio_fixture_path.as_deref(),
+            panic_message.as_deref(),
+        )
+        .map(|wav| (wav, Vec::new())),
+    });
+    let status = result.as_ref().map_or_else(|e| e.category(), |_| "ok");
+    METRICS.request(config.provider.name(), status);
+    result.inspect_err(|e| {
+        log::debug!("synthesis failed: {e:?}");
+        METRICS.error(e.category());
+    })
+}
+
+/// The parts of a `text:synthesize` response that are used.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    #[serde(default)]
+    audio_content: Option<String>,
+    #[serde(default)]
+    timepoints: Vec<ResponseTimepoint>,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+/// The `error` object Google sends instead of audio.
+#[derive(Deserialize)]
+struct ResponseError {
+    code: u16,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseTimepoint {
+    mark_name: String,
+    time_seconds: f64,
+}
+
+/// Requests `input` from Google Cloud TTS.
+fn request(
+    input: &Input,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+) -> Result<(Vec<u8>, Vec<Timepoint>), SynthesisError> {
+    let url = synthesis_url(input, config);
+    let body = request_body(input, language, voice, config);
+    let fingerprint = request_fingerprint(&url, &body);
+    let cached = lock_responses(config).get(&fingerprint).cloned();
+    let through = url::Url::parse(&url)
+        .ok()
+        .and_then(|url| proxy::for_url(config, &url));
+    let client = http_log::client(config).map_err(SynthesisError::Network)?;
+    let builder = build_request(&client, &url, &body, config);
+    let mut builder = auth::authorize(builder, config).map_err(SynthesisError::Auth)?;
+    if let Some((etag, _)) = &cached {
+        builder = builder.header(IF_NONE_MATCH, etag);
+    }
+    let resp = METRICS
+        .synthesis_latency
+        .time(|| {
+            let _span = correlation::span("http");
+            http_log::send(&client, builder)
+        })
+        .map_err(|e| match through {
+            // connections go to the proxy, so it is the one that failed
+            Some(proxy) if e.is_connect() => SynthesisError::Proxy { proxy, source: e },
+            _ => SynthesisError::Network(e),
+        })?;
+    let status = resp.status();
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(str::to_owned);
+    let body = resp.bytes().map_err(SynthesisError::Network)?.to_vec();
+    let body = match cached {
+        Some((_, cached)) if status == StatusCode::NOT_MODIFIED => cached,
+        _ if !status.is_success() => {
+            return Err(SynthesisError::Http {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+        _ => {
+            if let Some(etag) = etag {
+                let mut responses = lock_responses(config);
+                // an arbitrary one makes room, as this only saves bandwidth
+                if responses.len() >= ETAG_RESPONSES && !responses.contains_key(&fingerprint) {
+                    if let Some(key) = responses.keys().next().cloned() {
+                        responses.remove(&key);
+                    }
+                }
+                responses.insert(fingerprint, (etag, body.clone()));
+                if config.cache_enabled {
+                    if let Err(e) = cache::save_responses(&responses) {
+                        log::warn!("could not write to audio cache: {e}");
+                    }
+                }
+            }
+            body
+        }
+    };
+    parse_response(&body)
+}
+
+/// The `text:synthesize` URL for `input`. Input with `<mark/>`s goes to the beta
+/// API, the only one that reports timepoints.
+fn synthesis_url(input: &Input, config: &Configuration) -> String {
+    let version = if is_marked(input) { "v1beta1" } else { "v1" };
+    format!(
+        "{}/{version}/text:synthesize",
+        config.gcloud_endpoint.trim_end_matches('/')
+    )
+}
+
+/// The synthesis request to `url`, but for its credentials.
+fn build_request(
+    client: &Client,
+    url: &str,
+    body: &serde_json::Value,
+    config: &Configuration,
+) -> RequestBuilder {
+    let mut builder = client
+        .post(url)
+        .json(body)
+        .header(ACCEPT, "application/json");
+    for (name, value) in config.provider.extra_headers() {
+        builder = builder.header(name, value);
+    }
+    builder
+}
+
+/// The synthesis request for `text` as it would be sent, with credentials
+/// masked, for `--dry-run` and `dry_run`. Nothing is sent, not even a request for
+/// an access token.
+pub fn describe_request(text: &str, config: &Configuration) -> Result<String, SynthesisError> {
+    let (language, voice) = config.active_voice();
+    let input = Input::new(text, config);
+    if let ProviderConfig::Mock { .. } = config.provider {
+        return Ok(format!(
+            "The mock provider sends no request; it plays a tone for {:?}\n",
+            input.as_str()
+        ));
+    }
+    let client = http_log::client(config).map_err(SynthesisError::Network)?;
+    let body = request_body(&input, language, voice, config);
+    let builder = build_request(&client, &synthesis_url(&input, config), &body, config);
+    let request = auth::authorize_without_token(builder, config)
+        .build()
+        .map_err(SynthesisError::Network)?;
+    let mut description = http_log::render(&request);
+    if let Input::Ssml(ssml) = &input {
+        description += &format!("\nSSML:\n{ssml}\n");
+    }
+    Ok(description)
+}
+
+/// The body `synthesize` sends to Google for `text`, with `pronunciations` applied,
+/// to check what a configuration asks for without sending anything. Only Google
+/// is sent a body; the mock provider makes its audio locally.
+pub fn build_request_body(
+    text: &str,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+) -> serde_json::Value {
+    request_body(&Input::new(text, config), language, voice, config)
+}
+
+fn request_body(
+    input: &Input,
+    language: &str,
+    voice: &str,
+    config: &Configuration,
+) -> serde_json::Value {
+    let time_pointing = if is_marked(input) {
+        json!(["SSML_MARK"])
+    } else {
+        json!([])
+    };
+    json!({
+      "input": match input {
+        Input::Text(text) => json!({ "text": text }),
+        Input::Ssml(ssml) => json!({ "ssml": ssml }),
+      },
+      "voice": voice_params(language, voice, config),
+      "audioConfig": {
+        "audioEncoding": AUDIO_ENCODING,
+        "speakingRate": config.speaking_rate
+      },
+      "enableTimePointing": time_pointing
+    })
+}
+
+/// Whether `input` has `<mark/>`s to report timepoints for.
+fn is_marked(input: &Input) -> bool {
+    matches!(input, Input::Ssml(ssml) if ssml.contains("<mark "))
+}
+
+/// The WAV audio in the body of a `text:synthesize` response. An error object in
+/// it is reported like an error status.
+pub fn parse_synthesis_response(body: &str) -> Result<Vec<u8>, SynthesisError> {
+    parse_response(body.as_bytes()).map(|(wav, _)| wav)
+}
+
+fn parse_response(body: &[u8]) -> Result<(Vec<u8>, Vec<Timepoint>), SynthesisError> {
+    let response: Response = serde_json::from_slice(body).map_err(SynthesisError::JsonParse)?;
+    if let Some(error) = response.error {
+        return Err(SynthesisError::Http {
+            status: error.code,
+            body: String::from_utf8_lossy(body).into_owned(),
+        });
+    }
+    let encoded = response
+        .audio_content
+        .filter(|encoded| !encoded.is_empty())
+        .ok_or(SynthesisError::MissingAudio)?;
+    let wav = {
+        let _span = correlation::span("base64_decode");
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(SynthesisError::Base64Decode)?
+    };
+    let timepoints = response
+        .timepoints
+        .into_iter()
+        .filter_map(|timepoint| {
+            Some(Timepoint {
+                word: timepoint.mark_name.parse().ok()?,
+                at: Duration::try_from_secs_f64(timepoint.time_seconds).ok()?,
+            })
+        })
+        .collect();
+    Ok((wav, timepoints))
+}
+
+/// The `voice` object of a synthesis request.
+fn voice_params(language: &str, voice: &str, config: &Configuration) -> serde_json::Value {
+    let mut params = json!({ "languageCode": language, "name": voice });
+    if let Some(gender) = config.preferred_gender {
+        params["ssmlGender"] = json!(gender);
+    }
+    params
+}
+
+/// Response bodies by request fingerprint, with their ETags.
+type Responses = HashMap<String, (String, Vec<u8>)>;
+
+/// Identifies a synthesis request by its URL and body, for `lock_responses`.
+fn request_fingerprint(url: &str, body: &serde_json::Value) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(url.as_bytes());
+    hasher.update([0]);
+    hasher.update(body.to_string().as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Responses that came with an ETag, by request fingerprint, so repeating the
+/// request can send `If-None-Match` and take the body from here on a 304. With
+/// `cache_enabled`, they are kept in the cache directory, so this works across
+/// restarts too.
+fn lock_responses(config: &Configuration) -> MutexGuard<'static, Responses> {
+    static RESPONSES: OnceLock<Mutex<Responses>> = OnceLock::new();
+    RESPONSES
+        .get_or_init(|| {
+            if !config.cache_enabled {
+                return Default::default();
+            }
+            Mutex::new(cache::load_responses().unwrap_or_else(|e| {
+                log::warn!("could not read the saved responses: {e}");
+                Responses::new()
+            }))
+        })
+        .lock()
+        .unwrap()
+}
+
+/// Sleeps for `duration`, or until `cancel` is set.
+fn wait(duration: Duration, cancel: &AtomicBool) {
+    let end = Instant::now() + duration;
+    while !cancel.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now >= end {
+            break;
+        }
+        sleep((end - now).min(Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    clock::{self, Date},
+    history::{self, Message, MessageLogConfig, Status},
+    session::fmt_thousands,
+};
+
+/// How `--stats`, `--devices` and `--list-voices` print their output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    /// Only supported by `--devices`.
+    Csv,
+}
+
+/// Messages, characters, errors and synthesis time of a group of messages.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+struct Usage {
+    messages: u64,
+    characters: u64,
+    errors: u64,
+    /// Share of the messages that could not be spoken, from 0 to 1.
+    error_rate: f64,
+    /// Average over the messages that were synthesized rather than cached.
+    avg_synthesis_ms: Option<u64>,
+    #[serde(skip)]
+    synthesis_ms: u64,
+    #[serde(skip)]
+    synthesized: u64,
+}
+
+impl Usage {
+    fn add(&mut self, message: &Message) {
+        self.messages += 1;
+        self.characters += message.characters as u64;
+        if message.status == Status::Error {
+            self.errors += 1;
+        }
+        if let Some(ms) = message.synthesis_ms {
+            self.synthesis_ms += ms;
+            self.synthesized += 1;
+        }
+    }
+
+    /// Fills in the averages once every message was added.
+    fn finish(&mut self) {
+        self.error_rate = self.errors as f64 / self.messages.max(1) as f64;
+        self.avg_synthesis_ms =
+            (self.synthesized > 0).then(|| self.synthesis_ms / self.synthesized);
+    }
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct Report {
+    since: Option<String>,
+    total: Usage,
+    per_provider: BTreeMap<String, Usage>,
+    per_voice: BTreeMap<String, Usage>,
+    /// Keyed by `YYYY-MM`.
+    per_month: BTreeMap<String, Usage>,
+    /// Keyed by `YYYY-MM-DD`.
+    per_day: BTreeMap<String, Usage>,
+    /// Lines of the log that could not be read.
+    skipped_lines: u64,
+}
+
+impl Report {
+    fn add(&mut self, message: &Message, date: Date) {
+        let month = format!("{:04}-{:02}", date.year, date.month);
+        self.total.add(message);
+        for (groups, key) in [
+            (&mut self.per_provider, message.provider.clone()),
+            (&mut self.per_voice, message.voice.clone()),
+            (&mut self.per_month, month),
+            (&mut self.per_day, date.to_string()),
+        ] {
+            groups.entry(key).or_default().add(message);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.total.finish();
+        for groups in [
+            &mut self.per_provider,
+            &mut self.per_voice,
+            &mut self.per_month,
+            &mut self.per_day,
+        ] {
+            groups.values_mut().for_each(Usage::finish);
+        }
+    }
+}
+
+/// Prints usage per provider, voice, month and day from the message log.
+pub fn run(
+    format: OutputFormat,
+    since: Option<Date>,
+    message_log: &MessageLogConfig,
+) -> Result<(), String> {
+    let report = build_report(message_log, since)
+        .map_err(|e| format!("could not read {}: {e}", message_log.path))?;
+    if report.skipped_lines > 0 {
+        eprintln!(
+            "warning: skipped {} unreadable lines in {}",
+            report.skipped_lines, message_log.path
+        );
+    }
+    match format {
+        OutputFormat::Text => print!("{}", render_text(&report)),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Csv => return Err("--stats can't print csv; use text or json".into()),
+    }
+    Ok(())
+}
+
+/// Adds up the log one message at a time, so it is never held in memory whole.
+fn build_report(message_log: &MessageLogConfig, since: Option<Date>) -> std::io::Result<Report> {
+    let mut report = Report {
+        since: since.map(|date| date.to_string()),
+        ..Default::default()
+    };
+    report.skipped_lines = history::each_message(message_log, |message| {
+        let date = clock::local_at(message.timestamp).date;
+        if since.is_none_or(|since| date >= since) {
+            report.add(&message, date);
+        }
+    })?;
+    report.finish();
+    Ok(report)
+}
+
+fn render_text(report: &Report) -> String {
+    let mut out = String::new();
+    if let Some(since) = &report.since {
+        out += &format!("Since {since}\n");
+    }
+    let sections = [
+        ("Provider", &report.per_provider),
+        ("Voice", &report.per_voice),
+        ("Month", &report.per_month),
+        ("Day", &report.per_day),
+    ];
+    let width = sections
+        .iter()
+        .flat_map(|(title, groups)| groups.keys().map(String::len).chain([title.len()]))
+        .max()
+        .unwrap_or(0)
+        .max("Total".len());
+    let row = |name: &str, usage: &Usage| {
+        format!(
+            "{name:<width$}  {:>9}  {:>11}  {:>6}  {:>10}\n",
+            fmt_thousands(usage.messages),
+            fmt_thousands(usage.characters),
+            format!("{:.1}%", usage.error_rate * 100.),
+            usage
+                .avg_synthesis_ms
+                .map_or("-".to_owned(), |ms| format!("{ms} ms")),
+        )
+    };
+    let header = format!(
+        "{:<width$}  {:>9}  {:>11}  {:>6}  {:>10}\n",
+        "", "Messages", "Characters", "Errors", "Synthesis"
+    );
+    out += &header;
+    out += &row("Total", &report.total);
+    for (title, groups) in sections {
+        if groups.is_empty() {
+            continue;
+        }
+        out += &format!("\n{title}\n");
+        for (name, usage) in groups {
+            out += &row(name, usage);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    /// Midday UTC, so the local date is the same in nearly every timezone. Spans a
+    /// year and a month boundary, with an error, a cached message and bad lines.
+    const FIXTURE: &str = r#"{"timestamp":1767182400,"source":"overlay","text":"hello","provider":"google","voice":"en-US-Standard-A","characters":5,"synthesis_ms":200,"playback_ms":900,"status":"ok"}
+not json at all
+{"timestamp":1769860800,"source":"http","text":"again","provider":"google","voice":"en-US-Standard-B","characters":5,"synthesis_ms":400,"playback_ms":800,"status":"ok"}
+{"timestamp":1769860800,"source":"http","text":"again","provider":"google","voice":"en-US-Standard-B","characters":5,"status":"error","error":"429 from Google — quota exceeded, try again later"}
+
+{"timestamp":1769947200,"source":"file","text":"from the cache","provider":"mock","voice":"en-US-Standard-A","characters":14,"playback_ms":700,"status":"ok"}
+{"timestamp":1769947200,"truncated":
+"#;
+
+    fn fixture_log(name: &str) -> (PathBuf, MessageLogConfig) {
+        let dir = std::env::temp_dir().join(format!("stats-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = MessageLogConfig {
+            path: dir.join("messages.jsonl").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        fs::write(&config.path, FIXTURE).unwrap();
+        (dir, config)
+    }
+
+    fn usage(messages: u64, characters: u64, errors: u64, avg: Option<u64>) -> Usage {
+        let mut usage = Usage {
+            messages,
+            characters,
+            errors,
+            ..Default::default()
+        };
+        usage.error_rate = errors as f64 / messages as f64;
+        usage.avg_synthesis_ms = avg;
+        usage
+    }
+
+    fn comparable(mut usage: Usage) -> Usage {
+        usage.synthesis_ms = 0;
+        usage.synthesized = 0;
+        usage
+    }
+
+    #[test]
+    fn groups_by_provider_voice_month_and_day() {
+        let (dir, config) = fixture_log("groups");
+        let report = build_report(&config, None).unwrap();
+        assert_eq!(report.skipped_lines, 2);
+        assert_eq!(comparable(report.total.clone()), usage(4, 29, 1, Some(300)));
+        let groups = |groups: &BTreeMap<String, Usage>| -> Vec<(String, Usage)> {
+            groups
+                .iter()
+                .map(|(key, usage)| (key.clone(), comparable(usage.clone())))
+                .collect()
+        };
+        assert_eq!(
+            groups(&report.per_month),
+            [
+                ("2025-12".to_owned(), usage(1, 5, 0, Some(200))),
+                ("2026-01".to_owned(), usage(2, 10, 1, Some(400))),
+                ("2026-02".to_owned(), usage(1, 14, 0, None)),
+            ]
+        );
+        let days: Vec<&String> = report.per_day.keys().collect();
+        assert_eq!(days, ["2025-12-31", "2026-01-31", "2026-02-01"]);
+        assert_eq!(
+            groups(&report.per_provider),
+            [
+                ("google".to_owned(), usage(3, 15, 1, Some(300))),
+                ("mock".to_owned(), usage(1, 14, 0, None)),
+            ]
+        );
+        assert_eq!(report.per_voice["en-US-Standard-A"].messages, 2);
+        assert_eq!(report.per_voice["en-US-Standard-B"].errors, 1);
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn counts_only_messages_since_the_date() {
+        let (dir, config) = fixture_log("since");
+        let report = build_report(&config, Date::parse("2026-02-01")).unwrap();
+        assert_eq!(report.total.messages, 1);
+        assert_eq!(report.per_month.keys().collect::<Vec<_>>(), ["2026-02"]);
+        assert_eq!(report.since.as_deref(), Some("2026-02-01"));
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn renders_a_table() {
+        let (dir, config) = fixture_log("table");
+        let text = render_text(&build_report(&config, None).unwrap());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[..3],
+            [
+                "                   Messages   Characters  Errors   Synthesis",
+                "Total                     4           29   25.0%      300 ms",
+                "",
+            ]
+        );
+        assert!(lines.contains(&"mock                      1           14    0.0%           -"));
+        assert!(text.contains("\nMonth\n2025-12 "), "{text}");
+        _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,256 @@
+//! Live subtitles, shown word by word in a second window while a message plays.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eframe::{
+    egui::{self, Frame, ViewportCommand},
+    epaint::{
+        text::{LayoutJob, TextFormat},
+        Color32, FontId, Vec2,
+    },
+};
+use oneshot::TryRecvError;
+
+use crate::{worker::WorkerResult, Configuration};
+
+/// How long the subtitle stays up after playback ends.
+const LINGER: Duration = Duration::from_millis(500);
+/// Space between the text and the edge of its background.
+const MARGIN: f32 = 8.;
+
+/// What is playing right now, published by `speech::speak` while `subtitle_overlay`
+/// is on.
+pub static PLAYING: Mutex<Option<Playing>> = Mutex::new(None);
+
+/// When the provider says the word with this index is spoken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timepoint {
+    pub word: usize,
+    pub at: Duration,
+}
+
+/// A word of the message and when it should appear.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub word: String,
+    pub at: Duration,
+}
+
+/// A message being played.
+#[derive(Debug, Clone)]
+pub struct Playing {
+    pub cues: Vec<Cue>,
+    started: Instant,
+    ended: Option<Instant>,
+}
+
+/// Publishes `text`, whose playback of `duration` just started.
+pub fn start(text: &str, timepoints: &[Timepoint], duration: Duration) {
+    *PLAYING.lock().unwrap() = Some(Playing {
+        cues: cues(text, timepoints, duration),
+        started: Instant::now(),
+        ended: None,
+    });
+}
+
+/// Marks the message published last as done playing.
+pub fn finish() {
+    if let Some(playing) = PLAYING.lock().unwrap().as_mut() {
+        playing.ended.get_or_insert_with(Instant::now);
+    }
+}
+
+/// When each word of `text` is spoken: at its timepoint, or, without any, at the
+/// share of `duration` taken by the characters before it. Words the provider did
+/// not report appear with the word before them.
+pub fn cues(text: &str, timepoints: &[Timepoint], duration: Duration) -> Vec<Cue> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let total = words.iter().map(|word| word.chars().count()).sum::<usize>();
+    let mut before = 0;
+    let mut last = Duration::ZERO;
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let at = if timepoints.is_empty() {
+                duration.mul_f64(before as f64 / total.max(1) as f64)
+            } else {
+                timepoints
+                    .iter()
+                    .find(|timepoint| timepoint.word == i)
+                    .map_or(last, |timepoint| timepoint.at)
+            };
+            before += word.chars().count();
+            last = last.max(at);
+            Cue {
+                word: (*word).to_owned(),
+                at: last,
+            }
+        })
+        .collect()
+}
+
+/// The speaking job, until it reports back.
+enum Job {
+    Running(oneshot::Receiver<WorkerResult>),
+    Done(Option<WorkerResult>),
+}
+
+/// Shows the subtitle window at `subtitle_x`/`subtitle_y` until the job reporting
+/// to `result` is done, and returns its result.
+pub fn run(
+    config: Configuration,
+    result: oneshot::Receiver<WorkerResult>,
+) -> Result<Option<WorkerResult>, eframe::Error> {
+    match result.try_recv() {
+        Ok(result) => return Ok(Some(result)),
+        Err(TryRecvError::Disconnected) => return Ok(None),
+        Err(TryRecvError::Empty) => {}
+    }
+    let job = Arc::new(Mutex::new(Job::Running(result)));
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([config.width, 1.0])
+            .with_position([config.subtitle_x, config.subtitle_y])
+            .with_always_on_top()
+            .with_decorations(false)
+            .with_transparent(true)
+            .with_mouse_passthrough(true),
+        ..Default::default()
+    };
+    let app = SubtitleApp {
+        config,
+        job: job.clone(),
+        size: Vec2::ZERO,
+    };
+    eframe::run_native("TTS Subtitles", options, Box::new(|_cc| Box::new(app)))?;
+    // the window may have been closed before the job finished
+    let job = std::mem::replace(&mut *job.lock().unwrap(), Job::Done(None));
+    Ok(match job {
+        Job::Running(result) => result.recv().ok(),
+        Job::Done(result) => result,
+    })
+}
+
+struct SubtitleApp {
+    config: Configuration,
+    job: Arc<Mutex<Job>>,
+    /// Last size requested for the window, to avoid resizing every frame.
+    size: Vec2,
+}
+
+impl SubtitleApp {
+    /// The message with the words spoken so far, laid out to wrap at `width`.
+    fn layout(&self, playing: &Playing, now: Instant) -> LayoutJob {
+        let [r, g, b, a] = self.config.subtitle_text_color;
+        let color = Color32::from_rgba_unmultiplied(r, g, b, a);
+        let font = FontId::proportional(self.config.subtitle_font_size);
+        let elapsed = now - playing.started;
+        let mut job = LayoutJob::default();
+        for (i, cue) in playing.cues.iter().enumerate() {
+            // unspoken words take their space already, so the text does not move
+            let format = TextFormat {
+                font_id: font.clone(),
+                color: if cue.at <= elapsed {
+                    color
+                } else {
+                    Color32::TRANSPARENT
+                },
+                ..Default::default()
+            };
+            let text = if i == 0 {
+                cue.word.clone()
+            } else {
+                format!(" {}", cue.word)
+            };
+            job.append(&text, 0., format);
+        }
+        job.wrap.max_width = self.config.width - 2. * MARGIN;
+        job
+    }
+}
+
+impl eframe::App for SubtitleApp {
+    fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
+        [0.; 4]
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        {
+            let mut job = self.job.lock().unwrap();
+            if let Job::Running(result) = &*job {
+                match result.try_recv() {
+                    Ok(result) => *job = Job::Done(Some(result)),
+                    Err(TryRecvError::Disconnected) => *job = Job::Done(None),
+                    Err(TryRecvError::Empty) => {}
+                }
+            }
+            if let Job::Done(_) = &*job {
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+                return;
+            }
+        }
+        let now = Instant::now();
+        let playing = PLAYING
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|playing| playing.ended.is_none_or(|ended| now < ended + LINGER));
+        egui::CentralPanel::default()
+            .frame(Frame::none().fill(Color32::TRANSPARENT))
+            .show(ctx, |ui| {
+                let Some(playing) = playing else {
+                    return;
+                };
+                let galley = ui.fonts(|fonts| fonts.layout_job(self.layout(&playing, now)));
+                let size = galley.size() + Vec2::splat(2. * MARGIN);
+                if (size - self.size).length() > 0.5 {
+                    self.size = size;
+                    ctx.send_viewport_cmd(ViewportCommand::InnerSize(size));
+                }
+                let [r, g, b, a] = self.config.subtitle_background_color;
+                Frame::none()
+                    .fill(Color32::from_rgba_unmultiplied(r, g, b, a))
+                    .rounding(6.)
+                    .inner_margin(MARGIN)
+                    .show(ui, |ui| ui.label(galley));
+            });
+        ctx.request_repaint_after(Duration::from_millis(30));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(cues: &[Cue]) -> Vec<u64> {
+        cues.iter().map(|cue| cue.at.as_millis() as u64).collect()
+    }
+
+    #[test]
+    fn estimates_from_word_lengths_without_timepoints() {
+        let estimated = cues("a bb ccc  dddd", &[], Duration::from_millis(1000));
+        let words: Vec<&str> = estimated.iter().map(|cue| cue.word.as_str()).collect();
+        assert_eq!(words, ["a", "bb", "ccc", "dddd"]);
+        assert_eq!(at(&estimated), [0, 100, 300, 600]);
+        assert!(cues("", &[], Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn follows_the_timepoints() {
+        let timepoint = |word, ms| Timepoint {
+            word,
+            at: Duration::from_millis(ms),
+        };
+        let timed = cues(
+            "one two three four",
+            &[timepoint(0, 50), timepoint(1, 400), timepoint(3, 300)],
+            Duration::from_secs(1),
+        );
+        // "three" has no timepoint and "four" would come before "two"
+        assert_eq!(at(&timed), [50, 400, 400, 400]);
+    }
+}
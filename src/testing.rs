@@ -0,0 +1,39 @@
+//! Random inputs for the property tests.
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+/// Cases per property: `PROPTEST_CASES`, or 256.
+pub fn cases() -> usize {
+    std::env::var("PROPTEST_CASES")
+        .ok()
+        .and_then(|cases| cases.parse().ok())
+        .unwrap_or(256)
+}
+
+/// A generator seeded from `PROPTEST_SEED`, or randomly. The seed is printed so a
+/// failing run can be repeated.
+pub fn rng() -> StdRng {
+    let seed = std::env::var("PROPTEST_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    eprintln!("PROPTEST_SEED={seed}");
+    StdRng::seed_from_u64(seed)
+}
+
+/// Up to `max_len` characters of letters, digits, punctuation, whitespace and
+/// some multi-byte characters.
+pub fn text(rng: &mut StdRng, max_len: usize) -> String {
+    const POOL: &[char] = &[
+        'a', 'b', 'e', 'k', 's', 't', 'z', 'A', 'Q', '0', '1', '7', '9', ' ', ' ', ' ', '\n', '\t',
+        '.', '!', '?', ',', ':', '-', '\'', 'é', 'ß', 'ж', '日', '😀',
+    ];
+    let len = rng.gen_range(0..=max_len);
+    (0..len).map(|_| *POOL.choose(rng).unwrap()).collect()
+}
+
+/// A lowercase word of one to eight letters.
+pub fn word(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(1..=8);
+    (0..len).map(|_| rng.gen_range('a'..='z')).collect()
+}
@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use eframe::egui::{
+    self,
+    text::{CCursor, CCursorRange},
+    text_edit::TextEditOutput,
+};
+
+use crate::QuickReply;
+
+/// What the menu did this frame.
+#[derive(Default)]
+pub struct MenuResult {
+    /// Bottom edge of the open menu, so the window can grow to show it.
+    pub bottom: Option<f32>,
+    /// An item was picked, which takes focus away from the text box.
+    pub acted: bool,
+    /// Text to speak right away.
+    pub speak: Option<String>,
+}
+
+/// Right-click menu of the overlay's text box.
+#[derive(Default)]
+pub struct TextMenu {
+    /// Showing quick replies to insert instead of the main items.
+    library: bool,
+}
+
+impl TextMenu {
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        output: &mut TextEditOutput,
+        text: &mut String,
+        library: &[QuickReply],
+    ) -> MenuResult {
+        let id = output.response.id.with("menu");
+        if output.response.secondary_clicked() {
+            self.library = false;
+            ui.memory_mut(|memory| memory.open_popup(id));
+        }
+        let selection = output
+            .state
+            .ccursor_range()
+            .map(|range| {
+                let (a, b) = (range.primary.index, range.secondary.index);
+                byte_range(text, a.min(b)..a.max(b))
+            })
+            .unwrap_or(text.len()..text.len());
+        let mut result = MenuResult::default();
+        let mut cursor = None;
+        let mut reopen = false;
+        let shown = egui::popup::popup_below_widget(ui, id, &output.response, |ui| {
+            if self.library {
+                if library.is_empty() {
+                    ui.weak("No quick replies configured");
+                }
+                for reply in library {
+                    if ui.button(&reply.label).clicked() {
+                        text.replace_range(selection.clone(), &reply.text);
+                        cursor = Some(selection.start + reply.text.len());
+                    }
+                }
+                if ui.button("Back").clicked() {
+                    self.library = false;
+                    reopen = true;
+                }
+                return ui.min_rect().bottom();
+            }
+            let selected = text[selection.clone()].to_owned();
+            if ui
+                .add_enabled(!selected.is_empty(), egui::Button::new("Cut"))
+                .clicked()
+            {
+                ui.output_mut(|o| o.copied_text = selected.clone());
+                text.replace_range(selection.clone(), "");
+                cursor = Some(selection.start);
+            }
+            if ui
+                .add_enabled(!selected.is_empty(), egui::Button::new("Copy"))
+                .clicked()
+            {
+                ui.output_mut(|o| o.copied_text = selected.clone());
+            }
+            if ui.button("Paste").clicked() {
+                match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+                    Ok(pasted) => {
+                        text.replace_range(selection.clone(), &pasted);
+                        cursor = Some(selection.start + pasted.len());
+                    }
+                    Err(e) => log::warn!("could not read clipboard: {e}"),
+                }
+            }
+            if ui.button("Select All").clicked() {
+                output.state.set_ccursor_range(Some(CCursorRange::two(
+                    CCursor::new(0),
+                    CCursor::new(text.chars().count()),
+                )));
+            }
+            if ui.button("Clear").clicked() {
+                text.clear();
+                cursor = Some(0);
+            }
+            ui.separator();
+            if ui
+                .add_enabled(
+                    !selected.trim().is_empty(),
+                    egui::Button::new("Speak Selection"),
+                )
+                .clicked()
+            {
+                result.speak = Some(selected);
+            }
+            if ui.button("Insert from library…").clicked() {
+                self.library = true;
+                reopen = true;
+            }
+            ui.min_rect().bottom()
+        });
+        if let Some(bottom) = shown {
+            result.bottom = Some(bottom);
+            result.acted = ui.input(|i| i.pointer.any_click());
+        }
+        if let Some(byte) = cursor {
+            let chars = text[..byte].chars().count();
+            output
+                .state
+                .set_ccursor_range(Some(CCursorRange::one(CCursor::new(chars))));
+        }
+        if shown.is_some() {
+            output.state.clone().store(ui.ctx(), output.response.id);
+        }
+        if reopen {
+            ui.memory_mut(|memory| memory.open_popup(id));
+        }
+        result
+    }
+}
+
+/// Converts a range of character indices in `text` to byte offsets.
+fn byte_range(text: &str, chars: Range<usize>) -> Range<usize> {
+    let byte = |index| {
+        text.char_indices()
+            .nth(index)
+            .map_or(text.len(), |(byte, _)| byte)
+    };
+    byte(chars.start)..byte(chars.end)
+}
@@ -0,0 +1,110 @@
+//! Extra trust for networks that intercept TLS: the CAs in `ca_bundle_path` are
+//! trusted alongside the system ones, and `danger_accept_invalid_certs` turns
+//! checking off altogether.
+
+use std::fs;
+
+use reqwest::{blocking::ClientBuilder, Certificate};
+
+use crate::Configuration;
+
+const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const END: &str = "-----END CERTIFICATE-----";
+
+/// Adds the configured CAs to `builder` and turns off certificate checks if asked to.
+pub fn apply(mut builder: ClientBuilder, config: &Configuration) -> ClientBuilder {
+    if let Some(path) = &config.ca_bundle_path {
+        match ca_certificates(path) {
+            Ok(certificates) => {
+                for certificate in certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+            }
+            // `validate` read it fine, so it changed since
+            Err(e) => log::warn!("{e}"),
+        }
+    }
+    builder.danger_accept_invalid_certs(config.danger_accept_invalid_certs)
+}
+
+/// Every certificate in the PEM file at `path`.
+pub fn ca_certificates(path: &str) -> Result<Vec<Certificate>, String> {
+    let pem = fs::read_to_string(path)
+        .map_err(|e| format!("could not read ca_bundle_path {path}: {e}"))?;
+    let certificates = pem_blocks(&pem)
+        .enumerate()
+        .map(|(i, block)| {
+            Certificate::from_pem(block.as_bytes()).map_err(|e| {
+                format!(
+                    "certificate {} in ca_bundle_path {path} is invalid: {e}",
+                    i + 1
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(format!(
+            "ca_bundle_path {path} has no PEM certificates in it"
+        ));
+    }
+    Ok(certificates)
+}
+
+/// The `BEGIN CERTIFICATE` to `END CERTIFICATE` blocks of `pem`, which may have
+/// comments or other blocks between them.
+fn pem_blocks(pem: &str) -> impl Iterator<Item = &str> {
+    pem.match_indices(BEGIN).filter_map(move |(start, _)| {
+        let end = pem[start..].find(END)? + start + END.len();
+        Some(&pem[start..end])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CA: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBejCCASGgAwIBAgIUDEHREukTudn1+sDbUgWdlkeb0X8wCgYIKoZIzj0EAwIw
+EjEQMA4GA1UEAwwHVGVzdCBDQTAgFw0yNjEwMTUwNTIzMTZaGA8yMTI2MDkyMTA1
+MjMxNlowEjEQMA4GA1UEAwwHVGVzdCBDQTBZMBMGByqGSM49AgEGCCqGSM49AwEH
+A0IABOTkdjMB5u2P5E2Y4jmnwYjBRojF3fIch+p4NKI562fDTT4QnK6h+C8PhVAG
+SOTITRoD0RPWuJ0UFeFqgVuCSF6jUzBRMB0GA1UdDgQWBBS4O/K5tSYPLCr6K0eu
+/SgNx88WGTAfBgNVHSMEGDAWgBS4O/K5tSYPLCr6K0eu/SgNx88WGTAPBgNVHRMB
+Af8EBTADAQH/MAoGCCqGSM49BAMCA0cAMEQCIFcEXCMxQuJIG2zASKBl7Un8c4Iu
+o3vyLMyn1oGfkKIYAiASbhccqotKvvu2KtNMqo840vOF+O2h90dTtLRspnp8dQ==
+-----END CERTIFICATE-----
+";
+
+    fn bundle(name: &str, pem: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ca-{name}-{}.pem", std::process::id()));
+        fs::write(&path, pem).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reads_every_certificate_in_the_bundle() {
+        let path = bundle("two", &format!("# corporate root\n{CA}\n# again\n{CA}"));
+        assert_eq!(ca_certificates(&path).unwrap().len(), 2);
+        _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn explains_an_unusable_bundle() {
+        let empty = bundle("empty", "not a certificate\n");
+        assert!(ca_certificates(&empty)
+            .unwrap_err()
+            .ends_with("has no PEM certificates in it"));
+        let broken = bundle("broken", &format!("{CA}{BEGIN}\nbm90IGEgY2VydA==\n{END}\n"));
+        let error = ca_certificates(&broken).unwrap_err();
+        assert!(
+            error.starts_with("certificate 2 in ca_bundle_path "),
+            "{error}"
+        );
+        assert!(ca_certificates("/no/such/bundle.pem")
+            .unwrap_err()
+            .starts_with("could not read ca_bundle_path"));
+        _ = fs::remove_file(empty);
+        _ = fs::remove_file(broken);
+    }
+}
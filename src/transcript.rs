@@ -0,0 +1,246 @@
+//! `--export`: the message log over a time range as CSV or JSON, e.g. as a record
+//! of everything said during a stream.
+
+use std::borrow::Cow;
+
+use crate::{
+    archive::ArchiveConfig,
+    clock::{self, TimeBound},
+    history::{self, Message, MessageLogConfig, Source},
+    stats::OutputFormat,
+};
+
+/// Columns of the CSV output.
+const CSV_HEADER: &str =
+    "time,source,provider,voice,status,characters,synthesis_ms,first_audio_ms,playback_ms,text,error,audio";
+
+/// Which messages `--export` prints.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Filter {
+    pub from: Option<TimeBound>,
+    pub to: Option<TimeBound>,
+    pub source: Option<Source>,
+}
+
+impl Filter {
+    /// The Unix time range `from..to`, or an error if it is empty.
+    fn range(&self) -> Result<(u64, u64), String> {
+        let start = self.from.map_or(0, TimeBound::start);
+        let end = self.to.map_or(u64::MAX, TimeBound::end);
+        if start >= end {
+            return Err("--to is before --from, so nothing can match".into());
+        }
+        Ok((start, end))
+    }
+}
+
+/// Prints the messages matching `filter`, oldest first, as CSV or pretty JSON,
+/// with the path of their audio in `archive` when it was kept.
+pub fn run(
+    format: OutputFormat,
+    filter: Filter,
+    message_log: &MessageLogConfig,
+    archive: &ArchiveConfig,
+) -> Result<(), String> {
+    if format == OutputFormat::Text {
+        return Err("--export prints csv or json; pass --format csv or --format json".into());
+    }
+    let (messages, skipped) = select(message_log, filter)?;
+    if skipped > 0 {
+        eprintln!(
+            "warning: skipped {skipped} unreadable lines in {}",
+            message_log.path
+        );
+    }
+    match format {
+        OutputFormat::Csv => print!("{}", render_csv(&messages, archive)),
+        _ => println!(
+            "{}",
+            serde_json::to_string_pretty(&with_audio_paths(&messages, archive))
+                .map_err(|e| e.to_string())?
+        ),
+    }
+    Ok(())
+}
+
+/// The messages of the log matching `filter`, and how many lines could not be read.
+fn select(message_log: &MessageLogConfig, filter: Filter) -> Result<(Vec<Message>, u64), String> {
+    let (start, end) = filter.range()?;
+    let mut messages = Vec::new();
+    let skipped = history::each_message(message_log, |message| {
+        if (start..end).contains(&message.timestamp)
+            && filter.source.is_none_or(|source| message.source == source)
+        {
+            messages.push(message);
+        }
+    })
+    .map_err(|e| format!("could not read {}: {e}", message_log.path))?;
+    Ok((messages, skipped))
+}
+
+/// Where the audio of `message` is in `archive`, if it was kept.
+fn audio_path(message: &Message, archive: &ArchiveConfig) -> Option<String> {
+    let hash = message.audio_hash.as_ref()?;
+    Some(archive.path(hash).to_string_lossy().into_owned())
+}
+
+/// `messages` as JSON, with an `audio_path` added to those that were archived.
+fn with_audio_paths(messages: &[Message], archive: &ArchiveConfig) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .map(|message| {
+            let mut json = serde_json::to_value(message).unwrap_or_default();
+            if let (Some(path), Some(fields)) = (audio_path(message, archive), json.as_object_mut())
+            {
+                fields.insert("audio_path".into(), path.into());
+            }
+            json
+        })
+        .collect()
+}
+
+fn render_csv(messages: &[Message], archive: &ArchiveConfig) -> String {
+    let mut out = format!("{CSV_HEADER}\n");
+    for message in messages {
+        let source = serde_json::to_value(message.source).unwrap_or_default();
+        let status = serde_json::to_value(message.status).unwrap_or_default();
+        let optional = |ms: Option<u64>| ms.map(|ms| ms.to_string()).unwrap_or_default();
+        let fields = [
+            clock::rfc3339_utc(message.timestamp),
+            source.as_str().unwrap_or_default().to_owned(),
+            message.provider.clone(),
+            message.voice.clone(),
+            status.as_str().unwrap_or_default().to_owned(),
+            message.characters.to_string(),
+            optional(message.synthesis_ms),
+            optional(message.first_audio_ms),
+            optional(message.playback_ms),
+            message.text.clone(),
+            message.error.clone().unwrap_or_default(),
+            audio_path(message, archive).unwrap_or_default(),
+        ];
+        let fields: Vec<Cow<str>> = fields.iter().map(|field| csv_field(field)).collect();
+        out += &fields.join(",");
+        out.push('\n');
+    }
+    out
+}
+
+/// `field` quoted if it has a comma, quote, line break or surrounding space, with
+/// its quotes doubled.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    /// 2024-06-01T00:00:00Z, then an hour later from HTTP, then a day later.
+    const FIXTURE: &str = r#"{"timestamp":1717200000,"source":"overlay","text":"plain","provider":"google","voice":"en-US-Standard-A","characters":5,"synthesis_ms":200,"first_audio_ms":260,"playback_ms":900,"status":"ok","audio_hash":"ab12"}
+{"timestamp":1717203600,"source":"http","text":"she said \"hi, chat\"\nthen left","provider":"google","voice":"en-US-Standard-A","characters":25,"playback_ms":800,"status":"ok"}
+broken
+{"timestamp":1717286400,"source":"http","text":" padded ","provider":"mock","voice":"en-US-Standard-B","characters":8,"status":"error","error":"429 from Google — quota exceeded, try again later"}
+"#;
+
+    fn fixture_log(name: &str) -> (PathBuf, MessageLogConfig) {
+        let dir = std::env::temp_dir().join(format!("export-{name}-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let config = MessageLogConfig {
+            path: dir.join("messages.jsonl").to_string_lossy().into_owned(),
+            ..Default::default()
+        };
+        fs::write(&config.path, FIXTURE).unwrap();
+        (dir, config)
+    }
+
+    fn at(s: &str) -> Option<TimeBound> {
+        TimeBound::parse(s)
+    }
+
+    fn texts(messages: &[Message]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn filters_by_time_and_source() {
+        let (dir, config) = fixture_log("filter");
+        let (all, skipped) = select(&config, Filter::default()).unwrap();
+        assert_eq!((all.len(), skipped), (3, 1));
+        let filter = Filter {
+            from: at("2024-06-01T00:30:00Z"),
+            to: at("2024-06-02T00:00:00Z"),
+            source: None,
+        };
+        let (messages, _) = select(&config, filter).unwrap();
+        assert_eq!(
+            texts(&messages),
+            ["she said \"hi, chat\"\nthen left", " padded "]
+        );
+        let filter = Filter {
+            source: Some(Source::Overlay),
+            ..Default::default()
+        };
+        assert_eq!(texts(&select(&config, filter).unwrap().0), ["plain"]);
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_a_range_that_ends_before_it_starts() {
+        let filter = Filter {
+            from: at("2024-06-02"),
+            to: at("2024-06-01"),
+            source: None,
+        };
+        let error = filter.range().unwrap_err();
+        assert_eq!(error, "--to is before --from, so nothing can match");
+        let same_second = Filter {
+            from: at("2024-06-01T00:00:00Z"),
+            to: at("2024-06-01T00:00:00Z"),
+            source: None,
+        };
+        assert!(same_second.range().is_ok());
+    }
+
+    #[test]
+    fn quotes_csv_fields_that_need_it() {
+        let (dir, config) = fixture_log("csv");
+        let archive = ArchiveConfig {
+            dir: "clips".into(),
+            ..Default::default()
+        };
+        let csv = render_csv(&select(&config, Filter::default()).unwrap().0, &archive);
+        let audio = archive.path("ab12").to_string_lossy().into_owned();
+        assert_eq!(
+            csv,
+            format!(
+                "{CSV_HEADER}\n\
+                 2024-06-01T00:00:00Z,overlay,google,en-US-Standard-A,ok,5,200,260,900,plain,,{audio}\n\
+                 2024-06-01T01:00:00Z,http,google,en-US-Standard-A,ok,25,,,800,\"she said \"\"hi, chat\"\"\nthen left\",,\n\
+                 2024-06-02T00:00:00Z,http,mock,en-US-Standard-B,error,8,,,,\" padded \",\"429 from Google — quota exceeded, try again later\",\n"
+            )
+        );
+        _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn adds_the_archived_audio_path_to_json() {
+        let (dir, config) = fixture_log("json");
+        let archive = ArchiveConfig::default();
+        let json = with_audio_paths(&select(&config, Filter::default()).unwrap().0, &archive);
+        let expected = archive.path("ab12").to_string_lossy().into_owned();
+        assert_eq!(json[0]["audio_path"], expected.as_str());
+        assert!(json[1].get("audio_path").is_none());
+        _ = fs::remove_dir_all(dir);
+    }
+}
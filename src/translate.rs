@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    auth, http_log,
+    overrides::{self, Overrides},
+    Configuration,
+};
+
+/// Where messages are sent for translation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslationBackend {
+    /// Google Cloud Translation v2, with the same credentials as speech.
+    #[default]
+    Google,
+    /// A LibreTranslate server at `url`.
+    LibreTranslate,
+}
+
+/// The `[translation]` table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct TranslationConfig {
+    /// Language code to translate every message to, e.g. "es"; empty to speak
+    /// messages as typed. `[tr=es]` in front of a message sets it for that message.
+    pub target: String,
+    pub backend: TranslationBackend,
+    /// Base URL of the backend; Google's public API when empty.
+    pub url: String,
+    /// LibreTranslate API key, if the server needs one.
+    pub api_key: Option<String>,
+}
+
+/// Translates `text` if `config.translation` has a target, and switches `config`
+/// to the voice for that language. If translation fails the original text is
+/// returned and the voice is left alone.
+pub fn localize(text: String, config: &mut Configuration) -> String {
+    let target = config.translation.target.clone();
+    if target.is_empty() {
+        return text;
+    }
+    match translate(&text, &target, config) {
+        Ok(translated) => {
+            log::info!("translated {text:?} to {target}: {translated:?}");
+            let voice = match overrides::language_profile(&target, &config.voice_profiles) {
+                Some(profile) => Overrides {
+                    voice: Some(profile.gcloud_voice.clone()),
+                    language: Some(profile.gcloud_language.clone()),
+                    ..Default::default()
+                },
+                None => Overrides {
+                    language: Some(target),
+                    ..Default::default()
+                },
+            };
+            voice.apply(config);
+            translated
+        }
+        Err(e) => {
+            log::warn!("could not translate to {target}, speaking the original: {e}");
+            text
+        }
+    }
+}
+
+/// Sends `text` to the configured backend and returns its translation to `target`.
+pub fn translate(text: &str, target: &str, config: &Configuration) -> Result<String, String> {
+    let translation = &config.translation;
+    let client = http_log::client(config).map_err(|e| e.to_string())?;
+    let request = match translation.backend {
+        TranslationBackend::Google => {
+            let url = match translation.url.trim_end_matches('/') {
+                "" => "https://translation.googleapis.com",
+                url => url,
+            };
+            let request = client
+                .post(format!("{url}/language/translate/v2"))
+                .json(&json!({ "q": text, "target": target, "format": "text" }));
+            auth::authorize(request, config)?
+        }
+        TranslationBackend::LibreTranslate => {
+            if translation.url.is_empty() {
+                return Err("translation.url is not set".into());
+            }
+            client
+                .post(format!(
+                    "{}/translate",
+                    translation.url.trim_end_matches('/')
+                ))
+                .json(&json!({
+                    "q": text,
+                    "source": "auto",
+                    "target": target,
+                    "format": "text",
+                    "api_key": translation.api_key,
+                }))
+        }
+    };
+    let value: Value = http_log::send(&client, request)
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.json())
+        .map_err(|e| e.to_string())?;
+    let translated = match translation.backend {
+        TranslationBackend::Google => &value["data"]["translations"][0]["translatedText"],
+        TranslationBackend::LibreTranslate => &value["translatedText"],
+    };
+    translated
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| format!("unexpected response: {value}"))
+}
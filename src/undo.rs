@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+/// Undo steps kept; older ones are forgotten.
+const MAX_ENTRIES: usize = 50;
+
+/// Typing adds an undo step at most this often, so undo does not go back a
+/// character at a time.
+const TYPING_STEP: Duration = Duration::from_secs(2);
+
+/// Earlier contents of the overlay's text box, for the `undo` hotkey. egui's own
+/// undo does not see text replaced from outside the text box, such as a
+/// completion, so one undo would not take it back.
+#[derive(Default)]
+pub struct TextHistory {
+    /// Oldest first.
+    entries: Vec<String>,
+    last_push: Option<Instant>,
+}
+
+impl TextHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the text from before and after an edit made in one go, such as
+    /// inserting a completion, so that undo takes it back in one step.
+    pub fn replaced(&mut self, before: &str, after: &str) {
+        self.push(before);
+        self.push(after);
+    }
+
+    /// Records `before`, the text from before a change typed at `now`, unless
+    /// typing recorded a step shortly before.
+    pub fn typed(&mut self, before: &str, now: Instant) {
+        if self
+            .last_push
+            .is_some_and(|last| now.duration_since(last) < TYPING_STEP)
+        {
+            return;
+        }
+        self.push(before);
+        self.last_push = Some(now);
+    }
+
+    /// The text to go back to from `current`, if there is one.
+    pub fn undo(&mut self, current: &str) -> Option<String> {
+        while self.entries.last().is_some_and(|entry| entry == current) {
+            self.entries.pop();
+        }
+        let previous = self.entries.pop()?;
+        // typing after the undo starts a new step
+        self.last_push = None;
+        Some(previous)
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.entries.last().is_some_and(|entry| entry == text) {
+            return;
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(text.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_back_a_replacement_in_one_step() {
+        let mut history = TextHistory::new();
+        let start = Instant::now();
+        history.typed("", start);
+        history.typed("!h", start + Duration::from_millis(300));
+        history.replaced("!h", "Hello everyone!");
+        // typed right after the completion, so no new step
+        history.typed("Hello everyone!", start + Duration::from_millis(600));
+        let mut text = "Hello everyone! :)".to_owned();
+        for expected in ["Hello everyone!", "!h", ""] {
+            text = history.undo(&text).unwrap();
+            assert_eq!(text, expected);
+        }
+        assert_eq!(history.undo(&text), None);
+    }
+
+    #[test]
+    fn adds_a_typing_step_every_two_seconds() {
+        let mut history = TextHistory::new();
+        let start = Instant::now();
+        for (i, before) in ["", "a", "ab", "abc"].into_iter().enumerate() {
+            history.typed(before, start + Duration::from_millis(700) * i as u32);
+        }
+        assert_eq!(history.entries, ["", "abc"]);
+    }
+
+    #[test]
+    fn keeps_the_last_fifty_steps() {
+        let mut history = TextHistory::new();
+        for i in 0..60 {
+            history.replaced(&i.to_string(), &(i + 1).to_string());
+        }
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert_eq!(history.entries[0], "11");
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use crate::{error_log, http_log, provider::ProviderConfig, speech, Configuration};
+
+/// How long the connectivity check waits for the API.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks the output device, that the API can be reached and that it synthesizes
+/// a short phrase, printing a line for each. Returns whether all of them passed.
+pub fn run(config: &Configuration) -> bool {
+    let (language, voice) = config.active_voice();
+    let checks = [
+        (
+            "audio device",
+            speech::verify_audio_device(&config.output_device).map_err(|e| e.to_string()),
+        ),
+        ("connectivity", connectivity(config)),
+        (
+            "synthesis",
+            speech::synthesize("Testing.", language, voice, config)
+                .map(|wav| {
+                    format!(
+                        "{} bytes of audio from {}",
+                        wav.len(),
+                        config.provider.name()
+                    )
+                })
+                .map_err(|e| e.to_string()),
+        ),
+    ];
+    let mut ok = true;
+    for (check, result) in checks {
+        match result {
+            Ok(detail) => println!("✓ {check}: {detail}"),
+            Err(e) => {
+                println!("✗ {check}: {e}");
+                ok = false;
+            }
+        }
+    }
+    println!("{}", error_log::describe());
+    ok
+}
+
+/// Whether `gcloud_endpoint` answers at all; any HTTP status will do.
+fn connectivity(config: &Configuration) -> Result<String, String> {
+    if let ProviderConfig::Mock { .. } = config.provider {
+        return Ok("not needed for the mock provider".into());
+    }
+    let client = http_log::client_builder(config)
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    match client.get(&config.gcloud_endpoint).send() {
+        Ok(_) => Ok(format!("{} is reachable", config.gcloud_endpoint)),
+        Err(e) if e.is_timeout() => Err(format!(
+            "{} did not answer within {} seconds",
+            config.gcloud_endpoint,
+            TIMEOUT.as_secs()
+        )),
+        Err(_) => Err(format!("could not connect to {}", config.gcloud_endpoint)),
+    }
+}
@@ -0,0 +1,188 @@
+//! `--list-voices`: the voices the configured provider offers, so that a
+//! `gcloud_voice` can be picked without going through the provider's docs.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{auth, http_log, provider::ProviderConfig, stats::OutputFormat, Configuration};
+
+/// A voice, as `--list-voices` lists it.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoiceInfo {
+    pub name: String,
+    pub language_codes: Vec<String>,
+    pub gender: String,
+    pub sample_rate_hz: u32,
+}
+
+/// The parts of a Google `voices` response that are used.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleVoices {
+    #[serde(default)]
+    voices: Vec<GoogleVoice>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleVoice {
+    name: String,
+    #[serde(default)]
+    language_codes: Vec<String>,
+    #[serde(default)]
+    ssml_gender: String,
+    #[serde(default)]
+    natural_sample_rate_hertz: u32,
+}
+
+/// The voices of the configured provider, only those for `language` if given,
+/// sorted by name.
+pub fn list(config: &Configuration, language: Option<&str>) -> Result<Vec<VoiceInfo>, String> {
+    let mut voices = match &config.provider {
+        ProviderConfig::Google { .. } => google(config, language)?,
+        ProviderConfig::Mock { .. } => {
+            return Err(
+                "the mock provider has no voices to list; it plays a tone for any voice".into(),
+            )
+        }
+    };
+    voices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(voices)
+}
+
+fn google(config: &Configuration, language: Option<&str>) -> Result<Vec<VoiceInfo>, String> {
+    let url = format!("{}/v1/voices", config.gcloud_endpoint.trim_end_matches('/'));
+    let client = http_log::client(config).map_err(|e| e.to_string())?;
+    let mut request = client.get(url);
+    if let Some(language) = language {
+        request = request.query(&[("languageCode", language)]);
+    }
+    let request = auth::authorize(request, config)?;
+    let response = http_log::send(&client, request)
+        .map_err(|e| format!("could not reach Google: {}", e.without_url()))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().unwrap_or_default();
+        return Err(format!("{status} from Google: {}", body.trim()));
+    }
+    let response: GoogleVoices = response
+        .json()
+        .map_err(|e| format!("unexpected voice list from Google: {e}"))?;
+    Ok(response
+        .voices
+        .into_iter()
+        .map(|voice| VoiceInfo {
+            name: voice.name,
+            language_codes: voice.language_codes,
+            gender: voice.ssml_gender.to_lowercase(),
+            sample_rate_hz: voice.natural_sample_rate_hertz,
+        })
+        .collect())
+}
+
+/// Prints the voices for `--list-voices`.
+pub fn run(
+    config: &Configuration,
+    language: Option<&str>,
+    format: OutputFormat,
+) -> Result<(), String> {
+    let voices = list(config, language)?;
+    if voices.is_empty() && format == OutputFormat::Text {
+        match language {
+            Some(language) => println!("No voices for language {language}"),
+            None => println!("No voices"),
+        }
+        return Ok(());
+    }
+    match format {
+        OutputFormat::Text => print!("{}", render_text(&voices)),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&voices).map_err(|e| e.to_string())?
+        ),
+        OutputFormat::Csv => print!("{}", render_csv(&voices)),
+    }
+    Ok(())
+}
+
+fn render_text(voices: &[VoiceInfo]) -> String {
+    let rows: Vec<[String; 4]> = voices
+        .iter()
+        .map(|voice| {
+            [
+                voice.name.clone(),
+                voice.language_codes.join(", "),
+                voice.gender.clone(),
+                format!("{} Hz", voice.sample_rate_hz),
+            ]
+        })
+        .collect();
+    let header = ["NAME", "LANGUAGES", "GENDER", "SAMPLE RATE"].map(str::to_owned);
+    let widths: Vec<usize> = (0..3)
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        out += &format!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}\n",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        );
+    }
+    out
+}
+
+fn render_csv(voices: &[VoiceInfo]) -> String {
+    let mut out = "name,language_codes,gender,sample_rate_hz\n".to_owned();
+    for voice in voices {
+        out += &format!(
+            "\"{}\",{},{},{}\n",
+            voice.name.replace('"', "\"\""),
+            voice.language_codes.join(";"),
+            voice.gender,
+            voice.sample_rate_hz
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_up_the_table() {
+        let voices = [
+            VoiceInfo {
+                name: "en-US-Neural2-A".into(),
+                language_codes: vec!["en-US".into()],
+                gender: "male".into(),
+                sample_rate_hz: 24_000,
+            },
+            VoiceInfo {
+                name: "cmn-CN-Standard-A".into(),
+                language_codes: vec!["cmn-CN".into(), "zh-CN".into()],
+                gender: "female".into(),
+                sample_rate_hz: 24_000,
+            },
+        ];
+        assert_eq!(
+            render_text(&voices),
+            "\
+NAME               LANGUAGES      GENDER  SAMPLE RATE
+en-US-Neural2-A    en-US          male    24000 Hz
+cmn-CN-Standard-A  cmn-CN, zh-CN  female  24000 Hz
+"
+        );
+    }
+}
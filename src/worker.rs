@@ -0,0 +1,102 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+/// Outcome of a background job, delivered to whoever waits on it.
+pub type WorkerResult = Result<(), String>;
+
+/// Sends its result when dropped, so the waiter hears back even if the job never returns.
+struct CompletionGuard {
+    waiter: Option<oneshot::Sender<WorkerResult>>,
+    result: WorkerResult,
+}
+
+impl Drop for CompletionGuard {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            _ = waiter.send(std::mem::replace(&mut self.result, Ok(())));
+        }
+    }
+}
+
+/// Runs `job` on its own thread, reporting a panic as an error instead of unwinding silently.
+pub fn spawn<F>(waiter: oneshot::Sender<WorkerResult>, job: F)
+where
+    F: FnOnce() -> WorkerResult + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut guard = CompletionGuard {
+            waiter: Some(waiter),
+            result: Err("worker exited unexpectedly".into()),
+        };
+        guard.result = catching(job);
+    });
+}
+
+/// Runs `job` on the current thread, turning a panic into an error.
+pub fn catching(job: impl FnOnce() -> WorkerResult) -> WorkerResult {
+    panic::catch_unwind(AssertUnwindSafe(job)).unwrap_or_else(|payload| {
+        let message = panic_message(payload.as_ref());
+        log::error!("worker panicked: {message}");
+        Err(message)
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        (*s).to_owned()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "worker panicked".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn reports_a_panicking_job_to_the_waiter() {
+        let (sender, receiver) = oneshot::channel();
+        spawn(sender, || panic!("no audio device"));
+        assert_eq!(
+            receiver.recv_timeout(TIMEOUT),
+            Ok(Err("no audio device".into()))
+        );
+
+        let (sender, receiver) = oneshot::channel();
+        spawn(sender, || panic::panic_any(42));
+        assert_eq!(
+            receiver.recv_timeout(TIMEOUT),
+            Ok(Err("worker panicked".into()))
+        );
+
+        let (sender, receiver) = oneshot::channel();
+        spawn(sender, || Ok(()));
+        assert_eq!(receiver.recv_timeout(TIMEOUT), Ok(Ok(())));
+    }
+
+    #[test]
+    fn the_guard_answers_when_the_thread_unwinds_past_it() {
+        let (sender, receiver) = oneshot::channel();
+        let thread = thread::spawn(move || {
+            let _guard = CompletionGuard {
+                waiter: Some(sender),
+                result: Err("worker exited unexpectedly".into()),
+            };
+            panic!("not caught");
+        });
+        assert_eq!(
+            receiver.recv_timeout(TIMEOUT),
+            Ok(Err("worker exited unexpectedly".into()))
+        );
+        assert!(thread.join().is_err());
+    }
+}
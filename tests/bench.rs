@@ -0,0 +1,29 @@
+mod common;
+
+use tts_overlay::{bench, provider::ProviderConfig};
+
+#[test]
+fn times_each_phase_of_the_mock_provider() {
+    let mut config = common::config("http://127.0.0.1:9");
+    config.provider = ProviderConfig::Mock {
+        response_delay_ms: 20,
+        audio_fixture_path: None,
+        panic_message: None,
+    };
+    let report = bench::measure(&config, 3, "hello there").unwrap();
+    assert_eq!(
+        (report.provider, report.runs, report.characters),
+        ("mock", 3, 11)
+    );
+    assert!(report.audio_bytes > 44, "{report:?}");
+    let names: Vec<&str> = report.phases.iter().map(|phase| phase.name).collect();
+    // nothing to connect to
+    assert_eq!(names, ["request", "decode", "first_sample"]);
+    let [request, decode, first_sample] = &report.phases[..] else {
+        unreachable!()
+    };
+    assert!(request.min_ms >= 20., "{request:?}");
+    assert!(request.min_ms <= request.median_ms && request.median_ms <= request.p95_ms);
+    assert!(first_sample.min_ms >= request.min_ms, "{report:?}");
+    assert!(decode.p95_ms < first_sample.p95_ms, "{report:?}");
+}
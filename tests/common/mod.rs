@@ -0,0 +1,217 @@
+//! Helpers shared by the integration tests.
+
+// each test binary uses a different subset
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Once,
+    },
+    thread,
+    time::Duration,
+};
+
+use tts_overlay::Configuration;
+
+/// How long `MockServer::request` waits before the test fails.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An HTTP server that answers every request with the same response.
+pub struct MockServer {
+    pub url: String,
+    requests: mpsc::Receiver<String>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub fn start(status: u16, body: &str) -> Self {
+        Self::start_sequence(&[(status, "", body)])
+    }
+
+    /// Answers with each `(status, extra header lines, body)` in turn, then keeps
+    /// repeating the last one.
+    pub fn start_sequence(responses: &[(u16, &str, &str)]) -> Self {
+        Self::start_with(responses, Duration::ZERO)
+    }
+
+    /// Answers every request with the same response, `delay` after reading it.
+    pub fn start_slow(delay: Duration, status: u16, body: &str) -> Self {
+        Self::start_with(&[(status, "", body)], delay)
+    }
+
+    /// Answers every request with the same response, keeping the connection open
+    /// for the next one, as real servers do.
+    pub fn start_keep_alive(status: u16, body: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = connections.clone();
+        let body = body.to_owned();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                counted.fetch_add(1, Ordering::SeqCst);
+                let (sender, body) = (sender.clone(), body.clone());
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    while let Some(request) = read_request(&mut reader) {
+                        _ = sender.send(request);
+                        _ = write!(
+                            stream,
+                            "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                            body.len()
+                        );
+                    }
+                });
+            }
+        });
+        Self {
+            url,
+            requests,
+            connections,
+        }
+    }
+
+    fn start_with(responses: &[(u16, &str, &str)], delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, requests) = mpsc::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counted = connections.clone();
+        let mut responses: Vec<(u16, String, String)> = responses
+            .iter()
+            .map(|&(status, headers, body)| (status, headers.to_owned(), body.to_owned()))
+            .collect();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (status, headers, body) = if responses.len() > 1 {
+                    responses.remove(0)
+                } else {
+                    responses[0].clone()
+                };
+                let Ok(mut stream) = stream else { continue };
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let request = read_request(&mut reader).unwrap_or_default();
+                // before answering, so that the client never sees a response first
+                _ = sender.send(request);
+                thread::sleep(delay);
+                _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        Self {
+            url,
+            requests,
+            connections,
+        }
+    }
+
+    /// Headers and body of the next request the server received.
+    pub fn request(&self) -> String {
+        self.requests
+            .recv_timeout(REQUEST_TIMEOUT)
+            .expect("the server got no request")
+    }
+
+    /// How many requests arrived that `request` has not returned yet.
+    pub fn unread_requests(&self) -> usize {
+        self.requests.try_iter().count()
+    }
+
+    /// How many connections were opened to the server.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+/// Headers and body of the next request on `reader`, if the client sent one
+/// before closing the connection.
+fn read_request(reader: &mut BufReader<TcpStream>) -> Option<String> {
+    let mut request = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return (!request.is_empty()).then_some(request);
+        }
+        if line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        request.push_str(&line);
+    }
+    let mut content = vec![0; length];
+    _ = reader.read_exact(&mut content);
+    request.push_str(&String::from_utf8_lossy(&content));
+    Some(request)
+}
+
+/// A minimal configuration talking to `endpoint`.
+pub fn config(endpoint: &str) -> Configuration {
+    in_scratch_dir();
+    toml::from_str(&format!(
+        r#"
+        font_size = 24.0
+        width = 600.0
+        x = 0.0
+        y = 0.0
+        gcloud_token = "test-token"
+        gcloud_endpoint = "{endpoint}"
+        gcloud_language = "en-US"
+        gcloud_voice = "en-US-Standard-A"
+        output_device = "no such device"
+        drop_when_offline = true
+        "#
+    ))
+    .unwrap()
+}
+
+/// A short, silent 16-bit mono WAV file.
+pub fn wav() -> Vec<u8> {
+    let samples = [0u8; 32];
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&24_000u32.to_le_bytes());
+    wav.extend_from_slice(&48_000u32.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    wav.extend_from_slice(&samples);
+    wav
+}
+
+/// Set to the scratch directory for a test process started by another test, so
+/// that it sees the files the first one wrote.
+pub const SCRATCH_DIR: &str = "TTS_OVERLAY_TEST_DIR";
+
+/// Moves into a temporary directory, so history files written by the code under
+/// test don't end up in the repository.
+fn in_scratch_dir() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let dir = match std::env::var_os(SCRATCH_DIR) {
+            Some(dir) => dir.into(),
+            None => std::env::temp_dir().join(format!("tts-overlay-tests-{}", std::process::id())),
+        };
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_current_dir(dir).unwrap();
+    });
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::MockServer;
+use tts_overlay::{
+    health::{self, Health},
+    speech,
+};
+
+#[test]
+fn reports_a_reachable_provider() {
+    let server = MockServer::start(200, r#"{"voices": []}"#);
+    let config = common::config(&server.url);
+    assert_eq!(health::probe(&config, false), Health::Ok);
+    let request = server.request();
+    assert!(
+        request.starts_with("GET /v1/voices?languageCode=en-US "),
+        "{request}"
+    );
+}
+
+#[test]
+fn tells_an_expired_key_from_no_network() {
+    let server = MockServer::start(
+        400,
+        r#"{"error": {"code": 400, "message": "API key expired. Please renew the API key.", "status": "INVALID_ARGUMENT"}}"#,
+    );
+    let config = common::config(&server.url);
+    match health::probe(&config, false) {
+        Health::Down(reason) => assert!(
+            reason.ends_with(": API key expired. Please renew the API key."),
+            "{reason}"
+        ),
+        health => panic!("{health:?}"),
+    }
+    let config = common::config("http://127.0.0.1:9");
+    match health::probe(&config, false) {
+        Health::Down(reason) => assert!(reason.starts_with("network error: "), "{reason}"),
+        health => panic!("{health:?}"),
+    }
+}
+
+#[test]
+fn reports_an_exhausted_quota_as_degraded() {
+    let server = MockServer::start(429, r#"{"error": {"message": "Quota exceeded"}}"#);
+    let config = common::config(&server.url);
+    assert!(
+        matches!(health::probe(&config, false), Health::Degraded(reason) if reason.starts_with("429 from Google")),
+    );
+}
+
+#[test]
+fn leaves_the_connection_open_for_the_first_message_with_prewarm() {
+    let server = MockServer::start_keep_alive(200, r#"{"audioContent": ""}"#);
+    let config = common::config(&server.url);
+    assert_eq!(health::probe(&config, true), Health::Ok);
+    server.request();
+    // the audio is empty, but the request went through
+    _ = speech::synthesize("hello", "en-US", "en-US-Standard-A", &config);
+    assert!(server.request().starts_with("POST /v1/text:synthesize "));
+    assert_eq!(server.connections(), 1);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::{
+    fs,
+    io::Cursor,
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
+};
+
+use rodio::Source;
+use tts_overlay::{export, history, provider::ProviderConfig, speech, worker, Configuration};
+
+fn mock_config(response_delay_ms: u64, audio_fixture_path: Option<String>) -> Configuration {
+    let mut config = common::config("http://127.0.0.1:9");
+    config.provider = ProviderConfig::Mock {
+        response_delay_ms,
+        audio_fixture_path,
+        panic_message: None,
+    };
+    config
+}
+
+fn duration(wav: Vec<u8>) -> Duration {
+    let decoder = rodio::Decoder::new_wav(Cursor::new(wav)).unwrap();
+    decoder.total_duration().unwrap()
+}
+
+#[test]
+fn generates_a_tone_as_long_as_the_text() {
+    let config = mock_config(0, None);
+    let short = speech::synthesize("hi", "en-US", "voice", &config).unwrap();
+    let long = speech::synthesize("hello there", "en-US", "voice", &config).unwrap();
+    let (short, long) = (duration(short), duration(long));
+    assert!(short > Duration::ZERO);
+    assert_eq!(long.as_millis() * 2, short.as_millis() * 11);
+}
+
+#[test]
+fn returns_the_fixture() {
+    let path = std::env::temp_dir().join(format!("mock-fixture-{}.wav", std::process::id()));
+    fs::write(&path, common::wav()).unwrap();
+    let config = mock_config(0, Some(path.to_string_lossy().into_owned()));
+    let wav = speech::synthesize("hello", "en-US", "voice", &config).unwrap();
+    assert_eq!(wav, common::wav());
+    _ = fs::remove_file(path);
+}
+
+#[test]
+fn reports_a_missing_fixture() {
+    let config = mock_config(0, Some("no/such/fixture.wav".into()));
+    let error = speech::synthesize("hello", "en-US", "voice", &config)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.starts_with("unplayable audio: could not read no/such/fixture.wav"),
+        "{error}"
+    );
+}
+
+#[test]
+fn reports_a_panicking_provider_to_the_waiter() {
+    let mut config = mock_config(0, None);
+    config.provider = ProviderConfig::Mock {
+        response_delay_ms: 0,
+        audio_fixture_path: None,
+        panic_message: Some("the backend crashed".into()),
+    };
+    let (waiter, result) = oneshot::channel();
+    // as the overlay's speech worker does; the one-shot app exits with the error
+    worker::spawn(waiter, move || {
+        let cancel = AtomicBool::new(false);
+        speech::speak("hello".into(), config, history::Source::Overlay, &cancel)
+            .map_err(|e| e.to_string())
+    });
+    assert_eq!(
+        result.recv_timeout(Duration::from_secs(10)),
+        Ok(Err("the backend crashed".into()))
+    );
+}
+
+#[test]
+fn waits_before_answering() {
+    let config = mock_config(200, None);
+    let start = Instant::now();
+    speech::synthesize("hello", "en-US", "voice", &config).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn exports_the_lines_with_silence_between() {
+    let config = mock_config(0, None);
+    let dir = std::env::temp_dir().join(format!("mock-export-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("queue.txt"), dir.join("queue.wav"));
+    fs::write(&input, "hi\n\nhello\n").unwrap();
+    let length = export::run(&input, &output, 500, &config).unwrap();
+    // 100 ms for "hi", 500 ms of silence, 250 ms for "hello"
+    assert_eq!(length, Duration::from_millis(850));
+    assert_eq!(duration(fs::read(&output).unwrap()), length);
+    _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn is_selected_in_config_toml() {
+    let config: ProviderConfig = toml::from_str(
+        r#"
+        type = "mock"
+        response_delay_ms = 300
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        ProviderConfig::Mock {
+            response_delay_ms: 300,
+            audio_fixture_path: None,
+            panic_message: None,
+        }
+    );
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use std::{fs, io::Write, sync::Arc, thread, time::Duration};
+
+use common::MockServer;
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use tts_overlay::{
+    filewatch::FileWatcher, filters::Pipeline, queue::SpeechQueue, server, QuickReply,
+};
+
+/// Starts the control server on a free port, speaking through `provider`, and
+/// returns its URL.
+fn start(provider: &MockServer, token: Option<&str>) -> String {
+    start_with_chat(provider, token, None)
+}
+
+/// Like `start`, with a chat reader following `chat_log` if given.
+fn start_with_chat(provider: &MockServer, token: Option<&str>, chat_log: Option<&str>) -> String {
+    let mut config = common::config(&provider.url);
+    config.file_watch_poll_ms = 20;
+    let pipeline = Arc::new(Pipeline::new(&config, false).unwrap());
+    let queue = SpeechQueue::start(config.clone(), pipeline);
+    let chat_reader_paused = chat_log.map(|path| {
+        _ = fs::remove_file(path);
+        FileWatcher::start(path.into(), &config, queue.clone()).unwrap()
+    });
+    let reply = |label: &str, text: &str| QuickReply {
+        label: label.into(),
+        text: text.into(),
+        color: None,
+    };
+    let state = server::State {
+        queue,
+        clipboard_paused: None,
+        chat_reader_paused,
+        quick_replies: vec![
+            reply("Hi", "Hello chat!"),
+            reply("BRB", "Back in a minute."),
+        ],
+        token: token.map(str::to_owned),
+    };
+    let addr = server::start("127.0.0.1:0", state).unwrap();
+    format!("http://{addr}")
+}
+
+/// Sends `method path`, with `token` as the bearer token, and returns the
+/// status and JSON body.
+fn call(url: &str, method: &str, path: &str, token: Option<&str>) -> (u16, Value) {
+    let client = Client::new();
+    let mut request = client.request(method.parse().unwrap(), format!("{url}{path}"));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().unwrap();
+    let status = response.status().as_u16();
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/json",
+        "{method} {path}"
+    );
+    (status, response.json().unwrap())
+}
+
+fn requested_text(request: &str) -> String {
+    let body: Value = serde_json::from_str(&request[request.find('{').unwrap()..]).unwrap();
+    body["input"]["text"].as_str().unwrap().to_owned()
+}
+
+#[test]
+fn speaks_a_quick_reply_by_index() {
+    let provider = MockServer::start(200, r#"{"audioContent": ""}"#);
+    let url = start(&provider, None);
+    // curl -X POST localhost:7878/phrase/1
+    let (status, body) = call(&url, "POST", "/phrase/1", None);
+    assert_eq!(status, 202);
+    assert_eq!(body["queued"], true);
+    assert_eq!(body["label"], "BRB");
+    assert_eq!(body["ids"].as_array().map(Vec::len), Some(1));
+    assert_eq!(requested_text(&provider.request()), "Back in a minute.");
+    for index in ["2", "first"] {
+        assert_eq!(
+            call(&url, "POST", &format!("/phrase/{index}"), None),
+            (
+                404,
+                json!({ "error": format!("no quick reply at index {index:?}") })
+            )
+        );
+    }
+    assert_eq!(provider.unread_requests(), 0);
+}
+
+#[test]
+fn lists_the_quick_replies() {
+    let provider = MockServer::start(200, "{}");
+    let url = start(&provider, None);
+    // curl localhost:7878/phrase-list
+    assert_eq!(
+        call(&url, "GET", "/phrase-list", None),
+        (
+            200,
+            json!([{ "index": 0, "label": "Hi" }, { "index": 1, "label": "BRB" }])
+        )
+    );
+}
+
+#[test]
+fn skipping_with_nothing_playing_does_nothing() {
+    let provider = MockServer::start(200, "{}");
+    let url = start(&provider, None);
+    // curl -X POST localhost:7878/skip
+    assert_eq!(
+        call(&url, "POST", "/skip", None),
+        (200, json!({ "skipped": false }))
+    );
+    assert_eq!(provider.unread_requests(), 0);
+}
+
+#[test]
+fn pauses_and_resumes_the_chat_reader() {
+    let provider = MockServer::start(200, r#"{"audioContent": ""}"#);
+    let url = start_with_chat(&provider, None, Some("chat.log"));
+    let say = |line: &str| {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("chat.log")
+            .unwrap();
+        writeln!(file, "{line}").unwrap();
+    };
+    // curl -X POST localhost:7878/toggle-chat-reader
+    assert_eq!(
+        call(&url, "POST", "/toggle-chat-reader", None),
+        (200, json!({ "paused": true }))
+    );
+    say("said while paused");
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(provider.unread_requests(), 0);
+    assert_eq!(
+        call(&url, "POST", "/toggle-chat-reader", None),
+        (200, json!({ "paused": false }))
+    );
+    say("said once resumed");
+    assert_eq!(requested_text(&provider.request()), "said once resumed");
+}
+
+#[test]
+fn requires_the_bearer_token_when_one_is_set() {
+    let provider = MockServer::start(200, "{}");
+    let url = start(&provider, Some("hunter2"));
+    let unauthorized = (401, json!({ "error": "missing or wrong bearer token" }));
+    // curl -X POST -d 'hello' localhost:7878/speak
+    assert_eq!(call(&url, "POST", "/speak", None), unauthorized);
+    // curl -X POST -H 'Authorization: Bearer hunter3' -d 'hello' localhost:7878/speak
+    assert_eq!(call(&url, "POST", "/speak", Some("hunter3")), unauthorized);
+    assert_eq!(call(&url, "GET", "/metrics", None), unauthorized);
+    // curl -H 'Authorization: Bearer hunter2' localhost:7878/phrase-list
+    assert_eq!(call(&url, "GET", "/phrase-list", Some("hunter2")).0, 200);
+    assert_eq!(provider.unread_requests(), 0);
+}
+
+#[test]
+fn answers_errors_with_a_json_body() {
+    let provider = MockServer::start(200, "{}");
+    let url = start(&provider, None);
+    for (method, path, status, error) in [
+        // curl -X POST localhost:7878/speak
+        ("POST", "/speak", 400, "nothing to say"),
+        ("GET", "/nowhere", 404, "not found"),
+        ("GET", "/skip", 404, "not found"),
+        (
+            "POST",
+            "/toggle-chat-reader",
+            404,
+            "chat reader is not running",
+        ),
+        (
+            "POST",
+            "/cancel/next",
+            404,
+            "no queued message with id \"next\"",
+        ),
+        (
+            "POST",
+            "/clipboard/toggle",
+            404,
+            "clipboard watch is not running",
+        ),
+    ] {
+        assert_eq!(
+            call(&url, method, path, None),
+            (status, json!({ "error": error })),
+            "{method} {path}"
+        );
+    }
+}
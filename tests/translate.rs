@@ -0,0 +1,94 @@
+mod common;
+
+use common::MockServer;
+use tts_overlay::{
+    translate::{self, TranslationBackend},
+    Configuration, VoiceProfile,
+};
+
+fn config(server: &MockServer, backend: TranslationBackend) -> Configuration {
+    let mut config = common::config("http://127.0.0.1:9");
+    config.translation.target = "es".into();
+    config.translation.backend = backend;
+    config.translation.url = server.url.clone();
+    config
+}
+
+#[test]
+fn translates_with_google() {
+    let server = MockServer::start(
+        200,
+        r#"{"data": {"translations": [{"translatedText": "hola", "detectedSourceLanguage": "en"}]}}"#,
+    );
+    let config = config(&server, TranslationBackend::Google);
+    assert_eq!(
+        translate::translate("hello", "es", &config),
+        Ok("hola".into())
+    );
+    let request = server.request();
+    assert!(
+        request.starts_with("POST /language/translate/v2 "),
+        "{request}"
+    );
+    assert!(
+        request
+            .to_lowercase()
+            .contains("x-goog-api-key: test-token"),
+        "{request}"
+    );
+    assert!(request.contains(r#""target":"es""#), "{request}");
+}
+
+#[test]
+fn translates_with_libretranslate() {
+    let server = MockServer::start(200, r#"{"translatedText": "hola"}"#);
+    let mut config = config(&server, TranslationBackend::LibreTranslate);
+    config.translation.api_key = Some("libre-key".into());
+    assert_eq!(
+        translate::translate("hello", "es", &config),
+        Ok("hola".into())
+    );
+    let request = server.request();
+    assert!(request.starts_with("POST /translate "), "{request}");
+    assert!(request.contains(r#""api_key":"libre-key""#), "{request}");
+    assert!(request.contains(r#""source":"auto""#), "{request}");
+}
+
+#[test]
+fn switches_to_the_voice_for_the_target_language() {
+    let server = MockServer::start(200, r#"{"translatedText": "hola"}"#);
+    let mut config = config(&server, TranslationBackend::LibreTranslate);
+    config.voice_profiles.insert(
+        "spanish".into(),
+        VoiceProfile {
+            gcloud_language: "es-ES".into(),
+            gcloud_voice: "es-ES-Standard-A".into(),
+        },
+    );
+    assert_eq!(translate::localize("hello".into(), &mut config), "hola");
+    assert_eq!(config.gcloud_language, "es-ES");
+    assert_eq!(config.gcloud_voice, "es-ES-Standard-A");
+}
+
+#[test]
+fn speaks_the_original_when_translation_fails() {
+    let server = MockServer::start(503, "{}");
+    let mut config = config(&server, TranslationBackend::Google);
+    assert_eq!(translate::localize("hello".into(), &mut config), "hello");
+    assert_eq!(config.gcloud_language, "en-US");
+    assert_eq!(config.gcloud_voice, "en-US-Standard-A");
+}
+
+#[test]
+fn reports_an_unexpected_response() {
+    let server = MockServer::start(200, r#"{"error": "no"}"#);
+    let config = config(&server, TranslationBackend::LibreTranslate);
+    let error = translate::translate("hello", "es", &config).unwrap_err();
+    assert!(error.starts_with("unexpected response: "), "{error}");
+}
+
+#[test]
+fn leaves_text_alone_without_a_target() {
+    let mut config = common::config("http://127.0.0.1:9");
+    assert_eq!(translate::localize("hello".into(), &mut config), "hello");
+}
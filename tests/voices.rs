@@ -0,0 +1,52 @@
+mod common;
+
+use common::MockServer;
+use tts_overlay::{provider::ProviderConfig, voices};
+
+#[test]
+fn lists_the_voices_for_a_language() {
+    let server = MockServer::start(
+        200,
+        r#"{"voices": [
+            {"languageCodes": ["en-US"], "name": "en-US-Wavenet-B", "ssmlGender": "MALE", "naturalSampleRateHertz": 24000},
+            {"languageCodes": ["en-US"], "name": "en-US-Standard-A", "ssmlGender": "FEMALE", "naturalSampleRateHertz": 24000}
+        ]}"#,
+    );
+    let config = common::config(&server.url);
+    let voices = voices::list(&config, Some("en-US")).unwrap();
+    let names: Vec<&str> = voices.iter().map(|voice| voice.name.as_str()).collect();
+    assert_eq!(names, ["en-US-Standard-A", "en-US-Wavenet-B"]);
+    assert_eq!(voices[0].gender, "female");
+    assert_eq!(voices[0].sample_rate_hz, 24_000);
+    let request = server.request();
+    assert!(
+        request.starts_with("GET /v1/voices?languageCode=en-US "),
+        "{request}"
+    );
+    assert!(
+        request
+            .to_lowercase()
+            .contains("x-goog-api-key: test-token"),
+        "{request}"
+    );
+}
+
+#[test]
+fn says_when_the_provider_cannot_list_voices() {
+    let mut config = common::config("http://127.0.0.1:9");
+    config.provider = ProviderConfig::Mock {
+        response_delay_ms: 0,
+        audio_fixture_path: None,
+        panic_message: None,
+    };
+    let error = voices::list(&config, None).unwrap_err();
+    assert!(error.contains("mock provider has no voices"), "{error}");
+}
+
+#[test]
+fn reports_a_rejected_key() {
+    let server = MockServer::start(403, r#"{"error": {"message": "API key not valid"}}"#);
+    let config = common::config(&server.url);
+    let error = voices::list(&config, None).unwrap_err();
+    assert!(error.starts_with("403 Forbidden from Google: "), "{error}");
+}